@@ -1,4 +1,5 @@
 fn main() {
-    let mut app = oxidation_app::App::new("Hello Triangle", 1920, 1080);
+    let mut app = oxidation_app::App::new("Hello Triangle", 1920, 1080)
+        .with_log_level(oxidation_app::LevelFilter::Info);
     app.run();
 }
@@ -1,3 +1,4 @@
+pub use log::LevelFilter;
 use oxidation_engine as engine;
 use oxidation_vk as ovk;
 use std::rc::Rc;
@@ -16,35 +17,46 @@ use winit::{
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// let win_title = "MyApp";
 /// let win_size = (1920, 1080);
-/// let mut app = oxidation_app::App::new(win_title, win_size.0, win_size.1);
+/// let mut app = oxidation_app::App::new(win_title, win_size.0, win_size.1)
+///     .with_log_level(oxidation_app::LevelFilter::Info);
 ///  app.run();
+/// ```
 ///
 pub struct App {
     window: Option<Arc<Window>>,
     window_size: (u32, u32),
     window_title: String,
     driver: Option<Rc<Driver>>,
+    engine: Option<engine::Engine>,
 }
 
 impl App {
-    /// Create a new application instance.
+    /// Create a new application instance. No logger is installed, so applications are free
+    /// to set up their own - see [`Self::with_log_level`] to use the default logger.
     pub fn new(win_title: &str, win_width: u32, win_height: u32) -> Self {
-        env_logger::builder()
-            .target(env_logger::Target::Stdout)
-            .filter_level(log::LevelFilter::Trace)
-            .is_test(true)
-            .try_init()
-            .expect("Unable to build env logger.");
-
         Self {
             window: None,
             window_size: (win_width, win_height),
             window_title: String::from(win_title),
             driver: None,
+            engine: None,
+        }
+    }
+
+    /// Install a logger writing to stdout, filtered to the level. If the application has
+    /// already installed a logger, it's left in place along with its filter.
+    pub fn with_log_level(self, level: LevelFilter) -> Self {
+        let res = env_logger::builder()
+            .target(env_logger::Target::Stdout)
+            .filter_level(level)
+            .try_init();
+        if res.is_err() {
+            log::debug!("A logger is already installed, so the log level is unchanged.");
         }
+        self
     }
 
     /// Run the application.
@@ -86,7 +98,7 @@ impl ApplicationHandler for App {
         // Future work: Multiple engine contexts can be created with different drivers for multi-GPU and/or multi-window
         // rendering.
         let mut engine = engine::Engine::new(driver.clone());
-        let handle = engine.create_swapchain(self.window_size.0, self.window_size.1);
+        let handle = engine.create_swapchain(self.window_size.0, self.window_size.1, false);
         match handle {
             Ok(handle) => {
                 engine.set_current_swapchain(handle);
@@ -98,6 +110,7 @@ impl ApplicationHandler for App {
 
         self.window = Some(window);
         self.driver = Some(driver);
+        self.engine = Some(engine);
     }
 
     /// As required by the wininit ApplicationHandler trait.
@@ -106,9 +119,18 @@ impl ApplicationHandler for App {
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => {
+                if let Some(engine) = self.engine.as_mut()
+                    && let Err(err) = engine.shutdown()
+                {
+                    log::error!("Failed to shutdown the engine: {err}");
+                }
                 event_loop.exit();
             }
-            WindowEvent::Resized(_) => {
+            WindowEvent::Resized(size) => {
+                // Nothing can be rendered while minimized - redraws resume once restored.
+                if size.width == 0 || size.height == 0 {
+                    return;
+                }
                 self.window
                     .as_ref()
                     .expect("resize event without a window")
@@ -120,7 +142,23 @@ impl ApplicationHandler for App {
                     .window
                     .as_ref()
                     .expect("redraw request without a window");
+                let engine = self
+                    .engine
+                    .as_mut()
+                    .expect("redraw request without an engine");
+                match engine.update_paused() {
+                    Ok(true) => return,
+                    Ok(false) => {}
+                    Err(err) => log::error!("Failed to query the surface extent: {err}"),
+                }
+                if let Err(err) = engine.begin_frame() {
+                    log::error!("Failed to begin the frame: {err}");
+                    return;
+                }
                 window.pre_present_notify();
+                if let Err(err) = engine.end_frame() {
+                    log::error!("Failed to end the frame: {err}");
+                }
             }
             _ => (),
         }
@@ -1,23 +1,64 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
 /// A strongly typed handle used for safely passing
 /// a resource around rather than a pointer/reference.
 /// The id usually refers to an index into a container.
 ///
+/// Handles created by a [`Pool`](crate::pool::Pool) also carry the epoch of
+/// their slot, so a handle to a removed resource can be detected as stale
+/// even once the slot has been reused.
+///
 /// # Safety
 /// It is up to the user to ensure the id is valid
 /// and that it is within range of the associated container.
 ///
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Handle<T> {
     id: usize,
+    epoch: u32,
     phantom_data: PhantomData<T>,
 }
 
+// The traits are implemented manually, as deriving them would require
+// the resource type to also implement them.
+impl<T> Copy for Handle<T> {}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.epoch == other.epoch
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.epoch.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("id", &self.id)
+            .field("epoch", &self.epoch)
+            .finish()
+    }
+}
+
 impl<T> Default for Handle<T> {
     fn default() -> Self {
         Self {
             id: usize::MAX,
+            epoch: 0,
             phantom_data: PhantomData,
         }
     }
@@ -26,8 +67,14 @@ impl<T> Default for Handle<T> {
 impl<T> Handle<T> {
     /// Create a new handle for the specified type.
     pub fn new(id: usize) -> Handle<T> {
+        Self::with_epoch(id, 0)
+    }
+
+    /// Create a new handle which refers to the specified epoch of its slot.
+    pub fn with_epoch(id: usize, epoch: u32) -> Handle<T> {
         Self {
             id,
+            epoch,
             phantom_data: PhantomData,
         }
     }
@@ -37,6 +84,11 @@ impl<T> Handle<T> {
         self.id
     }
 
+    /// Get the epoch of the slot which the handle was created for.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
     /// Get whether this handle has a valid id.
     pub fn is_valid(&self) -> bool {
         self.id != usize::MAX
@@ -0,0 +1,188 @@
+use crate::handle::Handle;
+
+/// A container of resources which are referred to by handle. Slots
+/// of removed resources are re-used by later insertions.
+///
+/// Each slot has an epoch which is bumped when its resource is removed.
+/// Handles store the epoch they were created with, so a handle to a
+/// removed resource is stale - it no longer resolves, even once the slot
+/// has been reused, and [`Pool::is_current`] can be used to cheaply
+/// detect this, i.e. to drop cached references to destroyed resources.
+///
+/// # Examples
+///
+/// ```
+/// use oxidation_utils::pool::Pool;
+///
+/// let mut pool = Pool::new();
+/// let handle = pool.insert(42);
+/// assert_eq!(pool.get(handle), Some(&42));
+/// assert_eq!(pool.remove(handle), Some(42));
+/// assert!(pool.get(handle).is_none());
+///
+/// // The slot is reused, but the old handle remains stale.
+/// let other = pool.insert(7);
+/// assert!(pool.is_current(other));
+/// assert!(!pool.is_current(handle));
+/// ```
+///
+pub struct Pool<T> {
+    slots: Vec<Option<T>>,
+    epochs: Vec<u32>,
+    free_slots: Vec<usize>,
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Pool<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            epochs: Vec::new(),
+            free_slots: Vec::new(),
+        }
+    }
+
+    /// Insert a resource into the pool, returning its handle.
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        match self.free_slots.pop() {
+            Some(id) => {
+                self.slots[id] = Some(value);
+                Handle::with_epoch(id, self.epochs[id])
+            }
+            None => {
+                self.slots.push(Some(value));
+                self.epochs.push(0);
+                Handle::new(self.slots.len() - 1)
+            }
+        }
+    }
+
+    /// Whether the handle refers to a resource which is still in the pool.
+    pub fn is_current(&self, handle: Handle<T>) -> bool {
+        self.epochs.get(handle.get_id()) == Some(&handle.epoch())
+            && self.slots[handle.get_id()].is_some()
+    }
+
+    /// Get the resource referred to by the handle, if it's still in the pool.
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        self.slots[handle.get_id()].as_ref()
+    }
+
+    /// Get a mutable reference to the resource referred to by the handle.
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        self.slots[handle.get_id()].as_mut()
+    }
+
+    /// Remove the resource from the pool, returning it. The epoch of the slot is bumped,
+    /// so all handles to the resource become stale.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        let id = handle.get_id();
+        let value = self.slots[id].take()?;
+        self.epochs[id] = self.epochs[id].wrapping_add(1);
+        self.free_slots.push(id);
+        Some(value)
+    }
+
+    /// The number of resources in the pool.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free_slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over mutable references to all resources in the pool.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().flatten()
+    }
+
+    /// Remove all resources from the pool, returning them. The slots are
+    /// retained, so all existing handles become stale.
+    pub fn drain(&mut self) -> Vec<T> {
+        for epoch in self.epochs.iter_mut() {
+            *epoch = epoch.wrapping_add(1);
+        }
+        self.free_slots = (0..self.slots.len()).rev().collect();
+        self.slots.iter_mut().filter_map(Option::take).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_bumps_epoch_of_reused_slot() {
+        let mut pool = Pool::new();
+        let first = pool.insert(1);
+        assert_eq!(first.epoch(), 0);
+        pool.remove(first);
+
+        let second = pool.insert(2);
+        assert_eq!(second.get_id(), first.get_id());
+        assert_eq!(second.epoch(), 1);
+        assert_eq!(pool.get(second), Some(&2));
+        assert!(pool.get(first).is_none());
+        assert!(pool.remove(first).is_none());
+    }
+
+    #[test]
+    fn stale_handle_does_not_remove_new_value() {
+        let mut pool = Pool::new();
+        let first = pool.insert(1);
+        pool.remove(first);
+        let second = pool.insert(2);
+
+        assert!(pool.get_mut(first).is_none());
+        assert!(pool.remove(first).is_none());
+        assert_eq!(pool.len(), 1);
+        assert!(pool.is_current(second));
+    }
+
+    #[test]
+    fn drain_takes_all_values_and_invalidates_handles() {
+        let mut pool = Pool::new();
+        let a = pool.insert(1);
+        let b = pool.insert(2);
+        let removed = pool.insert(3);
+        pool.remove(removed);
+
+        let mut drained = pool.drain();
+        drained.sort();
+        assert_eq!(drained, vec![1, 2]);
+        assert!(pool.is_empty());
+        assert!(!pool.is_current(a));
+        assert!(!pool.is_current(b));
+
+        // The slots are reused with bumped epochs.
+        let c = pool.insert(4);
+        assert_eq!(c.get_id(), a.get_id());
+        assert_eq!(c.epoch(), a.epoch() + 1);
+        assert!(pool.get(a).is_none());
+        assert_eq!(pool.get(c), Some(&4));
+    }
+
+    #[test]
+    fn drain_is_eager() {
+        let mut pool = Pool::new();
+        let handle = pool.insert(1);
+        let _ = pool.drain();
+        assert!(pool.is_empty());
+        assert!(!pool.is_current(handle));
+    }
+}
@@ -0,0 +1,521 @@
+use oxidation_vk::Driver;
+use oxidation_vk::backend::{AllocationHint, BlendMode, CullMode, SamplerInfo, VertexLayout};
+use oxidation_vk::buffer::Buffer;
+use oxidation_vk::commands::Commands;
+use oxidation_vk::descriptor::{self, DescriptorWrite};
+use oxidation_vk::dynamic_buffer::DynamicGeometryBuffer;
+use oxidation_vk::error::OxidationError;
+use oxidation_vk::pipeline::{GraphicsPipeline, GraphicsPipelineInfo};
+use oxidation_vk::texture::{Texture, TextureInfo};
+use oxidation_vk::vk;
+use std::error::Error;
+
+/// The number of glyphs in each row of the atlas.
+const ATLAS_COLUMNS: u32 = 16;
+
+/// The size of the geometry region of each frame in flight - enough for around 800 glyphs.
+const GEOMETRY_FRAME_SIZE: vk::DeviceSize = 128 * 1024;
+
+/// A fixed size bitmap font of consecutive characters, i.e. the printable ASCII range. Each
+/// glyph is `glyph_height` rows of `glyph_width` bits, with each row padded to a whole number
+/// of bytes and bit 0 the leftmost pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct BitmapFont<'a> {
+    pub glyph_width: u32,
+    pub glyph_height: u32,
+    /// The character of the first glyph in the bitmap.
+    pub first_char: char,
+    pub glyph_count: u32,
+    pub bitmap: &'a [u8],
+}
+
+impl BitmapFont<'_> {
+    fn row_bytes(&self) -> usize {
+        self.glyph_width.div_ceil(8) as usize
+    }
+
+    /// Whether the pixel of the glyph is set.
+    fn is_set(&self, glyph: u32, x: u32, y: u32) -> bool {
+        let row = (glyph * self.glyph_height + y) as usize * self.row_bytes();
+        self.bitmap[row + (x / 8) as usize] & (1 << (x % 8)) != 0
+    }
+}
+
+/// A vertex of a glyph quad - the position is in pixels from the top left of the target.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TextVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl TextVertex {
+    pub fn layout() -> Result<VertexLayout, OxidationError> {
+        VertexLayout::new(0)
+            .add_attribute(vk::Format::R32G32_SFLOAT)?
+            .add_attribute(vk::Format::R32G32_SFLOAT)?
+            .add_attribute(vk::Format::R32G32B32A32_SFLOAT)
+    }
+}
+
+/// The placement of the glyphs of a font within its atlas.
+#[derive(Debug, Copy, Clone)]
+pub struct GlyphLayout {
+    pub glyph_width: u32,
+    pub glyph_height: u32,
+    pub first_char: char,
+    pub glyph_count: u32,
+}
+
+impl GlyphLayout {
+    pub fn atlas_extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: ATLAS_COLUMNS * self.glyph_width,
+            height: self.glyph_count.div_ceil(ATLAS_COLUMNS) * self.glyph_height,
+        }
+    }
+
+    /// The index of the glyph of the character - `None` if the font doesn't contain it.
+    fn glyph_index(&self, c: char) -> Option<u32> {
+        let idx = (c as u32).checked_sub(self.first_char as u32)?;
+        (idx < self.glyph_count).then_some(idx)
+    }
+
+    /// Build a quad of four vertices for each glyph of the text, with the top left of the first
+    /// glyph at the position. Newlines start a new line, while whitespace and characters
+    /// missing from the font only advance the position.
+    pub fn glyph_quads(&self, x: f32, y: f32, text: &str, color: [f32; 4]) -> Vec<TextVertex> {
+        let extent = self.atlas_extent();
+        let (width, height) = (self.glyph_width as f32, self.glyph_height as f32);
+        let mut vertices = Vec::with_capacity(text.len() * 4);
+        let mut pen = [x, y];
+        for c in text.chars() {
+            if c == '\n' {
+                pen = [x, pen[1] + height];
+                continue;
+            }
+            if let Some(idx) = self.glyph_index(c).filter(|_| !c.is_whitespace()) {
+                let u = (idx % ATLAS_COLUMNS * self.glyph_width) as f32 / extent.width as f32;
+                let v = (idx / ATLAS_COLUMNS * self.glyph_height) as f32 / extent.height as f32;
+                let (du, dv) = (width / extent.width as f32, height / extent.height as f32);
+                let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+                vertices.extend(corners.map(|(cx, cy)| TextVertex {
+                    position: [pen[0] + cx * width, pen[1] + cy * height],
+                    uv: [u + cx * du, v + cy * dv],
+                    color,
+                }));
+            }
+            pen[0] += width;
+        }
+        vertices
+    }
+}
+
+/// The indices of the quads, two triangles each.
+fn quad_indices(quad_count: usize) -> Vec<u32> {
+    (0..quad_count as u32)
+        .flat_map(|quad| [0, 1, 2, 2, 3, 0].map(|idx| quad * 4 + idx))
+        .collect()
+}
+
+/// The geometry of the queued text, written for the current frame.
+#[derive(Copy, Clone)]
+struct FlushedText {
+    vertex_offset: vk::DeviceSize,
+    index_offset: vk::DeviceSize,
+    index_count: u32,
+}
+
+/// Renders text for debug overlays, such as frame statistics, from a bitmap font - see
+/// [`Engine::draw_text`](crate::Engine::draw_text).
+///
+/// The shaders are supplied by the user. The vertex shader takes [`TextVertex`] attributes,
+/// with the extent of the target in pixels as push constants, and the fragment shader samples
+/// the atlas, which reads as white with the glyph coverage in the alpha channel:
+///
+/// ```glsl
+/// // Vertex shader.
+/// layout (location = 0) in vec2 inPosition;
+/// layout (location = 1) in vec2 inUv;
+/// layout (location = 2) in vec4 inColor;
+/// layout (push_constant) uniform PushConstants { vec2 targetExtent; };
+/// gl_Position = vec4(inPosition / targetExtent * 2.0 - 1.0, 0.0, 1.0);
+///
+/// // Fragment shader.
+/// layout (binding = 0) uniform sampler2D fontAtlas;
+/// outColor = inColor * texture(fontAtlas, inUv);
+/// ```
+///
+pub struct DebugText {
+    layout: GlyphLayout,
+    atlas: Texture,
+    set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: GraphicsPipeline,
+    geometry: DynamicGeometryBuffer,
+    /// The glyph quads queued since the text was last drawn.
+    vertices: Vec<TextVertex>,
+    flushed: Option<FlushedText>,
+}
+
+impl DebugText {
+    /// Create the debug text renderer, with a geometry region for each of the frames in flight.
+    pub fn new(
+        driver: &Driver,
+        font: &BitmapFont,
+        vertex_shader: vk::ShaderModule,
+        fragment_shader: vk::ShaderModule,
+        color_format: vk::Format,
+        frames_in_flight: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let glyph_bytes = font.row_bytes() * font.glyph_height as usize;
+        if font.glyph_width == 0
+            || font.glyph_height == 0
+            || font.bitmap.len() < glyph_bytes * font.glyph_count as usize
+        {
+            return Err(Box::from(format!(
+                "The font bitmap of {} bytes doesn't hold {} glyphs of {}x{} pixels.",
+                font.bitmap.len(),
+                font.glyph_count,
+                font.glyph_width,
+                font.glyph_height
+            )));
+        }
+        let layout = GlyphLayout {
+            glyph_width: font.glyph_width,
+            glyph_height: font.glyph_height,
+            first_char: font.first_char,
+            glyph_count: font.glyph_count,
+        };
+        let vertex_layout = TextVertex::layout()?;
+        let atlas = create_atlas(driver, font, &layout)?;
+
+        let device = &driver.device;
+        let bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(descriptor::push_set_layout_flags(device))
+            .bindings(&bindings);
+        let set_layout = unsafe {
+            device
+                .device
+                .create_descriptor_set_layout(&set_layout_info, None)?
+        };
+        let set_layouts = [set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(size_of::<[f32; 2]>() as u32)];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe { device.device.create_pipeline_layout(&layout_info, None)? };
+
+        let pipeline_info = GraphicsPipelineInfo {
+            vertex_shader,
+            fragment_shader,
+            vertex_layouts: vec![vertex_layout],
+            cull_mode: CullMode::None,
+            color_formats: vec![color_format],
+            blend_modes: vec![BlendMode::AlphaBlend],
+            layout: pipeline_layout,
+            ..Default::default()
+        };
+        let pipeline = GraphicsPipeline::new(device, &pipeline_info)?;
+        let geometry = DynamicGeometryBuffer::new(driver, GEOMETRY_FRAME_SIZE, frames_in_flight)?;
+
+        Ok(Self {
+            layout,
+            atlas,
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            geometry,
+            vertices: Vec::new(),
+            flushed: None,
+        })
+    }
+
+    pub fn glyph_layout(&self) -> &GlyphLayout {
+        &self.layout
+    }
+
+    /// Queue the text to be drawn with the next call to [`Self::draw`].
+    pub fn queue(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        let quads = self.layout.glyph_quads(x, y, text, color);
+        self.vertices.extend(quads);
+    }
+
+    /// The number of glyph quads queued to be drawn.
+    pub fn queued_glyph_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// Write the queued glyphs into the geometry region of the frame. This must be recorded
+    /// outside of a render pass, as the geometry may be copied from a staging buffer.
+    pub fn flush(
+        &mut self,
+        driver: &Driver,
+        cmds: vk::CommandBuffer,
+        frame_index: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        self.geometry.begin_frame(frame_index);
+        self.flushed = None;
+        if self.vertices.is_empty() {
+            return Ok(());
+        }
+        let indices = quad_indices(self.queued_glyph_count());
+        let vertex_offset = self
+            .geometry
+            .append(&self.vertices, size_of::<TextVertex>() as vk::DeviceSize)?;
+        let index_offset = self
+            .geometry
+            .append(&indices, size_of::<u32>() as vk::DeviceSize)?;
+        self.geometry.flush(driver, cmds)?;
+        self.vertices.clear();
+        self.flushed = Some(FlushedText {
+            vertex_offset,
+            index_offset,
+            index_count: indices.len() as u32,
+        });
+        Ok(())
+    }
+
+    /// Draw the glyphs written by the last flush into a target of the extent. Must be recorded
+    /// within a render pass whose colour attachment matches the format of the pipeline.
+    pub fn draw(
+        &mut self,
+        driver: &Driver,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+        extent: vk::Extent2D,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(flushed) = self.flushed.take() else {
+            return Ok(());
+        };
+        let device = &driver.device;
+        let writes = [DescriptorWrite::Image {
+            binding: 0,
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            view: self.atlas.sampled_view(),
+            sampler: self.atlas.sampler(),
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let target_extent = [extent.width as f32, extent.height as f32];
+        unsafe {
+            device.device.cmd_bind_pipeline(
+                cmds,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipeline,
+            );
+            device.device.cmd_push_constants(
+                cmds,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(
+                    target_extent.as_ptr() as *const u8,
+                    size_of_val(&target_extent),
+                ),
+            );
+        }
+        commands.push_descriptor_set(
+            device,
+            cmds,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline_layout,
+            self.set_layout,
+            0,
+            &writes,
+        )?;
+        Commands::set_viewport(&device.device, cmds, extent, false);
+        Commands::set_scissor(&device.device, cmds, extent);
+        unsafe {
+            device.device.cmd_bind_vertex_buffers(
+                cmds,
+                0,
+                &[self.geometry.buffer()],
+                &[flushed.vertex_offset],
+            );
+            device.device.cmd_bind_index_buffer(
+                cmds,
+                self.geometry.buffer(),
+                flushed.index_offset,
+                vk::IndexType::UINT32,
+            );
+        }
+        commands.draw_indexed(&device.device, cmds, flushed.index_count, 1, 0, 0, 0);
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        let device = &driver.device.device;
+        self.pipeline.destroy(device);
+        unsafe {
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        self.geometry.destroy(driver);
+        self.atlas.destroy(driver);
+    }
+}
+
+/// Rasterize the glyphs of the font into a single channel atlas texture, which is swizzled to
+/// read as white with the coverage in the alpha channel.
+fn create_atlas(
+    driver: &Driver,
+    font: &BitmapFont,
+    layout: &GlyphLayout,
+) -> Result<Texture, Box<dyn Error>> {
+    let extent = layout.atlas_extent();
+    let mut texels = vec![0u8; (extent.width * extent.height) as usize];
+    for glyph in 0..font.glyph_count {
+        let origin_x = glyph % ATLAS_COLUMNS * font.glyph_width;
+        let origin_y = glyph / ATLAS_COLUMNS * font.glyph_height;
+        for y in 0..font.glyph_height {
+            for x in 0..font.glyph_width {
+                if font.is_set(glyph, x, y) {
+                    texels[((origin_y + y) * extent.width + origin_x + x) as usize] = u8::MAX;
+                }
+            }
+        }
+    }
+
+    let info = TextureInfo {
+        width: extent.width,
+        height: extent.height,
+        format: vk::Format::R8_UNORM,
+        components: vk::ComponentMapping {
+            r: vk::ComponentSwizzle::ONE,
+            g: vk::ComponentSwizzle::ONE,
+            b: vk::ComponentSwizzle::ONE,
+            a: vk::ComponentSwizzle::R,
+        },
+        ..Default::default()
+    };
+    let mut atlas = Texture::new(
+        driver,
+        &info,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        Some(&SamplerInfo::point_clamp()),
+        &[],
+    )?;
+    if let Err(err) = upload_atlas(driver, &mut atlas, &texels) {
+        atlas.destroy(driver);
+        return Err(err);
+    }
+    Ok(atlas)
+}
+
+/// Copy the texels into the atlas through a temporary staging buffer, leaving it ready for
+/// sampling. Blocks until the copy has completed.
+fn upload_atlas(driver: &Driver, atlas: &mut Texture, texels: &[u8]) -> Result<(), Box<dyn Error>> {
+    let size = texels.len() as vk::DeviceSize;
+    let mut staging = Buffer::new(
+        driver,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        AllocationHint::Mapped,
+        &[],
+    )?;
+    let res = staging.write(driver, 0, texels).and_then(|_| {
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width: atlas.width(),
+                height: atlas.height(),
+                depth: 1,
+            });
+        driver.submit_immediate(|cmds| {
+            atlas.transition(
+                &driver.device,
+                cmds,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags2::NONE,
+                vk::PipelineStageFlags2::COPY,
+                1,
+            );
+            unsafe {
+                driver.device.device.cmd_copy_buffer_to_image(
+                    cmds,
+                    staging.buffer,
+                    atlas.image(),
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                )
+            };
+            atlas.transition(
+                &driver.device,
+                cmds,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::COPY,
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                1,
+            );
+        })?;
+        Ok(())
+    });
+    staging.destroy(driver);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [f32; 4] = [1.0; 4];
+
+    fn ascii_layout() -> GlyphLayout {
+        GlyphLayout {
+            glyph_width: 8,
+            glyph_height: 16,
+            first_char: ' ',
+            glyph_count: 95,
+        }
+    }
+
+    #[test]
+    fn each_visible_glyph_has_a_quad() {
+        let vertices = ascii_layout().glyph_quads(0.0, 0.0, "Hi there", WHITE);
+        // The space only advances the position.
+        assert_eq!(vertices.len(), 7 * 4);
+        assert_eq!(vertices[0].position, [0.0, 0.0]);
+        assert_eq!(vertices[2].position, [8.0, 16.0]);
+        // The first glyph after the space is three glyphs along.
+        assert_eq!(vertices[8].position, [24.0, 0.0]);
+    }
+
+    #[test]
+    fn characters_outside_the_font_are_skipped() {
+        let layout = ascii_layout();
+        let vertices = layout.glyph_quads(10.0, 20.0, "a\u{e9}\tb", WHITE);
+        assert_eq!(vertices.len(), 2 * 4);
+        // The missing characters still advance the position.
+        assert_eq!(vertices[4].position, [10.0 + 3.0 * 8.0, 20.0]);
+        assert_eq!(layout.glyph_index('\u{e9}'), None);
+        assert_eq!(layout.glyph_index(' '), Some(0));
+    }
+
+    #[test]
+    fn newline_starts_a_new_line() {
+        let vertices = ascii_layout().glyph_quads(4.0, 0.0, "a\nb", WHITE);
+        assert_eq!(vertices.len(), 2 * 4);
+        assert_eq!(vertices[4].position, [4.0, 16.0]);
+    }
+
+    #[test]
+    fn quad_indices_form_two_triangles_per_quad() {
+        assert_eq!(quad_indices(2), [0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4]);
+        assert!(quad_indices(0).is_empty());
+    }
+}
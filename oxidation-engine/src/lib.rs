@@ -1,8 +1,106 @@
+pub mod debug_text;
+pub mod material;
+pub mod mesh;
+pub mod render_target;
+pub mod scheduling;
+pub mod stats;
+
+use debug_text::{BitmapFont, DebugText};
+use log::error;
+use material::Material;
+use mesh::Mesh;
 use oxidation_utils::handle;
-use oxidation_vk::{Driver, swapchain::Swapchain};
+use oxidation_utils::pool::Pool;
+use oxidation_vk::backend::{SamplerInfo, VertexLayout};
+use oxidation_vk::barrier;
+use oxidation_vk::commands::Commands;
+use oxidation_vk::commands::MAX_CMD_BUFFER_IN_FLIGHT_COUNT;
+use oxidation_vk::defrag::{DefragStats, Relocatable};
+use oxidation_vk::error::OxidationError;
+use oxidation_vk::pipeline::{GraphicsPipeline, GraphicsPipelineInfo};
+use oxidation_vk::rendering::{RenderingAttachment, RenderingInfo};
+use oxidation_vk::submit_batch::{SemaphoreSubmit, SubmitBatch};
+use oxidation_vk::swapchain::{FrameCapture, PresentModeChange, Swapchain};
+use oxidation_vk::sync::{self, FenceWait};
+use oxidation_vk::texture::Texture;
+use oxidation_vk::timestamp::GpuTimer;
+use oxidation_vk::vk_check;
+use oxidation_vk::{Driver, vk};
+use render_target::RenderTargetBuilder;
+use scheduling::{ComputeJob, QueueKind, QueueScheduling};
+use stats::FrameStats;
+use std::time::Instant;
 use std::{error::Error, rc::Rc};
 
 type SwapchainHandle = handle::Handle<Swapchain>;
+pub type TextureHandle = handle::Handle<Texture>;
+pub type PipelineHandle = handle::Handle<GraphicsPipeline>;
+pub type MaterialHandle = handle::Handle<Material>;
+pub type MeshHandle = handle::Handle<Mesh>;
+
+/// The number of frames the CPU may record ahead of the GPU by default - see
+/// [`Engine::new_with_frames_in_flight`].
+pub const DEFAULT_FRAMES_IN_FLIGHT: u32 = 2;
+
+/// A swapchain image acquired by [`Engine::acquire_next_image`] which has yet to be presented.
+#[derive(Debug, Copy, Clone)]
+struct AcquiredImage {
+    swapchain: SwapchainHandle,
+    image_index: u32,
+    /// The generation of the swapchain at the time of the acquire.
+    generation: u64,
+}
+
+/// How the engine reacts when the swapchain reports that it's suboptimal for the surface, i.e.
+/// after a display rotation or when moved to a monitor with a different format. A suboptimal
+/// swapchain can still be presented to, so unlike an out of date swapchain, recreating it is
+/// a tradeoff between presentation quality and the cost of a device wait.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum SuboptimalPolicy {
+    /// Keep presenting to the suboptimal swapchain. Avoids any stall, but the presentation
+    /// engine may have to scale or convert each image, which can cost performance and quality
+    /// until the swapchain is out of date or recreated by the application.
+    Ignore,
+    /// Recreate the swapchain at the start of the next frame. The current image is still
+    /// rendered and presented, so no frame is dropped, with the device wait deferred to a
+    /// frame boundary.
+    #[default]
+    RecreateNextFrame,
+    /// Wait for the device and recreate the swapchain as soon as the suboptimal result is
+    /// returned. If reported by an acquire, the acquired image is discarded and the frame is
+    /// skipped - this gives the quickest recovery at the cost of a stall and a dropped frame.
+    RecreateImmediately,
+}
+
+/// A global texture quality setting, i.e. driven by a graphics settings menu, which determines
+/// the anisotropy and mip bias of the default sampler. Anisotropy is clamped to the device
+/// limit, and disabled if unsupported by the device.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TextureQuality {
+    /// No anisotropic filtering, with mips biased half a level smaller to reduce bandwidth.
+    Low,
+    /// 4x anisotropic filtering.
+    Medium,
+    /// 8x anisotropic filtering.
+    High,
+    /// 16x anisotropic filtering.
+    Ultra,
+}
+
+impl TextureQuality {
+    /// The sampler info of the default sampler at this quality.
+    pub fn sampler_info(&self) -> SamplerInfo {
+        match self {
+            TextureQuality::Low => SamplerInfo {
+                mip_lod_bias: 2,
+                ..SamplerInfo::linear_repeat()
+            },
+            TextureQuality::Medium => SamplerInfo::anisotropic(4),
+            TextureQuality::High => SamplerInfo::anisotropic(8),
+            TextureQuality::Ultra => SamplerInfo::anisotropic(16),
+        }
+    }
+}
 
 /// The engine is the main entry point into the API.
 ///
@@ -13,40 +111,152 @@ type SwapchainHandle = handle::Handle<Swapchain>;
 /// # Examples
 ///
 /// Create engine with swapchain
-/// ```
+/// ```ignore
 /// let driver = std::rc::Rc::new(oxidation_vk::Driver::new()?);
 /// let mut engine = oxidation_engine::Engine::new(driver);
 /// let win_size = (1980,1080);
-/// let handle = engine.create_swapchain(win_size.0, win_size.1);
+/// let handle = engine.create_swapchain(win_size.0, win_size.1, false);
 /// ```
 ///
 pub struct Engine {
     pub driver: Rc<Driver>,
     /// Resources that are owned by the engine.
     swapchains: Vec<Swapchain>,
+    /// Textures wrapping the images of each swapchain, indexed by swapchain - see
+    /// [`Engine::swapchain_targets`].
+    swapchain_targets: Vec<Vec<TextureHandle>>,
+    textures: Pool<Texture>,
+    pipelines: Vec<GraphicsPipeline>,
+    materials: Vec<Material>,
+    meshes: Pool<Mesh>,
 
     current_swapchain: SwapchainHandle,
+    suboptimal_policy: SuboptimalPolicy,
+    queue_scheduling: QueueScheduling,
+    texture_quality: Option<TextureQuality>,
+    /// Set when the current swapchain is to be recreated at the start of the next frame.
+    recreate_pending: bool,
+    /// The image acquired for the current frame - see [`Engine::present`].
+    acquired: Option<AcquiredImage>,
+    /// Set while the surface has a zero area extent, i.e. the window is minimized.
+    paused: bool,
+    /// The number of frames which have begun rendering.
+    frame_count: u64,
+    /// The number of frames which may be in flight, which the per-frame resources are sized by.
+    frames_in_flight: u32,
+    /// Times the GPU work of each frame in flight - `None` if timestamps aren't supported.
+    gpu_timer: Option<GpuTimer>,
+    /// Signalled once the work of the frame last ended in each slot has completed, along with
+    /// the frame, indexed by frame slot - `None` until a frame has ended in the slot.
+    frame_fences: Vec<Option<(vk::Fence, u64)>>,
+    /// The most recent frame whose fence has been signalled - see [`Engine::destroy_texture`].
+    completed_frame: Option<u64>,
+    frame_start: Option<Instant>,
+    last_frame_stats: FrameStats,
+    /// Renders text queued with [`Engine::draw_text`] - `None` until enabled.
+    debug_text: Option<DebugText>,
+    /// Textures destroyed while still in use by frames in flight, which are destroyed once
+    /// those frames have completed.
+    deferred_textures: Vec<Texture>,
 }
 
 impl Engine {
-    /// Create a new engine instance.
+    /// Create a new engine instance, with [`DEFAULT_FRAMES_IN_FLIGHT`] frames in flight.
     pub fn new(driver: Rc<Driver>) -> Self {
+        Self::create(driver, DEFAULT_FRAMES_IN_FLIGHT)
+    }
+
+    /// Create a new engine instance which allows the number of frames to be in flight - i.e.
+    /// 3 trades a frame of latency for throughput when the CPU and GPU times vary. The
+    /// per-frame resources, such as the GPU timer slots and the debug text geometry, are sized
+    /// by the count, and presents are paced so the CPU doesn't record further ahead.
+    ///
+    /// Returns an error if the count is zero or exceeds the command buffers which can be in
+    /// flight. Swapchains with fewer images than the count can't be created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::rc::Rc;
+    /// # use oxidation_engine::Engine;
+    /// # use oxidation_vk::Driver;
+    /// # fn create(driver: Rc<Driver>) -> Result<(), Box<dyn std::error::Error>> {
+    /// let engine = Engine::new_with_frames_in_flight(driver, 3)?;
+    /// assert_eq!(engine.frames_in_flight(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn new_with_frames_in_flight(
+        driver: Rc<Driver>,
+        frames_in_flight: u32,
+    ) -> Result<Self, OxidationError> {
+        validate_frames_in_flight(frames_in_flight)?;
+        Ok(Self::create(driver, frames_in_flight))
+    }
+
+    fn create(driver: Rc<Driver>, frames_in_flight: u32) -> Self {
         let swapchains = Vec::new();
+        let textures = Pool::new();
+        let gpu_timer = match GpuTimer::new(&driver, frames_in_flight) {
+            Ok(timer) => Some(timer),
+            Err(err) => {
+                log::warn!("GPU frame timing is unavailable: {err}");
+                None
+            }
+        };
 
         Self {
             driver,
             swapchains,
+            swapchain_targets: Vec::new(),
+            textures,
+            pipelines: Vec::new(),
+            materials: Vec::new(),
+            meshes: Pool::new(),
             current_swapchain: Default::default(),
+            suboptimal_policy: SuboptimalPolicy::default(),
+            queue_scheduling: QueueScheduling::default(),
+            texture_quality: None,
+            recreate_pending: false,
+            acquired: None,
+            paused: false,
+            frame_count: 0,
+            frames_in_flight,
+            gpu_timer,
+            frame_fences: vec![None; frames_in_flight as usize],
+            completed_frame: None,
+            frame_start: None,
+            last_frame_stats: FrameStats::default(),
+            debug_text: None,
+            deferred_textures: Vec::new(),
         }
     }
 
     /// Create a new swapchain based on  a window surface.
     /// Multiple swapchains can be created and rendered to by a single
     /// driver instance.
+    ///
+    /// If `srgb_views` is set, sRGB encoding is applied by the hardware when rendering into
+    /// a UNORM swapchain, if supported by the device.
     pub fn create_swapchain(
         &mut self,
         width: u32,
         height: u32,
+        srgb_views: bool,
+    ) -> Result<SwapchainHandle, Box<dyn Error>> {
+        self.create_layered_swapchain(width, height, srgb_views, 1)
+    }
+
+    /// Create a swapchain whose images have multiple array layers, i.e. 2 for layered stereo
+    /// rendering with one layer per eye. Returns an error if the surface doesn't support the
+    /// number of layers.
+    pub fn create_layered_swapchain(
+        &mut self,
+        width: u32,
+        height: u32,
+        srgb_views: bool,
+        array_layers: u32,
     ) -> Result<SwapchainHandle, Box<dyn Error>> {
         let swapchain = Swapchain::new(
             &self.driver.instance,
@@ -54,17 +264,1382 @@ impl Engine {
             &self.driver.surface,
             width,
             height,
+            srgb_views,
+            array_layers,
         )?;
+        if let Err(err) =
+            validate_swapchain_image_count(swapchain.images.len(), self.frames_in_flight)
+        {
+            let mut swapchain = swapchain;
+            swapchain.destroy(&self.driver.device.device);
+            return Err(Box::new(err));
+        }
         let handle = SwapchainHandle::new(self.swapchains.len());
         self.swapchains.push(swapchain);
+        self.swapchain_targets.push(Vec::new());
         Ok(handle)
     }
 
+    /// The number of frames which may be in flight - see [`Self::new_with_frames_in_flight`].
+    #[inline]
+    pub fn frames_in_flight(&self) -> u32 {
+        self.frames_in_flight
+    }
+
     /// Set the current swapchain.
     ///
     /// All render commands will be rendereed to this swapchain.
     #[inline]
     pub fn set_current_swapchain(&mut self, handle: SwapchainHandle) {
         self.current_swapchain = handle;
+        self.recreate_pending = false;
+    }
+
+    /// Set how a suboptimal swapchain is handled - see [`SuboptimalPolicy`] for the tradeoffs.
+    /// Defaults to [`SuboptimalPolicy::RecreateNextFrame`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Prefer a consistent frame rate over the quickest recovery on rotation.
+    /// engine.set_suboptimal_policy(SuboptimalPolicy::Ignore);
+    /// ```
+    ///
+    #[inline]
+    pub fn set_suboptimal_policy(&mut self, policy: SuboptimalPolicy) {
+        self.suboptimal_policy = policy;
+    }
+
+    #[inline]
+    pub fn suboptimal_policy(&self) -> SuboptimalPolicy {
+        self.suboptimal_policy
+    }
+
+    /// Get the current swapchain.
+    ///
+    /// Returns `None` if no current swapchain has been set or the handle is out of range.
+    pub fn current_swapchain(&self) -> Option<&Swapchain> {
+        if !self.current_swapchain.is_valid() {
+            return None;
+        }
+        self.swapchains.get(self.current_swapchain.get_id())
+    }
+
+    /// Get the current swapchain as mutable.
+    ///
+    /// Returns `None` if no current swapchain has been set or the handle is out of range.
+    pub fn current_swapchain_mut(&mut self) -> Option<&mut Swapchain> {
+        if !self.current_swapchain.is_valid() {
+            return None;
+        }
+        self.swapchains.get_mut(self.current_swapchain.get_id())
+    }
+
+    /// The images of the current swapchain as engine textures, indexed by swapchain image
+    /// index, so they can be used as render targets like any other texture. The textures are
+    /// refreshed when the swapchain has been recreated since the last call, with the previous
+    /// handles becoming stale - so handles shouldn't be held across a recreation.
+    ///
+    /// The images remain owned by the swapchain; destroying the textures only destroys their
+    /// views. The textures are created in the undefined layout.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let targets = engine.swapchain_targets()?;
+    /// if engine.acquire_next_image(u64::MAX)? {
+    ///     let image_index = engine.driver.current_image_index();
+    ///     let target = engine.texture(targets[image_index as usize]);
+    /// }
+    /// ```
+    ///
+    pub fn swapchain_targets(&mut self) -> Result<Vec<TextureHandle>, OxidationError> {
+        let swapchain_idx = self.current_swapchain.get_id();
+        let swapchain = self
+            .current_swapchain()
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        let targets = &self.swapchain_targets[swapchain_idx];
+        let target_images: Vec<Option<(vk::Image, vk::Extent2D)>> = targets
+            .iter()
+            .map(|handle| {
+                self.textures
+                    .get(*handle)
+                    .map(|texture| (texture.image(), texture.extent()))
+            })
+            .collect();
+        if swapchain_targets_are_current(&target_images, &swapchain.images, swapchain.extents) {
+            return Ok(targets.clone());
+        }
+
+        let info = swapchain.image_info();
+        let textures: Vec<Texture> = swapchain
+            .images
+            .iter()
+            .map(|image| {
+                Texture::from_raw_image(
+                    &self.driver,
+                    *image,
+                    &info,
+                    swapchain.usage,
+                    vk::ImageLayout::UNDEFINED,
+                )
+            })
+            .collect();
+        for handle in std::mem::take(&mut self.swapchain_targets[swapchain_idx]) {
+            self.destroy_texture(handle);
+        }
+        let targets: Vec<TextureHandle> = textures
+            .into_iter()
+            .map(|texture| self.add_texture(texture))
+            .collect();
+        self.swapchain_targets[swapchain_idx] = targets.clone();
+        Ok(targets)
+    }
+
+    /// Get the color format of the current swapchain.
+    ///
+    /// Returns an error if no current swapchain has been set.
+    pub fn current_swapchain_format(&self) -> Result<vk::Format, Box<dyn Error>> {
+        let swapchain = self
+            .current_swapchain()
+            .ok_or("No valid current swapchain has been set.")?;
+        Ok(swapchain.color_format())
+    }
+
+    /// Change the present mode of the current swapchain, e.g. when toggling vsync. The
+    /// swapchain is only recreated if the device can't switch to the mode on the next present.
+    ///
+    /// Returns the present mode now in use - if the surface doesn't support the requested
+    /// mode, the swapchain falls back to the best supported mode.
+    pub fn set_present_mode(
+        &mut self,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<vk::PresentModeKHR, Box<dyn Error>> {
+        let driver = self.driver.clone();
+        let swapchain = self
+            .current_swapchain_mut()
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        if swapchain.set_present_mode(present_mode) == PresentModeChange::RequiresRecreate {
+            unsafe { vk_check!(driver.device.device.device_wait_idle(), "vkDeviceWaitIdle")? };
+            swapchain.recreate_with_present_mode(
+                &driver.instance,
+                &driver.device,
+                &driver.surface,
+                present_mode,
+            )?;
+        }
+        Ok(swapchain.present_mode())
+    }
+
+    /// Set how compute work is scheduled relative to graphics work - see [`QueueScheduling`].
+    /// Defaults to [`QueueScheduling::Serial`].
+    #[inline]
+    pub fn set_queue_scheduling(&mut self, scheduling: QueueScheduling) {
+        self.queue_scheduling = scheduling;
+    }
+
+    #[inline]
+    pub fn queue_scheduling(&self) -> QueueScheduling {
+        self.queue_scheduling
+    }
+
+    /// The queue which compute jobs are submitted to under the current scheduling mode.
+    pub fn compute_queue(&self) -> QueueKind {
+        let device = &self.driver.device;
+        scheduling::select_compute_queue(
+            self.queue_scheduling,
+            device.compute_queue_idx != device.graphics_queue_idx,
+        )
+    }
+
+    /// The queue family which the command buffers of compute jobs must be allocated from.
+    pub fn compute_queue_family(&self) -> u32 {
+        match self.compute_queue() {
+            QueueKind::Graphics => self.driver.device.graphics_queue_idx,
+            QueueKind::Compute => self.driver.device.compute_queue_idx,
+        }
+    }
+
+    /// Submit a compute job to the queue selected by the scheduling mode, returning the queue
+    /// it was submitted to. With [`QueueScheduling::Overlapped`], the job executes concurrently
+    /// with graphics work other than that synchronised by its semaphores. The fence, if not
+    /// null, is signalled once the job has completed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// engine.set_queue_scheduling(QueueScheduling::Overlapped);
+    /// // An independent job, i.e. a particle simulation for the next frame.
+    /// engine.submit_compute(&ComputeJob::new(cmds), vk::Fence::null())?;
+    /// ```
+    ///
+    pub fn submit_compute(
+        &self,
+        job: &ComputeJob,
+        fence: vk::Fence,
+    ) -> Result<QueueKind, OxidationError> {
+        let kind = self.compute_queue();
+        let queue = match kind {
+            QueueKind::Graphics => self.driver.device.graphics_queue,
+            QueueKind::Compute => self.driver.device.compute_queue,
+        };
+        let mut batch = SubmitBatch::new();
+        batch.add(job.cmds, &job.wait_semaphores, &job.signal_semaphores);
+        vk_check!(
+            batch.submit(&self.driver.device, queue, fence),
+            "vkQueueSubmit"
+        )?;
+        Ok(kind)
+    }
+
+    /// Mark the start of a new frame. All work submitted to the graphics queue until
+    /// `end_frame()` is called is labelled as part of this frame, allowing tools such as
+    /// RenderDoc to delimit frames. Labels are only emitted if debug utils is enabled.
+    ///
+    /// If the current swapchain was reported as suboptimal during the previous frame and the
+    /// policy is [`SuboptimalPolicy::RecreateNextFrame`], it's recreated here.
+    ///
+    /// The per-frame resources, such as the GPU timer slots and the debug text geometry, are
+    /// reused every [`Self::frames_in_flight`] frames, so this waits until the frame which
+    /// last used them has completed.
+    pub fn begin_frame(&mut self) -> Result<(), OxidationError> {
+        if self.recreate_pending
+            && let Err(err) = self.recreate_current_swapchain()
+        {
+            error!("Failed to recreate the suboptimal swapchain: {err}");
+        }
+        self.driver.device.begin_queue_label(
+            self.driver.device.graphics_queue,
+            &format!("Frame {}", self.frame_count),
+            [0.0, 1.0, 0.0, 1.0],
+        )?;
+        self.frame_count += 1;
+        if let Some((fence, frame)) = self.frame_fences[self.frame_slot()] {
+            sync::wait_all(&self.driver.device.device, &[fence], u64::MAX)?;
+            self.completed_frame = self.completed_frame.max(Some(frame));
+        }
+        self.destroy_completed_textures();
+        self.frame_start = Some(Instant::now());
+        self.driver.graphics_commands.reset_record_counts();
+        Ok(())
+    }
+
+    /// Mark the end of the current frame, collecting its statistics - see
+    /// [`Self::last_frame_stats`]. All work of the frame must have been submitted to the
+    /// graphics queue, as the frame is complete once that work has completed.
+    pub fn end_frame(&mut self) -> Result<(), OxidationError> {
+        self.driver
+            .device
+            .end_queue_label(self.driver.device.graphics_queue);
+
+        let commands = &self.driver.graphics_commands;
+        let mut stats = FrameStats {
+            cpu_time: self
+                .frame_start
+                .take()
+                .map(|start| start.elapsed())
+                .unwrap_or_default(),
+            // Retained until a more recent frame has completed.
+            gpu_time: self.last_frame_stats.gpu_time,
+            draw_count: commands.draw_count(),
+            dispatch_count: commands.dispatch_count(),
+            ..Default::default()
+        };
+        // The slot which is next to be reused holds the oldest frame in flight.
+        let oldest_slot = frame_slot(self.frame_count + 1, self.frames_in_flight) as u32;
+        if let Some(timer) = self.gpu_timer.as_mut()
+            && let Some(gpu_time) = timer.elapsed(&self.driver.device.device, oldest_slot)
+        {
+            stats.gpu_time = Some(gpu_time);
+        }
+        match self.driver.allocator().get_heap_budgets() {
+            Ok(budgets) => {
+                for budget in budgets {
+                    stats.allocated_bytes += budget.statistics.allocationBytes;
+                    stats.allocation_count += budget.statistics.allocationCount;
+                }
+            }
+            Err(err) => log::warn!("Unable to query the memory budgets: {err}"),
+        }
+        self.last_frame_stats = stats;
+        self.signal_frame_fence()
+    }
+
+    /// Submit the fence of the current frame's slot, which is signalled once all work submitted
+    /// to the graphics queue so far has completed.
+    fn signal_frame_fence(&mut self) -> Result<(), OxidationError> {
+        let device = &self.driver.device.device;
+        let slot = self.frame_slot();
+        if let Some((fence, _)) = self.frame_fences[slot] {
+            sync::reset(device, &[fence])?;
+        }
+        let fence = match self.frame_fences[slot].take() {
+            Some((fence, _)) => fence,
+            None => unsafe {
+                vk_check!(
+                    device.create_fence(&vk::FenceCreateInfo::default(), None),
+                    "vkCreateFence"
+                )?
+            },
+        };
+        // An empty submission signals the fence once all previously submitted work completes.
+        let res = unsafe {
+            vk_check!(
+                device.queue_submit(self.driver.device.graphics_queue, &[], fence),
+                "vkQueueSubmit"
+            )
+        };
+        match res {
+            // An unsubmitted fence would never be signalled, so isn't waited on.
+            Ok(()) => self.frame_fences[slot] = Some((fence, self.frame_count)),
+            Err(_) => unsafe { device.destroy_fence(fence, None) },
+        }
+        res
+    }
+
+    /// The statistics of the last frame to have ended.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use ash::vk;
+    /// # use oxidation_engine::Engine;
+    /// # fn frame(engine: &mut Engine, cmds: vk::CommandBuffer) -> Result<(), Box<dyn std::error::Error>> {
+    /// engine.begin_frame()?;
+    /// engine.begin_gpu_timing(cmds);
+    /// // ..record and submit the frame.
+    /// engine.end_gpu_timing(cmds);
+    /// engine.end_frame()?;
+    ///
+    /// let stats = engine.last_frame_stats();
+    /// println!("{} draws in {:?}", stats.draw_count, stats.cpu_time);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn last_frame_stats(&self) -> &FrameStats {
+        &self.last_frame_stats
+    }
+
+    /// Begin timing the GPU work of the current frame, which should be recorded at the start
+    /// of the frame's first command buffer (outside of a render pass).
+    pub fn begin_gpu_timing(&mut self, cmds: vk::CommandBuffer) {
+        let slot = self.gpu_timer_slot();
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.begin(&self.driver.device, cmds, slot);
+        }
+    }
+
+    /// End timing the GPU work of the current frame, which should be recorded at the end of
+    /// the frame's last command buffer.
+    pub fn end_gpu_timing(&mut self, cmds: vk::CommandBuffer) {
+        let slot = self.gpu_timer_slot();
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.end(&self.driver.device, cmds, slot);
+        }
+    }
+
+    /// Destroy the deferred textures which are no longer used by frames in flight.
+    fn destroy_completed_textures(&mut self) {
+        let completed_frame = self.completed_frame;
+        let (in_flight, completed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.deferred_textures)
+            .into_iter()
+            .partition(|texture| texture.is_in_flight(completed_frame));
+        for mut texture in completed {
+            texture.destroy(&self.driver);
+        }
+        self.deferred_textures = in_flight;
+    }
+
+    /// The GPU timer slot of the current frame.
+    fn gpu_timer_slot(&self) -> u32 {
+        self.frame_slot() as u32
+    }
+
+    /// The slot of the per-frame resources used by the current frame.
+    fn frame_slot(&self) -> usize {
+        frame_slot(self.frame_count, self.frames_in_flight)
+    }
+
+    /// The frame whose fence covers work submitted now - the current frame until it has
+    /// ended, otherwise the next frame.
+    fn usage_frame(&self) -> u64 {
+        usage_frame(self.frame_count, self.frame_start.is_some())
+    }
+
+    /// Update the most recent completed frame from the frame fences which have been signalled,
+    /// without waiting.
+    fn poll_completed_frames(&mut self) {
+        let device = &self.driver.device.device;
+        for (fence, frame) in self.frame_fences.iter().flatten() {
+            if unsafe { device.get_fence_status(*fence) }.unwrap_or(false) {
+                self.completed_frame = self.completed_frame.max(Some(*frame));
+            }
+        }
+    }
+
+    /// Whether rendering is paused as the surface has a zero area extent, i.e. the window is
+    /// minimized - see [`Self::update_paused`].
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Check the extent of the surface, pausing rendering while it has a zero area, i.e. the
+    /// window is minimized, as no swapchain images can be acquired or presented. When the
+    /// surface is restored, the current swapchain is recreated for its new extent and
+    /// rendering resumes. Returns whether rendering is paused, in which case the frame should
+    /// be skipped.
+    ///
+    /// This is checked by [`Self::acquire_next_image`], so only needs to be called directly by
+    /// render loops which skip the acquire.
+    pub fn update_paused(&mut self) -> Result<bool, OxidationError> {
+        let extent = Swapchain::surface_extent(
+            &self.driver.instance,
+            &self.driver.device,
+            &self.driver.surface,
+        )?;
+        let (paused, resumed) = pause_state(self.paused, extent);
+        if resumed && self.current_swapchain().is_some() {
+            self.recreate_current_swapchain()?;
+        }
+        self.paused = paused;
+        Ok(paused)
+    }
+
+    /// Acquire the next image of the current swapchain for rendering, which is presented with
+    /// [`Self::present`].
+    ///
+    /// Returns `false` if the acquire timed out or rendering is paused as the window is
+    /// minimized, in which case the frame should be skipped.
+    /// When the swapchain has present fences, this first waits until fewer presents than the
+    /// frames in flight are in progress, so the CPU doesn't queue frames
+    /// further ahead of the display - otherwise the acquire alone paces the CPU.
+    /// A suboptimal swapchain is handled according to the [`SuboptimalPolicy`] - with
+    /// [`SuboptimalPolicy::RecreateImmediately`] the swapchain is recreated and `false` is
+    /// returned.
+    pub fn acquire_next_image(&mut self, timeout_ns: u64) -> Result<bool, OxidationError> {
+        // Any image acquired by a skipped frame is never presented.
+        self.acquired = None;
+        if self.update_paused()? {
+            return Ok(false);
+        }
+        let swapchain = self
+            .current_swapchain()
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        let pacing = swapchain.wait_for_presents(
+            &self.driver.device.device,
+            self.frames_in_flight as usize - 1,
+            timeout_ns,
+        )?;
+        if pacing == FenceWait::TimedOut {
+            return Ok(false);
+        }
+        let generation = swapchain.generation();
+        match self.driver.acquire_next_image(swapchain, timeout_ns) {
+            Ok((image_index, suboptimal)) => {
+                let render = self.on_acquired(suboptimal)?;
+                if render {
+                    self.acquired = Some(AcquiredImage {
+                        swapchain: self.current_swapchain,
+                        image_index,
+                        generation,
+                    });
+                }
+                Ok(render)
+            }
+            Err(err) if err.is_frame_skippable() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Present the image acquired by [`Self::acquire_next_image`] once the semaphore, which may
+    /// be null, is signalled. A suboptimal swapchain is handled according to the
+    /// [`SuboptimalPolicy`].
+    ///
+    /// Returns `false` if the present was skipped - when the swapchain was recreated since the
+    /// acquire, i.e. by a resize, the acquired index refers to a destroyed image so the frame
+    /// is dropped, as it is when the swapchain is out of date. A dropped frame's semaphore is
+    /// waited on by an empty submission so it can be signalled again. Returns an error if no
+    /// image has been acquired since the last present.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// if engine.acquire_next_image(u64::MAX)? {
+    ///     // ..record and submit the frame, signalling `render_done`.
+    ///     engine.present(render_done)?;
+    /// }
+    /// ```
+    ///
+    pub fn present(&mut self, wait_semaphore: vk::Semaphore) -> Result<bool, OxidationError> {
+        let acquired = self.acquired.take().ok_or_else(|| {
+            OxidationError::Other("No swapchain image has been acquired to present.".into())
+        })?;
+        let swapchain = self
+            .swapchains
+            .get(acquired.swapchain.get_id())
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        if !acquire_is_current(acquired.generation, swapchain.generation()) {
+            log::debug!(
+                "Skipping the present of image {} as the swapchain was recreated after the acquire.",
+                acquired.image_index
+            );
+            // Nothing waits on the semaphore if the present is dropped, so consume it here to
+            // leave it unsignalled for reuse.
+            if wait_semaphore != vk::Semaphore::null() {
+                self.driver.submit_immediate_after(
+                    &[SemaphoreSubmit::new(
+                        wait_semaphore,
+                        vk::PipelineStageFlags2::ALL_COMMANDS,
+                    )],
+                    |_| {},
+                )?;
+            }
+            return Ok(false);
+        }
+
+        let results =
+            self.driver
+                .present_multi(&[(swapchain, acquired.image_index, wait_semaphore)]);
+        match results[0] {
+            Ok(suboptimal) => {
+                if acquired.swapchain == self.current_swapchain {
+                    self.apply_suboptimal_policy(suboptimal)?;
+                }
+                Ok(true)
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Apply the suboptimal policy after an acquire, returning whether the acquired image
+    /// should be rendered to.
+    fn on_acquired(&mut self, suboptimal: bool) -> Result<bool, OxidationError> {
+        if suboptimal && self.suboptimal_policy == SuboptimalPolicy::RecreateImmediately {
+            // The image semaphore is signalled by the acquire, so must be waited on before
+            // the swapchain it belongs to is destroyed.
+            self.driver.submit_immediate_after(
+                &[SemaphoreSubmit::new(
+                    self.driver.image_ready_signal(),
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                )],
+                |_| {},
+            )?;
+            self.recreate_current_swapchain()?;
+            return Ok(false);
+        }
+        self.apply_suboptimal_policy(suboptimal)?;
+        Ok(true)
+    }
+
+    /// Apply the suboptimal policy after an acquire or present of the current swapchain.
+    fn apply_suboptimal_policy(&mut self, suboptimal: bool) -> Result<(), OxidationError> {
+        if !suboptimal {
+            return Ok(());
+        }
+        match self.suboptimal_policy {
+            SuboptimalPolicy::Ignore => {}
+            SuboptimalPolicy::RecreateNextFrame => self.recreate_pending = true,
+            SuboptimalPolicy::RecreateImmediately => self.recreate_current_swapchain()?,
+        }
+        Ok(())
+    }
+
+    /// Wait for the device and recreate the current swapchain at its current extent - the
+    /// extent is renegotiated with the surface if it has changed.
+    fn recreate_current_swapchain(&mut self) -> Result<(), OxidationError> {
+        self.recreate_pending = false;
+        let driver = self.driver.clone();
+        let swapchain = self
+            .current_swapchain_mut()
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        unsafe { vk_check!(driver.device.device.device_wait_idle(), "vkDeviceWaitIdle")? };
+        let extent = swapchain.extents;
+        swapchain.recreate(
+            &driver.instance,
+            &driver.device,
+            &driver.surface,
+            extent.width,
+            extent.height,
+        )?;
+        Ok(())
+    }
+
+    /// Clear the current swapchain to the colour and present it - the simplest possible frame,
+    /// which is useful as a smoke test of the whole pipeline. The next image is acquired,
+    /// cleared with a dynamic rendering pass and presented, waiting for the clear to complete.
+    /// The colour is a nominal SDR colour, encoded for the color space of the swapchain.
+    ///
+    /// Returns `false` if the frame was skipped as no image could be acquired, or if the
+    /// swapchain is out of date or was recreated under [`SuboptimalPolicy::RecreateImmediately`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// engine.set_current_swapchain(handle);
+    /// engine.clear_current_swapchain([0.1, 0.2, 0.3, 1.0])?;
+    /// ```
+    ///
+    pub fn clear_current_swapchain(&mut self, color: [f32; 4]) -> Result<bool, OxidationError> {
+        match self.acquire_next_image(u64::MAX) {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(OxidationError::VkResult(vk::Result::ERROR_OUT_OF_DATE_KHR)) => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        let driver = self.driver.clone();
+        let image_index = driver.current_image_index();
+        let swapchain = self
+            .current_swapchain()
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        let image = swapchain.images[image_index as usize];
+        let view = swapchain.image_views[image_index as usize];
+        let clear_color = swapchain.encode_clear_color(color);
+
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+        let layout_barrier = |old_layout, new_layout| {
+            vk::ImageMemoryBarrier2::default()
+                .image(image)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .subresource_range(range)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+                .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+        };
+        let rendering_info = RenderingInfo {
+            render_area: vk::Rect2D::default().extent(swapchain.extents),
+            color_attachments: vec![RenderingAttachment {
+                view,
+                clear_color,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let wait = SemaphoreSubmit::new(
+            driver.image_ready_signal(),
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        );
+        let mut render_res = Ok(());
+        driver.submit_immediate_after(&[wait], |cmds| {
+            // The previous contents are cleared so can be discarded.
+            let to_attachment = layout_barrier(
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+            .dst_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+            barrier::cmd_image_barriers(&driver.device, cmds, &[to_attachment]);
+
+            render_res = Commands::begin_rendering(&driver.device, cmds, &rendering_info)
+                .map(|()| Commands::end_rendering(&driver.device.device, cmds));
+
+            let to_present = layout_barrier(
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            )
+            .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE);
+            barrier::cmd_image_barriers(&driver.device, cmds, &[to_present]);
+        })?;
+
+        // The clear has completed, so there's no semaphore to wait on before presenting. The
+        // image is presented even if the clear couldn't be recorded, so it's returned to the
+        // swapchain before the error is.
+        let presented = self.present(vk::Semaphore::null());
+        render_res?;
+        presented
+    }
+
+    /// Capture the contents of the current swapchain image as tightly packed RGBA8 pixels,
+    /// for example to save a screenshot. The image is expected to have been rendered and
+    /// presented, so is in the present layout.
+    ///
+    /// Returns an error if no current swapchain has been set or the surface doesn't support
+    /// copying from the swapchain images.
+    pub fn capture_frame(&mut self) -> Result<FrameCapture, OxidationError> {
+        let image_index = self.driver.current_image_index();
+        let swapchain = self
+            .current_swapchain()
+            .ok_or(OxidationError::NoCurrentSwapchain)?;
+        swapchain.capture(&self.driver, image_index, vk::ImageLayout::PRESENT_SRC_KHR)
+    }
+
+    /// Create a builder for a render target texture. By default, the render target
+    /// matches the extent and format of the current swapchain.
+    pub fn render_target_builder(&mut self) -> RenderTargetBuilder<'_> {
+        RenderTargetBuilder::new(self)
+    }
+
+    /// Create a render target for rendering without a surface, i.e. for video encoding or
+    /// streaming. The target can be rendered into with [`Self::render_frame_offscreen`] and
+    /// then sampled or copied from.
+    pub fn create_offscreen_target(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+    ) -> Result<TextureHandle, Box<dyn Error>> {
+        self.render_target_builder()
+            .extent(width, height)
+            .format(format)
+            .usage(
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+            )
+            .build()
+    }
+
+    /// Render a frame into an offscreen target rather than a swapchain, clearing it to the
+    /// colour. Waits for the frame to complete, leaving the target in the transfer source
+    /// layout ready for readback.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let target = engine.create_offscreen_target(1280, 720, vk::Format::R8G8B8A8_UNORM)?;
+    /// engine.render_frame_offscreen(target, [1.0, 0.0, 0.0, 1.0])?;
+    /// let capture = engine.texture(target).unwrap().capture(&engine.driver)?;
+    /// assert_eq!(&capture.pixels[..4], &[255, 0, 0, 255]);
+    /// ```
+    ///
+    pub fn render_frame_offscreen(
+        &mut self,
+        target: TextureHandle,
+        color: [f32; 4],
+    ) -> Result<(), OxidationError> {
+        let driver = self.driver.clone();
+        let texture = self
+            .textures
+            .get_mut(target)
+            .ok_or_else(|| OxidationError::Other("Invalid offscreen target handle.".into()))?;
+        let rendering_info = RenderingInfo {
+            render_area: vk::Rect2D::default().extent(texture.extent()),
+            color_attachments: vec![RenderingAttachment {
+                view: texture.image_view(),
+                clear_color: color,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut render_res = Ok(());
+        driver.submit_immediate(|cmds| {
+            // The previous contents are cleared so can be discarded.
+            texture.transition(
+                &driver.device,
+                cmds,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::TOP_OF_PIPE,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                1,
+            );
+            render_res = Commands::begin_rendering(&driver.device, cmds, &rendering_info)
+                .map(|()| Commands::end_rendering(&driver.device.device, cmds));
+            texture.transition(
+                &driver.device,
+                cmds,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags2::TRANSFER,
+                1,
+            );
+        })?;
+        render_res
+    }
+
+    /// Get a texture which is owned by the engine.
+    pub fn texture(&self, handle: TextureHandle) -> Option<&Texture> {
+        self.textures.get(handle)
+    }
+
+    /// Whether the handle refers to a texture which hasn't been destroyed.
+    pub fn is_texture_current(&self, handle: TextureHandle) -> bool {
+        self.textures.is_current(handle)
+    }
+
+    /// Destroy a texture. If it was used by a frame whose fence hasn't yet been signalled,
+    /// destruction is deferred until a later frame begins once that frame has completed,
+    /// otherwise it's destroyed immediately. Any material bindings to the texture are dropped,
+    /// rather than left referring to a freed image.
+    ///
+    /// Textures are marked as used when bound by a material. Render targets and transfer
+    /// textures are written by commands recorded outside of the engine, so are treated as used
+    /// by the frame in progress.
+    pub fn destroy_texture(&mut self, handle: TextureHandle) {
+        self.poll_completed_frames();
+        if let Some(mut texture) = self.textures.remove(handle) {
+            let written = vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST;
+            if texture.usage().intersects(written) {
+                texture.mark_used(self.usage_frame());
+            }
+            match texture.is_in_flight(self.completed_frame) {
+                true => self.deferred_textures.push(texture),
+                false => texture.destroy(&self.driver),
+            }
+        }
+        for material in self.materials.iter_mut() {
+            material.drop_stale_textures(&self.textures);
+        }
+    }
+
+    /// Set the texture quality, updating the default sampler. Textures owned by the engine which
+    /// use the default sampler switch to the new sampler, so materials pick it up the next time
+    /// they're bound. Textures created with a specific sampler are unaffected.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // The user has moved the texture quality slider.
+    /// engine.set_texture_quality(TextureQuality::Ultra);
+    /// ```
+    ///
+    pub fn set_texture_quality(&mut self, quality: TextureQuality) {
+        let old_sampler = self.driver.default_sampler();
+        self.driver.set_default_sampler_info(quality.sampler_info());
+        let sampler = self.driver.default_sampler();
+        for texture in self.textures.iter_mut() {
+            if texture.sampler() == old_sampler {
+                texture.set_sampler(sampler);
+            }
+        }
+        self.texture_quality = Some(quality);
+    }
+
+    /// The texture quality last set - `None` if the driver's default sampler is unchanged.
+    #[inline]
+    pub fn texture_quality(&self) -> Option<TextureQuality> {
+        self.texture_quality
+    }
+
+    /// Create a graphics pipeline which is owned by the engine. The pipeline layout remains
+    /// owned by the caller.
+    pub fn create_pipeline(
+        &mut self,
+        info: &GraphicsPipelineInfo,
+    ) -> Result<PipelineHandle, Box<dyn Error>> {
+        let pipeline = GraphicsPipeline::new(&self.driver.device, info)?;
+        let handle = PipelineHandle::new(self.pipelines.len());
+        self.pipelines.push(pipeline);
+        Ok(handle)
+    }
+
+    /// Get a pipeline which is owned by the engine.
+    pub fn pipeline(&self, handle: PipelineHandle) -> Option<&GraphicsPipeline> {
+        if !handle.is_valid() {
+            return None;
+        }
+        self.pipelines.get(handle.get_id())
+    }
+
+    /// Add a material to the engine. Returns an error if the pipeline or any of the textures
+    /// of the material aren't owned by the engine.
+    pub fn create_material(
+        &mut self,
+        material: Material,
+    ) -> Result<MaterialHandle, Box<dyn Error>> {
+        if self.pipeline(material.pipeline()).is_none() {
+            return Err(Box::from("The material pipeline is invalid."));
+        }
+        // Resolving the writes validates the texture handles.
+        material.descriptor_writes(self)?;
+        let handle = MaterialHandle::new(self.materials.len());
+        self.materials.push(material);
+        Ok(handle)
+    }
+
+    /// Get a material which is owned by the engine.
+    pub fn material(&self, handle: MaterialHandle) -> Option<&Material> {
+        if !handle.is_valid() {
+            return None;
+        }
+        self.materials.get(handle.get_id())
+    }
+
+    /// Bind the pipeline of the material, along with its textures (to descriptor set zero)
+    /// and push constants, ready for drawing. The textures are pushed into the command buffer
+    /// when the device supports push descriptors, which requires the set layout of the
+    /// material to have been created with the `PUSH_DESCRIPTOR_KHR` flag - see
+    /// [`Material`].
+    pub fn bind_material(
+        &self,
+        handle: MaterialHandle,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+    ) -> Result<(), Box<dyn Error>> {
+        let material = self.material(handle).ok_or("Invalid material handle.")?;
+        let pipeline = self
+            .pipeline(material.pipeline())
+            .ok_or("The material pipeline is invalid.")?;
+        let device = &self.driver.device;
+        unsafe {
+            device.device.cmd_bind_pipeline(
+                cmds,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline,
+            )
+        };
+
+        let writes = material.descriptor_writes(self)?;
+        for texture in material.textures() {
+            if let Some(texture) = self.texture(texture.texture) {
+                texture.mark_used(self.usage_frame());
+            }
+        }
+        if !writes.is_empty() {
+            commands.push_descriptor_set(
+                device,
+                cmds,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.layout,
+                material.set_layout(),
+                0,
+                &writes,
+            )?;
+        }
+
+        let (stages, data) = material.push_constant_data();
+        if !data.is_empty() {
+            unsafe {
+                device
+                    .device
+                    .cmd_push_constants(cmds, pipeline.layout, stages, 0, data)
+            };
+        }
+        Ok(())
+    }
+
+    /// Upload interleaved vertex data, described by the layout, and its indices to device
+    /// local memory. Blocks until the upload has completed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let layout = VertexLayout::new(0)
+    ///     .add_attribute(vk::Format::R32G32B32_SFLOAT)?
+    ///     .add_attribute(vk::Format::R32G32_SFLOAT)?;
+    /// let mesh = engine.create_mesh(&vertex_bytes, &[0, 1, 2], layout)?;
+    ///
+    /// // Draw time..
+    /// engine.bind_material(material, &mut commands, cmds)?;
+    /// engine.draw_mesh(cmds, mesh)?;
+    /// ```
+    ///
+    pub fn create_mesh(
+        &mut self,
+        vertices: &[u8],
+        indices: &[u32],
+        layout: VertexLayout,
+    ) -> Result<MeshHandle, Box<dyn Error>> {
+        let mesh = Mesh::new(&self.driver, vertices, indices, layout)?;
+        Ok(self.meshes.insert(mesh))
+    }
+
+    /// Get a mesh which is owned by the engine.
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&Mesh> {
+        self.meshes.get(handle)
+    }
+
+    /// Destroy a mesh. The mesh must no longer be in use by the device.
+    pub fn destroy_mesh(&mut self, handle: MeshHandle) {
+        if let Some(mut mesh) = self.meshes.remove(handle) {
+            mesh.destroy(&self.driver);
+        }
+    }
+
+    /// Defragment device memory, relocating the textures and meshes owned by the engine - see
+    /// [`Driver::defragment`]. Waits for the device to become idle, so should be called
+    /// between frames, i.e. periodically when streaming a large world.
+    pub fn defragment(&mut self) -> Result<DefragStats, OxidationError> {
+        let mut resources: Vec<Relocatable> = self
+            .textures
+            .iter_mut()
+            .map(Relocatable::Texture)
+            .chain(
+                self.meshes
+                    .iter_mut()
+                    .flat_map(|mesh| mesh.buffers_mut())
+                    .map(Relocatable::Buffer),
+            )
+            .collect();
+        self.driver.defragment(&mut resources)
+    }
+
+    /// Bind the buffers of the mesh and draw it - the pipeline the mesh is drawn with must
+    /// already be bound.
+    pub fn draw_mesh(
+        &self,
+        cmds: vk::CommandBuffer,
+        handle: MeshHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        let mesh = self.mesh(handle).ok_or("Invalid mesh handle.")?;
+        mesh.draw(
+            &self.driver.graphics_commands,
+            &self.driver.device.device,
+            cmds,
+        );
+        Ok(())
+    }
+
+    /// Enable text rendering for debug overlays with the bitmap font and shaders - see
+    /// [`DebugText`] for the shader interfaces. The text is drawn into colour attachments
+    /// of the format.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let font = BitmapFont {
+    ///     glyph_width: 8,
+    ///     glyph_height: 8,
+    ///     first_char: ' ',
+    ///     glyph_count: 95,
+    ///     bitmap: &FONT_8X8,
+    /// };
+    /// engine.enable_debug_text(&font, text_vert, text_frag, engine.current_swapchain_format()?)?;
+    ///
+    /// // Each frame..
+    /// engine.draw_text(8.0, 8.0, &format!("{:?}", engine.last_frame_stats().cpu_time), [1.0; 4])?;
+    /// engine.flush_debug_text(cmds)?;
+    /// // ..begin rendering into the swapchain image.
+    /// engine.draw_debug_text(&mut commands, cmds, extent)?;
+    /// ```
+    ///
+    pub fn enable_debug_text(
+        &mut self,
+        font: &BitmapFont,
+        vertex_shader: vk::ShaderModule,
+        fragment_shader: vk::ShaderModule,
+        color_format: vk::Format,
+    ) -> Result<(), Box<dyn Error>> {
+        let debug_text = DebugText::new(
+            &self.driver,
+            font,
+            vertex_shader,
+            fragment_shader,
+            color_format,
+            self.frames_in_flight,
+        )?;
+        if let Some(mut old) = self.debug_text.replace(debug_text) {
+            unsafe { self.driver.device.device.device_wait_idle()? };
+            old.destroy(&self.driver);
+        }
+        Ok(())
+    }
+
+    /// Queue text to be drawn at the position, in pixels from the top left of the target, with
+    /// the next call to [`Self::draw_debug_text`]. Returns an error if debug text hasn't been
+    /// enabled with [`Self::enable_debug_text`].
+    pub fn draw_text(
+        &mut self,
+        x: f32,
+        y: f32,
+        text: &str,
+        color: [f32; 4],
+    ) -> Result<(), Box<dyn Error>> {
+        let debug_text = self
+            .debug_text
+            .as_mut()
+            .ok_or("Debug text hasn't been enabled.")?;
+        debug_text.queue(x, y, text, color);
+        Ok(())
+    }
+
+    /// Write the queued text for the current frame. This must be recorded outside of a render
+    /// pass, before [`Self::draw_debug_text`].
+    pub fn flush_debug_text(&mut self, cmds: vk::CommandBuffer) -> Result<(), Box<dyn Error>> {
+        match self.debug_text.as_mut() {
+            Some(debug_text) => debug_text.flush(&self.driver, cmds, self.frame_count),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw the text written by [`Self::flush_debug_text`] into a target of the extent, within
+    /// the current render pass.
+    pub fn draw_debug_text(
+        &mut self,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+        extent: vk::Extent2D,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.debug_text.as_mut() {
+            Some(debug_text) => debug_text.draw(&self.driver, commands, cmds, extent),
+            None => Ok(()),
+        }
+    }
+
+    /// Destroy all resources owned by the engine. This must be called before the driver is
+    /// dropped, as the swapchains and textures hold objects created from the device. Waits for
+    /// the device to become idle so no resource is destroyed while still in use.
+    ///
+    /// Called automatically when the engine is dropped, though calling it explicitly allows
+    /// for any errors to be handled. Calling it more than once has no effect.
+    pub fn shutdown(&mut self) -> Result<(), OxidationError> {
+        if self.swapchains.is_empty()
+            && self.textures.is_empty()
+            && self.deferred_textures.is_empty()
+            && self.pipelines.is_empty()
+            && self.meshes.is_empty()
+            && self.gpu_timer.is_none()
+            && self.frame_fences.iter().all(Option::is_none)
+            && self.debug_text.is_none()
+        {
+            return Ok(());
+        }
+        unsafe {
+            vk_check!(
+                self.driver.device.device.device_wait_idle(),
+                "vkDeviceWaitIdle"
+            )?
+        };
+
+        self.materials.clear();
+        for mut mesh in self.meshes.drain() {
+            mesh.destroy(&self.driver);
+        }
+        for mut pipeline in self.pipelines.drain(..) {
+            pipeline.destroy(&self.driver.device.device);
+        }
+        for mut texture in self.textures.drain() {
+            texture.destroy(&self.driver);
+        }
+        for mut texture in self.deferred_textures.drain(..) {
+            texture.destroy(&self.driver);
+        }
+        for mut swapchain in self.swapchains.drain(..) {
+            swapchain.destroy(&self.driver.device.device);
+        }
+        self.swapchain_targets.clear();
+        if let Some(mut timer) = self.gpu_timer.take() {
+            timer.destroy(&self.driver.device.device);
+        }
+        for (fence, _) in self.frame_fences.iter_mut().filter_map(Option::take) {
+            unsafe { self.driver.device.device.destroy_fence(fence, None) };
+        }
+        if let Some(mut debug_text) = self.debug_text.take() {
+            debug_text.destroy(&self.driver);
+        }
+        self.current_swapchain = Default::default();
+        Ok(())
+    }
+
+    pub(crate) fn add_texture(&mut self, texture: Texture) -> TextureHandle {
+        self.textures.insert(texture)
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            error!("Engine shutdown failed: {err}");
+        }
+    }
+}
+
+/// Check the number of frames in flight is within the command buffers which can be in flight.
+fn validate_frames_in_flight(frames_in_flight: u32) -> Result<(), OxidationError> {
+    if frames_in_flight == 0 || frames_in_flight as usize > MAX_CMD_BUFFER_IN_FLIGHT_COUNT {
+        return Err(OxidationError::Other(format!(
+            "The number of frames in flight must be between 1 and {MAX_CMD_BUFFER_IN_FLIGHT_COUNT}, not {frames_in_flight}."
+        )));
+    }
+    Ok(())
+}
+
+/// Check the swapchain has an image for each frame in flight.
+fn validate_swapchain_image_count(
+    image_count: usize,
+    frames_in_flight: u32,
+) -> Result<(), OxidationError> {
+    if image_count < frames_in_flight as usize {
+        return Err(OxidationError::Unsupported(format!(
+            "The swapchain has {image_count} images, fewer than the {frames_in_flight} frames in flight."
+        )));
+    }
+    Ok(())
+}
+
+/// Whether an image acquired from a swapchain at `acquired_generation` can still be presented,
+/// i.e. the swapchain hasn't been recreated since.
+fn acquire_is_current(acquired_generation: u64, swapchain_generation: u64) -> bool {
+    acquired_generation == swapchain_generation
+}
+
+/// The frame whose fence covers work submitted now - `frame_count` while that frame is in
+/// progress, otherwise the next frame to begin.
+fn usage_frame(frame_count: u64, in_frame: bool) -> u64 {
+    match in_frame {
+        true => frame_count,
+        false => frame_count + 1,
+    }
+}
+
+/// The slot of the per-frame resources used by the frame.
+fn frame_slot(frame_count: u64, frames_in_flight: u32) -> usize {
+    (frame_count % frames_in_flight as u64) as usize
+}
+
+/// Whether rendering is paused for the surface extent - while it has a zero area - and
+/// whether rendering resumes, as it was previously paused.
+fn pause_state(was_paused: bool, extent: vk::Extent2D) -> (bool, bool) {
+    let paused = extent.width == 0 || extent.height == 0;
+    (paused, was_paused && !paused)
+}
+
+/// Whether the swapchain targets - the image and extent of each target texture, `None` if the
+/// texture has been destroyed - still wrap the images of the swapchain. They're stale once
+/// the swapchain has been recreated.
+fn swapchain_targets_are_current(
+    targets: &[Option<(vk::Image, vk::Extent2D)>],
+    images: &[vk::Image],
+    extent: vk::Extent2D,
+) -> bool {
+    targets.len() == images.len()
+        && targets
+            .iter()
+            .zip(images)
+            .all(|(target, image)| *target == Some((*image, extent)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidation_vk::vk::Handle;
+
+    #[test]
+    fn frames_in_flight_must_be_in_range() {
+        assert!(validate_frames_in_flight(0).is_err());
+        assert!(validate_frames_in_flight(1).is_ok());
+        assert!(validate_frames_in_flight(DEFAULT_FRAMES_IN_FLIGHT).is_ok());
+        assert!(validate_frames_in_flight(MAX_CMD_BUFFER_IN_FLIGHT_COUNT as u32).is_ok());
+        assert!(validate_frames_in_flight(MAX_CMD_BUFFER_IN_FLIGHT_COUNT as u32 + 1).is_err());
+    }
+
+    #[test]
+    fn swapchain_targets_match_the_swapchain_images() {
+        let images: Vec<vk::Image> = (1..=3).map(vk::Image::from_raw).collect();
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        let targets: Vec<_> = images.iter().map(|image| Some((*image, extent))).collect();
+        assert!(swapchain_targets_are_current(&targets, &images, extent));
+        // One target is required per image.
+        assert!(!swapchain_targets_are_current(
+            &targets[..2],
+            &images,
+            extent
+        ));
+        assert!(!swapchain_targets_are_current(&[], &images, extent));
+        // A destroyed target texture requires the list to be refreshed.
+        let mut destroyed = targets.clone();
+        destroyed[1] = None;
+        assert!(!swapchain_targets_are_current(&destroyed, &images, extent));
+    }
+
+    #[test]
+    fn swapchain_targets_are_refreshed_on_recreation() {
+        let images: Vec<vk::Image> = (1..=3).map(vk::Image::from_raw).collect();
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        let targets: Vec<_> = images.iter().map(|image| Some((*image, extent))).collect();
+
+        let recreated: Vec<vk::Image> = (4..=6).map(vk::Image::from_raw).collect();
+        assert!(!swapchain_targets_are_current(&targets, &recreated, extent));
+        let resized = vk::Extent2D {
+            width: 1024,
+            height: 768,
+        };
+        assert!(!swapchain_targets_are_current(&targets, &images, resized));
+    }
+
+    #[test]
+    fn texture_quality_sets_anisotropy_and_bias() {
+        let low = TextureQuality::Low.sampler_info();
+        let high = TextureQuality::High.sampler_info();
+        assert_eq!(low.enable_anisotropy, vk::FALSE);
+        assert_eq!(high.enable_anisotropy, vk::TRUE);
+        assert!(high.anisotropy > low.anisotropy);
+        assert!(TextureQuality::Ultra.sampler_info().anisotropy > high.anisotropy);
+        // Low quality biases towards the smaller mips.
+        assert!(low.mip_lod_bias > 0);
+        assert_eq!(high.mip_lod_bias, 0);
+    }
+
+    #[test]
+    fn zero_area_extent_pauses_rendering() {
+        let extent = |width, height| vk::Extent2D { width, height };
+        assert_eq!(pause_state(false, extent(0, 0)), (true, false));
+        assert_eq!(pause_state(false, extent(800, 0)), (true, false));
+        assert_eq!(pause_state(true, extent(0, 0)), (true, false));
+        assert_eq!(pause_state(false, extent(800, 600)), (false, false));
+    }
+
+    #[test]
+    fn restored_extent_resumes_rendering() {
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        assert_eq!(pause_state(true, extent), (false, true));
+    }
+
+    #[test]
+    fn frame_slots_cycle_through_the_frames_in_flight() {
+        for frames_in_flight in [1, 2, 3] {
+            let slots: Vec<usize> = (0..6)
+                .map(|frame| frame_slot(frame, frames_in_flight))
+                .collect();
+            let expected: Vec<usize> = (0..6)
+                .map(|frame| frame % frames_in_flight as usize)
+                .collect();
+            assert_eq!(slots, expected);
+            assert!(slots.iter().all(|slot| *slot < frames_in_flight as usize));
+        }
+    }
+
+    #[test]
+    fn swapchain_needs_an_image_per_frame_in_flight() {
+        assert!(validate_swapchain_image_count(3, 3).is_ok());
+        assert!(validate_swapchain_image_count(3, 2).is_ok());
+        assert!(matches!(
+            validate_swapchain_image_count(2, 3),
+            Err(OxidationError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn present_is_skipped_if_the_swapchain_is_recreated_after_the_acquire() {
+        let mut generation = 3;
+        let acquired = generation;
+        assert!(acquire_is_current(acquired, generation));
+
+        // A resize recreates the swapchain between the acquire and the present.
+        generation += 1;
+        assert!(!acquire_is_current(acquired, generation));
+        // The next acquire is from the recreated swapchain.
+        assert!(acquire_is_current(generation, generation));
+    }
+
+    #[test]
+    fn textures_are_used_by_the_frame_whose_fence_covers_them() {
+        // Between begin_frame and end_frame, work belongs to the frame in progress.
+        assert_eq!(usage_frame(5, true), 5);
+        // Otherwise it's submitted with, and fenced by, the next frame.
+        assert_eq!(usage_frame(5, false), 6);
+        assert_eq!(usage_frame(0, false), 1);
     }
 }
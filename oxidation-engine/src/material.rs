@@ -0,0 +1,126 @@
+use crate::{Engine, PipelineHandle, TextureHandle};
+use oxidation_utils::pool::Pool;
+use oxidation_vk::descriptor::DescriptorWrite;
+use oxidation_vk::texture::Texture;
+use oxidation_vk::vk;
+use std::error::Error;
+
+/// A texture bound to a combined image sampler binding of a material.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MaterialTexture {
+    pub binding: u32,
+    pub texture: TextureHandle,
+}
+
+/// Associates a graphics pipeline with the resources it's drawn with - the textures bound
+/// to the descriptor set and the push constant data. Materials are owned by the engine and
+/// bound at draw time with [`Engine::bind_material`].
+///
+/// The textures are bound with push descriptors where supported, so the set layout must be
+/// created with the flags from
+/// [`push_set_layout_flags`](oxidation_vk::descriptor::push_set_layout_flags).
+///
+/// # Examples
+///
+/// ```ignore
+/// let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+///     .flags(descriptor::push_set_layout_flags(&engine.driver.device))
+///     .bindings(&bindings);
+/// let set_layout = unsafe { device.create_descriptor_set_layout(&set_layout_info, None)? };
+/// let material = Material::new(pipeline, set_layout)
+///     .texture(0, albedo)
+///     .texture(1, normal)
+///     .push_constants(vk::ShaderStageFlags::FRAGMENT, &tint.to_ne_bytes());
+/// let handle = engine.create_material(material)?;
+///
+/// // Draw time..
+/// engine.bind_material(handle, &mut commands, cmds)?;
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct Material {
+    pipeline: PipelineHandle,
+    /// The layout of descriptor set zero of the pipeline, which the textures are written to.
+    set_layout: vk::DescriptorSetLayout,
+    textures: Vec<MaterialTexture>,
+    push_constant_stages: vk::ShaderStageFlags,
+    push_constants: Vec<u8>,
+}
+
+impl Material {
+    pub fn new(pipeline: PipelineHandle, set_layout: vk::DescriptorSetLayout) -> Self {
+        Self {
+            pipeline,
+            set_layout,
+            textures: Vec::new(),
+            push_constant_stages: vk::ShaderStageFlags::empty(),
+            push_constants: Vec::new(),
+        }
+    }
+
+    /// Bind a texture to the combined image sampler at the binding. Binding a texture to a
+    /// binding which is already in use replaces the previous texture.
+    pub fn texture(mut self, binding: u32, texture: TextureHandle) -> Self {
+        self.textures.retain(|t| t.binding != binding);
+        self.textures.push(MaterialTexture { binding, texture });
+        self
+    }
+
+    /// The push constant data, which is pushed from offset zero for the shader stages.
+    pub fn push_constants(mut self, stages: vk::ShaderStageFlags, data: &[u8]) -> Self {
+        self.push_constant_stages = stages;
+        self.push_constants = data.to_vec();
+        self
+    }
+
+    #[inline]
+    pub fn pipeline(&self) -> PipelineHandle {
+        self.pipeline
+    }
+
+    #[inline]
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    #[inline]
+    pub fn textures(&self) -> &[MaterialTexture] {
+        &self.textures
+    }
+
+    #[inline]
+    pub fn push_constant_data(&self) -> (vk::ShaderStageFlags, &[u8]) {
+        (self.push_constant_stages, &self.push_constants)
+    }
+
+    /// Remove the bindings to textures which have since been destroyed.
+    pub(crate) fn drop_stale_textures(&mut self, textures: &Pool<Texture>) {
+        self.textures.retain(|t| textures.is_current(t.texture));
+    }
+
+    /// The descriptor writes for the textures of the material, resolved from their handles.
+    /// Returns an error if a texture handle doesn't refer to a texture owned by the engine.
+    pub fn descriptor_writes(
+        &self,
+        engine: &Engine,
+    ) -> Result<Vec<DescriptorWrite>, Box<dyn Error>> {
+        self.textures
+            .iter()
+            .map(|t| {
+                let texture = engine.texture(t.texture).ok_or_else(|| {
+                    format!(
+                        "The texture bound to material binding {} is invalid.",
+                        t.binding
+                    )
+                })?;
+                Ok(DescriptorWrite::Image {
+                    binding: t.binding,
+                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    view: texture.sampled_view(),
+                    sampler: texture.sampler(),
+                    layout: texture.layout(),
+                })
+            })
+            .collect()
+    }
+}
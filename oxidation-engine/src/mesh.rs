@@ -0,0 +1,100 @@
+use oxidation_vk::Driver;
+use oxidation_vk::backend::VertexLayout;
+use oxidation_vk::buffer::Buffer;
+use oxidation_vk::commands::Commands;
+use oxidation_vk::vk;
+use std::error::Error;
+
+/// Interleaved vertex data and its indices, held in device local buffers.
+pub struct Mesh {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: u32,
+    layout: VertexLayout,
+}
+
+impl Mesh {
+    /// Upload the vertex and index data to device local buffers. The vertex data must be
+    /// interleaved as described by the layout. Blocks until the upload has completed.
+    pub fn new(
+        driver: &Driver,
+        vertices: &[u8],
+        indices: &[u32],
+        layout: VertexLayout,
+    ) -> Result<Self, Box<dyn Error>> {
+        if vertices.is_empty() || indices.is_empty() {
+            return Err(Box::from("A mesh requires vertex and index data."));
+        }
+        let stride = layout.stride() as usize;
+        if stride == 0 || !vertices.len().is_multiple_of(stride) {
+            return Err(Box::from(format!(
+                "Vertex data of {} bytes is not a multiple of the layout stride ({stride}).",
+                vertices.len()
+            )));
+        }
+
+        let index_bytes = unsafe {
+            std::slice::from_raw_parts(indices.as_ptr() as *const u8, size_of_val(indices))
+        };
+        let mut vertex_buffer =
+            Buffer::with_data(driver, vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        let index_buffer =
+            match Buffer::with_data(driver, index_bytes, vk::BufferUsageFlags::INDEX_BUFFER) {
+                Ok(buffer) => buffer,
+                Err(err) => {
+                    vertex_buffer.destroy(driver);
+                    return Err(err);
+                }
+            };
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            layout,
+        })
+    }
+
+    #[inline]
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    #[inline]
+    pub fn vertex_count(&self) -> u32 {
+        (self.vertex_buffer.size / self.layout.stride() as vk::DeviceSize) as u32
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    #[inline]
+    pub fn vertex_buffer(&self) -> &Buffer {
+        &self.vertex_buffer
+    }
+
+    #[inline]
+    pub fn index_buffer(&self) -> &Buffer {
+        &self.index_buffer
+    }
+
+    /// Bind the vertex and index buffers and draw all indices of the mesh.
+    pub fn draw(&self, commands: &Commands, device: &ash::Device, cmds: vk::CommandBuffer) {
+        let binding = self.layout.binding_description().binding;
+        Commands::bind_vertex_buffers(device, cmds, binding, &[&self.vertex_buffer], &[0]);
+        Commands::bind_index_buffer(device, cmds, &self.index_buffer, 0, vk::IndexType::UINT32);
+        commands.draw_indexed(device, cmds, self.index_count, 1, 0, 0, 0);
+    }
+
+    /// Mutable access to the vertex and index buffers, i.e. for relocation by defragmentation.
+    pub(crate) fn buffers_mut(&mut self) -> [&mut Buffer; 2] {
+        [&mut self.vertex_buffer, &mut self.index_buffer]
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        self.vertex_buffer.destroy(driver);
+        self.index_buffer.destroy(driver);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{Engine, TextureHandle};
+use oxidation_vk::backend::SamplerInfo;
+use oxidation_vk::error::OxidationError;
+use oxidation_vk::texture::{Texture, TextureInfo};
+use oxidation_vk::vk;
+use std::error::Error;
+
+/// A builder for creating textures which are to be used as render targets.
+///
+/// By default, the render target matches the extent and color format of the
+/// current swapchain and is usable as both a color attachment and a sampled image.
+///
+/// # Examples
+///
+/// ```ignore
+/// let handle = engine
+///     .render_target_builder()
+///     .format(vk::Format::R16G16B16A16_SFLOAT)
+///     .build()?;
+/// ```
+///
+pub struct RenderTargetBuilder<'a> {
+    engine: &'a mut Engine,
+    extent: Option<vk::Extent2D>,
+    format: Option<vk::Format>,
+    mip_levels: u32,
+    usage: vk::ImageUsageFlags,
+    sampler_info: SamplerInfo,
+    transient: bool,
+    min_extent: Option<vk::Extent2D>,
+}
+
+impl<'a> RenderTargetBuilder<'a> {
+    pub(crate) fn new(engine: &'a mut Engine) -> Self {
+        Self {
+            engine,
+            extent: None,
+            format: None,
+            mip_levels: 1,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            sampler_info: SamplerInfo::default(),
+            transient: false,
+            min_extent: None,
+        }
+    }
+
+    /// Override the dimensions of the render target - defaults to the current swapchain extent.
+    pub fn extent(mut self, width: u32, height: u32) -> Self {
+        self.extent = Some(vk::Extent2D { width, height });
+        self
+    }
+
+    /// Override the format of the render target - defaults to the current swapchain format.
+    pub fn format(mut self, format: vk::Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    pub fn usage(mut self, usage: vk::ImageUsageFlags) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn sampler(mut self, sampler_info: SamplerInfo) -> Self {
+        self.sampler_info = sampler_info;
+        self
+    }
+
+    /// Mark the render target as transient - its contents are only used within the render pass
+    /// (i.e. MSAA or depth attachments which are resolved or discarded), so it can't be sampled
+    /// or copied. The attachment is backed by lazily allocated memory when supported by the
+    /// device, which on tile-based GPUs may never be committed. Any sampler specified is
+    /// ignored.
+    pub fn transient(mut self, transient: bool) -> Self {
+        self.transient = transient;
+        self
+    }
+
+    /// If the render target can't be allocated, because the extent exceeds the device limits
+    /// or there isn't enough memory, retry at progressively halved dimensions down to the
+    /// minimum extent rather than failing. Use [`Self::build_with_extent`] to retrieve the
+    /// extent which was actually allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // A 4K target on a GPU with little memory may be allocated at 2K instead.
+    /// let (handle, extent) = engine
+    ///     .render_target_builder()
+    ///     .extent(3840, 2160)
+    ///     .downscale_on_failure(960, 540)
+    ///     .build_with_extent()?;
+    /// ```
+    ///
+    pub fn downscale_on_failure(mut self, min_width: u32, min_height: u32) -> Self {
+        self.min_extent = Some(vk::Extent2D {
+            width: min_width,
+            height: min_height,
+        });
+        self
+    }
+
+    /// Allocate the render target, returning a handle to the texture which is owned by the engine.
+    ///
+    /// Returns an error if the extent or format haven't been specified and there is no
+    /// current swapchain to take them from.
+    pub fn build(self) -> Result<TextureHandle, Box<dyn Error>> {
+        self.build_with_extent().map(|(handle, _)| handle)
+    }
+
+    /// Allocate the render target, returning a handle to the texture along with its extent,
+    /// which is smaller than requested if the target was downscaled on failure.
+    pub fn build_with_extent(self) -> Result<(TextureHandle, vk::Extent2D), Box<dyn Error>> {
+        let (extent, format) = match (self.extent, self.format) {
+            (Some(extent), Some(format)) => (extent, format),
+            (extent, format) => {
+                let swapchain = self.engine.current_swapchain().ok_or(
+                    "No extent or format specified for the render target and no current swapchain is set.",
+                )?;
+                (
+                    extent.unwrap_or(swapchain.extents),
+                    format.unwrap_or(swapchain.color_format()),
+                )
+            }
+        };
+
+        // Transient attachments can only be used as attachments.
+        let usage = match self.transient {
+            true => {
+                (self.usage
+                    & (vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                        | vk::ImageUsageFlags::INPUT_ATTACHMENT))
+                    | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+            }
+            false => self.usage,
+        };
+
+        let max_dimension = self.engine.driver.device.limits.max_image_dimension2_d;
+        let mut extent = extent;
+        loop {
+            let res = match extent.width.max(extent.height) > max_dimension {
+                true => Err(Box::from(format!(
+                    "The render target extent {}x{} exceeds the maximum image dimension of {max_dimension}.",
+                    extent.width, extent.height
+                ))),
+                false => self.create_texture(extent, format, usage),
+            };
+            let err = match res {
+                Ok(texture) => break Ok((self.engine.add_texture(texture), extent)),
+                Err(err) => err,
+            };
+            let downscaled = vk::Extent2D {
+                width: extent.width / 2,
+                height: extent.height / 2,
+            };
+            let Some(min_extent) = self.min_extent else {
+                return Err(err);
+            };
+            if !is_allocation_failure(err.as_ref(), max_dimension, extent)
+                || downscaled.width < min_extent.width.max(1)
+                || downscaled.height < min_extent.height.max(1)
+            {
+                return Err(err);
+            }
+            log::warn!(
+                "Unable to allocate a {}x{} render target ({err}), downscaling to {}x{}.",
+                extent.width,
+                extent.height,
+                downscaled.width,
+                downscaled.height
+            );
+            extent = downscaled;
+        }
+    }
+
+    fn create_texture(
+        &self,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<Texture, Box<dyn Error>> {
+        // The mip chain is shortened if the extent is too small for all of the levels.
+        let mip_levels = self
+            .mip_levels
+            .min(extent.width.max(extent.height).max(1).ilog2() + 1);
+        let info = TextureInfo {
+            width: extent.width,
+            height: extent.height,
+            mip_levels,
+            format,
+            ..Default::default()
+        };
+        // Transient targets can't be sampled, so no sampler is created for them.
+        let sampler_info = (!self.transient).then_some(SamplerInfo {
+            mip_levels,
+            ..self.sampler_info
+        });
+        Texture::new(
+            &self.engine.driver,
+            &info,
+            usage,
+            sampler_info.as_ref(),
+            &[],
+        )
+    }
+}
+
+/// Whether creating a texture of the extent failed due to the device limits or running out of
+/// memory, rather than an invalid format or usage which downscaling wouldn't resolve.
+fn is_allocation_failure(
+    err: &(dyn Error + 'static),
+    max_dimension: u32,
+    extent: vk::Extent2D,
+) -> bool {
+    if extent.width.max(extent.height) > max_dimension {
+        return true;
+    }
+    matches!(
+        err.downcast_ref::<OxidationError>(),
+        Some(OxidationError::VkResult(
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY
+        ))
+    )
+}
@@ -0,0 +1,88 @@
+use oxidation_vk::submit_batch::SemaphoreSubmit;
+use oxidation_vk::vk;
+
+/// How compute work submitted through the engine is scheduled relative to graphics work.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum QueueScheduling {
+    /// All compute work is submitted to the graphics queue, executing in submission order
+    /// with the graphics work.
+    #[default]
+    Serial,
+    /// Compute work is submitted to the dedicated compute queue, if the device has one, so
+    /// it can execute concurrently with graphics work. Any dependencies between the two
+    /// must be expressed with semaphores on the [`ComputeJob`].
+    ///
+    /// Resources created with `vk::SharingMode::EXCLUSIVE` which are accessed on both queues
+    /// must have their ownership transferred between the queue families - a release barrier
+    /// recorded on the queue which last accessed the resource, followed by a matching acquire
+    /// barrier on the other queue, with a semaphore between the two submissions. The transfer
+    /// can be skipped if the contents of the resource don't need to be preserved, or the
+    /// resource can be created with `vk::SharingMode::CONCURRENT` at some cost in performance.
+    Overlapped,
+}
+
+/// The queue which a job is submitted to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum QueueKind {
+    Graphics,
+    Compute,
+}
+
+/// Select the queue to submit compute work to for the scheduling mode. Compute work only
+/// overlaps graphics when the device has a separate compute queue family.
+pub fn select_compute_queue(scheduling: QueueScheduling, has_compute_queue: bool) -> QueueKind {
+    match scheduling {
+        QueueScheduling::Overlapped if has_compute_queue => QueueKind::Compute,
+        _ => QueueKind::Graphics,
+    }
+}
+
+/// A recorded compute command buffer along with the semaphores which synchronise it with
+/// other work. A job with no wait semaphores is independent of graphics work, so can execute
+/// as soon as it's submitted.
+///
+/// # Examples
+///
+/// ```ignore
+/// // Post-processing reads the scene colour, so waits on the graphics work which renders it.
+/// let job = ComputeJob::new(cmds)
+///     .wait(SemaphoreSubmit::new(scene_done, vk::PipelineStageFlags2::COMPUTE_SHADER))
+///     .signal(SemaphoreSubmit::new(post_done, vk::PipelineStageFlags2::COMPUTE_SHADER));
+/// engine.submit_compute(&job, vk::Fence::null())?;
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct ComputeJob {
+    pub(crate) cmds: vk::CommandBuffer,
+    pub(crate) wait_semaphores: Vec<SemaphoreSubmit>,
+    pub(crate) signal_semaphores: Vec<SemaphoreSubmit>,
+}
+
+impl ComputeJob {
+    /// The command buffer must be allocated from the queue family returned by
+    /// [`Engine::compute_queue_family`](crate::Engine::compute_queue_family).
+    pub fn new(cmds: vk::CommandBuffer) -> Self {
+        Self {
+            cmds,
+            wait_semaphores: Vec::new(),
+            signal_semaphores: Vec::new(),
+        }
+    }
+
+    /// Wait on the semaphore before executing, i.e. for graphics work the job depends on.
+    pub fn wait(mut self, semaphore: SemaphoreSubmit) -> Self {
+        self.wait_semaphores.push(semaphore);
+        self
+    }
+
+    /// Signal the semaphore once complete, i.e. for graphics work which depends on the job.
+    pub fn signal(mut self, semaphore: SemaphoreSubmit) -> Self {
+        self.signal_semaphores.push(semaphore);
+        self
+    }
+
+    /// Whether the job doesn't wait on any other work.
+    pub fn is_independent(&self) -> bool {
+        self.wait_semaphores.is_empty()
+    }
+}
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+/// Statistics of a completed frame, i.e. for displaying in a debug overlay. See
+/// [`Engine::last_frame_stats`](crate::Engine::last_frame_stats).
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameStats {
+    /// The time between `begin_frame()` and `end_frame()`.
+    pub cpu_time: Duration,
+    /// The GPU time of the most recent frame which has completed on the device - as this
+    /// isn't waited on, it's usually a few frames behind. `None` if no frame has been timed
+    /// with [`Engine::begin_gpu_timing`](crate::Engine::begin_gpu_timing) or the device
+    /// doesn't support timestamps.
+    pub gpu_time: Option<Duration>,
+    /// The number of draws recorded through the graphics command helpers.
+    pub draw_count: u32,
+    /// The number of dispatches recorded through the graphics command helpers.
+    pub dispatch_count: u32,
+    /// The total size of all device memory allocations made through the allocator.
+    pub allocated_bytes: u64,
+    pub allocation_count: u32,
+}
@@ -1,5 +1,9 @@
 mod convert_to_vk;
+mod vertex_layout;
 
+pub use vertex_layout::{VertexAttribute, VertexLayout, VertexPacking};
+
+use crate::memory_pool::MemoryPoolHandle;
 use ash::vk;
 
 #[derive(Hash, Eq, PartialEq, Copy, Clone)]
@@ -67,6 +71,291 @@ impl CompareOp {
     }
 }
 
+/// How polygons are rasterized. Anything other than `Fill` requires the `fillModeNonSolid`
+/// device feature - useful for inspecting the topology of meshes.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    /// Wireframe rendering, where only the edges of polygons are drawn.
+    Line,
+    Point,
+}
+
+impl PolygonMode {
+    pub fn to_vk(&self) -> vk::PolygonMode {
+        match self {
+            PolygonMode::Fill => vk::PolygonMode::FILL,
+            PolygonMode::Line => vk::PolygonMode::LINE,
+            PolygonMode::Point => vk::PolygonMode::POINT,
+        }
+    }
+}
+
+/// Which faces of polygons are discarded before rasterization.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum CullMode {
+    None,
+    Front,
+    #[default]
+    Back,
+    FrontAndBack,
+}
+
+impl CullMode {
+    pub fn to_vk(&self) -> vk::CullModeFlags {
+        match self {
+            CullMode::None => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK,
+            CullMode::FrontAndBack => vk::CullModeFlags::FRONT_AND_BACK,
+        }
+    }
+}
+
+/// The winding order, in framebuffer space, of front facing polygons. Note that flipping the
+/// viewport Y axis (see [`viewport`]) also flips the winding of polygons.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum FrontFace {
+    Clockwise,
+    #[default]
+    CounterClockwise,
+}
+
+impl FrontFace {
+    pub fn to_vk(&self) -> vk::FrontFace {
+        match self {
+            FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+            FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+        }
+    }
+}
+
+/// How presented images are queued for display, in the swapchain's default order of
+/// preference when no mode is requested (immediate is only used as a last resort).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum PresentMode {
+    /// The latest image replaces any queued image at vblank - low latency without tearing.
+    #[default]
+    Mailbox,
+    /// Adaptive vsync - as FIFO, but an image presented after a missed vblank is displayed
+    /// immediately, trading a tear for less stutter when a frame occasionally runs late.
+    FifoRelaxed,
+    /// Vsync - images are queued and displayed at vblank. Always supported.
+    Fifo,
+    /// Images are displayed immediately, which may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn to_vk(&self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+/// Presets for how the output of the fragment shader is blended with the contents of a
+/// colour attachment. All presets write all colour components.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum BlendMode {
+    /// The output replaces the attachment contents - blending is disabled.
+    #[default]
+    Opaque,
+    /// Standard (non pre-multiplied) alpha blending, for transparent surfaces and UI.
+    AlphaBlend,
+    /// The output, scaled by its alpha, is added to the attachment - i.e. for particles.
+    Additive,
+    /// The attachment contents are multiplied by the output.
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn to_vk(&self) -> vk::PipelineColorBlendAttachmentState {
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .alpha_blend_op(vk::BlendOp::ADD);
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => return state.blend_enable(false),
+            BlendMode::AlphaBlend => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Multiply => (
+                vk::BlendFactor::DST_COLOR,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::DST_ALPHA,
+                vk::BlendFactor::ZERO,
+            ),
+        };
+        state
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+    }
+}
+
+/// The depth convention used for rendering. Reverse-Z maps the near plane to 1.0 and the
+/// far plane to 0.0, which distributes floating point depth precision far more evenly.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DepthMode {
+    Standard,
+    ReverseZ,
+}
+
+impl DepthMode {
+    /// The value the depth attachment is cleared to - the far plane.
+    pub fn clear_value(self) -> vk::ClearDepthStencilValue {
+        let depth = match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseZ => 0.0,
+        };
+        vk::ClearDepthStencilValue { depth, stencil: 0 }
+    }
+
+    /// The depth compare op which passes for fragments closer to the viewer.
+    pub fn compare_op(self) -> CompareOp {
+        match self {
+            DepthMode::Standard => CompareOp::LessOrEqual,
+            DepthMode::ReverseZ => CompareOp::GreaterOrEqual,
+        }
+    }
+}
+
+/// A viewport covering the extent with a depth range of [0, 1]. Vulkan's NDC Y axis points
+/// down, the opposite of OpenGL - if `flip_y` is set, a negative height viewport with the
+/// origin at the bottom of the extent is returned so Y points up and content renders the same
+/// as with OpenGL. Users which flip Y themselves (i.e. in the projection matrix) should not set
+/// this, otherwise the content will be double-flipped.
+pub fn viewport(extent: vk::Extent2D, flip_y: bool) -> vk::Viewport {
+    let (y, height) = match flip_y {
+        true => (extent.height as f32, -(extent.height as f32)),
+        false => (0.0, extent.height as f32),
+    };
+    vk::Viewport {
+        x: 0.0,
+        y,
+        width: extent.width as f32,
+        height,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }
+}
+
+/// A scissor covering the extent. The scissor is always specified in framebuffer coordinates
+/// so is unaffected by a flipped viewport.
+pub fn scissor(extent: vk::Extent2D) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent,
+    }
+}
+
+/// Attachments of at least this size (in bytes) are given a dedicated allocation by default,
+/// smaller attachments are pooled so they don't use up the device's allocation count.
+pub const DEDICATED_ALLOCATION_MIN_SIZE: vk::DeviceSize = 4 << 20;
+
+/// How the memory for a resource should be allocated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum AllocationHint {
+    /// The resource has its own device memory allocation. Best suited to large resources,
+    /// such as render targets, but the number of allocations a device supports is limited.
+    Dedicated,
+    /// The resource is sub-allocated from a larger block of device local memory - suited to
+    /// the majority of (smaller) resources.
+    Pooled,
+    /// The resource is sub-allocated from host visible memory which is persistently mapped,
+    /// for resources which are written sequentially by the host.
+    Mapped,
+    /// The resource is backed by lazily allocated memory, which may never be committed on
+    /// tile-based GPUs. Only suitable for transient attachments.
+    LazilyAllocated,
+    /// The resource is sub-allocated from a custom memory pool - see
+    /// [`Driver::create_memory_pool`](crate::Driver::create_memory_pool).
+    Custom(MemoryPoolHandle),
+}
+
+impl AllocationHint {
+    /// The hint used for images which don't specify one, from the tiling, usage and
+    /// approximate size in bytes of the image. Linear images are mapped, transient attachments
+    /// are lazily allocated and large attachments are dedicated - all other images are pooled.
+    pub fn default_for_image(
+        tiling: vk::ImageTiling,
+        usage: vk::ImageUsageFlags,
+        size: vk::DeviceSize,
+    ) -> Self {
+        let attachment_usage =
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+        if tiling == vk::ImageTiling::LINEAR {
+            AllocationHint::Mapped
+        } else if usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+            AllocationHint::LazilyAllocated
+        } else if usage.intersects(attachment_usage) && size >= DEDICATED_ALLOCATION_MIN_SIZE {
+            AllocationHint::Dedicated
+        } else {
+            AllocationHint::Pooled
+        }
+    }
+
+    pub fn to_vma(self) -> vk_mem::AllocationCreateInfo {
+        let (flags, usage) = match self {
+            AllocationHint::Dedicated => (
+                vk_mem::AllocationCreateFlags::DEDICATED_MEMORY,
+                vk_mem::MemoryUsage::AutoPreferDevice,
+            ),
+            AllocationHint::Pooled => (
+                vk_mem::AllocationCreateFlags::empty(),
+                vk_mem::MemoryUsage::AutoPreferDevice,
+            ),
+            AllocationHint::Mapped => (
+                vk_mem::AllocationCreateFlags::MAPPED
+                    | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+                vk_mem::MemoryUsage::Auto,
+            ),
+            AllocationHint::LazilyAllocated => (
+                vk_mem::AllocationCreateFlags::empty(),
+                vk_mem::MemoryUsage::GpuLazy,
+            ),
+            // The memory type is determined by the pool.
+            AllocationHint::Custom(_) => (
+                vk_mem::AllocationCreateFlags::empty(),
+                vk_mem::MemoryUsage::Unknown,
+            ),
+        };
+        vk_mem::AllocationCreateInfo {
+            flags,
+            usage,
+            priority: match self {
+                AllocationHint::Dedicated => 1.0,
+                _ => 0.5,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Copy, Clone)]
+pub enum LodClamp {
+    /// Clamp the max LOD to the last mip level of the image.
+    MipLevels,
+    /// Don't clamp the max LOD - all mip levels of the image view are accessible.
+    None,
+}
+
 #[derive(Hash, Eq, PartialEq, Copy, Clone)]
 pub struct SamplerInfo {
     pub min_filter: SamplerFilter,
@@ -77,6 +366,347 @@ pub struct SamplerInfo {
     pub compare_op: CompareOp,
     pub anisotropy: u32,
     pub mip_levels: u32,
+    /// The minimum mip level which can be accessed by the sampler.
+    pub min_lod: u32,
+    pub max_lod_clamp: LodClamp,
+    /// The bias added to the mip level selected by the hardware, in quarters of a level so
+    /// the info can be hashed - i.e. 2 biases by half a level. Positive values select smaller
+    /// mips. Clamped to the device limit by the sampler cache.
+    pub mip_lod_bias: i32,
     pub enable_compare: vk::Bool32,
     pub enable_anisotropy: vk::Bool32,
 }
+
+impl Default for SamplerInfo {
+    fn default() -> Self {
+        Self {
+            min_filter: SamplerFilter::Linear,
+            mag_filter: SamplerFilter::Linear,
+            addr_mode_u: SamplerAddressMode::ClampToEdge,
+            addr_mode_v: SamplerAddressMode::ClampToEdge,
+            addr_mode_w: SamplerAddressMode::ClampToEdge,
+            compare_op: CompareOp::Never,
+            anisotropy: 1,
+            mip_levels: 1,
+            min_lod: 0,
+            max_lod_clamp: LodClamp::MipLevels,
+            mip_lod_bias: 0,
+            enable_compare: vk::FALSE,
+            enable_anisotropy: vk::FALSE,
+        }
+    }
+}
+
+/// Presets for commonly used samplers. All mip levels of the image view are accessible
+/// with the presets, and samplers are cached as with any other sampler info.
+///
+/// # Examples
+///
+/// ```ignore
+/// let sampler_info = SamplerInfo::anisotropic(8);
+/// let texture = Texture::new(&driver, &info, vk::ImageUsageFlags::SAMPLED, Some(&sampler_info), &[])?;
+/// ```
+///
+impl SamplerInfo {
+    /// Nearest filtering, clamped to the edge - i.e. for pixel art or lookup textures.
+    pub fn point_clamp() -> Self {
+        Self::preset(SamplerFilter::Nearest, SamplerAddressMode::ClampToEdge)
+    }
+
+    /// Nearest filtering, repeated.
+    pub fn point_repeat() -> Self {
+        Self::preset(SamplerFilter::Nearest, SamplerAddressMode::Repeat)
+    }
+
+    /// Linear filtering, clamped to the edge - i.e. for render targets and UI.
+    pub fn linear_clamp() -> Self {
+        Self::preset(SamplerFilter::Linear, SamplerAddressMode::ClampToEdge)
+    }
+
+    /// Linear filtering, repeated - suitable for most material textures.
+    pub fn linear_repeat() -> Self {
+        Self::preset(SamplerFilter::Linear, SamplerAddressMode::Repeat)
+    }
+
+    /// Linear filtering, repeated, with the specified max anisotropy. Anisotropy is disabled
+    /// by the sampler cache if not supported by the device, and otherwise clamped to the
+    /// device limit.
+    pub fn anisotropic(level: u32) -> Self {
+        Self {
+            anisotropy: level.max(1),
+            enable_anisotropy: vk::TRUE,
+            ..Self::linear_repeat()
+        }
+    }
+
+    /// A comparison sampler for shadow maps, returning the result of comparing the reference
+    /// value against the depth texture with the op, rather than the depth itself. The depth
+    /// aspect of the texture must be bound - see `Texture::sampled_view`.
+    ///
+    /// Linear filtering is used, so the hardware averages the results of the comparisons of
+    /// the surrounding texels (2x2 PCF). This requires the depth format to support
+    /// `SAMPLED_IMAGE_FILTER_LINEAR` - otherwise the filter must be nearest and the
+    /// filtering performed in the shader.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let sampler_info = SamplerInfo::shadow_comparison(CompareOp::LessOrEqual);
+    /// let shadow_map = Texture::new(
+    ///     &driver,
+    ///     &info,
+    ///     vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+    ///     Some(&sampler_info),
+    ///     &[],
+    /// )?;
+    /// ```
+    ///
+    pub fn shadow_comparison(op: CompareOp) -> Self {
+        Self {
+            compare_op: op,
+            enable_compare: vk::TRUE,
+            ..Self::linear_clamp()
+        }
+    }
+
+    /// Set the address mode of all three dimensions.
+    pub fn address_mode(mut self, mode: SamplerAddressMode) -> Self {
+        self.addr_mode_u = mode;
+        self.addr_mode_v = mode;
+        self.addr_mode_w = mode;
+        self
+    }
+
+    fn preset(filter: SamplerFilter, addr_mode: SamplerAddressMode) -> Self {
+        Self {
+            min_filter: filter,
+            mag_filter: filter,
+            max_lod_clamp: LodClamp::None,
+            ..Default::default()
+        }
+        .address_mode(addr_mode)
+    }
+
+    /// The max LOD value for the sampler. For N mip levels, the highest accessible LOD is N - 1.
+    pub fn max_lod(&self) -> f32 {
+        match self.max_lod_clamp {
+            LodClamp::MipLevels => self.mip_levels.saturating_sub(1) as f32,
+            LodClamp::None => vk::LOD_CLAMP_NONE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: vk::Extent2D = vk::Extent2D {
+        width: 1280,
+        height: 720,
+    };
+
+    #[test]
+    fn viewport_covers_extent() {
+        let viewport = viewport(EXTENT, false);
+        assert_eq!((viewport.x, viewport.y), (0.0, 0.0));
+        assert_eq!((viewport.width, viewport.height), (1280.0, 720.0));
+        assert_eq!((viewport.min_depth, viewport.max_depth), (0.0, 1.0));
+    }
+
+    #[test]
+    fn flipped_viewport_starts_at_bottom() {
+        let viewport = viewport(EXTENT, true);
+        assert_eq!(viewport.y, 720.0);
+        assert_eq!(viewport.height, -720.0);
+        assert_eq!(viewport.width, 1280.0);
+        // The scissor is unaffected by the flip.
+        assert_eq!(scissor(EXTENT).extent, EXTENT);
+    }
+
+    #[test]
+    fn small_attachments_are_pooled() {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let hint = |size| AllocationHint::default_for_image(vk::ImageTiling::OPTIMAL, usage, size);
+        assert_eq!(hint(64 * 64 * 4), AllocationHint::Pooled);
+        assert_eq!(hint(1920 * 1080 * 4), AllocationHint::Dedicated);
+    }
+
+    #[test]
+    fn default_hint_follows_tiling_and_usage() {
+        let size = 1920 * 1080 * 4;
+        assert_eq!(
+            AllocationHint::default_for_image(
+                vk::ImageTiling::LINEAR,
+                vk::ImageUsageFlags::SAMPLED,
+                size
+            ),
+            AllocationHint::Mapped
+        );
+        assert_eq!(
+            AllocationHint::default_for_image(
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                size
+            ),
+            AllocationHint::LazilyAllocated
+        );
+        assert_eq!(
+            AllocationHint::default_for_image(
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::SAMPLED,
+                size
+            ),
+            AllocationHint::Pooled
+        );
+    }
+
+    #[test]
+    fn to_vma_maps_each_hint() {
+        let dedicated = AllocationHint::Dedicated.to_vma();
+        assert!(
+            dedicated
+                .flags
+                .contains(vk_mem::AllocationCreateFlags::DEDICATED_MEMORY)
+        );
+        assert_eq!(dedicated.priority, 1.0);
+
+        let pooled = AllocationHint::Pooled.to_vma();
+        assert!(pooled.flags.is_empty());
+        assert!(matches!(
+            pooled.usage,
+            vk_mem::MemoryUsage::AutoPreferDevice
+        ));
+        assert_eq!(pooled.priority, 0.5);
+
+        let mapped = AllocationHint::Mapped.to_vma();
+        assert!(mapped.flags.contains(
+            vk_mem::AllocationCreateFlags::MAPPED
+                | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+        ));
+
+        assert!(matches!(
+            AllocationHint::LazilyAllocated.to_vma().usage,
+            vk_mem::MemoryUsage::GpuLazy
+        ));
+    }
+
+    #[test]
+    fn sampler_presets_set_filter_and_address_mode() {
+        let point = SamplerInfo::point_repeat();
+        assert!(point.min_filter == SamplerFilter::Nearest);
+        assert!(point.mag_filter == SamplerFilter::Nearest);
+        assert!(
+            [point.addr_mode_u, point.addr_mode_v, point.addr_mode_w]
+                .iter()
+                .all(|mode| *mode == SamplerAddressMode::Repeat)
+        );
+
+        let linear = SamplerInfo::linear_clamp();
+        assert!(linear.min_filter == SamplerFilter::Linear);
+        assert!(linear.addr_mode_w == SamplerAddressMode::ClampToEdge);
+    }
+
+    #[test]
+    fn sampler_presets_access_all_mip_levels() {
+        let info = SamplerInfo {
+            mip_levels: 8,
+            ..SamplerInfo::linear_repeat()
+        };
+        assert_eq!(info.max_lod(), vk::LOD_CLAMP_NONE);
+        assert_eq!(SamplerInfo::default().max_lod(), 0.0);
+    }
+
+    #[test]
+    fn anisotropic_preset_enables_anisotropy() {
+        let info = SamplerInfo::anisotropic(8);
+        assert_eq!(info.anisotropy, 8);
+        assert_eq!(info.enable_anisotropy, vk::TRUE);
+        assert!(info.addr_mode_u == SamplerAddressMode::Repeat);
+        assert_eq!(SamplerInfo::anisotropic(0).anisotropy, 1);
+    }
+
+    #[test]
+    fn shadow_comparison_preset_enables_compare() {
+        let info = SamplerInfo::shadow_comparison(CompareOp::LessOrEqual);
+        assert_eq!(info.enable_compare, vk::TRUE);
+        assert!(info.compare_op == CompareOp::LessOrEqual);
+        assert!(info.min_filter == SamplerFilter::Linear);
+        assert!(info.addr_mode_u == SamplerAddressMode::ClampToEdge);
+    }
+
+    #[test]
+    fn polygon_mode_to_vk() {
+        assert_eq!(PolygonMode::default().to_vk(), vk::PolygonMode::FILL);
+        assert_eq!(PolygonMode::Line.to_vk(), vk::PolygonMode::LINE);
+        assert_eq!(PolygonMode::Point.to_vk(), vk::PolygonMode::POINT);
+    }
+
+    #[test]
+    fn cull_mode_to_vk() {
+        assert_eq!(CullMode::default().to_vk(), vk::CullModeFlags::BACK);
+        assert_eq!(CullMode::None.to_vk(), vk::CullModeFlags::NONE);
+        assert_eq!(CullMode::Front.to_vk(), vk::CullModeFlags::FRONT);
+        assert_eq!(
+            CullMode::FrontAndBack.to_vk(),
+            vk::CullModeFlags::FRONT_AND_BACK
+        );
+    }
+
+    #[test]
+    fn front_face_to_vk() {
+        assert_eq!(
+            FrontFace::default().to_vk(),
+            vk::FrontFace::COUNTER_CLOCKWISE
+        );
+        assert_eq!(FrontFace::Clockwise.to_vk(), vk::FrontFace::CLOCKWISE);
+    }
+
+    #[test]
+    fn opaque_blend_mode_disables_blending() {
+        let state = BlendMode::default().to_vk();
+        assert_eq!(state.blend_enable, vk::FALSE);
+        assert_eq!(state.color_write_mask, vk::ColorComponentFlags::RGBA);
+    }
+
+    #[test]
+    fn blend_modes_to_vk() {
+        let factors = |mode: BlendMode| {
+            let state = mode.to_vk();
+            assert_eq!(state.blend_enable, vk::TRUE);
+            assert_eq!(state.color_write_mask, vk::ColorComponentFlags::RGBA);
+            (
+                state.src_color_blend_factor,
+                state.dst_color_blend_factor,
+                state.src_alpha_blend_factor,
+                state.dst_alpha_blend_factor,
+            )
+        };
+        assert_eq!(
+            factors(BlendMode::AlphaBlend),
+            (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            )
+        );
+        assert_eq!(
+            factors(BlendMode::Additive),
+            (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+            )
+        );
+        assert_eq!(
+            factors(BlendMode::Multiply),
+            (
+                vk::BlendFactor::DST_COLOR,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::DST_ALPHA,
+                vk::BlendFactor::ZERO,
+            )
+        );
+    }
+}
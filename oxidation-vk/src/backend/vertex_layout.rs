@@ -0,0 +1,225 @@
+use crate::error::OxidationError;
+use ash::vk;
+
+/// The packing rules used when computing the offsets of vertex attributes.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum VertexPacking {
+    /// Attributes are tightly packed with no padding between them.
+    #[default]
+    Tight,
+    /// Attributes follow `std140`-style alignment rules - two component types are
+    /// aligned to twice the component size, three and four component types to
+    /// four times the component size. The stride is rounded up to the largest alignment.
+    Std140,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub format: vk::Format,
+    pub offset: u32,
+}
+
+/// Describes the layout of a single interleaved vertex buffer binding.
+/// Attributes are added in shader location order with the offsets and
+/// stride computed automatically based upon the packing rules.
+///
+/// # Examples
+///
+/// ```
+/// use ash::vk;
+/// use oxidation_vk::backend::VertexLayout;
+///
+/// let layout = VertexLayout::new(0)
+///     .add_attribute(vk::Format::R32G32B32_SFLOAT)?
+///     .add_attribute(vk::Format::R32G32_SFLOAT)?
+///     .add_attribute(vk::Format::R32G32B32A32_SFLOAT)?;
+/// assert_eq!(layout.stride(), 36);
+/// # Ok::<(), oxidation_vk::error::OxidationError>(())
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct VertexLayout {
+    binding: u32,
+    input_rate: vk::VertexInputRate,
+    packing: VertexPacking,
+    attributes: Vec<VertexAttribute>,
+    /// The largest alignment of all attributes - used for padding the stride.
+    max_alignment: u32,
+    stride: u32,
+}
+
+impl VertexLayout {
+    /// Create a new empty vertex layout for the specified binding.
+    pub fn new(binding: u32) -> Self {
+        Self {
+            binding,
+            input_rate: vk::VertexInputRate::VERTEX,
+            packing: VertexPacking::Tight,
+            attributes: Vec::new(),
+            max_alignment: 1,
+            stride: 0,
+        }
+    }
+
+    /// Set the packing rules for the layout. This must be set before
+    /// any attributes are added.
+    pub fn packing(mut self, packing: VertexPacking) -> Self {
+        assert!(
+            self.attributes.is_empty(),
+            "Vertex packing must be set before adding attributes."
+        );
+        self.packing = packing;
+        self
+    }
+
+    /// Set whether the binding is advanced per vertex or per instance.
+    pub fn input_rate(mut self, input_rate: vk::VertexInputRate) -> Self {
+        self.input_rate = input_rate;
+        self
+    }
+
+    /// Add a new attribute to the layout. The shader location is the index of the
+    /// attribute within the layout.
+    ///
+    /// Returns an error if the format isn't supported as a vertex attribute.
+    pub fn add_attribute(mut self, format: vk::Format) -> Result<Self, OxidationError> {
+        let (component_count, component_size) = format_component_info(format).ok_or_else(|| {
+            OxidationError::Unsupported(format!(
+                "{format:?} is not supported as a vertex attribute format."
+            ))
+        })?;
+
+        let alignment = match self.packing {
+            VertexPacking::Tight => 1,
+            VertexPacking::Std140 => match component_count {
+                1 => component_size,
+                2 => 2 * component_size,
+                _ => 4 * component_size,
+            },
+        };
+        let offset = self.stride.next_multiple_of(alignment);
+
+        self.attributes.push(VertexAttribute {
+            location: self.attributes.len() as u32,
+            format,
+            offset,
+        });
+        self.max_alignment = self.max_alignment.max(alignment);
+        self.stride = offset + component_count * component_size;
+        Ok(self)
+    }
+
+    /// The stride, in bytes, of a single vertex.
+    pub fn stride(&self) -> u32 {
+        self.stride.next_multiple_of(self.max_alignment)
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn binding_description(&self) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(self.binding)
+            .stride(self.stride())
+            .input_rate(self.input_rate)
+    }
+
+    pub fn attribute_descriptions(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .map(|attr| {
+                vk::VertexInputAttributeDescription::default()
+                    .binding(self.binding)
+                    .location(attr.location)
+                    .format(attr.format)
+                    .offset(attr.offset)
+            })
+            .collect()
+    }
+}
+
+/// Get the number of components and the size, in bytes, of each component
+/// for the formats which are supported as vertex attributes.
+fn format_component_info(format: vk::Format) -> Option<(u32, u32)> {
+    let info = match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SINT => {
+            (1, 1)
+        }
+        vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8_SINT => (2, 1),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R8G8B8A8_SINT => (4, 1),
+        vk::Format::R16_SFLOAT | vk::Format::R16_UINT | vk::Format::R16_SINT => (1, 2),
+        vk::Format::R16G16_SFLOAT | vk::Format::R16G16_UINT | vk::Format::R16G16_SINT => (2, 2),
+        vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R16G16B16A16_SINT => (4, 2),
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT => (1, 4),
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT | vk::Format::R32G32_SINT => (2, 4),
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT => {
+            (3, 4)
+        }
+        vk::Format::R32G32B32A32_SFLOAT
+        | vk::Format::R32G32B32A32_UINT
+        | vk::Format::R32G32B32A32_SINT => (4, 4),
+        _ => return None,
+    };
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec3_vec2_vec4(packing: VertexPacking) -> VertexLayout {
+        VertexLayout::new(0)
+            .packing(packing)
+            .add_attribute(vk::Format::R32G32B32_SFLOAT)
+            .and_then(|layout| layout.add_attribute(vk::Format::R32G32_SFLOAT))
+            .and_then(|layout| layout.add_attribute(vk::Format::R32G32B32A32_SFLOAT))
+            .unwrap()
+    }
+
+    fn offsets(layout: &VertexLayout) -> Vec<u32> {
+        layout.attributes().iter().map(|attr| attr.offset).collect()
+    }
+
+    #[test]
+    fn tight_packing_has_no_padding() {
+        let layout = vec3_vec2_vec4(VertexPacking::Tight);
+        assert_eq!(offsets(&layout), [0, 12, 20]);
+        assert_eq!(layout.stride(), 36);
+    }
+
+    #[test]
+    fn std140_packing_aligns_attributes_and_stride() {
+        let layout = vec3_vec2_vec4(VertexPacking::Std140);
+        // The vec2 follows the vec3 at an 8 byte boundary, the vec4 at a 16 byte boundary.
+        assert_eq!(offsets(&layout), [0, 16, 32]);
+        assert_eq!(layout.stride(), 48);
+        assert_eq!(layout.binding_description().stride, 48);
+    }
+
+    #[test]
+    fn attribute_locations_follow_insertion_order() {
+        let layout = vec3_vec2_vec4(VertexPacking::Tight);
+        let locations: Vec<u32> = layout
+            .attribute_descriptions()
+            .iter()
+            .map(|attr| attr.location)
+            .collect();
+        assert_eq!(locations, [0, 1, 2]);
+    }
+
+    #[test]
+    fn unsupported_format_is_an_error() {
+        let res = VertexLayout::new(0).add_attribute(vk::Format::BC1_RGB_UNORM_BLOCK);
+        assert!(matches!(res, Err(OxidationError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::device::ContextDevice;
+use ash::vk;
+
+/// The access mask required for the src half of a barrier transitioning from the specified layout.
+pub(crate) fn src_access_flags(layout: vk::ImageLayout) -> vk::AccessFlags2 {
+    match layout {
+        vk::ImageLayout::UNDEFINED => vk::AccessFlags2::empty(),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::AccessFlags2::TRANSFER_READ,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags2::TRANSFER_WRITE,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::AccessFlags2::SHADER_SAMPLED_READ,
+        vk::ImageLayout::GENERAL => vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL => {
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+        }
+        _ => vk::AccessFlags2::empty(),
+    }
+}
+
+/// The access mask required for the dst half of a barrier transitioning to the specified layout.
+pub(crate) fn dst_access_flags(layout: vk::ImageLayout) -> vk::AccessFlags2 {
+    match layout {
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::AccessFlags2::TRANSFER_READ,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags2::TRANSFER_WRITE,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::AccessFlags2::SHADER_SAMPLED_READ,
+        vk::ImageLayout::GENERAL => {
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE
+        }
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL => {
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+        }
+        vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL | vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL => {
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags2::SHADER_SAMPLED_READ
+        }
+        _ => vk::AccessFlags2::empty(),
+    }
+}
+
+/// The pipeline stages at which an image in the specified layout is usually accessed.
+pub(crate) fn layout_stage_flags(layout: vk::ImageLayout) -> vk::PipelineStageFlags2 {
+    match layout {
+        vk::ImageLayout::UNDEFINED => vk::PipelineStageFlags2::NONE,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL | vk::ImageLayout::TRANSFER_DST_OPTIMAL => {
+            vk::PipelineStageFlags2::ALL_TRANSFER
+        }
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => {
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+        }
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL => {
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+        }
+        vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL | vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL => {
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER
+        }
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        _ => vk::PipelineStageFlags2::ALL_COMMANDS,
+    }
+}
+
+/// Convert sync2 stage flags to the legacy equivalent. The legacy flags share the bit
+/// values of the lower 32-bits; the finer grained sync2 stages map to their legacy parent.
+pub(crate) fn to_legacy_stage_flags(flags: vk::PipelineStageFlags2) -> vk::PipelineStageFlags {
+    let mut legacy = vk::PipelineStageFlags::from_raw(flags.as_raw() as u32);
+    if flags.intersects(
+        vk::PipelineStageFlags2::COPY
+            | vk::PipelineStageFlags2::BLIT
+            | vk::PipelineStageFlags2::RESOLVE
+            | vk::PipelineStageFlags2::CLEAR,
+    ) {
+        legacy |= vk::PipelineStageFlags::TRANSFER;
+    }
+    if flags.intersects(
+        vk::PipelineStageFlags2::INDEX_INPUT | vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+    ) {
+        legacy |= vk::PipelineStageFlags::VERTEX_INPUT;
+    }
+    if flags.intersects(vk::PipelineStageFlags2::PRE_RASTERIZATION_SHADERS) {
+        legacy |= vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::TESSELLATION_CONTROL_SHADER
+            | vk::PipelineStageFlags::TESSELLATION_EVALUATION_SHADER
+            | vk::PipelineStageFlags::GEOMETRY_SHADER;
+    }
+    // An empty stage mask is valid with sync2 but not for the legacy barrier.
+    if legacy.is_empty() {
+        legacy = vk::PipelineStageFlags::TOP_OF_PIPE;
+    }
+    legacy
+}
+
+/// Convert sync2 access flags to the legacy equivalent. As with the stage flags, the
+/// finer grained shader read/write flags map to their legacy parent.
+pub(crate) fn to_legacy_access_flags(flags: vk::AccessFlags2) -> vk::AccessFlags {
+    let mut legacy = vk::AccessFlags::from_raw(flags.as_raw() as u32);
+    if flags
+        .intersects(vk::AccessFlags2::SHADER_SAMPLED_READ | vk::AccessFlags2::SHADER_STORAGE_READ)
+    {
+        legacy |= vk::AccessFlags::SHADER_READ;
+    }
+    if flags.intersects(vk::AccessFlags2::SHADER_STORAGE_WRITE) {
+        legacy |= vk::AccessFlags::SHADER_WRITE;
+    }
+    legacy
+}
+
+/// Record the image barriers into the command buffer. If `VK_KHR_synchronization2` is enabled
+/// on the device `vkCmdPipelineBarrier2` is used, otherwise the barriers are converted to their
+/// legacy equivalent, with the stage masks of all barriers combined.
+pub fn cmd_image_barriers(
+    device: &ContextDevice,
+    cmds: vk::CommandBuffer,
+    barriers: &[vk::ImageMemoryBarrier2],
+) {
+    cmd_barriers(device, cmds, barriers, &[]);
+}
+
+/// Record the image and buffer barriers into the command buffer with a single pipeline barrier
+/// - see [`cmd_image_barriers`] for how devices without `VK_KHR_synchronization2` are handled.
+pub fn cmd_barriers(
+    device: &ContextDevice,
+    cmds: vk::CommandBuffer,
+    image_barriers: &[vk::ImageMemoryBarrier2],
+    buffer_barriers: &[vk::BufferMemoryBarrier2],
+) {
+    if image_barriers.is_empty() && buffer_barriers.is_empty() {
+        return;
+    }
+
+    if device.enabled_features().synchronization2 {
+        let dep_info = vk::DependencyInfo::default()
+            .image_memory_barriers(image_barriers)
+            .buffer_memory_barriers(buffer_barriers);
+        unsafe { device.device.cmd_pipeline_barrier2(cmds, &dep_info) };
+        return;
+    }
+
+    let mut src_stage_flags = vk::PipelineStageFlags2::empty();
+    let mut dst_stage_flags = vk::PipelineStageFlags2::empty();
+    let legacy_image_barriers: Vec<vk::ImageMemoryBarrier> = image_barriers
+        .iter()
+        .map(|barrier| {
+            src_stage_flags |= barrier.src_stage_mask;
+            dst_stage_flags |= barrier.dst_stage_mask;
+            vk::ImageMemoryBarrier::default()
+                .image(barrier.image)
+                .old_layout(barrier.old_layout)
+                .new_layout(barrier.new_layout)
+                .subresource_range(barrier.subresource_range)
+                .src_queue_family_index(barrier.src_queue_family_index)
+                .dst_queue_family_index(barrier.dst_queue_family_index)
+                .src_access_mask(to_legacy_access_flags(barrier.src_access_mask))
+                .dst_access_mask(to_legacy_access_flags(barrier.dst_access_mask))
+        })
+        .collect();
+    let legacy_buffer_barriers: Vec<vk::BufferMemoryBarrier> = buffer_barriers
+        .iter()
+        .map(|barrier| {
+            src_stage_flags |= barrier.src_stage_mask;
+            dst_stage_flags |= barrier.dst_stage_mask;
+            vk::BufferMemoryBarrier::default()
+                .buffer(barrier.buffer)
+                .offset(barrier.offset)
+                .size(barrier.size)
+                .src_queue_family_index(barrier.src_queue_family_index)
+                .dst_queue_family_index(barrier.dst_queue_family_index)
+                .src_access_mask(to_legacy_access_flags(barrier.src_access_mask))
+                .dst_access_mask(to_legacy_access_flags(barrier.dst_access_mask))
+        })
+        .collect();
+
+    unsafe {
+        device.device.cmd_pipeline_barrier(
+            cmds,
+            to_legacy_stage_flags(src_stage_flags),
+            to_legacy_stage_flags(dst_stage_flags),
+            vk::DependencyFlags::empty(),
+            &[],
+            &legacy_buffer_barriers,
+            &legacy_image_barriers,
+        )
+    };
+}
+
+/// Collects the image and buffer barriers required before a pass, so they're recorded with a
+/// single pipeline barrier rather than one per resource - i.e. a pass which reads the outputs
+/// of several earlier passes.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut batch = BarrierBatch::new();
+/// for texture in &pass_inputs {
+///     batch.add_image(read_barrier(texture));
+/// }
+/// batch.add_buffer(indirect_args_barrier);
+/// // A single vkCmdPipelineBarrier(2) for all of the inputs.
+/// batch.record(&driver.device, cmds);
+/// ```
+///
+#[derive(Default)]
+pub struct BarrierBatch {
+    image_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+    buffer_barriers: Vec<vk::BufferMemoryBarrier2<'static>>,
+}
+
+impl BarrierBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_image(&mut self, barrier: vk::ImageMemoryBarrier2<'static>) {
+        self.image_barriers.push(barrier);
+    }
+
+    pub fn add_buffer(&mut self, barrier: vk::BufferMemoryBarrier2<'static>) {
+        self.buffer_barriers.push(barrier);
+    }
+
+    /// The number of barriers in the batch.
+    pub fn len(&self) -> usize {
+        self.image_barriers.len() + self.buffer_barriers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record all of the barriers with a single pipeline barrier, leaving the batch empty for
+    /// reuse. Nothing is recorded if the batch is empty.
+    pub fn record(&mut self, device: &ContextDevice, cmds: vk::CommandBuffer) {
+        self.record_with(|image_barriers, buffer_barriers| {
+            cmd_barriers(device, cmds, image_barriers, buffer_barriers)
+        });
+    }
+
+    /// Pass the batched barriers to `record` and clear the batch, keeping its capacity.
+    fn record_with(
+        &mut self,
+        record: impl FnOnce(&[vk::ImageMemoryBarrier2], &[vk::BufferMemoryBarrier2]),
+    ) {
+        record(&self.image_barriers, &self.buffer_barriers);
+        self.image_barriers.clear();
+        self.buffer_barriers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barrier_batch_counts_and_clears_after_recording() {
+        let mut batch = BarrierBatch::new();
+        assert!(batch.is_empty());
+        batch.add_image(vk::ImageMemoryBarrier2::default());
+        batch.add_image(vk::ImageMemoryBarrier2::default());
+        batch.add_buffer(vk::BufferMemoryBarrier2::default());
+        assert_eq!(batch.len(), 3);
+        assert!(!batch.is_empty());
+
+        let mut recorded = (0, 0);
+        batch.record_with(|images, buffers| recorded = (images.len(), buffers.len()));
+        assert_eq!(recorded, (2, 1));
+        assert!(batch.is_empty());
+
+        // The batch can be reused after recording.
+        batch.add_buffer(vk::BufferMemoryBarrier2::default());
+        batch.record_with(|images, buffers| recorded = (images.len(), buffers.len()));
+        assert_eq!(recorded, (0, 1));
+    }
+
+    #[test]
+    fn legacy_stage_flags_merge_sync2_stages() {
+        assert_eq!(
+            to_legacy_stage_flags(
+                vk::PipelineStageFlags2::COPY | vk::PipelineStageFlags2::FRAGMENT_SHADER
+            ),
+            vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+        assert_eq!(
+            to_legacy_stage_flags(
+                vk::PipelineStageFlags2::INDEX_INPUT
+                    | vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT
+            ),
+            vk::PipelineStageFlags::VERTEX_INPUT
+        );
+        assert!(
+            to_legacy_stage_flags(vk::PipelineStageFlags2::PRE_RASTERIZATION_SHADERS).contains(
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::GEOMETRY_SHADER
+            )
+        );
+    }
+
+    #[test]
+    fn empty_stage_flags_become_top_of_pipe() {
+        assert_eq!(
+            to_legacy_stage_flags(vk::PipelineStageFlags2::NONE),
+            vk::PipelineStageFlags::TOP_OF_PIPE
+        );
+    }
+}
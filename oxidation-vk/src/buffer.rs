@@ -0,0 +1,384 @@
+use crate::Driver;
+use crate::backend::AllocationHint;
+use crate::barrier;
+use crate::device;
+use crate::host_memory;
+use crate::vk_check;
+use ash::prelude::VkResult;
+use ash::vk;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// A buffer encompasses a Vulkan buffer object along with its memory allocation.
+///
+/// # Examples
+///
+/// ```ignore
+/// use ash::vk;
+/// use oxidation_vk::backend::AllocationHint;
+/// use oxidation_vk::buffer::Buffer;
+///
+/// let buffer = Buffer::new(&driver, 1024, vk::BufferUsageFlags::VERTEX_BUFFER, AllocationHint::Pooled, &[])?;
+/// ```
+///
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    pub size: vk::DeviceSize,
+    pub usage: vk::BufferUsageFlags,
+    pub memory: vk_mem::Allocation,
+    sharing_mode: vk::SharingMode,
+    /// The queue families the buffer is shared between - empty for exclusive sharing.
+    queue_families: Vec<u32>,
+    /// Texel buffer views over this buffer, keyed by their format, offset and range.
+    texel_views: HashMap<(vk::Format, vk::DeviceSize, vk::DeviceSize), vk::BufferView>,
+}
+
+impl Buffer {
+    /// Create a new buffer of the specified size, with the memory allocated as described by the
+    /// allocation hint. If the buffer will be accessed by more than one queue family, these can
+    /// be specified with `shared_queues` - see [`device::queue_sharing`].
+    pub fn new(
+        driver: &Driver,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        alloc_hint: AllocationHint,
+        shared_queues: &[u32],
+    ) -> Result<Self, Box<dyn Error>> {
+        let (sharing_mode, queue_family_indices) = device::queue_sharing(shared_queues);
+        // Descriptor buffers refer to uniform and storage buffers by their device address.
+        let mut usage = usage;
+        if driver.device.descriptor_buffer.is_some()
+            && usage.intersects(
+                vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            )
+        {
+            usage |= vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+        }
+        let create_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(sharing_mode)
+            .queue_family_indices(&queue_family_indices);
+        let alloc_info = alloc_hint.to_vma();
+
+        let (buffer, memory) = driver.with_allocator(Some(alloc_hint), |vma_alloc| unsafe {
+            vk_check!(
+                vma_alloc.create_buffer(&create_info, &alloc_info),
+                "vmaCreateBuffer"
+            )
+        })??;
+
+        Ok(Self {
+            buffer,
+            size,
+            usage,
+            memory,
+            sharing_mode,
+            queue_families: queue_family_indices,
+            texel_views: HashMap::new(),
+        })
+    }
+
+    /// Create a device local buffer holding the data, which is copied through a temporary
+    /// staging buffer. Blocks until the copy has completed, after which the data is visible
+    /// to all later commands.
+    pub fn with_data(
+        driver: &Driver,
+        data: &[u8],
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self, Box<dyn Error>> {
+        let size = data.len() as vk::DeviceSize;
+        let mut stage = Buffer::new(
+            driver,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            AllocationHint::Mapped,
+            &[],
+        )?;
+        // Transfer src usage allows the buffer to be relocated by defragmentation.
+        let mut buffer = match Buffer::new(
+            driver,
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::TRANSFER_SRC,
+            AllocationHint::Pooled,
+            &[],
+        ) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                stage.destroy(driver);
+                return Err(err);
+            }
+        };
+
+        let res = stage.upload(driver, data, &buffer);
+        // The copy has completed (or failed) at this point, so the stage is no longer in use.
+        stage.destroy(driver);
+        if let Err(err) = res {
+            buffer.destroy(driver);
+            return Err(err);
+        }
+        Ok(buffer)
+    }
+
+    /// Write the data to the buffer at the offset through its persistent mapping - the buffer
+    /// must have been created with [`AllocationHint::Mapped`]. Non-coherent memory is
+    /// flushed, so the data is visible to the device.
+    pub fn write(
+        &self,
+        driver: &Driver,
+        offset: vk::DeviceSize,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let mapped = self.mapped_range(driver, offset, data.len())?;
+        unsafe { mapped.copy_from_nonoverlapping(data.as_ptr(), data.len()) };
+        host_memory::flush_mapped(driver, &self.memory, offset, data.len() as vk::DeviceSize)?;
+        Ok(())
+    }
+
+    /// Read the contents of the buffer at the offset through its persistent mapping, i.e.
+    /// readback of device writes once they have completed. Non-coherent memory is
+    /// invalidated first, so the device writes are visible to the host.
+    pub fn read(
+        &self,
+        driver: &Driver,
+        offset: vk::DeviceSize,
+        dst: &mut [u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let mapped = self.mapped_range(driver, offset, dst.len())?;
+        host_memory::invalidate_mapped(driver, &self.memory, offset, dst.len() as vk::DeviceSize)?;
+        unsafe { mapped.copy_to_nonoverlapping(dst.as_mut_ptr(), dst.len()) };
+        Ok(())
+    }
+
+    /// The mapped pointer to the range of the buffer.
+    fn mapped_range(
+        &self,
+        driver: &Driver,
+        offset: vk::DeviceSize,
+        len: usize,
+    ) -> Result<*mut u8, Box<dyn Error>> {
+        if offset + len as vk::DeviceSize > self.size {
+            return Err(Box::from(format!(
+                "Range of {len} bytes at offset {offset} is out of bounds of the buffer of {} bytes.",
+                self.size
+            )));
+        }
+        let mapped = driver
+            .allocator()
+            .get_allocation_info(&self.memory)
+            .mapped_data as *mut u8;
+        if mapped.is_null() {
+            return Err(Box::from(
+                "The buffer isn't mapped - it must be created with AllocationHint::Mapped.",
+            ));
+        }
+        Ok(unsafe { mapped.add(offset as usize) })
+    }
+
+    /// Write the data to this (mapped) buffer and copy it to the destination buffer.
+    fn upload(&self, driver: &Driver, data: &[u8], dst: &Buffer) -> Result<(), Box<dyn Error>> {
+        let size = data.len() as vk::DeviceSize;
+        self.write(driver, 0, data)?;
+
+        let region = vk::BufferCopy::default().size(size);
+        let barrier = vk::BufferMemoryBarrier2::default()
+            .buffer(dst.buffer)
+            .offset(0)
+            .size(size)
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED);
+        driver.submit_immediate(|cmds| {
+            unsafe {
+                driver
+                    .device
+                    .device
+                    .cmd_copy_buffer(cmds, self.buffer, dst.buffer, &[region])
+            };
+            barrier::cmd_barriers(&driver.device, cmds, &[], &[barrier]);
+        })?;
+        Ok(())
+    }
+
+    /// A texel buffer view of the buffer, interpreting the range as formatted texels for
+    /// access as a uniform or storage texel buffer. Views are cached, so requesting the same
+    /// view again returns the existing view. Views are destroyed along with the buffer.
+    ///
+    /// Returns an error if the buffer wasn't created with texel buffer usage, if the format
+    /// isn't supported for the texel buffer usage(s) of the buffer, or if the range is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut particles = Buffer::new(
+    ///     &driver,
+    ///     size,
+    ///     vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER,
+    ///     AllocationHint::Pooled,
+    ///     &[],
+    /// )?;
+    /// let view = particles.create_texel_view(&driver, vk::Format::R32G32B32A32_SFLOAT, 0, vk::WHOLE_SIZE)?;
+    /// ```
+    ///
+    pub fn create_texel_view(
+        &mut self,
+        driver: &Driver,
+        format: vk::Format,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Result<vk::BufferView, Box<dyn Error>> {
+        if let Some(view) = self.texel_views.get(&(format, offset, range)) {
+            return Ok(*view);
+        }
+
+        let texel_usage =
+            vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER | vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER;
+        if !self.usage.intersects(texel_usage) {
+            return Err(Box::from(
+                "Texel views require the buffer to have uniform or storage texel buffer usage.",
+            ));
+        }
+
+        // The format must support all of the texel buffer usages of the buffer.
+        let format_features = unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_format_properties(driver.device.physical_device, format)
+                .buffer_features
+        };
+        let mut required_features = vk::FormatFeatureFlags::empty();
+        if self
+            .usage
+            .contains(vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER)
+        {
+            required_features |= vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER;
+        }
+        if self
+            .usage
+            .contains(vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER)
+        {
+            required_features |= vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER;
+        }
+        if !format_features.contains(required_features) {
+            return Err(Box::from(format!(
+                "Format {format:?} is not supported for texel buffers with usage {:?}.",
+                self.usage & texel_usage
+            )));
+        }
+
+        let alignment = driver.device.limits.min_texel_buffer_offset_alignment;
+        if !offset.is_multiple_of(alignment) {
+            return Err(Box::from(format!(
+                "Texel view offset {offset} must be a multiple of {alignment}."
+            )));
+        }
+        if offset >= self.size || (range != vk::WHOLE_SIZE && offset + range > self.size) {
+            return Err(Box::from(format!(
+                "Texel view range (offset: {offset}, range: {range}) exceeds the buffer size of {}.",
+                self.size
+            )));
+        }
+
+        let create_info = vk::BufferViewCreateInfo::default()
+            .buffer(self.buffer)
+            .format(format)
+            .offset(offset)
+            .range(range);
+        let view = unsafe {
+            driver
+                .device
+                .device
+                .create_buffer_view(&create_info, None)?
+        };
+        self.texel_views.insert((format, offset, range), view);
+        Ok(view)
+    }
+
+    /// Whether the buffer can be relocated by defragmentation - the contents are copied, so
+    /// transfer usage is required, and mapped buffers are excluded as their pointers may be
+    /// held by the user.
+    pub(crate) fn is_relocatable(&self, driver: &Driver) -> bool {
+        self.usage
+            .contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+            && driver
+                .vma_allocator
+                .get_allocation_info(&self.memory)
+                .mapped_data
+                .is_null()
+    }
+
+    /// Create a buffer, not yet bound to memory, with the same parameters as this buffer.
+    pub(crate) fn create_unbound_buffer(&self, device: &ash::Device) -> VkResult<vk::Buffer> {
+        let create_info = vk::BufferCreateInfo::default()
+            .size(self.size)
+            .usage(self.usage)
+            .sharing_mode(self.sharing_mode)
+            .queue_family_indices(&self.queue_families);
+        unsafe { device.create_buffer(&create_info, None) }
+    }
+
+    /// Record the copy of the contents to the new buffer, which must be bound to memory. The
+    /// buffer isn't modified - once the copy has completed, [`Self::replace_buffer`] swaps to
+    /// the new buffer.
+    pub(crate) fn record_relocation(
+        &self,
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        new_buffer: vk::Buffer,
+    ) {
+        let region = vk::BufferCopy::default().size(self.size);
+        unsafe { device.cmd_copy_buffer(cmds, self.buffer, new_buffer, &[region]) };
+    }
+
+    /// Replace the buffer with the new buffer, once the copy recorded by
+    /// [`Self::record_relocation`] has completed. The texel views are recreated for the new
+    /// buffer. Returns the old buffer and its views, to be destroyed by the caller - on error,
+    /// the buffer is left unchanged.
+    pub(crate) fn replace_buffer(
+        &mut self,
+        device: &ash::Device,
+        new_buffer: vk::Buffer,
+    ) -> VkResult<(vk::Buffer, Vec<vk::BufferView>)> {
+        let mut new_views = Vec::with_capacity(self.texel_views.len());
+        for (format, offset, range) in self.texel_views.keys() {
+            let create_info = vk::BufferViewCreateInfo::default()
+                .buffer(new_buffer)
+                .format(*format)
+                .offset(*offset)
+                .range(*range);
+            match unsafe { device.create_buffer_view(&create_info, None) } {
+                Ok(view) => new_views.push(view),
+                Err(err) => {
+                    for view in new_views {
+                        unsafe { device.destroy_buffer_view(view, None) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let old_buffer = std::mem::replace(&mut self.buffer, new_buffer);
+        let old_views = self
+            .texel_views
+            .values_mut()
+            .zip(new_views)
+            .map(|(view, new_view)| std::mem::replace(view, new_view))
+            .collect();
+        Ok((old_buffer, old_views))
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        for (_, view) in self.texel_views.drain() {
+            unsafe { driver.device.device.destroy_buffer_view(view, None) };
+        }
+        unsafe {
+            driver
+                .vma_allocator
+                .destroy_buffer(self.buffer, &mut self.memory)
+        };
+    }
+}
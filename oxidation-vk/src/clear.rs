@@ -0,0 +1,161 @@
+use crate::barrier;
+use crate::commands::Commands;
+use crate::descriptor::DescriptorWrite;
+use crate::device::ContextDevice;
+use crate::pipeline::ComputePipeline;
+use crate::texture::Texture;
+use ash::vk;
+use std::error::Error;
+
+/// The workgroup size, in both dimensions, of the clear shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Whether a texture with the (optimal tiling) format features can be cleared with
+/// `vkCmdClearColorImage`, rather than requiring a compute dispatch.
+pub fn supports_transfer_clear(features: vk::FormatFeatureFlags) -> bool {
+    features.contains(vk::FormatFeatureFlags::TRANSFER_DST)
+}
+
+/// Clears storage images using a compute shader, for formats which can't be cleared with
+/// `vkCmdClearColorImage`. The shader is supplied by the user and must have the following
+/// interface, with the clear value passed as push constants - the value should be
+/// interpreted as float, int or uint data as appropriate for the image format:
+///
+/// ```glsl
+/// layout (local_size_x = 8, local_size_y = 8) in;
+/// layout (binding = 0, rgba16f) uniform writeonly image2DArray dstImage;
+/// layout (push_constant) uniform PushConstants { vec4 value; };
+/// ```
+///
+/// A dispatch is made per mip level, with one invocation per texel and the z dimension
+/// covering the array layers.
+///
+/// # Examples
+///
+/// ```ignore
+/// driver.enable_compute_clears(clear_shader)?;
+/// // Storage textures which can't be cleared by a transfer now use the compute path.
+/// texture.clear_storage(&mut driver, cmds, vk::ClearColorValue::default())?;
+/// ```
+///
+pub struct ComputeClear {
+    pipeline: ComputePipeline,
+}
+
+impl ComputeClear {
+    pub fn new(device: &ContextDevice, shader: vk::ShaderModule) -> Result<Self, Box<dyn Error>> {
+        let bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<vk::ClearColorValue>() as u32)];
+        let pipeline = ComputePipeline::new(device, shader, &bindings, &push_constant_ranges)?;
+        Ok(Self { pipeline })
+    }
+
+    /// Record the clear of all mip levels and array layers of the texture, which must be in
+    /// the general layout.
+    pub(crate) fn record(
+        &self,
+        device: &ContextDevice,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+        texture: &mut Texture,
+        value: vk::ClearColorValue,
+    ) -> Result<(), Box<dyn Error>> {
+        unsafe {
+            device.device.cmd_bind_pipeline(
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.pipeline,
+            );
+            // The clear value union is always 16 bytes, regardless of the member in use.
+            let value_bytes = std::slice::from_raw_parts(
+                (&value as *const vk::ClearColorValue) as *const u8,
+                size_of::<vk::ClearColorValue>(),
+            );
+            device.device.cmd_push_constants(
+                cmds,
+                self.pipeline.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                value_bytes,
+            );
+        }
+
+        let info = *texture.info();
+        let layer_count = texture.array_layer_count();
+        for level in 0..info.mip_levels {
+            let view = texture.storage_mip_view(level, &device.device)?;
+            let writes = [DescriptorWrite::Image {
+                binding: 0,
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                view,
+                sampler: vk::Sampler::null(),
+                layout: vk::ImageLayout::GENERAL,
+            }];
+            commands.push_descriptor_set(
+                device,
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.layout,
+                self.pipeline.set_layout,
+                0,
+                &writes,
+            )?;
+
+            let width = (info.width >> level).max(1);
+            let height = (info.height >> level).max(1);
+            commands.dispatch(
+                &device.device,
+                cmds,
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                layer_count,
+            );
+        }
+
+        let barriers: Vec<vk::ImageMemoryBarrier2> = (0..info.mip_levels)
+            .map(|level| {
+                texture
+                    .level_barrier(
+                        level,
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::GENERAL,
+                        vk::PipelineStageFlags2::COMPUTE_SHADER,
+                        vk::PipelineStageFlags2::ALL_COMMANDS,
+                    )
+                    .dst_access_mask(
+                        vk::AccessFlags2::SHADER_STORAGE_READ
+                            | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    )
+            })
+            .collect();
+        barrier::cmd_image_barriers(device, cmds, &barriers);
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.pipeline.destroy(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_clear_requires_transfer_dst() {
+        assert!(supports_transfer_clear(
+            vk::FormatFeatureFlags::TRANSFER_DST | vk::FormatFeatureFlags::STORAGE_IMAGE
+        ));
+        assert!(!supports_transfer_clear(
+            vk::FormatFeatureFlags::STORAGE_IMAGE
+        ));
+        assert!(!supports_transfer_clear(vk::FormatFeatureFlags::empty()));
+    }
+}
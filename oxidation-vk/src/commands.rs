@@ -1,6 +1,59 @@
+use crate::backend;
+use crate::buffer::Buffer;
+use crate::descriptor::{DescriptorWrite, DescriptorWriteInfos, TransientDescriptorPools};
+use crate::descriptor_buffer::DescriptorBuffer;
+use crate::device::ContextDevice;
+use crate::error::OxidationError;
+use crate::rendering::RenderingInfo;
+use crate::submit_batch::{SemaphoreSubmit, SubmitBatch};
+use crate::sync;
+use crate::vk_check;
+use ash::prelude::VkResult;
 use ash::vk;
+use std::cell::Cell;
 
 pub const MAX_CMD_BUFFER_IN_FLIGHT_COUNT: usize = 10;
+/// The max number of transient descriptor sets which can be allocated when push descriptors are unavailable.
+pub const MAX_TRANSIENT_DESCRIPTOR_SET_COUNT: u32 = 256;
+
+/// The parameters for a single indexed indirect draw. This mirrors the layout of
+/// `VkDrawIndexedIndirectCommand` so can be written directly into an indirect
+/// buffer, either from the CPU or by a compute shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DrawIndexedIndirectCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+const _: () =
+    assert!(size_of::<DrawIndexedIndirectCommand>() == size_of::<vk::DrawIndexedIndirectCommand>());
+
+/// The number of draw and dispatch commands recorded through the helpers. The helpers only
+/// borrow the `Commands` immutably, hence the cells.
+#[derive(Debug, Default)]
+struct RecordCounts {
+    draws: Cell<u32>,
+    dispatches: Cell<u32>,
+}
+
+impl RecordCounts {
+    fn draw(&self) {
+        self.draws.set(self.draws.get() + 1);
+    }
+
+    fn dispatch(&self) {
+        self.dispatches.set(self.dispatches.get() + 1);
+    }
+
+    fn reset(&self) {
+        self.draws.set(0);
+        self.dispatches.set(0);
+    }
+}
 
 #[derive(Debug, Copy, Clone, Default)]
 pub struct CmdBuffer {
@@ -35,6 +88,23 @@ pub struct Commands {
     cmd_buffers: [Option<CmdBuffer>; MAX_CMD_BUFFER_IN_FLIGHT_COUNT],
     /// A container of signal slots - these are all initialised upon object creation.
     signals: [vk::Semaphore; MAX_CMD_BUFFER_IN_FLIGHT_COUNT],
+    /// Pools used for allocating descriptor sets when push descriptors aren't supported by
+    /// the device - another pool is added when these are exhausted.
+    transient_desc_pools: TransientDescriptorPools,
+    /// Transient descriptor sets along with the cmd buffer they were bound to and the pool
+    /// they were allocated from - these are freed once the cmd buffer has finished on the queue.
+    transient_desc_sets: Vec<(vk::CommandBuffer, vk::DescriptorPool, vk::DescriptorSet)>,
+    /// Descriptors are written into this buffer by [`Self::bind_descriptor_set`] when the
+    /// device supports descriptor buffers.
+    descriptor_buffer: Option<DescriptorBuffer>,
+    /// Command buffers which have been enqueued but not yet submitted to the queue.
+    pending_batch: SubmitBatch,
+    pending_cmds: Vec<CmdBuffer>,
+    /// The total number of queue submissions made by this object.
+    submit_count: usize,
+    /// The number of draw and dispatch commands recorded through the helpers since the
+    /// counts were last reset - see [`Self::reset_record_counts`].
+    record_counts: RecordCounts,
 }
 
 impl Commands {
@@ -66,6 +136,13 @@ impl Commands {
             cmd_queue,
             cmd_buffers: [Default::default(); MAX_CMD_BUFFER_IN_FLIGHT_COUNT],
             signals,
+            transient_desc_pools: TransientDescriptorPools::new(MAX_TRANSIENT_DESCRIPTOR_SET_COUNT),
+            transient_desc_sets: Vec::new(),
+            descriptor_buffer: None,
+            pending_batch: SubmitBatch::new(),
+            pending_cmds: Vec::new(),
+            submit_count: 0,
+            record_counts: RecordCounts::default(),
         }
     }
 
@@ -73,18 +150,27 @@ impl Commands {
     /// will be returned. If not, a command buffer is grabbed from the pool
     /// if one is available, otherwise to gain a free slot - it will wait for
     /// a command buffer to finish on the queue before destroying and creating
-    /// a new command buffer in that slot.
-    pub fn get(&mut self, device: &ash::Device) -> vk::CommandBuffer {
+    /// a new command buffer in that slot. Any enqueued command buffers are
+    /// submitted first, as these will never finish otherwise.
+    pub fn get(&mut self, device: &ContextDevice) -> Result<vk::CommandBuffer, OxidationError> {
         // If there is already a bound cmd buffer, return that.
         if let Some(current) = self.current_cmds {
-            return current.buffer;
+            return Ok(current.buffer);
         }
 
         // Otherwise, if there are no available cmd buffers, wait for them
         // to finish.
-        while self.available_cmd_count == 0 {
-            self.free_cmd_buffers(device);
+        if self.available_cmd_count == 0 {
+            self.submit_pending(device)?;
+            self.free_cmd_buffers(&device.device)?;
+            if self.available_cmd_count == 0 {
+                return Err(OxidationError::Other(
+                    "No command buffer slots became available after waiting on the queue."
+                        .to_string(),
+                ));
+            }
         }
+        let device = &device.device;
 
         // Find the next available free cmd buffer slot.
         for i in 0..self.cmd_buffers.len() {
@@ -93,18 +179,28 @@ impl Commands {
                     .command_pool(self.main_cmd_pool)
                     .level(vk::CommandBufferLevel::PRIMARY)
                     .command_buffer_count(1);
-                let buffer = unsafe { device.allocate_command_buffers(&alloc_info).unwrap()[0] };
+                let buffer = vk_check!(
+                    unsafe { device.allocate_command_buffers(&alloc_info) },
+                    "vkAllocateCommandBuffers"
+                )?[0];
 
                 // Begin the cmd buffer now so it's ready for recording commands.
                 let begin_info = vk::CommandBufferBeginInfo::default()
                     .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-                unsafe {
-                    device.begin_command_buffer(buffer, &begin_info).unwrap();
-                };
+                if let Err(err) = unsafe { device.begin_command_buffer(buffer, &begin_info) } {
+                    unsafe { device.free_command_buffers(self.main_cmd_pool, &[buffer]) };
+                    return vk_check!(Err(err), "vkBeginCommandBuffer");
+                }
 
                 // Create a fence to go with the cmd buffer for signalling when it has finished on the queue.
                 let create_fence_info = vk::FenceCreateInfo::default();
-                let fence = unsafe { device.create_fence(&create_fence_info, None).unwrap() };
+                let fence = match unsafe { device.create_fence(&create_fence_info, None) } {
+                    Ok(fence) => fence,
+                    Err(err) => {
+                        unsafe { device.free_command_buffers(self.main_cmd_pool, &[buffer]) };
+                        return vk_check!(Err(err), "vkCreateFence");
+                    }
+                };
 
                 let cmd_buffer = CmdBuffer { buffer, fence };
 
@@ -112,92 +208,766 @@ impl Commands {
                 self.current_cmds = self.cmd_buffers[i];
                 self.current_signal = self.signals[i];
                 self.available_cmd_count -= 1;
+                break;
             }
         }
 
         assert!(self.current_cmds.is_some());
-        self.current_cmds.unwrap().buffer
+        Ok(self.current_cmds.unwrap().buffer)
     }
 
-    pub fn free_cmd_buffers(&mut self, device: &ash::Device) {
+    pub fn free_cmd_buffers(&mut self, device: &ash::Device) -> Result<(), OxidationError> {
+        // Wait for all cmd buffers that are currently active - the bound cmd buffer and
+        // those enqueued but not yet submitted will not be signalled so are ignored.
         let mut fences: Vec<vk::Fence> = Vec::with_capacity(MAX_CMD_BUFFER_IN_FLIGHT_COUNT);
-        for cmd_buffer in &self.cmd_buffers {
-            if let Some(cmds) = cmd_buffer
-                && cmds.buffer != self.current_cmds.unwrap().buffer
-            {
+        for cmds in self.cmd_buffers.iter().flatten() {
+            let is_current = self
+                .current_cmds
+                .is_some_and(|current| current.buffer == cmds.buffer);
+            let is_pending = self
+                .pending_cmds
+                .iter()
+                .any(|pending| pending.buffer == cmds.buffer);
+            if !is_current && !is_pending && !fences.contains(&cmds.fence) {
                 fences.push(cmds.fence);
             }
         }
-        // Wait for all cmd buffers that are currently active.
-        if !fences.is_empty() {
-            unsafe { device.wait_for_fences(&fences, true, u64::MAX).unwrap() };
-        }
+        sync::wait_all(device, &fences, u64::MAX)?;
+
+        // Cmd buffers submitted as part of a batch share a fence, so ensure each is only destroyed once.
+        let mut freed_fences: Vec<vk::Fence> = Vec::new();
         for i in 0..MAX_CMD_BUFFER_IN_FLIGHT_COUNT {
             if let Some(cmds) = self.cmd_buffers[i] {
-                let res = unsafe { device.wait_for_fences(&[cmds.fence], true, 0) };
-                if res.is_ok() {
+                let res = sync::wait_all(device, &[cmds.fence], 0);
+                if res.is_ok_and(|res| res.is_signalled()) {
+                    self.free_transient_desc_sets(device, cmds.buffer)?;
+                    if let Some(descriptor_buffer) = self.descriptor_buffer.as_mut() {
+                        descriptor_buffer.release(cmds.buffer);
+                    }
                     unsafe { device.free_command_buffers(self.main_cmd_pool, &[cmds.buffer]) };
-                    unsafe { device.destroy_fence(cmds.fence, Option::None) };
+                    if !freed_fences.contains(&cmds.fence) {
+                        freed_fences.push(cmds.fence);
+                    }
                     self.cmd_buffers[i] = None;
                     self.available_cmd_count += 1;
                 }
             }
         }
+        for fence in freed_fences {
+            unsafe { device.destroy_fence(fence, None) };
+        }
+        Ok(())
+    }
+
+    fn free_transient_desc_sets(
+        &mut self,
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+    ) -> Result<(), OxidationError> {
+        let (freed, retained): (Vec<_>, Vec<_>) = self
+            .transient_desc_sets
+            .drain(..)
+            .partition(|(buffer, _, _)| *buffer == cmds);
+        self.transient_desc_sets = retained;
+        if freed.is_empty() {
+            return Ok(());
+        }
+
+        // Once no sets are in use, resetting the pools is cheaper than freeing the sets and
+        // undoes any fragmentation.
+        if self.transient_desc_sets.is_empty() {
+            return vk_check!(
+                self.transient_desc_pools.reset(device),
+                "vkResetDescriptorPool"
+            );
+        }
+        let mut pools: Vec<vk::DescriptorPool> = Vec::new();
+        for (_, pool, _) in &freed {
+            if !pools.contains(pool) {
+                pools.push(*pool);
+            }
+        }
+        for pool in pools {
+            let sets: Vec<vk::DescriptorSet> = freed
+                .iter()
+                .filter(|(_, set_pool, _)| *set_pool == pool)
+                .map(|(_, _, set)| *set)
+                .collect();
+            vk_check!(
+                self.transient_desc_pools.free(device, pool, &sets),
+                "vkFreeDescriptorSets"
+            )?;
+        }
+        Ok(())
     }
 
     /// Flush the current command buffer to the queue. This will
     /// invalidate the currently bound cmd buffer, so a call to `get()`
     /// will bind a new command buffer.
-    pub fn flush(&mut self, device: &ash::Device) {
-        // Early return if there are no commands to flush.
-        if self.current_cmds.is_none() {
-            return;
+    pub fn flush(&mut self, device: &ContextDevice) -> Result<(), OxidationError> {
+        self.enqueue(&device.device)?;
+        self.submit_pending(device)
+    }
+
+    /// Enqueue the current command buffer into a pending batch rather than submitting it
+    /// to the queue immediately. All pending command buffers are submitted with a single
+    /// call to `submit_pending()`. As with `flush()`, this invalidates the bound cmd buffer.
+    pub fn enqueue(&mut self, device: &ash::Device) -> Result<(), OxidationError> {
+        // Early return if there are no commands to enqueue.
+        let Some(cmds) = self.current_cmds.take() else {
+            return Ok(());
+        };
+
+        vk_check!(
+            unsafe { device.end_command_buffer(cmds.buffer) },
+            "vkEndCommandBuffer"
+        )?;
+
+        // Each cmd buffer waits on the previously submitted cmd buffer along with any client semaphores.
+        let mut wait_signals: Vec<SemaphoreSubmit> = self
+            .external_signals
+            .drain(..)
+            .map(|signal| SemaphoreSubmit::new(signal, vk::PipelineStageFlags2::ALL_COMMANDS))
+            .collect();
+        if let Some(signal) = self.submitted_signal {
+            wait_signals.push(SemaphoreSubmit::new(
+                signal,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            ));
+        }
+        let signal =
+            SemaphoreSubmit::new(self.current_signal, vk::PipelineStageFlags2::ALL_COMMANDS);
+
+        self.pending_batch
+            .add(cmds.buffer, &wait_signals, &[signal]);
+        self.pending_cmds.push(cmds);
+        self.submitted_signal = Some(self.current_signal);
+        Ok(())
+    }
+
+    /// Submit all enqueued command buffers to the queue with a single submit call.
+    pub fn submit_pending(&mut self, device: &ContextDevice) -> Result<(), OxidationError> {
+        let Some(last) = self.pending_cmds.last() else {
+            return Ok(());
+        };
+
+        // Only one fence is signalled per submit, so the fence of the last cmd buffer is
+        // shared by all cmd buffers in the batch.
+        let fence = last.fence;
+        // The shared fence is also recorded on the pending cmds, so a failed submit can be retried.
+        for pending in self.pending_cmds.iter_mut() {
+            if pending.fence == fence {
+                continue;
+            }
+            unsafe { device.device.destroy_fence(pending.fence, None) };
+            pending.fence = fence;
+            for cmds in self.cmd_buffers.iter_mut().flatten() {
+                if cmds.buffer == pending.buffer {
+                    cmds.fence = fence;
+                }
+            }
         }
 
+        self.pending_batch.submit(device, self.cmd_queue, fence)?;
+        self.pending_cmds.clear();
+        self.submit_count += 1;
+        Ok(())
+    }
+
+    /// The queue which commands are submitted to.
+    pub fn queue(&self) -> vk::Queue {
+        self.cmd_queue
+    }
+
+    /// Allocate a primary command buffer from the main pool for one-off work. The command
+    /// buffer isn't tracked by this object, so must be freed with `free_one_shot` once the
+    /// work has completed.
+    pub(crate) fn allocate_one_shot(&self, device: &ash::Device) -> VkResult<vk::CommandBuffer> {
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(self.main_cmd_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        Ok(unsafe { device.allocate_command_buffers(&alloc_info)?[0] })
+    }
+
+    pub(crate) fn free_one_shot(&self, device: &ash::Device, cmds: vk::CommandBuffer) {
+        unsafe { device.free_command_buffers(self.main_cmd_pool, &[cmds]) };
+    }
+
+    /// The total number of queue submissions made - useful for measuring the effect of batching.
+    pub fn submit_count(&self) -> usize {
+        self.submit_count
+    }
+
+    /// The number of pools transient descriptor sets are allocated from - this grows when the
+    /// pools are exhausted.
+    pub fn transient_pool_count(&self) -> usize {
+        self.transient_desc_pools.pool_count()
+    }
+
+    pub fn add_external_wait_signal(&mut self, signal: vk::Semaphore) {
+        self.external_signals.push(signal);
+    }
+
+    /// Push descriptors directly into the command buffer using `VK_KHR_push_descriptor`,
+    /// avoiding the need to allocate descriptor sets for frequently changing resources.
+    /// If the extension isn't supported by the device, a transient descriptor set is
+    /// allocated, updated and bound instead - this is freed once the command buffer
+    /// has finished on the queue.
+    ///
+    /// When push descriptors are supported, the set layout must have been created with
+    /// the `PUSH_DESCRIPTOR_KHR` flag - see
+    /// [`push_set_layout_flags`](crate::descriptor::push_set_layout_flags).
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_descriptor_set(
+        &mut self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set_layout: vk::DescriptorSetLayout,
+        set: u32,
+        writes: &[DescriptorWrite],
+    ) -> Result<(), OxidationError> {
+        let infos = DescriptorWriteInfos::new(writes);
+
+        if let Some(push_descriptor) = &device.push_descriptor {
+            let vk_writes = infos.to_vk(writes, vk::DescriptorSet::null());
+            unsafe {
+                push_descriptor.cmd_push_descriptor_set(cmds, bind_point, layout, set, &vk_writes)
+            };
+            return Ok(());
+        }
+
+        // Fallback path - allocate a transient set for this cmd buffer.
+        let (pool, desc_set) = self
+            .transient_desc_pools
+            .allocate(&device.device, set_layout)?;
+        let vk_writes = infos.to_vk(writes, desc_set);
         unsafe {
+            device.device.update_descriptor_sets(&vk_writes, &[]);
             device
-                .end_command_buffer(self.current_cmds.unwrap().buffer)
-                .unwrap()
+                .device
+                .cmd_bind_descriptor_sets(cmds, bind_point, layout, set, &[desc_set], &[]);
+        }
+        self.transient_desc_sets.push((cmds, pool, desc_set));
+        Ok(())
+    }
+
+    /// Bind a set of descriptors by the fastest path supported by the device - descriptors are
+    /// written into the descriptor buffer if these commands hold one, avoiding descriptor set
+    /// management entirely, otherwise this falls back to [`Self::push_descriptor_set`].
+    ///
+    /// The set layout must be created with [`set_layout_flags`](crate::descriptor_buffer::set_layout_flags), and the
+    /// pipeline with [`pipeline_create_flags`](crate::descriptor_buffer::pipeline_create_flags), for these commands.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+    ///     .flags(descriptor_buffer::set_layout_flags(&driver.device, &driver.compute_commands))
+    ///     .bindings(&bindings);
+    /// // Record time..
+    /// let writes = [DescriptorWrite::Buffer {
+    ///     binding: 0,
+    ///     ty: vk::DescriptorType::STORAGE_BUFFER,
+    ///     buffer: particles.buffer,
+    ///     offset: 0,
+    ///     range: vk::WHOLE_SIZE,
+    /// }];
+    /// commands.bind_descriptor_set(&driver.device, cmds, vk::PipelineBindPoint::COMPUTE, layout, set_layout, 0, &writes)?;
+    /// ```
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn bind_descriptor_set(
+        &mut self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set_layout: vk::DescriptorSetLayout,
+        set: u32,
+        writes: &[DescriptorWrite],
+    ) -> Result<(), OxidationError> {
+        if let Some(descriptor_buffer) = self.descriptor_buffer.as_mut() {
+            return descriptor_buffer
+                .bind_set(device, cmds, bind_point, layout, set_layout, set, writes);
+        }
+        self.push_descriptor_set(device, cmds, bind_point, layout, set_layout, set, writes)
+    }
+
+    /// Use the descriptor buffer for [`Self::bind_descriptor_set`].
+    pub(crate) fn enable_descriptor_buffer(&mut self, descriptor_buffer: DescriptorBuffer) {
+        self.descriptor_buffer = Some(descriptor_buffer);
+    }
+
+    /// Whether descriptors bound with [`Self::bind_descriptor_set`] are written into a
+    /// descriptor buffer.
+    pub fn has_descriptor_buffer(&self) -> bool {
+        self.descriptor_buffer.is_some()
+    }
+
+    pub(crate) fn take_descriptor_buffer(&mut self) -> Option<DescriptorBuffer> {
+        self.descriptor_buffer.take()
+    }
+
+    /// Begin a dynamic rendering pass. If a view mask is specified, the number of views
+    /// must be within the multiview limits of the device.
+    pub fn begin_rendering(
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        info: &RenderingInfo,
+    ) -> Result<(), OxidationError> {
+        // The index of the most significant bit of the view mask must be less than the max view count.
+        let view_count = u32::BITS - info.view_mask.leading_zeros();
+        if view_count > device.max_multiview_view_count {
+            return Err(OxidationError::Unsupported(format!(
+                "View mask {:#b} exceeds the max multiview count ({}) of the device.",
+                info.view_mask, device.max_multiview_view_count
+            )));
+        }
+
+        let color_attachments: Vec<vk::RenderingAttachmentInfo> = info
+            .color_attachments
+            .iter()
+            .map(|attachment| attachment.to_vk())
+            .collect();
+        let depth_attachment = info.depth_attachment.map(|a| a.to_vk_depth_stencil());
+        let stencil_attachment = info.stencil_attachment.map(|a| a.to_vk_depth_stencil());
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(info.render_area)
+            .layer_count(info.layer_count)
+            .view_mask(info.view_mask)
+            .color_attachments(&color_attachments);
+        if let Some(depth_attachment) = &depth_attachment {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+        }
+        if let Some(stencil_attachment) = &stencil_attachment {
+            rendering_info = rendering_info.stencil_attachment(stencil_attachment);
+        }
+        unsafe { device.device.cmd_begin_rendering(cmds, &rendering_info) };
+        Ok(())
+    }
+
+    pub fn end_rendering(device: &ash::Device, cmds: vk::CommandBuffer) {
+        unsafe { device.cmd_end_rendering(cmds) };
+    }
+
+    /// Set the dynamic viewport to cover the extent. If `flip_y` is set, the viewport is
+    /// flipped so Y points up in NDC - see [`backend::viewport`].
+    pub fn set_viewport(
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        flip_y: bool,
+    ) {
+        let viewport = backend::viewport(extent, flip_y);
+        unsafe { device.cmd_set_viewport(cmds, 0, &[viewport]) };
+    }
+
+    /// Begin a conditional rendering block - draws, dispatches and clears recorded until
+    /// [`Self::end_conditional_rendering`] are discarded if the 32-bit predicate at the
+    /// buffer offset is zero (or non-zero if `inverted` is set). The buffer must have been
+    /// created with conditional rendering usage and the offset must be a multiple of four.
+    ///
+    /// Returns an error if conditional rendering isn't enabled on the device, or if the buffer
+    /// usage or offset is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // The visibility of each object is written by a culling compute pass.
+    /// Commands::begin_conditional_rendering(&driver.device, cmds, &visibility, object_idx * 4, false)?;
+    /// device.cmd_draw_indexed(cmds, index_count, 1, 0, 0, 0);
+    /// Commands::end_conditional_rendering(&driver.device, cmds)?;
+    /// ```
+    ///
+    pub fn begin_conditional_rendering(
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: vk::DeviceSize,
+        inverted: bool,
+    ) -> Result<(), OxidationError> {
+        let conditional_rendering = Self::conditional_rendering(device)?;
+        validate_predicate_buffer(buffer.usage, buffer.size, offset)?;
+        let flags = match inverted {
+            true => vk::ConditionalRenderingFlagsEXT::INVERTED,
+            false => vk::ConditionalRenderingFlagsEXT::empty(),
+        };
+        let begin_info = vk::ConditionalRenderingBeginInfoEXT::default()
+            .buffer(buffer.buffer)
+            .offset(offset)
+            .flags(flags);
+        // Ash doesn't provide wrappers for this extension, so the function pointers are used.
+        unsafe {
+            (conditional_rendering
+                .fp()
+                .cmd_begin_conditional_rendering_ext)(cmds, &begin_info)
         };
+        Ok(())
+    }
 
-        let mut wait_signals: Vec<vk::Semaphore> = Vec::with_capacity(5);
-        if let Some(signal) = self.submitted_signal {
-            wait_signals.push(signal);
-        }
-        wait_signals.extend_from_slice(&self.external_signals);
-        let stage_flags: [vk::PipelineStageFlags; 2] = [
-            vk::PipelineStageFlags::ALL_COMMANDS,
-            vk::PipelineStageFlags::ALL_COMMANDS,
-        ];
-        let buffers = [self.current_cmds.unwrap().buffer];
-        let signals = [self.current_signal];
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(&wait_signals)
-            .wait_dst_stage_mask(&stage_flags)
-            .command_buffers(&buffers)
-            .signal_semaphores(&signals);
+    /// End the current conditional rendering block.
+    pub fn end_conditional_rendering(
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+    ) -> Result<(), OxidationError> {
+        let conditional_rendering = Self::conditional_rendering(device)?;
+        unsafe { (conditional_rendering.fp().cmd_end_conditional_rendering_ext)(cmds) };
+        Ok(())
+    }
+
+    fn conditional_rendering(
+        device: &ContextDevice,
+    ) -> Result<&ash::ext::conditional_rendering::Device, OxidationError> {
+        device.conditional_rendering.as_ref().ok_or_else(|| {
+            OxidationError::Unsupported(
+                "Conditional rendering (VK_EXT_conditional_rendering) is not enabled.".to_string(),
+            )
+        })
+    }
+
+    /// Set the dynamic scissor to cover the extent.
+    pub fn set_scissor(device: &ash::Device, cmds: vk::CommandBuffer, extent: vk::Extent2D) {
+        Self::set_scissor_rect(device, cmds, backend::scissor(extent));
+    }
+
+    /// Set the dynamic scissor to a region of the framebuffer, i.e. for clipping UI elements.
+    pub fn set_scissor_rect(device: &ash::Device, cmds: vk::CommandBuffer, rect: vk::Rect2D) {
+        unsafe { device.cmd_set_scissor(cmds, 0, &[rect]) };
+    }
+
+    /// The number of draw commands recorded through the draw helpers since the counts were
+    /// last reset. Indirect draws count as a single draw, regardless of the draw count.
+    pub fn draw_count(&self) -> u32 {
+        self.record_counts.draws.get()
+    }
+
+    /// The number of dispatches recorded through [`Self::dispatch`] since the counts were
+    /// last reset.
+    pub fn dispatch_count(&self) -> u32 {
+        self.record_counts.dispatches.get()
+    }
+
+    /// Reset the draw and dispatch counts, i.e. at the start of a frame.
+    pub fn reset_record_counts(&self) {
+        self.record_counts.reset();
+    }
+
+    /// Record a compute dispatch into the specified command buffer.
+    pub fn dispatch(
+        &self,
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        group_count_x: u32,
+        group_count_y: u32,
+        group_count_z: u32,
+    ) {
+        self.record_counts.dispatch();
+        unsafe { device.cmd_dispatch(cmds, group_count_x, group_count_y, group_count_z) };
+    }
+
+    /// Record a non-indexed draw into the specified command buffer.
+    pub fn draw(
+        &self,
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    ) {
+        self.record_counts.draw();
+        unsafe {
+            device.cmd_draw(
+                cmds,
+                vertex_count,
+                instance_count,
+                first_vertex,
+                first_instance,
+            )
+        };
+    }
+
+    /// Record an indexed draw into the specified command buffer. An index buffer
+    /// must have been bound beforehand via `bind_index_buffer`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed(
+        &self,
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) {
+        self.record_counts.draw();
+        unsafe {
+            device.cmd_draw_indexed(
+                cmds,
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            )
+        };
+    }
+
+    /// Bind one or more vertex buffers, starting at the specified binding. There
+    /// must be an offset for each buffer.
+    pub fn bind_vertex_buffers(
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        first_binding: u32,
+        buffers: &[&Buffer],
+        offsets: &[vk::DeviceSize],
+    ) {
+        assert_eq!(buffers.len(), offsets.len());
+        let vk_buffers: Vec<vk::Buffer> = buffers
+            .iter()
+            .map(|buffer| {
+                debug_assert!(buffer.usage.contains(vk::BufferUsageFlags::VERTEX_BUFFER));
+                buffer.buffer
+            })
+            .collect();
+        unsafe { device.cmd_bind_vertex_buffers(cmds, first_binding, &vk_buffers, offsets) };
+    }
+
+    /// Record a number of indexed draws, with the draw parameters sourced from a
+    /// buffer of `DrawIndexedIndirectCommand`s. Returns an error if the buffer range is
+    /// invalid, or more than one draw is requested without `multiDrawIndirect`.
+    pub fn draw_indexed_indirect(
+        &self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+    ) -> Result<(), OxidationError> {
+        validate_indirect_buffer(buffer, offset, draw_count, stride)?;
+        if draw_count > 1 && !device.enabled_features().multi_draw_indirect {
+            return Err(OxidationError::Unsupported(
+                "Indirect draws with a draw count above one require multiDrawIndirect.".to_string(),
+            ));
+        }
+        self.record_counts.draw();
         unsafe {
             device
-                .queue_submit(
-                    self.cmd_queue,
-                    &[submit_info],
-                    self.current_cmds.unwrap().fence,
-                )
-                .unwrap()
+                .device
+                .cmd_draw_indexed_indirect(cmds, buffer.buffer, offset, draw_count, stride)
         };
+        Ok(())
     }
 
-    pub fn add_external_wait_signal(&mut self, signal: vk::Semaphore) {
-        self.external_signals.push(signal);
+    /// Record a number of indexed draws, with the draw parameters sourced from a
+    /// buffer of `DrawIndexedIndirectCommand`s and the number of draws read from the
+    /// count buffer at the time of execution (clamped to `max_draw_count`). Requires the
+    /// `drawIndirectCount` feature.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed_indirect_count(
+        &self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: vk::DeviceSize,
+        count_buffer: &Buffer,
+        count_offset: vk::DeviceSize,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<(), OxidationError> {
+        if !device.enabled_features().draw_indirect_count {
+            return Err(OxidationError::Unsupported(
+                "Indirect count draws require drawIndirectCount.".to_string(),
+            ));
+        }
+        validate_indirect_buffer(buffer, offset, max_draw_count, stride)?;
+        if !count_buffer
+            .usage
+            .contains(vk::BufferUsageFlags::INDIRECT_BUFFER)
+        {
+            return Err(OxidationError::Other(
+                "The indirect draw count buffer requires indirect buffer usage.".to_string(),
+            ));
+        }
+        if count_offset + size_of::<u32>() as vk::DeviceSize > count_buffer.size {
+            return Err(OxidationError::Other(
+                "The indirect draw count offset exceeds the size of the count buffer.".to_string(),
+            ));
+        }
+        self.record_counts.draw();
+        unsafe {
+            device.device.cmd_draw_indexed_indirect_count(
+                cmds,
+                buffer.buffer,
+                offset,
+                count_buffer.buffer,
+                count_offset,
+                max_draw_count,
+                stride,
+            )
+        };
+        Ok(())
+    }
+
+    pub fn bind_index_buffer(
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType,
+    ) {
+        debug_assert!(buffer.usage.contains(vk::BufferUsageFlags::INDEX_BUFFER));
+        unsafe { device.cmd_bind_index_buffer(cmds, buffer.buffer, offset, index_type) };
+    }
+
+    /// Record a copy of the regions of one buffer to another, i.e. to gather the live elements
+    /// of a GPU array into a compacted buffer. The source must have transfer src usage and the
+    /// destination transfer dst usage, and all regions must lie within both buffers - otherwise
+    /// an error is returned and nothing is recorded. Regions copied within a single buffer
+    /// must not overlap.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Compact elements 0 and 2 of a 64 byte stride array.
+    /// let regions = [
+    ///     vk::BufferCopy { src_offset: 0, dst_offset: 0, size: 64 },
+    ///     vk::BufferCopy { src_offset: 128, dst_offset: 64, size: 64 },
+    /// ];
+    /// Commands::copy_buffer(&driver.device.device, cmds, &elements, &compacted, &regions)?;
+    /// ```
+    ///
+    pub fn copy_buffer(
+        device: &ash::Device,
+        cmds: vk::CommandBuffer,
+        src: &Buffer,
+        dst: &Buffer,
+        regions: &[vk::BufferCopy],
+    ) -> Result<(), OxidationError> {
+        validate_buffer_copy(
+            (src.usage, src.size),
+            (dst.usage, dst.size),
+            src.buffer == dst.buffer,
+            regions,
+        )?;
+        unsafe { device.cmd_copy_buffer(cmds, src.buffer, dst.buffer, regions) };
+        Ok(())
     }
 
     pub fn destroy(&mut self, device: &ash::Device) {
         for signal in self.signals {
             unsafe { device.destroy_semaphore(signal, None) };
         }
+        self.transient_desc_pools.destroy(device);
     }
 }
 
+fn validate_indirect_buffer(
+    buffer: &Buffer,
+    offset: vk::DeviceSize,
+    draw_count: u32,
+    stride: u32,
+) -> Result<(), OxidationError> {
+    if !buffer.usage.contains(vk::BufferUsageFlags::INDIRECT_BUFFER) {
+        return Err(OxidationError::Other(
+            "The indirect draw buffer requires indirect buffer usage.".to_string(),
+        ));
+    }
+    if (stride as usize) < size_of::<DrawIndexedIndirectCommand>() || !stride.is_multiple_of(4) {
+        return Err(OxidationError::Other(
+            "The indirect draw stride must be a multiple of four and at least the size of a draw command."
+                .to_string(),
+        ));
+    }
+    if draw_count > 0 {
+        let required_size = offset
+            + (draw_count - 1) as vk::DeviceSize * stride as vk::DeviceSize
+            + size_of::<DrawIndexedIndirectCommand>() as vk::DeviceSize;
+        if required_size > buffer.size {
+            return Err(OxidationError::Other(
+                "The indirect draw range exceeds the size of the buffer.".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check the buffers, as their usage and size, can be copied between with the regions.
+fn validate_buffer_copy(
+    (src_usage, src_size): (vk::BufferUsageFlags, vk::DeviceSize),
+    (dst_usage, dst_size): (vk::BufferUsageFlags, vk::DeviceSize),
+    same_buffer: bool,
+    regions: &[vk::BufferCopy],
+) -> Result<(), OxidationError> {
+    if !src_usage.contains(vk::BufferUsageFlags::TRANSFER_SRC) {
+        return Err(OxidationError::Other(String::from(
+            "The source of a buffer copy requires transfer src usage.",
+        )));
+    }
+    if !dst_usage.contains(vk::BufferUsageFlags::TRANSFER_DST) {
+        return Err(OxidationError::Other(String::from(
+            "The destination of a buffer copy requires transfer dst usage.",
+        )));
+    }
+    for (idx, region) in regions.iter().enumerate() {
+        let in_bounds = |offset: vk::DeviceSize, size: vk::DeviceSize| {
+            offset
+                .checked_add(region.size)
+                .is_some_and(|end| end <= size)
+        };
+        if region.size == 0
+            || !in_bounds(region.src_offset, src_size)
+            || !in_bounds(region.dst_offset, dst_size)
+        {
+            return Err(OxidationError::Other(format!(
+                "Buffer copy region {idx} is empty or out of bounds (src: {} bytes, dst: {} bytes).",
+                src_size, dst_size
+            )));
+        }
+    }
+    // Within a single buffer, no region may read memory which is written by any region.
+    let overlaps = |a: &vk::BufferCopy, b: &vk::BufferCopy| {
+        a.src_offset < b.dst_offset + b.size && b.dst_offset < a.src_offset + a.size
+    };
+    if same_buffer
+        && regions
+            .iter()
+            .any(|a| regions.iter().any(|b| overlaps(a, b)))
+    {
+        return Err(OxidationError::Other(String::from(
+            "Buffer copy regions within the same buffer must not overlap.",
+        )));
+    }
+    Ok(())
+}
+
+fn validate_predicate_buffer(
+    usage: vk::BufferUsageFlags,
+    size: vk::DeviceSize,
+    offset: vk::DeviceSize,
+) -> Result<(), OxidationError> {
+    if !usage.contains(vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT) {
+        return Err(OxidationError::Other(
+            "The predicate buffer requires conditional rendering usage.".to_string(),
+        ));
+    }
+    if !offset.is_multiple_of(4) {
+        return Err(OxidationError::Other(format!(
+            "Conditional rendering offset {offset} must be a multiple of four."
+        )));
+    }
+    if offset.saturating_add(4) > size {
+        return Err(OxidationError::Other(format!(
+            "The predicate at offset {offset} exceeds the size of the buffer."
+        )));
+    }
+    Ok(())
+}
+
 fn create_cmd_pool(
     queue_family_idx: u32,
     flags: vk::CommandPoolCreateFlags,
@@ -208,3 +978,76 @@ fn create_cmd_pool(
         .flags(vk::CommandPoolCreateFlags::TRANSIENT | flags);
     unsafe { device.create_command_pool(&create_info, None).unwrap() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREDICATE_USAGE: vk::BufferUsageFlags = vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT;
+
+    #[test]
+    fn predicate_buffer_requires_conditional_rendering_usage() {
+        assert!(validate_predicate_buffer(PREDICATE_USAGE, 64, 0).is_ok());
+        assert!(validate_predicate_buffer(vk::BufferUsageFlags::STORAGE_BUFFER, 64, 0).is_err());
+    }
+
+    #[test]
+    fn predicate_must_be_aligned_and_in_range() {
+        assert!(validate_predicate_buffer(PREDICATE_USAGE, 64, 60).is_ok());
+        assert!(validate_predicate_buffer(PREDICATE_USAGE, 64, 2).is_err());
+        assert!(validate_predicate_buffer(PREDICATE_USAGE, 64, 64).is_err());
+    }
+
+    #[test]
+    fn record_counts_track_draws_and_dispatches() {
+        let counts = RecordCounts::default();
+        for _ in 0..3 {
+            counts.draw();
+        }
+        counts.dispatch();
+        assert_eq!(counts.draws.get(), 3);
+        assert_eq!(counts.dispatches.get(), 1);
+
+        counts.reset();
+        assert_eq!(counts.draws.get(), 0);
+        assert_eq!(counts.dispatches.get(), 0);
+    }
+
+    const SRC: (vk::BufferUsageFlags, vk::DeviceSize) = (vk::BufferUsageFlags::TRANSFER_SRC, 256);
+    const DST: (vk::BufferUsageFlags, vk::DeviceSize) = (vk::BufferUsageFlags::TRANSFER_DST, 128);
+
+    fn region(src_offset: u64, dst_offset: u64, size: u64) -> vk::BufferCopy {
+        vk::BufferCopy {
+            src_offset,
+            dst_offset,
+            size,
+        }
+    }
+
+    #[test]
+    fn buffer_copy_regions_must_be_in_bounds() {
+        let regions = [region(0, 0, 64), region(128, 64, 64)];
+        assert!(validate_buffer_copy(SRC, DST, false, &regions).is_ok());
+        // Past the end of the destination, and an empty region.
+        assert!(validate_buffer_copy(SRC, DST, false, &[region(0, 96, 64)]).is_err());
+        assert!(validate_buffer_copy(SRC, DST, false, &[region(0, 0, 0)]).is_err());
+        assert!(validate_buffer_copy(SRC, DST, false, &[region(u64::MAX, 0, 1)]).is_err());
+    }
+
+    #[test]
+    fn buffer_copy_requires_transfer_usage() {
+        let regions = [region(0, 0, 64)];
+        assert!(validate_buffer_copy(DST, DST, false, &regions).is_err());
+        assert!(validate_buffer_copy(SRC, SRC, false, &regions).is_err());
+    }
+
+    #[test]
+    fn buffer_copy_within_a_buffer_must_not_overlap() {
+        let both = (
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            256,
+        );
+        assert!(validate_buffer_copy(both, both, true, &[region(0, 128, 64)]).is_ok());
+        assert!(validate_buffer_copy(both, both, true, &[region(0, 32, 64)]).is_err());
+    }
+}
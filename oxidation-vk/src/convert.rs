@@ -0,0 +1,206 @@
+use crate::barrier;
+use crate::commands::Commands;
+use crate::descriptor::DescriptorWrite;
+use crate::device::ContextDevice;
+use crate::pipeline::ComputePipeline;
+use crate::texture::Texture;
+use ash::vk;
+use std::error::Error;
+
+/// The workgroup size, in both dimensions, of the conversion shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The method used to convert a texture from one format to another.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConversionPath {
+    /// Each level is blitted to the destination, with the format conversion performed by
+    /// the blit.
+    Blit,
+    /// Each level is read by a compute shader and written to the destination as a storage
+    /// image. Used for pairs of formats which can't be blitted.
+    Compute,
+}
+
+/// Select the conversion path from the (optimal tiling) features of the source and destination
+/// formats. Blitting is preferred when supported. Returns `None` if neither path is supported.
+pub fn select_conversion_path(
+    src_features: vk::FormatFeatureFlags,
+    dst_features: vk::FormatFeatureFlags,
+) -> Option<ConversionPath> {
+    if src_features.contains(vk::FormatFeatureFlags::BLIT_SRC)
+        && dst_features.contains(vk::FormatFeatureFlags::BLIT_DST)
+    {
+        Some(ConversionPath::Blit)
+    } else if src_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        && dst_features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+    {
+        Some(ConversionPath::Compute)
+    } else {
+        None
+    }
+}
+
+/// Converts textures between formats which can't be blitted using a compute shader. The shader
+/// is supplied by the user and must have the following interface - the destination format
+/// qualifier should match the format being converted to:
+///
+/// ```glsl
+/// layout (local_size_x = 8, local_size_y = 8) in;
+/// layout (binding = 0) uniform sampler2DArray srcImage;
+/// layout (binding = 1, rgba8) uniform writeonly image2DArray dstImage;
+/// ```
+///
+/// A dispatch is made per mip level, with one invocation per texel and the z dimension
+/// covering the array layers. The source should be read with `texelFetch`.
+///
+/// # Examples
+///
+/// ```ignore
+/// driver.enable_compute_format_conversion(convert_shader)?;
+/// let rgba = texture.convert_format(&mut driver, vk::Format::R8G8B8A8_UNORM, cmds)?;
+/// ```
+///
+pub struct ComputeFormatConverter {
+    pipeline: ComputePipeline,
+}
+
+impl ComputeFormatConverter {
+    pub fn new(device: &ContextDevice, shader: vk::ShaderModule) -> Result<Self, Box<dyn Error>> {
+        let bindings = [
+            (0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (1, vk::DescriptorType::STORAGE_IMAGE),
+        ]
+        .map(|(binding, ty)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        });
+        let pipeline = ComputePipeline::new(device, shader, &bindings, &[])?;
+        Ok(Self { pipeline })
+    }
+
+    /// Record the conversion of all mip levels and array layers of the source into the
+    /// destination, which must have the same dimensions and storage usage. The source must be
+    /// in the shader read-only layout and the destination in the general layout.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record(
+        &self,
+        device: &ContextDevice,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+        sampler: vk::Sampler,
+        src: &mut Texture,
+        dst: &mut Texture,
+    ) -> Result<(), Box<dyn Error>> {
+        unsafe {
+            device.device.cmd_bind_pipeline(
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.pipeline,
+            )
+        };
+
+        let info = *dst.info();
+        let layer_count = dst.array_layer_count();
+        for level in 0..info.mip_levels {
+            let writes = [
+                DescriptorWrite::Image {
+                    binding: 0,
+                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    view: src.storage_mip_view(level, &device.device)?,
+                    sampler,
+                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+                DescriptorWrite::Image {
+                    binding: 1,
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    view: dst.storage_mip_view(level, &device.device)?,
+                    sampler: vk::Sampler::null(),
+                    layout: vk::ImageLayout::GENERAL,
+                },
+            ];
+            commands.push_descriptor_set(
+                device,
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.layout,
+                self.pipeline.set_layout,
+                0,
+                &writes,
+            )?;
+
+            let width = (info.width >> level).max(1);
+            let height = (info.height >> level).max(1);
+            commands.dispatch(
+                &device.device,
+                cmds,
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                layer_count,
+            );
+        }
+
+        let barriers = dst.layout_barriers(
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            info.mip_levels as usize,
+        );
+        barrier::cmd_image_barriers(device, cmds, &barriers);
+        dst.set_image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.pipeline.destroy(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversion_prefers_blit() {
+        let features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE
+            | vk::FormatFeatureFlags::STORAGE_IMAGE;
+        assert_eq!(
+            select_conversion_path(features, features),
+            Some(ConversionPath::Blit)
+        );
+    }
+
+    #[test]
+    fn conversion_falls_back_to_compute() {
+        assert_eq!(
+            select_conversion_path(
+                vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+                vk::FormatFeatureFlags::STORAGE_IMAGE
+            ),
+            Some(ConversionPath::Compute)
+        );
+    }
+
+    #[test]
+    fn unsupported_conversion_selects_none() {
+        assert_eq!(
+            select_conversion_path(
+                vk::FormatFeatureFlags::BLIT_SRC,
+                vk::FormatFeatureFlags::SAMPLED_IMAGE
+            ),
+            None
+        );
+        assert_eq!(
+            select_conversion_path(
+                vk::FormatFeatureFlags::SAMPLED_IMAGE,
+                vk::FormatFeatureFlags::BLIT_DST
+            ),
+            None
+        );
+    }
+}
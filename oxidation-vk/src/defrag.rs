@@ -0,0 +1,240 @@
+use crate::Driver;
+use crate::buffer::Buffer;
+use crate::error::OxidationError;
+use crate::texture::Texture;
+use crate::vk_check;
+use ash::prelude::VkResult;
+use ash::vk;
+use vk_mem::ffi;
+
+/// A resource whose memory may be relocated by [`Driver::defragment`].
+pub enum Relocatable<'a> {
+    Texture(&'a mut Texture),
+    Buffer(&'a mut Buffer),
+}
+
+impl Relocatable<'_> {
+    fn allocation(&self) -> Option<&vk_mem::Allocation> {
+        match self {
+            Relocatable::Texture(texture) => texture.vma_allocation(),
+            Relocatable::Buffer(buffer) => Some(&buffer.memory),
+        }
+    }
+
+    fn is_relocatable(&self, driver: &Driver) -> bool {
+        match self {
+            Relocatable::Texture(texture) => texture.is_relocatable(),
+            Relocatable::Buffer(buffer) => buffer.is_relocatable(driver),
+        }
+    }
+}
+
+/// The results of a defragmentation.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DefragStats {
+    /// The total number of bytes copied to new locations.
+    pub bytes_moved: vk::DeviceSize,
+    /// The total number of bytes released by freeing empty memory blocks.
+    pub bytes_freed: vk::DeviceSize,
+    pub allocations_moved: u32,
+    pub device_memory_blocks_freed: u32,
+}
+
+/// An object created in the new location of a resource, not yet holding its contents.
+#[derive(Copy, Clone)]
+enum NewObject {
+    Image(vk::Image),
+    Buffer(vk::Buffer),
+}
+
+/// The objects replaced by a relocated resource.
+enum Replaced {
+    Texture(vk::Image, Vec<vk::ImageView>),
+    Buffer(vk::Buffer, Vec<vk::BufferView>),
+}
+
+/// The raw handle of an allocation, for matching allocations to the moves of a pass.
+fn raw_allocation(allocation: &vk_mem::Allocation) -> ffi::VmaAllocation {
+    // SAFETY: `vk_mem::Allocation` is a newtype over the raw allocation handle.
+    unsafe { *(allocation as *const vk_mem::Allocation as *const ffi::VmaAllocation) }
+}
+
+/// View a raw allocation handle, which remains owned by the allocator, as an allocation.
+fn borrow_allocation(raw: &ffi::VmaAllocation) -> &vk_mem::Allocation {
+    // SAFETY: as above - the allocation isn't dropped or freed through the reference.
+    unsafe { &*(raw as *const ffi::VmaAllocation as *const vk_mem::Allocation) }
+}
+
+pub(crate) fn defragment(
+    driver: &Driver,
+    resources: &mut [Relocatable],
+) -> Result<DefragStats, OxidationError> {
+    // Resources are copied and their old objects destroyed, so none may be in use.
+    unsafe { vk_check!(driver.device.device.device_wait_idle(), "vkDeviceWaitIdle")? };
+
+    // Zeroed flags select the balanced algorithm, with no limit on the moves per pass.
+    let info: ffi::VmaDefragmentationInfo = unsafe { std::mem::zeroed() };
+    let context = unsafe {
+        vk_check!(
+            driver.vma_allocator.begin_defragmentation(&info),
+            "vmaBeginDefragmentation"
+        )?
+    };
+    let mut res = Ok(());
+    loop {
+        let more_passes = context.begin_pass(|moves| {
+            res = relocate_moves(driver, resources, moves);
+        });
+        if res.is_err() || !more_passes {
+            break;
+        }
+    }
+    let stats = context.end();
+    res?;
+
+    Ok(DefragStats {
+        bytes_moved: stats.bytesMoved,
+        bytes_freed: stats.bytesFreed,
+        allocations_moved: stats.allocationsMoved,
+        device_memory_blocks_freed: stats.deviceMemoryBlocksFreed,
+    })
+}
+
+/// Perform the moves of a single pass. Allocations which don't belong to one of the resources,
+/// or which can't be relocated, are left in place.
+fn relocate_moves(
+    driver: &Driver,
+    resources: &mut [Relocatable],
+    moves: &mut [ffi::VmaDefragmentationMove],
+) -> Result<(), OxidationError> {
+    let device = &driver.device.device;
+    let ignore = ffi::VmaDefragmentationMoveOperation::VMA_DEFRAGMENTATION_MOVE_OPERATION_IGNORE;
+
+    // The move, the resource and the object created in the new location.
+    let mut pending = Vec::new();
+    for (move_idx, mv) in moves.iter_mut().enumerate() {
+        let resource_idx = resources.iter().position(|resource| {
+            resource.allocation().map(raw_allocation) == Some(mv.srcAllocation)
+                && resource.is_relocatable(driver)
+        });
+        let Some(resource_idx) = resource_idx else {
+            mv.operation = ignore;
+            continue;
+        };
+        // The new object is bound to the temporary allocation, which takes the place of the
+        // source allocation once the pass ends.
+        match create_bound(driver, &resources[resource_idx], &mv.dstTmpAllocation) {
+            Ok(object) => pending.push((move_idx, resource_idx, object)),
+            Err(err) => {
+                log::warn!("Unable to relocate an allocation during defragmentation: {err}");
+                mv.operation = ignore;
+            }
+        }
+    }
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // Only the copies are recorded, so the resources are unchanged if the submit fails.
+    let submit_res = driver.submit_immediate(|cmds| {
+        for (_, resource_idx, object) in pending.iter().copied() {
+            match (&resources[resource_idx], object) {
+                (Relocatable::Texture(texture), NewObject::Image(image)) => {
+                    texture.record_relocation(&driver.device, cmds, image)
+                }
+                (Relocatable::Buffer(buffer), NewObject::Buffer(new_buffer)) => {
+                    buffer.record_relocation(device, cmds, new_buffer)
+                }
+                _ => unreachable!("The new object always matches the resource type."),
+            }
+        }
+    });
+    if let Err(err) = submit_res {
+        for (move_idx, _, object) in pending {
+            moves[move_idx].operation = ignore;
+            destroy_new_object(device, object);
+        }
+        return Err(err);
+    }
+
+    // The copies have completed, so the resources are swapped to their new objects and the
+    // old objects destroyed before the pass ends. Resources which can't be swapped are left
+    // in place.
+    for (move_idx, resource_idx, object) in pending {
+        let res = match (&mut resources[resource_idx], object) {
+            (Relocatable::Texture(texture), NewObject::Image(image)) => texture
+                .replace_image(device, image)
+                .map(|(image, views)| Replaced::Texture(image, views)),
+            (Relocatable::Buffer(buffer), NewObject::Buffer(new_buffer)) => buffer
+                .replace_buffer(device, new_buffer)
+                .map(|(buffer, views)| Replaced::Buffer(buffer, views)),
+            _ => unreachable!("The new object always matches the resource type."),
+        };
+        match res {
+            Ok(old) => destroy_replaced(device, old),
+            Err(err) => {
+                log::warn!("Unable to relocate an allocation during defragmentation: {err}");
+                moves[move_idx].operation = ignore;
+                destroy_new_object(device, object);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn destroy_replaced(device: &ash::Device, old: Replaced) {
+    unsafe {
+        match old {
+            Replaced::Texture(image, views) => {
+                for view in views {
+                    device.destroy_image_view(view, None);
+                }
+                device.destroy_image(image, None);
+            }
+            Replaced::Buffer(buffer, views) => {
+                for view in views {
+                    device.destroy_buffer_view(view, None);
+                }
+                device.destroy_buffer(buffer, None);
+            }
+        }
+    }
+}
+
+/// Create an object matching the resource and bind it to the allocation.
+fn create_bound(
+    driver: &Driver,
+    resource: &Relocatable,
+    allocation: &ffi::VmaAllocation,
+) -> VkResult<NewObject> {
+    let device = &driver.device.device;
+    let allocation = borrow_allocation(allocation);
+    let object = match resource {
+        Relocatable::Texture(texture) => NewObject::Image(texture.create_unbound_image(device)?),
+        Relocatable::Buffer(buffer) => NewObject::Buffer(buffer.create_unbound_buffer(device)?),
+    };
+    let res = unsafe {
+        match object {
+            NewObject::Image(image) => driver.vma_allocator.bind_image_memory(allocation, image),
+            NewObject::Buffer(buffer) => {
+                driver.vma_allocator.bind_buffer_memory(allocation, buffer)
+            }
+        }
+    };
+    match res {
+        Ok(()) => Ok(object),
+        Err(err) => {
+            destroy_new_object(device, object);
+            Err(err)
+        }
+    }
+}
+
+fn destroy_new_object(device: &ash::Device, object: NewObject) {
+    unsafe {
+        match object {
+            NewObject::Image(image) => device.destroy_image(image, None),
+            NewObject::Buffer(buffer) => device.destroy_buffer(buffer, None),
+        }
+    }
+}
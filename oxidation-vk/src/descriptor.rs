@@ -0,0 +1,310 @@
+use crate::device::ContextDevice;
+use ash::prelude::VkResult;
+use ash::vk;
+
+/// A single descriptor write. Used either for pushing descriptors directly into a
+/// command buffer or for updating an allocated descriptor set.
+#[derive(Debug, Copy, Clone)]
+pub enum DescriptorWrite {
+    Buffer {
+        binding: u32,
+        ty: vk::DescriptorType,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    },
+    Image {
+        binding: u32,
+        ty: vk::DescriptorType,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    },
+}
+
+impl DescriptorWrite {
+    pub fn binding(&self) -> u32 {
+        match self {
+            DescriptorWrite::Buffer { binding, .. } => *binding,
+            DescriptorWrite::Image { binding, .. } => *binding,
+        }
+    }
+
+    pub fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            DescriptorWrite::Buffer { ty, .. } => *ty,
+            DescriptorWrite::Image { ty, .. } => *ty,
+        }
+    }
+}
+
+/// The flags which set layouts used with
+/// [`Commands::push_descriptor_set`](crate::commands::Commands::push_descriptor_set) must be
+/// created with - `PUSH_DESCRIPTOR_KHR` when push descriptors are supported by the device.
+pub fn push_set_layout_flags(device: &ContextDevice) -> vk::DescriptorSetLayoutCreateFlags {
+    match device.push_descriptor {
+        Some(_) => vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
+        None => vk::DescriptorSetLayoutCreateFlags::empty(),
+    }
+}
+
+/// Holds the buffer/image infos referenced by a set of Vulkan descriptor writes, so
+/// they outlive the `vk::WriteDescriptorSet`s built from them.
+pub(crate) struct DescriptorWriteInfos {
+    buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    image_infos: Vec<vk::DescriptorImageInfo>,
+}
+
+impl DescriptorWriteInfos {
+    pub(crate) fn new(writes: &[DescriptorWrite]) -> Self {
+        let mut buffer_infos = Vec::new();
+        let mut image_infos = Vec::new();
+        for write in writes {
+            match *write {
+                DescriptorWrite::Buffer {
+                    buffer,
+                    offset,
+                    range,
+                    ..
+                } => buffer_infos.push(
+                    vk::DescriptorBufferInfo::default()
+                        .buffer(buffer)
+                        .offset(offset)
+                        .range(range),
+                ),
+                DescriptorWrite::Image {
+                    view,
+                    sampler,
+                    layout,
+                    ..
+                } => image_infos.push(
+                    vk::DescriptorImageInfo::default()
+                        .image_view(view)
+                        .sampler(sampler)
+                        .image_layout(layout),
+                ),
+            }
+        }
+        Self {
+            buffer_infos,
+            image_infos,
+        }
+    }
+
+    /// Build the Vulkan descriptor writes. The destination set is ignored when pushing descriptors.
+    pub(crate) fn to_vk<'a>(
+        &'a self,
+        writes: &[DescriptorWrite],
+        dst_set: vk::DescriptorSet,
+    ) -> Vec<vk::WriteDescriptorSet<'a>> {
+        let mut buffer_idx = 0;
+        let mut image_idx = 0;
+        writes
+            .iter()
+            .map(|write| {
+                let vk_write = vk::WriteDescriptorSet::default()
+                    .dst_set(dst_set)
+                    .dst_binding(write.binding())
+                    .descriptor_type(write.descriptor_type());
+                match write {
+                    DescriptorWrite::Buffer { .. } => {
+                        buffer_idx += 1;
+                        vk_write.buffer_info(&self.buffer_infos[buffer_idx - 1..buffer_idx])
+                    }
+                    DescriptorWrite::Image { .. } => {
+                        image_idx += 1;
+                        vk_write.image_info(&self.image_infos[image_idx - 1..image_idx])
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Create a descriptor pool which can be used for allocating transient descriptor sets
+/// of the most commonly used descriptor types. Sets allocated from this pool can be freed individually.
+fn create_transient_pool(device: &ash::Device, max_sets: u32) -> VkResult<vk::DescriptorPool> {
+    let pool_sizes = [
+        vk::DescriptorType::UNIFORM_BUFFER,
+        vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+        vk::DescriptorType::STORAGE_BUFFER,
+        vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        vk::DescriptorType::SAMPLED_IMAGE,
+        vk::DescriptorType::STORAGE_IMAGE,
+        vk::DescriptorType::SAMPLER,
+    ]
+    .map(|ty| {
+        vk::DescriptorPoolSize::default()
+            .ty(ty)
+            .descriptor_count(max_sets)
+    });
+    let create_info = vk::DescriptorPoolCreateInfo::default()
+        .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        .max_sets(max_sets)
+        .pool_sizes(&pool_sizes);
+    unsafe { device.create_descriptor_pool(&create_info, None) }
+}
+
+/// A growable list of transient descriptor pools. When the pools are exhausted, another pool
+/// of the same size is created and the allocation retried, rather than failing - the number
+/// of sets needed per frame grows with the complexity of the scene. The first pool is created
+/// on the first allocation.
+pub(crate) struct TransientDescriptorPools {
+    pools: Vec<vk::DescriptorPool>,
+    /// The maximum number of sets, and descriptors of each type, per pool.
+    max_sets: u32,
+}
+
+impl TransientDescriptorPools {
+    pub(crate) fn new(max_sets: u32) -> Self {
+        Self {
+            pools: Vec::new(),
+            max_sets,
+        }
+    }
+
+    /// Allocate a set from the first pool with space for it, creating a new pool if all are
+    /// exhausted. Returns the set along with the pool it was allocated from, for freeing.
+    pub(crate) fn allocate(
+        &mut self,
+        device: &ash::Device,
+        set_layout: vk::DescriptorSetLayout,
+    ) -> VkResult<(vk::DescriptorPool, vk::DescriptorSet)> {
+        let set_layouts = [set_layout];
+        let max_sets = self.max_sets;
+        self.allocate_with(
+            || create_transient_pool(device, max_sets),
+            |pool| {
+                let alloc_info = vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(pool)
+                    .set_layouts(&set_layouts);
+                unsafe {
+                    device
+                        .allocate_descriptor_sets(&alloc_info)
+                        .map(|sets| sets[0])
+                }
+            },
+        )
+    }
+
+    fn allocate_with(
+        &mut self,
+        create_pool: impl FnOnce() -> VkResult<vk::DescriptorPool>,
+        mut allocate: impl FnMut(vk::DescriptorPool) -> VkResult<vk::DescriptorSet>,
+    ) -> VkResult<(vk::DescriptorPool, vk::DescriptorSet)> {
+        // Try the most recently created pool first, as the older pools are likely full.
+        for pool in self.pools.iter().rev() {
+            match allocate(*pool) {
+                Ok(set) => return Ok((*pool, set)),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let pool = create_pool()?;
+        self.pools.push(pool);
+        log::debug!(
+            "Transient descriptor pools exhausted - grown to {} pools.",
+            self.pools.len()
+        );
+        Ok((pool, allocate(pool)?))
+    }
+
+    /// Free sets which were allocated from the pool.
+    pub(crate) fn free(
+        &self,
+        device: &ash::Device,
+        pool: vk::DescriptorPool,
+        sets: &[vk::DescriptorSet],
+    ) -> VkResult<()> {
+        unsafe { device.free_descriptor_sets(pool, sets) }
+    }
+
+    /// Reset all of the pools, freeing every set allocated from them - none may still be in
+    /// use by the device.
+    pub(crate) fn reset(&self, device: &ash::Device) -> VkResult<()> {
+        for pool in &self.pools {
+            unsafe { device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())? };
+        }
+        Ok(())
+    }
+
+    pub(crate) fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub(crate) fn destroy(&mut self, device: &ash::Device) {
+        for pool in self.pools.drain(..) {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+    use std::collections::HashMap;
+
+    /// Simulated pools, each with space for a number of sets.
+    struct FakePools {
+        capacity: u32,
+        allocated: HashMap<vk::DescriptorPool, u32>,
+    }
+
+    impl FakePools {
+        fn allocate(
+            &mut self,
+            pools: &mut TransientDescriptorPools,
+        ) -> VkResult<(vk::DescriptorPool, vk::DescriptorSet)> {
+            let next_pool = vk::DescriptorPool::from_raw(self.allocated.len() as u64 + 1);
+            let capacity = self.capacity;
+            let allocated = &mut self.allocated;
+            pools.allocate_with(
+                || Ok(next_pool),
+                |pool| {
+                    let count = allocated.entry(pool).or_default();
+                    if *count == capacity {
+                        return Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY);
+                    }
+                    *count += 1;
+                    Ok(vk::DescriptorSet::from_raw(
+                        pool.as_raw() * 100 + *count as u64,
+                    ))
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn pools_grow_when_exhausted() {
+        let mut pools = TransientDescriptorPools::new(2);
+        let mut fake = FakePools {
+            capacity: 2,
+            allocated: HashMap::new(),
+        };
+        let first = vk::DescriptorPool::from_raw(1);
+        assert_eq!(fake.allocate(&mut pools).unwrap().0, first);
+        assert_eq!(fake.allocate(&mut pools).unwrap().0, first);
+        assert_eq!(pools.pool_count(), 1);
+
+        // The first pool is full, so another is created.
+        let (pool, set) = fake.allocate(&mut pools).unwrap();
+        assert_eq!(pool, vk::DescriptorPool::from_raw(2));
+        assert_eq!(set, vk::DescriptorSet::from_raw(201));
+        assert_eq!(pools.pool_count(), 2);
+    }
+
+    #[test]
+    fn other_allocation_errors_are_returned() {
+        let mut pools = TransientDescriptorPools::new(2);
+        pools.pools.push(vk::DescriptorPool::from_raw(1));
+        let res = pools.allocate_with(
+            || unreachable!(),
+            |_| Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+        );
+        assert_eq!(res, Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY));
+        assert_eq!(pools.pool_count(), 1);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::Driver;
+use crate::backend::AllocationHint;
+use crate::buffer::Buffer;
+use crate::commands::Commands;
+use crate::descriptor::DescriptorWrite;
+use crate::device::ContextDevice;
+use crate::error::OxidationError;
+use ash::vk;
+use std::collections::VecDeque;
+use std::error::Error;
+
+/// The size of the descriptor buffer created for each command queue.
+pub const DESCRIPTOR_BUFFER_SIZE: vk::DeviceSize = 1 << 20;
+
+/// The sizes of the descriptors written into descriptor buffers, which are specific to the
+/// device, along with the alignment of the descriptor set offsets.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DescriptorBufferProperties {
+    pub offset_alignment: vk::DeviceSize,
+    pub uniform_buffer_size: usize,
+    pub storage_buffer_size: usize,
+    pub combined_image_sampler_size: usize,
+    pub sampled_image_size: usize,
+    pub storage_image_size: usize,
+}
+
+impl DescriptorBufferProperties {
+    pub(crate) fn new(props: &vk::PhysicalDeviceDescriptorBufferPropertiesEXT) -> Self {
+        Self {
+            offset_alignment: props.descriptor_buffer_offset_alignment,
+            uniform_buffer_size: props.uniform_buffer_descriptor_size,
+            storage_buffer_size: props.storage_buffer_descriptor_size,
+            combined_image_sampler_size: props.combined_image_sampler_descriptor_size,
+            sampled_image_size: props.sampled_image_descriptor_size,
+            storage_image_size: props.storage_image_descriptor_size,
+        }
+    }
+
+    /// The size of a descriptor of the type - `None` for types which can't be written into
+    /// a descriptor buffer by [`DescriptorBuffer`], such as dynamic buffers.
+    pub fn descriptor_size(&self, ty: vk::DescriptorType) -> Option<usize> {
+        match ty {
+            vk::DescriptorType::UNIFORM_BUFFER => Some(self.uniform_buffer_size),
+            vk::DescriptorType::STORAGE_BUFFER => Some(self.storage_buffer_size),
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER => Some(self.combined_image_sampler_size),
+            vk::DescriptorType::SAMPLED_IMAGE => Some(self.sampled_image_size),
+            vk::DescriptorType::STORAGE_IMAGE => Some(self.storage_image_size),
+            _ => None,
+        }
+    }
+}
+
+/// The flags which set layouts bound with [`Commands::bind_descriptor_set`] must be created
+/// with - descriptor buffers are used if the commands hold one, then push descriptors,
+/// otherwise transient descriptor sets are allocated.
+pub fn set_layout_flags(
+    device: &ContextDevice,
+    commands: &Commands,
+) -> vk::DescriptorSetLayoutCreateFlags {
+    layout_flags(
+        commands.has_descriptor_buffer(),
+        device.push_descriptor.is_some(),
+    )
+}
+
+/// The flags which pipelines using set layouts created with [`set_layout_flags`] must be
+/// created with.
+pub fn pipeline_create_flags(commands: &Commands) -> vk::PipelineCreateFlags {
+    match commands.has_descriptor_buffer() {
+        true => vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT,
+        false => vk::PipelineCreateFlags::empty(),
+    }
+}
+
+fn layout_flags(
+    descriptor_buffer: bool,
+    push_descriptor: bool,
+) -> vk::DescriptorSetLayoutCreateFlags {
+    if descriptor_buffer {
+        vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT
+    } else if push_descriptor {
+        vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+    } else {
+        vk::DescriptorSetLayoutCreateFlags::empty()
+    }
+}
+
+/// A region of the buffer written to by a command buffer.
+struct Allocation {
+    cmds: vk::CommandBuffer,
+    start: vk::DeviceSize,
+    /// Set once the command buffer has finished on the queue.
+    released: bool,
+}
+
+/// A host visible buffer which descriptors are written directly into, using
+/// `VK_EXT_descriptor_buffer`, rather than allocating and updating descriptor sets. The buffer
+/// is used as a ring - the descriptors written for a command buffer are retained until it has
+/// finished on the queue.
+pub struct DescriptorBuffer {
+    buffer: Buffer,
+    mapped: *mut u8,
+    address: vk::DeviceAddress,
+    /// The end of the most recent allocation.
+    head: vk::DeviceSize,
+    allocations: VecDeque<Allocation>,
+}
+
+impl DescriptorBuffer {
+    /// Create a descriptor buffer of the size. Returns an error if the device doesn't support
+    /// descriptor buffers, or the buffer memory isn't host coherent - descriptors are written
+    /// while recording, without access to the allocator to flush them.
+    pub fn new(driver: &Driver, size: vk::DeviceSize) -> Result<Self, Box<dyn Error>> {
+        if driver.device.descriptor_buffer.is_none() {
+            return Err(Box::new(OxidationError::Unsupported(String::from(
+                "Descriptor buffers require VK_EXT_descriptor_buffer.",
+            ))));
+        }
+        // Combined image samplers require the sampler usage as well as the resource usage.
+        let usage = vk::BufferUsageFlags::RESOURCE_DESCRIPTOR_BUFFER_EXT
+            | vk::BufferUsageFlags::SAMPLER_DESCRIPTOR_BUFFER_EXT
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+        let mut buffer = Buffer::new(driver, size, usage, AllocationHint::Mapped, &[])?;
+        let mapped = driver
+            .vma_allocator
+            .get_allocation_info(&buffer.memory)
+            .mapped_data as *mut u8;
+        let memory_flags = unsafe {
+            driver
+                .vma_allocator
+                .get_allocation_memory_properties(&buffer.memory)
+        };
+        if mapped.is_null() || !memory_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+            buffer.destroy(driver);
+            return Err(Box::from(
+                "The descriptor buffer memory isn't host visible and coherent.",
+            ));
+        }
+        let address = unsafe {
+            driver.device.device.get_buffer_device_address(
+                &vk::BufferDeviceAddressInfo::default().buffer(buffer.buffer),
+            )
+        };
+        Ok(Self {
+            buffer,
+            mapped,
+            address,
+            head: 0,
+            allocations: VecDeque::new(),
+        })
+    }
+
+    /// Write the descriptors of a set into the buffer and bind them to the command buffer.
+    /// The set layout must have been created with the `DESCRIPTOR_BUFFER_EXT` flag, and the
+    /// pipeline with the `DESCRIPTOR_BUFFER_EXT` pipeline flag. Returns an error if the buffer
+    /// is full or a descriptor type can't be written into a descriptor buffer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn bind_set(
+        &mut self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set_layout: vk::DescriptorSetLayout,
+        set: u32,
+        writes: &[DescriptorWrite],
+    ) -> Result<(), OxidationError> {
+        let loader = device.descriptor_buffer.as_ref().ok_or_else(|| {
+            OxidationError::Unsupported(
+                "Descriptor buffers aren't supported by the device.".to_string(),
+            )
+        })?;
+        let props = &device.descriptor_buffer_props;
+
+        let set_size = unsafe { loader.get_descriptor_set_layout_size(set_layout) };
+        let offset = self
+            .allocate(cmds, set_size, props.offset_alignment)
+            .ok_or_else(|| OxidationError::Other("The descriptor buffer is full.".to_string()))?;
+        for write in writes {
+            let ty = write.descriptor_type();
+            let size = props.descriptor_size(ty).ok_or_else(|| {
+                OxidationError::Unsupported(format!(
+                    "Descriptors of type {ty:?} can't be written into a descriptor buffer."
+                ))
+            })?;
+            let binding_offset = unsafe {
+                loader.get_descriptor_set_layout_binding_offset(set_layout, write.binding())
+            };
+            // SAFETY: the allocation lies within the mapped buffer and isn't in use by the
+            // device, as the command buffers which last used it have finished.
+            let dst = unsafe {
+                std::slice::from_raw_parts_mut(
+                    self.mapped.add((offset + binding_offset) as usize),
+                    size,
+                )
+            };
+            write_descriptor(device, loader, write, dst);
+        }
+
+        let binding_info = vk::DescriptorBufferBindingInfoEXT::default()
+            .address(self.address)
+            .usage(self.buffer.usage);
+        unsafe {
+            loader.cmd_bind_descriptor_buffers(cmds, &[binding_info]);
+            loader.cmd_set_descriptor_buffer_offsets(
+                cmds,
+                bind_point,
+                layout,
+                set,
+                &[0],
+                &[offset],
+            );
+        }
+        Ok(())
+    }
+
+    /// Release the regions written for the command buffer, which has finished on the queue.
+    pub(crate) fn release(&mut self, cmds: vk::CommandBuffer) {
+        for allocation in self.allocations.iter_mut() {
+            allocation.released |= allocation.cmds == cmds;
+        }
+        while self
+            .allocations
+            .front()
+            .is_some_and(|allocation| allocation.released)
+        {
+            self.allocations.pop_front();
+        }
+    }
+
+    /// Allocate a region for the command buffer, returning its offset - `None` if there isn't
+    /// a large enough free region.
+    fn allocate(
+        &mut self,
+        cmds: vk::CommandBuffer,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let align = |offset: vk::DeviceSize| offset.next_multiple_of(alignment.max(1));
+        let capacity = self.buffer.size;
+        let start = match self.allocations.front() {
+            None => 0,
+            Some(oldest) => {
+                let tail = oldest.start;
+                let head = align(self.head);
+                // Not wrapped - free space is after the head, and before the tail from the
+                // start of the buffer.
+                if self.head > tail {
+                    if head + size <= capacity {
+                        head
+                    } else if size <= tail {
+                        0
+                    } else {
+                        return None;
+                    }
+                } else if head + size <= tail {
+                    head
+                } else {
+                    return None;
+                }
+            }
+        };
+        if start + size > capacity {
+            return None;
+        }
+        self.head = start + size;
+        self.allocations.push_back(Allocation {
+            cmds,
+            start,
+            released: false,
+        });
+        Some(start)
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        self.allocations.clear();
+        self.buffer.destroy(driver);
+    }
+}
+
+/// Write the descriptor into the memory, which is the size of a descriptor of its type.
+fn write_descriptor(
+    device: &ContextDevice,
+    loader: &ash::ext::descriptor_buffer::Device,
+    write: &DescriptorWrite,
+    dst: &mut [u8],
+) {
+    match *write {
+        DescriptorWrite::Buffer {
+            ty,
+            buffer,
+            offset,
+            range,
+            ..
+        } => {
+            let buffer_address = unsafe {
+                device.device.get_buffer_device_address(
+                    &vk::BufferDeviceAddressInfo::default().buffer(buffer),
+                )
+            };
+            let address_info = vk::DescriptorAddressInfoEXT::default()
+                .address(buffer_address + offset)
+                .range(range);
+            let data = match ty {
+                vk::DescriptorType::UNIFORM_BUFFER => vk::DescriptorDataEXT {
+                    p_uniform_buffer: &address_info,
+                },
+                _ => vk::DescriptorDataEXT {
+                    p_storage_buffer: &address_info,
+                },
+            };
+            let info = vk::DescriptorGetInfoEXT::default().ty(ty).data(data);
+            unsafe { loader.get_descriptor(&info, dst) };
+        }
+        DescriptorWrite::Image {
+            ty,
+            view,
+            sampler,
+            layout,
+            ..
+        } => {
+            let image_info = vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .sampler(sampler)
+                .image_layout(layout);
+            let data = match ty {
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER => vk::DescriptorDataEXT {
+                    p_combined_image_sampler: &image_info,
+                },
+                vk::DescriptorType::SAMPLED_IMAGE => vk::DescriptorDataEXT {
+                    p_sampled_image: &image_info,
+                },
+                _ => vk::DescriptorDataEXT {
+                    p_storage_image: &image_info,
+                },
+            };
+            let info = vk::DescriptorGetInfoEXT::default().ty(ty).data(data);
+            unsafe { loader.get_descriptor(&info, dst) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_flags_follow_the_bind_path() {
+        // The descriptor buffer is preferred, then push descriptors.
+        assert_eq!(
+            layout_flags(true, true),
+            vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT
+        );
+        assert_eq!(
+            layout_flags(false, true),
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+        );
+        assert_eq!(
+            layout_flags(false, false),
+            vk::DescriptorSetLayoutCreateFlags::empty()
+        );
+    }
+}
@@ -1,8 +1,158 @@
 use ash::khr::{surface, swapchain};
 use ash::{Entry, Instance, vk};
+use log::warn;
 use std::error::Error;
+use std::ffi::{CStr, CString, c_char};
 
-use crate::instance::ContextInstance;
+use crate::descriptor_buffer::DescriptorBufferProperties;
+use crate::error::OxidationError;
+use crate::instance::{self, ContextInstance};
+use crate::vk_check;
+
+/// The optional device features which have been enabled. Features are only enabled if
+/// supported by the device, so rendering code should check these to pick a fallback path
+/// where required.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct EnabledFeatures {
+    /// Not all devices (usually low-end hardware) support sampler anisotropy.
+    pub sampler_anisotropy: bool,
+    pub tessellation_shader: bool,
+    pub geometry_shader: bool,
+    pub multi_draw_indirect: bool,
+    pub multi_viewport: bool,
+    pub depth_clamp: bool,
+    /// Required for line (wireframe) and point polygon modes.
+    pub fill_mode_non_solid: bool,
+    /// Sparse binding and residency for 2D images - only enabled if the graphics queue also
+    /// supports sparse binding, as that's where binds are submitted. See [`SparseTexture`].
+    ///
+    /// [`SparseTexture`]: crate::sparse::SparseTexture
+    pub sparse_residency_image_2d: bool,
+    /// Sparse binding and residency for buffers, with the same queue requirement as images.
+    /// See [`SparseBuffer`].
+    ///
+    /// [`SparseBuffer`]: crate::sparse::SparseBuffer
+    pub sparse_residency_buffer: bool,
+    pub texture_compression_etc2: bool,
+    pub texture_compression_bc: bool,
+    pub shader_storage_image_extended_formats: bool,
+    pub multiview: bool,
+    /// Multiview with geometry/tessellation shaders is only enabled if the base feature is also enabled.
+    pub multiview_geometry_shader: bool,
+    pub multiview_tessellation_shader: bool,
+    pub draw_indirect_count: bool,
+    /// Enabled only if all of the descriptor indexing features used for bindless
+    /// sampled images are supported.
+    pub descriptor_indexing: bool,
+    pub dynamic_rendering: bool,
+    /// Core in Vulkan 1.3 - when not enabled the legacy barriers and submits are used.
+    pub synchronization2: bool,
+    pub robust_image_access: bool,
+    /// Whether the depth and stencil aspects of an image can be in different layouts.
+    pub separate_depth_stencil_layouts: bool,
+    /// 16-bit types, i.e. half floats, can be read and written in storage buffers.
+    pub storage_buffer_16bit_access: bool,
+    /// 8-bit integer types can be read and written in storage buffers.
+    pub storage_buffer_8bit_access: bool,
+    /// Buffers can be accessed through their device address.
+    pub buffer_device_address: bool,
+    /// Requires `VK_EXT_swapchain_maintenance1` - allows the present mode of a swapchain to be
+    /// changed without recreating it.
+    pub swapchain_maintenance1: bool,
+    /// Requires `VK_EXT_conditional_rendering` - see [`Commands::begin_conditional_rendering`].
+    ///
+    /// [`Commands::begin_conditional_rendering`]: crate::commands::Commands::begin_conditional_rendering
+    pub conditional_rendering: bool,
+    /// Requires `VK_EXT_descriptor_buffer` and buffer device addresses - descriptors can be
+    /// written directly into buffer memory rather than allocated sets. See [`DescriptorBuffer`].
+    ///
+    /// [`DescriptorBuffer`]: crate::descriptor_buffer::DescriptorBuffer
+    pub descriptor_buffer: bool,
+}
+
+impl EnabledFeatures {
+    /// Determine the features to enable from those supported by the device.
+    pub fn from_supported(
+        core: &vk::PhysicalDeviceFeatures,
+        multiview: &vk::PhysicalDeviceMultiviewFeatures,
+        storage16: &vk::PhysicalDevice16BitStorageFeatures,
+        features12: &vk::PhysicalDeviceVulkan12Features,
+        features13: &vk::PhysicalDeviceVulkan13Features,
+    ) -> Self {
+        let is_set = |b: vk::Bool32| b == vk::TRUE;
+        Self {
+            sampler_anisotropy: is_set(core.sampler_anisotropy),
+            tessellation_shader: is_set(core.tessellation_shader),
+            geometry_shader: is_set(core.geometry_shader),
+            multi_draw_indirect: is_set(core.multi_draw_indirect),
+            multi_viewport: is_set(core.multi_viewport),
+            depth_clamp: is_set(core.depth_clamp),
+            fill_mode_non_solid: is_set(core.fill_mode_non_solid),
+            sparse_residency_image_2d: is_set(core.sparse_binding)
+                && is_set(core.sparse_residency_image2_d),
+            sparse_residency_buffer: is_set(core.sparse_binding)
+                && is_set(core.sparse_residency_buffer),
+            texture_compression_etc2: is_set(core.texture_compression_etc2),
+            texture_compression_bc: is_set(core.texture_compression_bc),
+            shader_storage_image_extended_formats: is_set(
+                core.shader_storage_image_extended_formats,
+            ),
+            multiview: is_set(multiview.multiview),
+            multiview_geometry_shader: is_set(multiview.multiview)
+                && is_set(multiview.multiview_geometry_shader)
+                && is_set(core.geometry_shader),
+            multiview_tessellation_shader: is_set(multiview.multiview)
+                && is_set(multiview.multiview_tessellation_shader)
+                && is_set(core.tessellation_shader),
+            draw_indirect_count: is_set(features12.draw_indirect_count),
+            descriptor_indexing: is_set(features12.descriptor_indexing)
+                && is_set(features12.shader_sampled_image_array_non_uniform_indexing)
+                && is_set(features12.runtime_descriptor_array)
+                && is_set(features12.descriptor_binding_variable_descriptor_count)
+                && is_set(features12.descriptor_binding_partially_bound)
+                && is_set(features12.descriptor_binding_sampled_image_update_after_bind),
+            dynamic_rendering: is_set(features13.dynamic_rendering),
+            synchronization2: is_set(features13.synchronization2),
+            robust_image_access: is_set(features13.robust_image_access),
+            separate_depth_stencil_layouts: is_set(features12.separate_depth_stencil_layouts),
+            storage_buffer_16bit_access: is_set(storage16.storage_buffer16_bit_access),
+            storage_buffer_8bit_access: is_set(features12.storage_buffer8_bit_access),
+            buffer_device_address: is_set(features12.buffer_device_address),
+            // Extension features are determined once the extensions have been filtered.
+            swapchain_maintenance1: false,
+            conditional_rendering: false,
+            descriptor_buffer: false,
+        }
+    }
+
+    /// The size in bytes of the components to use in storage buffer layouts - the preferred
+    /// size of 1 or 2 bytes if the device supports storage buffer access of that size,
+    /// otherwise 4 bytes, so compact layouts fall back to 32-bit components.
+    pub fn storage_component_size(&self, preferred: u32) -> u32 {
+        match preferred {
+            1 if self.storage_buffer_8bit_access => 1,
+            2 if self.storage_buffer_16bit_access => 2,
+            _ => 4,
+        }
+    }
+
+    fn core_features(&self) -> vk::PhysicalDeviceFeatures {
+        vk::PhysicalDeviceFeatures::default()
+            .sampler_anisotropy(self.sampler_anisotropy)
+            .tessellation_shader(self.tessellation_shader)
+            .geometry_shader(self.geometry_shader)
+            .multi_draw_indirect(self.multi_draw_indirect)
+            .multi_viewport(self.multi_viewport)
+            .depth_clamp(self.depth_clamp)
+            .fill_mode_non_solid(self.fill_mode_non_solid)
+            .sparse_binding(self.sparse_residency_image_2d || self.sparse_residency_buffer)
+            .sparse_residency_image2_d(self.sparse_residency_image_2d)
+            .sparse_residency_buffer(self.sparse_residency_buffer)
+            .texture_compression_etc2(self.texture_compression_etc2)
+            .texture_compression_bc(self.texture_compression_bc)
+            .shader_storage_image_extended_formats(self.shader_storage_image_extended_formats)
+    }
+}
 
 pub struct ContextDevice {
     pub device: ash::Device,
@@ -10,9 +160,33 @@ pub struct ContextDevice {
     pub graphics_queue_idx: u32,
     pub compute_queue_idx: u32,
     pub present_queue_idx: u32,
+    /// A dedicated transfer queue if the device has one, otherwise this is the graphics queue.
+    pub transfer_queue_idx: u32,
     pub graphics_queue: vk::Queue,
     pub compute_queue: vk::Queue,
     pub present_queue: vk::Queue,
+    pub transfer_queue: vk::Queue,
+    /// The optional features which were actually enabled on this device.
+    enabled_features: EnabledFeatures,
+    pub limits: vk::PhysicalDeviceLimits,
+    /// Loader for `VK_KHR_push_descriptor` - `None` if the extension isn't supported by the device.
+    pub push_descriptor: Option<ash::khr::push_descriptor::Device>,
+    /// Whether `VK_KHR_swapchain_mutable_format` is enabled, allowing swapchain images to be
+    /// viewed with a different (i.e. sRGB) format.
+    pub swapchain_mutable_format_enabled: bool,
+    /// Loader for `VK_EXT_conditional_rendering` - `None` if the feature isn't enabled.
+    pub conditional_rendering: Option<ash::ext::conditional_rendering::Device>,
+    /// Loader for `VK_EXT_descriptor_buffer` - `None` if the feature isn't enabled.
+    pub descriptor_buffer: Option<ash::ext::descriptor_buffer::Device>,
+    /// The descriptor sizes and alignment of descriptor buffers - zeroed if the feature isn't
+    /// enabled.
+    pub descriptor_buffer_props: DescriptorBufferProperties,
+    /// Loader for debug utils labels - `None` if the debug utils extension isn't enabled.
+    pub debug_utils: Option<ash::ext::debug_utils::Device>,
+    /// The maximum number of views which can be rendered to in a single multiview pass.
+    pub max_multiview_view_count: u32,
+    /// The extensions which were enabled on this device.
+    enabled_extensions: Vec<&'static CStr>,
 }
 
 impl ContextDevice {
@@ -23,7 +197,7 @@ impl ContextDevice {
         let (physical_device, queue_family_idx) =
             find_physical_device(&c_instance.instance, &c_instance.entry, surface)?;
 
-        let (graphics_queue_idx, compute_queue_idx, present_queue_idx) =
+        let (graphics_queue_idx, compute_queue_idx, present_queue_idx, transfer_queue_idx) =
             create_queue_indices(&c_instance.instance, physical_device, queue_family_idx);
 
         let queue_priority = [1.0];
@@ -54,68 +228,220 @@ impl ContextDevice {
             )
         }
 
-        let phys_features = unsafe {
+        // Check for a separate transfer queue.
+        if transfer_queue_idx != graphics_queue_idx && transfer_queue_idx != compute_queue_idx {
+            queue_infos.push(
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(transfer_queue_idx)
+                    .queue_priorities(&queue_priority),
+            )
+        }
+
+        let device_extensions = unsafe {
+            vk_check!(
+                c_instance
+                    .instance
+                    .enumerate_device_extension_properties(physical_device),
+                "vkEnumerateDeviceExtensionProperties"
+            )?
+        };
+        // Descriptor indexing is core in Vulkan 1.2, but the extension is requested for
+        // drivers which still require it.
+        let mut optional_extensions = vec![
+            ash::ext::descriptor_indexing::NAME,
+            ash::khr::push_descriptor::NAME,
+            ash::khr::swapchain_mutable_format::NAME,
+            ash::ext::conditional_rendering::NAME,
+            ash::ext::descriptor_buffer::NAME,
+        ];
+        // Swapchain maintenance depends on the surface maintenance instance extension.
+        if c_instance.surface_maintenance1_enabled {
+            optional_extensions.push(ash::ext::swapchain_maintenance1::NAME);
+        }
+        let enabled_extensions =
+            filter_extensions(&[swapchain::NAME], &optional_extensions, &device_extensions)?;
+        let device_extension_names_raw: Vec<*const c_char> =
+            enabled_extensions.iter().map(|ext| ext.as_ptr()).collect();
+
+        let push_descriptor_supported =
+            enabled_extensions.contains(&ash::khr::push_descriptor::NAME);
+        let swapchain_mutable_format_enabled =
+            enabled_extensions.contains(&ash::khr::swapchain_mutable_format::NAME);
+
+        let conditional_rendering_ext =
+            enabled_extensions.contains(&ash::ext::conditional_rendering::NAME);
+        let swapchain_maintenance1_ext =
+            enabled_extensions.contains(&ash::ext::swapchain_maintenance1::NAME);
+        let descriptor_buffer_ext = enabled_extensions.contains(&ash::ext::descriptor_buffer::NAME);
+
+        // Query the supported features - only those supported are enabled on the device.
+        // The 1.1 features are queried with their individual structs, which can't be chained
+        // along with `PhysicalDeviceVulkan11Features`.
+        let mut supported_multi_view = vk::PhysicalDeviceMultiviewFeatures::default();
+        let mut supported_storage16 = vk::PhysicalDevice16BitStorageFeatures::default();
+        let mut supported_features12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut supported_features13 = vk::PhysicalDeviceVulkan13Features::default();
+        let mut supported_features = vk::PhysicalDeviceFeatures2::default()
+            .push_next(&mut supported_multi_view)
+            .push_next(&mut supported_storage16)
+            .push_next(&mut supported_features12)
+            .push_next(&mut supported_features13);
+        unsafe {
+            c_instance
+                .instance
+                .get_physical_device_features2(physical_device, &mut supported_features)
+        };
+        let supported_core = supported_features.features;
+        let mut enabled_features = EnabledFeatures::from_supported(
+            &supported_core,
+            &supported_multi_view,
+            &supported_storage16,
+            &supported_features12,
+            &supported_features13,
+        );
+
+        let queue_properties = unsafe {
             c_instance
                 .instance
-                .get_physical_device_features(physical_device)
+                .get_physical_device_queue_family_properties(physical_device)
         };
+        let graphics_sparse_binding = queue_properties[graphics_queue_idx as usize]
+            .queue_flags
+            .contains(vk::QueueFlags::SPARSE_BINDING);
+        enabled_features.sparse_residency_image_2d &= graphics_sparse_binding;
+        enabled_features.sparse_residency_buffer &= graphics_sparse_binding;
 
-        let mut robust_info = vk::PhysicalDeviceImageRobustnessFeatures {
-            robust_image_access: vk::TRUE,
-            ..Default::default()
+        // Extension features can only be queried if the extension is supported.
+        let mut supported_conditional =
+            vk::PhysicalDeviceConditionalRenderingFeaturesEXT::default();
+        let mut supported_maintenance1 =
+            vk::PhysicalDeviceSwapchainMaintenance1FeaturesEXT::default();
+        let mut supported_descriptor_buffer =
+            vk::PhysicalDeviceDescriptorBufferFeaturesEXT::default();
+        let mut supported_ext_features = vk::PhysicalDeviceFeatures2::default();
+        if conditional_rendering_ext {
+            supported_ext_features = supported_ext_features.push_next(&mut supported_conditional);
+        }
+        if swapchain_maintenance1_ext {
+            supported_ext_features = supported_ext_features.push_next(&mut supported_maintenance1);
+        }
+        if descriptor_buffer_ext {
+            supported_ext_features =
+                supported_ext_features.push_next(&mut supported_descriptor_buffer);
+        }
+        unsafe {
+            c_instance
+                .instance
+                .get_physical_device_features2(physical_device, &mut supported_ext_features)
         };
+        enabled_features.conditional_rendering =
+            supported_conditional.conditional_rendering == vk::TRUE;
+        enabled_features.swapchain_maintenance1 =
+            supported_maintenance1.swapchain_maintenance1 == vk::TRUE;
+        // Buffer descriptors are written from the device address of the buffer.
+        enabled_features.descriptor_buffer = supported_descriptor_buffer.descriptor_buffer
+            == vk::TRUE
+            && enabled_features.buffer_device_address;
+
+        let mut multi_view_info = vk::PhysicalDeviceMultiviewFeatures::default()
+            .multiview(enabled_features.multiview)
+            .multiview_geometry_shader(enabled_features.multiview_geometry_shader)
+            .multiview_tessellation_shader(enabled_features.multiview_tessellation_shader);
+        let mut storage16_info = vk::PhysicalDevice16BitStorageFeatures::default()
+            .storage_buffer16_bit_access(enabled_features.storage_buffer_16bit_access);
+        let di = enabled_features.descriptor_indexing;
         let mut features12 = vk::PhysicalDeviceVulkan12Features::default()
-            .draw_indirect_count(true)
-            .shader_sampled_image_array_non_uniform_indexing(true)
-            .runtime_descriptor_array(true)
-            .descriptor_binding_variable_descriptor_count(true)
-            .descriptor_binding_partially_bound(true)
-            .descriptor_binding_sampled_image_update_after_bind(true)
-            .descriptor_indexing(true);
-        let mut multi_view_info = vk::PhysicalDeviceMultiviewFeaturesKHR::default()
-            .multiview(true)
-            .multiview_geometry_shader(true)
-            .multiview_tessellation_shader(true);
-
-        let phys_dev_features = vk::PhysicalDeviceFeatures {
-            texture_compression_etc2: phys_features.texture_compression_etc2,
-            texture_compression_bc: phys_features.texture_compression_bc,
-            sampler_anisotropy: phys_features.sampler_anisotropy,
-            tessellation_shader: phys_features.tessellation_shader,
-            shader_storage_image_extended_formats: phys_features
-                .shader_storage_image_extended_formats,
-            multi_draw_indirect: phys_features.multi_draw_indirect,
-            multi_viewport: phys_features.multi_viewport,
-            depth_clamp: phys_features.depth_clamp,
-            ..Default::default()
-        };
+            .draw_indirect_count(enabled_features.draw_indirect_count)
+            .shader_sampled_image_array_non_uniform_indexing(di)
+            .runtime_descriptor_array(di)
+            .descriptor_binding_variable_descriptor_count(di)
+            .descriptor_binding_partially_bound(di)
+            .descriptor_binding_sampled_image_update_after_bind(di)
+            .descriptor_indexing(di)
+            .separate_depth_stencil_layouts(enabled_features.separate_depth_stencil_layouts)
+            .storage_buffer8_bit_access(enabled_features.storage_buffer_8bit_access)
+            .buffer_device_address(enabled_features.buffer_device_address);
+        // Vulkan 1.3 features - these are core in 1.3 but the device may be limited to an older version.
+        let mut features13 = vk::PhysicalDeviceVulkan13Features::default()
+            .dynamic_rendering(enabled_features.dynamic_rendering)
+            .synchronization2(enabled_features.synchronization2)
+            .robust_image_access(enabled_features.robust_image_access);
+
+        let mut conditional_rendering_info =
+            vk::PhysicalDeviceConditionalRenderingFeaturesEXT::default()
+                .conditional_rendering(enabled_features.conditional_rendering);
+
+        let mut descriptor_buffer_info = vk::PhysicalDeviceDescriptorBufferFeaturesEXT::default()
+            .descriptor_buffer(enabled_features.descriptor_buffer);
+
         let mut required_features = vk::PhysicalDeviceFeatures2::default()
-            .features(phys_dev_features)
+            .features(enabled_features.core_features())
             .push_next(&mut multi_view_info)
+            .push_next(&mut storage16_info)
             .push_next(&mut features12)
-            .push_next(&mut robust_info);
-
-        let device_extension_names_raw = [
-            swapchain::NAME.as_ptr(),
-            // TODO: Check that this is valid for the device.
-            ash::ext::descriptor_indexing::NAME.as_ptr(),
-        ];
+            .push_next(&mut features13);
+        let mut swapchain_maintenance1_info =
+            vk::PhysicalDeviceSwapchainMaintenance1FeaturesEXT::default()
+                .swapchain_maintenance1(enabled_features.swapchain_maintenance1);
+        if conditional_rendering_ext {
+            required_features = required_features.push_next(&mut conditional_rendering_info);
+        }
+        if swapchain_maintenance1_ext {
+            required_features = required_features.push_next(&mut swapchain_maintenance1_info);
+        }
+        if descriptor_buffer_ext {
+            required_features = required_features.push_next(&mut descriptor_buffer_info);
+        }
 
         let device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_infos)
-            .enabled_features(&phys_features)
             .enabled_extension_names(&device_extension_names_raw)
             .push_next(&mut required_features);
 
         let device = unsafe {
+            vk_check!(
+                c_instance
+                    .instance
+                    .create_device(physical_device, &device_create_info, None),
+                "vkCreateDevice"
+            )?
+        };
+
+        let mut multi_view_props = vk::PhysicalDeviceMultiviewProperties::default();
+        let mut descriptor_buffer_props =
+            vk::PhysicalDeviceDescriptorBufferPropertiesEXT::default();
+        let mut props = vk::PhysicalDeviceProperties2::default().push_next(&mut multi_view_props);
+        if descriptor_buffer_ext {
+            props = props.push_next(&mut descriptor_buffer_props);
+        }
+        unsafe {
             c_instance
                 .instance
-                .create_device(physical_device, &device_create_info, None)?
+                .get_physical_device_properties2(physical_device, &mut props)
         };
+        let limits = props.properties.limits;
+        let descriptor_buffer_props = DescriptorBufferProperties::new(&descriptor_buffer_props);
+
+        let descriptor_buffer = enabled_features
+            .descriptor_buffer
+            .then(|| ash::ext::descriptor_buffer::Device::new(&c_instance.instance, &device));
+
+        let push_descriptor = push_descriptor_supported
+            .then(|| ash::khr::push_descriptor::Device::new(&c_instance.instance, &device));
+
+        let conditional_rendering = enabled_features
+            .conditional_rendering
+            .then(|| ash::ext::conditional_rendering::Device::new(&c_instance.instance, &device));
+
+        let debug_utils = c_instance
+            .debug_loader
+            .as_ref()
+            .map(|_| ash::ext::debug_utils::Device::new(&c_instance.instance, &device));
 
         let graphics_queue = unsafe { device.get_device_queue(graphics_queue_idx, 0) };
         let compute_queue = unsafe { device.get_device_queue(compute_queue_idx, 0) };
         let present_queue = unsafe { device.get_device_queue(present_queue_idx, 0) };
+        let transfer_queue = unsafe { device.get_device_queue(transfer_queue_idx, 0) };
 
         Ok(Self {
             device,
@@ -123,12 +449,62 @@ impl ContextDevice {
             graphics_queue_idx,
             compute_queue_idx,
             present_queue_idx,
+            transfer_queue_idx,
             graphics_queue,
             compute_queue,
             present_queue,
+            transfer_queue,
+            enabled_features,
+            limits,
+            max_multiview_view_count: multi_view_props.max_multiview_view_count,
+            push_descriptor,
+            swapchain_mutable_format_enabled,
+            conditional_rendering,
+            descriptor_buffer,
+            descriptor_buffer_props,
+            debug_utils,
+            enabled_extensions,
         })
     }
 
+    /// The optional features which were enabled on this device.
+    pub fn enabled_features(&self) -> EnabledFeatures {
+        self.enabled_features
+    }
+
+    /// The extensions which were enabled on this device, both required and optional.
+    pub fn enabled_extensions(&self) -> &[&'static CStr] {
+        &self.enabled_extensions
+    }
+
+    /// Open a debug label region on the queue, used by tools such as RenderDoc to delimit
+    /// work. This is a no-op if the debug utils extension isn't enabled. Returns an error if
+    /// the name contains a nul byte.
+    pub fn begin_queue_label(
+        &self,
+        queue: vk::Queue,
+        name: &str,
+        color: [f32; 4],
+    ) -> Result<(), OxidationError> {
+        if let Some(debug_utils) = &self.debug_utils {
+            let name = CString::new(name).map_err(|err| {
+                OxidationError::Other(format!("Invalid queue label {name:?}: {err}"))
+            })?;
+            let label = vk::DebugUtilsLabelEXT::default()
+                .label_name(&name)
+                .color(color);
+            unsafe { debug_utils.queue_begin_debug_utils_label(queue, &label) };
+        }
+        Ok(())
+    }
+
+    /// Close the last debug label region opened on the queue.
+    pub fn end_queue_label(&self, queue: vk::Queue) {
+        if let Some(debug_utils) = &self.debug_utils {
+            unsafe { debug_utils.queue_end_debug_utils_label(queue) };
+        }
+    }
+
     pub fn destroy(&mut self) {
         unsafe { self.device.destroy_device(None) };
     }
@@ -180,7 +556,7 @@ fn create_queue_indices(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
     queue_family_idx: u32,
-) -> (u32, u32, u32) {
+) -> (u32, u32, u32, u32) {
     let graphics_queue_idx = queue_family_idx;
     // This could potentially get over-ridden if there is a separate queue on the device.
     let mut compute_queue_idx = graphics_queue_idx;
@@ -197,6 +573,254 @@ fn create_queue_indices(
             compute_queue_idx = idx as u32;
         }
     }
+    let transfer_queue_idx =
+        find_transfer_queue_family(&queue_properties).unwrap_or(graphics_queue_idx);
+
+    (
+        graphics_queue_idx,
+        compute_queue_idx,
+        present_queue_idx,
+        transfer_queue_idx,
+    )
+}
+
+/// Find a queue family which supports transfer but not graphics or compute operations. Transfer
+/// only queue families are usually backed by the device's dedicated DMA engines, so are the
+/// most efficient choice for uploads. Returns `None` if the device has no such queue family.
+///
+/// # Examples
+///
+/// ```
+/// use ash::vk;
+/// use oxidation_vk::device::find_transfer_queue_family;
+///
+/// let families = [
+///     vk::QueueFamilyProperties::default()
+///         .queue_flags(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+///     vk::QueueFamilyProperties::default()
+///         .queue_flags(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+///     vk::QueueFamilyProperties::default().queue_flags(vk::QueueFlags::TRANSFER),
+/// ];
+/// assert_eq!(find_transfer_queue_family(&families), Some(2));
+/// assert_eq!(find_transfer_queue_family(&families[..2]), None);
+/// ```
+///
+pub fn find_transfer_queue_family(queue_properties: &[vk::QueueFamilyProperties]) -> Option<u32> {
+    queue_properties
+        .iter()
+        .position(|prop| {
+            prop.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !prop
+                    .queue_flags
+                    .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .map(|idx| idx as u32)
+}
+
+/// Get the sharing mode for a resource which is accessed by the specified queue families. If
+/// more than one distinct family is specified, the resource is created with `CONCURRENT`
+/// sharing along with the distinct family indices, otherwise `EXCLUSIVE` sharing is used.
+///
+/// Concurrent access can be slower on some hardware (e.g. disabling compression of images)
+/// but avoids the queue family ownership transfer barriers required for exclusive
+/// resources. Prefer exclusive sharing with ownership transfers for resources which
+/// are frequently accessed, such as render targets.
+///
+/// # Examples
+///
+/// ```
+/// use ash::vk;
+/// use oxidation_vk::device::queue_sharing;
+///
+/// let (mode, families) = queue_sharing(&[0, 2, 0]);
+/// assert_eq!(mode, vk::SharingMode::CONCURRENT);
+/// assert_eq!(families, vec![0, 2]);
+///
+/// let (mode, families) = queue_sharing(&[1, 1]);
+/// assert_eq!(mode, vk::SharingMode::EXCLUSIVE);
+/// assert!(families.is_empty());
+/// ```
+///
+pub fn queue_sharing(shared_queues: &[u32]) -> (vk::SharingMode, Vec<u32>) {
+    let mut families: Vec<u32> = Vec::with_capacity(shared_queues.len());
+    for idx in shared_queues {
+        if !families.contains(idx) {
+            families.push(*idx);
+        }
+    }
+    match families.len() > 1 {
+        true => (vk::SharingMode::CONCURRENT, families),
+        false => (vk::SharingMode::EXCLUSIVE, Vec::new()),
+    }
+}
 
-    (graphics_queue_idx, compute_queue_idx, present_queue_idx)
+/// Filter the device extensions to those advertised by the device. Optional extensions which
+/// aren't available are dropped (with a warning), while a missing required extension is an error.
+///
+/// # Examples
+///
+/// ```ignore
+/// let available = unsafe { instance.enumerate_device_extension_properties(physical_device)? };
+/// let extensions = filter_extensions(
+///     &[ash::khr::swapchain::NAME],
+///     &[ash::khr::push_descriptor::NAME],
+///     &available,
+/// )?;
+/// ```
+///
+pub fn filter_extensions<'a>(
+    required: &[&'a CStr],
+    optional: &[&'a CStr],
+    available: &[vk::ExtensionProperties],
+) -> Result<Vec<&'a CStr>, Box<dyn Error>> {
+    let missing: Vec<&CStr> = required
+        .iter()
+        .copied()
+        .filter(|ext| !instance::find_extension(ext, available))
+        .collect();
+    if !missing.is_empty() {
+        return Err(Box::from(format!(
+            "Required device extension(s) {missing:?} are not supported by the device."
+        )));
+    }
+
+    let mut extensions = required.to_vec();
+    for ext in optional {
+        match instance::find_extension(ext, available) {
+            true => extensions.push(ext),
+            false => warn!("Device extension {ext:?} is not supported by the device - skipping."),
+        }
+    }
+    Ok(extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn available(names: &[&CStr]) -> Vec<vk::ExtensionProperties> {
+        names
+            .iter()
+            .map(|name| {
+                vk::ExtensionProperties::default()
+                    .extension_name(name)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unavailable_optional_extensions_are_dropped() {
+        let available = available(&[ash::khr::swapchain::NAME]);
+        let extensions = filter_extensions(
+            &[ash::khr::swapchain::NAME],
+            &[ash::khr::push_descriptor::NAME],
+            &available,
+        )
+        .unwrap();
+        assert_eq!(extensions, [ash::khr::swapchain::NAME]);
+    }
+
+    #[test]
+    fn available_optional_extensions_are_kept() {
+        let available = available(&[ash::khr::push_descriptor::NAME, ash::khr::swapchain::NAME]);
+        let extensions = filter_extensions(
+            &[ash::khr::swapchain::NAME],
+            &[ash::khr::push_descriptor::NAME],
+            &available,
+        )
+        .unwrap();
+        assert_eq!(
+            extensions,
+            [ash::khr::swapchain::NAME, ash::khr::push_descriptor::NAME]
+        );
+    }
+
+    #[test]
+    fn missing_required_extension_is_an_error() {
+        let available = available(&[ash::khr::push_descriptor::NAME]);
+        assert!(filter_extensions(&[ash::khr::swapchain::NAME], &[], &available).is_err());
+    }
+
+    fn features_from(
+        core: vk::PhysicalDeviceFeatures,
+        multiview: vk::PhysicalDeviceMultiviewFeatures,
+    ) -> EnabledFeatures {
+        EnabledFeatures::from_supported(
+            &core,
+            &multiview,
+            &vk::PhysicalDevice16BitStorageFeatures::default(),
+            &vk::PhysicalDeviceVulkan12Features::default(),
+            &vk::PhysicalDeviceVulkan13Features::default(),
+        )
+    }
+
+    #[test]
+    fn unsupported_features_stay_disabled() {
+        let features = features_from(
+            vk::PhysicalDeviceFeatures::default(),
+            vk::PhysicalDeviceMultiviewFeatures::default(),
+        );
+        assert_eq!(features, EnabledFeatures::default());
+    }
+
+    #[test]
+    fn supported_features_are_enabled() {
+        let core = vk::PhysicalDeviceFeatures::default()
+            .sampler_anisotropy(true)
+            .tessellation_shader(true)
+            .fill_mode_non_solid(true);
+        let features = features_from(core, vk::PhysicalDeviceMultiviewFeatures::default());
+        assert!(features.sampler_anisotropy);
+        assert!(features.tessellation_shader);
+        assert!(features.fill_mode_non_solid);
+        assert!(!features.geometry_shader);
+        assert!(!features.texture_compression_bc);
+    }
+
+    #[test]
+    fn dependent_features_require_their_base_feature() {
+        // Sparse residency without sparse binding, and multiview geometry shaders without
+        // geometry shaders, can't be enabled.
+        let core = vk::PhysicalDeviceFeatures::default()
+            .sparse_residency_image2_d(true)
+            .sparse_residency_buffer(true);
+        let multiview = vk::PhysicalDeviceMultiviewFeatures::default()
+            .multiview(true)
+            .multiview_geometry_shader(true);
+        let features = features_from(core, multiview);
+        assert!(!features.sparse_residency_image_2d);
+        assert!(!features.sparse_residency_buffer);
+        assert!(features.multiview);
+        assert!(!features.multiview_geometry_shader);
+    }
+
+    #[test]
+    fn small_storage_access_is_enabled_only_when_supported() {
+        let storage16 =
+            vk::PhysicalDevice16BitStorageFeatures::default().storage_buffer16_bit_access(true);
+        let features12 =
+            vk::PhysicalDeviceVulkan12Features::default().storage_buffer8_bit_access(true);
+        let supported = EnabledFeatures::from_supported(
+            &vk::PhysicalDeviceFeatures::default(),
+            &vk::PhysicalDeviceMultiviewFeatures::default(),
+            &storage16,
+            &features12,
+            &vk::PhysicalDeviceVulkan13Features::default(),
+        );
+        assert!(supported.storage_buffer_16bit_access);
+        assert!(supported.storage_buffer_8bit_access);
+        assert_eq!(supported.storage_component_size(1), 1);
+        assert_eq!(supported.storage_component_size(2), 2);
+
+        let unsupported = features_from(
+            vk::PhysicalDeviceFeatures::default(),
+            vk::PhysicalDeviceMultiviewFeatures::default(),
+        );
+        assert!(!unsupported.storage_buffer_16bit_access);
+        assert!(!unsupported.storage_buffer_8bit_access);
+        // Compact layouts fall back to 32-bit components.
+        assert_eq!(unsupported.storage_component_size(1), 4);
+        assert_eq!(unsupported.storage_component_size(2), 4);
+    }
 }
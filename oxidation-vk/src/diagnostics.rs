@@ -0,0 +1,149 @@
+use crate::Driver;
+use ash::khr::surface;
+use ash::vk;
+use std::ffi::CStr;
+use std::fmt::Write;
+
+impl Driver {
+    /// A human readable report of the device and surface the driver was created with, for
+    /// including in bug reports - the GPU, driver and API versions, the enabled extensions and
+    /// features, the queue family layout, and the formats and present modes of the surface.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let driver = oxidation_vk::Driver::new(extensions, &window)?;
+    /// log::info!("{}", driver.diagnostics());
+    /// ```
+    ///
+    pub fn diagnostics(&self) -> String {
+        let instance = &self.instance.instance;
+        let physical_device = self.device.physical_device;
+        let props = unsafe { instance.get_physical_device_properties(physical_device) };
+
+        // Writing into a string can't fail, so the results are ignored.
+        let mut report = device_report(&props, self.device.enabled_extensions());
+        let _ = writeln!(
+            report,
+            "Enabled features: {:#?}",
+            self.device.enabled_features()
+        );
+
+        let _ = writeln!(
+            report,
+            "Queue families: graphics {}, compute {}, present {}, transfer {}",
+            self.device.graphics_queue_idx,
+            self.device.compute_queue_idx,
+            self.device.present_queue_idx,
+            self.device.transfer_queue_idx
+        );
+        let families =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        for (idx, family) in families.iter().enumerate() {
+            let _ = writeln!(
+                report,
+                "    {idx}: {:?} x{}",
+                family.queue_flags, family.queue_count
+            );
+        }
+
+        let surface_loader = surface::Instance::new(&self.instance.entry, instance);
+        match unsafe {
+            surface_loader.get_physical_device_surface_formats(physical_device, self.surface)
+        } {
+            Ok(formats) => {
+                let _ = writeln!(report, "Surface formats:");
+                for format in formats {
+                    let _ = writeln!(report, "    {:?} {:?}", format.format, format.color_space);
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(report, "Surface formats: unavailable ({err})");
+            }
+        }
+        match unsafe {
+            surface_loader.get_physical_device_surface_present_modes(physical_device, self.surface)
+        } {
+            Ok(modes) => {
+                let _ = writeln!(report, "Present modes: {modes:?}");
+            }
+            Err(err) => {
+                let _ = writeln!(report, "Present modes: unavailable ({err})");
+            }
+        }
+        report
+    }
+}
+
+/// The part of the report describing the GPU, its driver and API versions and the enabled
+/// device extensions.
+fn device_report(props: &vk::PhysicalDeviceProperties, extensions: &[&CStr]) -> String {
+    let device_name = props
+        .device_name_as_c_str()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| String::from("<invalid name>"));
+
+    let mut report = String::new();
+    let _ = writeln!(report, "GPU: {device_name} ({:?})", props.device_type);
+    let _ = writeln!(
+        report,
+        "Vendor ID: {:#06x}, device ID: {:#06x}",
+        props.vendor_id, props.device_id
+    );
+    let _ = writeln!(
+        report,
+        "Driver version: {} ({:#x})",
+        format_version(props.driver_version),
+        props.driver_version
+    );
+    let _ = writeln!(
+        report,
+        "Vulkan API version: {}",
+        format_version(props.api_version)
+    );
+
+    let _ = writeln!(report, "Enabled device extensions:");
+    for extension in extensions {
+        let _ = writeln!(report, "    {}", extension.to_string_lossy());
+    }
+    report
+}
+
+/// Format a version packed in the Vulkan API version encoding.
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_report_contains_the_gpu_and_extensions() {
+        let props = vk::PhysicalDeviceProperties::default()
+            .device_name(c"Test GPU")
+            .unwrap()
+            .device_type(vk::PhysicalDeviceType::DISCRETE_GPU)
+            .vendor_id(0x10de)
+            .api_version(vk::make_api_version(0, 1, 3, 250));
+        let report = device_report(
+            &props,
+            &[ash::khr::swapchain::NAME, ash::ext::mesh_shader::NAME],
+        );
+        assert!(report.contains("GPU: Test GPU (DISCRETE_GPU)"));
+        assert!(report.contains("Vendor ID: 0x10de"));
+        assert!(report.contains("Vulkan API version: 1.3.250"));
+        assert!(report.contains("    VK_KHR_swapchain\n"));
+        assert!(report.contains("    VK_EXT_mesh_shader\n"));
+    }
+
+    #[test]
+    fn versions_are_unpacked() {
+        assert_eq!(format_version(vk::make_api_version(0, 1, 2, 3)), "1.2.3");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::Driver;
+use crate::barrier;
+use crate::host_memory;
+use crate::vk_check;
+use ash::vk;
+use std::error::Error;
+use vk_mem::Alloc;
+
+/// A host-visible buffer used as the copy source when the geometry buffer isn't host visible.
+struct StagingRing {
+    buffer: vk::Buffer,
+    memory: vk_mem::Allocation,
+}
+
+/// A buffer for geometry which is regenerated every frame, such as UI or debug lines. The buffer
+/// is split into a region per frame in flight, so the host can write the geometry of the next
+/// frame while the previous frames are still being read by the device.
+///
+/// Where the device has host-visible device local memory (i.e. resizable BAR), the geometry
+/// is written directly into the buffer. Otherwise, it's written into a persistent staging ring
+/// and copied to the device buffer on `flush()`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut geometry = DynamicGeometryBuffer::new(&driver, 64 * 1024, 3)?;
+///
+/// // Each frame..
+/// geometry.begin_frame(frame_index);
+/// let vertex_offset = geometry.append(&vertices, 4)?;
+/// let index_offset = geometry.append(&indices, 4)?;
+/// geometry.flush(&driver, cmds)?;
+/// ```
+///
+pub struct DynamicGeometryBuffer {
+    buffer: vk::Buffer,
+    memory: vk_mem::Allocation,
+    /// The mapped memory which is written to - either the device buffer or the staging ring.
+    mapped: *mut u8,
+    staging: Option<StagingRing>,
+    frame_size: vk::DeviceSize,
+    frame_count: u32,
+    current_frame: u32,
+    /// The write offset within the region of the current frame.
+    write_offset: vk::DeviceSize,
+}
+
+impl DynamicGeometryBuffer {
+    /// Create a buffer with a region of `frame_size` bytes for each of the frames in flight.
+    pub fn new(
+        driver: &Driver,
+        frame_size: vk::DeviceSize,
+        frame_count: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let size = buffer_size(frame_size, frame_count)?;
+        let create_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        // Prefer host-visible device local memory, allowing VMA to pick non host-visible memory
+        // in which case a staging copy is required.
+        let alloc_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::AutoPreferDevice,
+            flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+                | vk_mem::AllocationCreateFlags::HOST_ACCESS_ALLOW_TRANSFER_INSTEAD
+                | vk_mem::AllocationCreateFlags::MAPPED,
+            ..Default::default()
+        };
+        let (buffer, mut memory) = unsafe {
+            vk_check!(
+                driver
+                    .vma_allocator
+                    .create_buffer(&create_info, &alloc_info),
+                "vmaCreateBuffer"
+            )?
+        };
+
+        let mem_props = unsafe {
+            driver
+                .vma_allocator
+                .get_allocation_memory_properties(&memory)
+        };
+        let (mapped, staging) = match mem_props.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            true => (
+                driver
+                    .vma_allocator
+                    .get_allocation_info(&memory)
+                    .mapped_data as *mut u8,
+                None,
+            ),
+            false => {
+                let create_info = vk::BufferCreateInfo::default()
+                    .size(size)
+                    .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                let alloc_info = vk_mem::AllocationCreateInfo {
+                    usage: vk_mem::MemoryUsage::Auto,
+                    flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+                        | vk_mem::AllocationCreateFlags::MAPPED,
+                    ..Default::default()
+                };
+                let staging = unsafe {
+                    vk_check!(
+                        driver
+                            .vma_allocator
+                            .create_buffer(&create_info, &alloc_info),
+                        "vmaCreateBuffer"
+                    )
+                };
+                let (staging_buffer, staging_memory) = match staging {
+                    Ok(staging) => staging,
+                    Err(err) => {
+                        unsafe { driver.vma_allocator.destroy_buffer(buffer, &mut memory) };
+                        return Err(Box::new(err));
+                    }
+                };
+                let mapped = driver
+                    .vma_allocator
+                    .get_allocation_info(&staging_memory)
+                    .mapped_data as *mut u8;
+                (
+                    mapped,
+                    Some(StagingRing {
+                        buffer: staging_buffer,
+                        memory: staging_memory,
+                    }),
+                )
+            }
+        };
+
+        Ok(Self {
+            buffer,
+            memory,
+            mapped,
+            staging,
+            frame_size,
+            frame_count,
+            current_frame: 0,
+            write_offset: 0,
+        })
+    }
+
+    /// Whether the geometry is written directly into device visible memory, with no staging copy.
+    pub fn is_direct(&self) -> bool {
+        self.staging.is_none()
+    }
+
+    /// The buffer to bind as the vertex and/or index buffer.
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    /// Begin writing the geometry of a new frame. The region for the frame is reused, so the
+    /// device must have finished with the frame `frame_count` frames prior.
+    pub fn begin_frame(&mut self, frame_index: u64) {
+        self.current_frame = frame_slot(frame_index, self.frame_count);
+        self.write_offset = 0;
+    }
+
+    /// Append the data to the region of the current frame, returning the offset of the data
+    /// within the buffer for use when binding or drawing. The data is aligned to `alignment`
+    /// bytes, e.g. the vertex stride or index size.
+    pub fn append<T: Copy>(
+        &mut self,
+        data: &[T],
+        alignment: vk::DeviceSize,
+    ) -> Result<vk::DeviceSize, Box<dyn Error>> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let offset = self.write_offset.next_multiple_of(alignment.max(1));
+        if offset + size > self.frame_size {
+            return Err(Box::from(format!(
+                "Dynamic geometry of {size} bytes exceeds the remaining space of the frame region \
+                ({} of {} bytes used).",
+                self.write_offset, self.frame_size
+            )));
+        }
+
+        let buffer_offset = self.frame_offset() + offset;
+        unsafe {
+            self.mapped
+                .add(buffer_offset as usize)
+                .copy_from_nonoverlapping(data.as_ptr() as *const u8, size as usize)
+        };
+        self.write_offset = offset + size;
+        Ok(buffer_offset)
+    }
+
+    /// Make the geometry of the current frame visible to the device. If the buffer isn't
+    /// host-visible, a copy from the staging ring is recorded into the command buffer, which
+    /// must be before any draws which use the geometry.
+    pub fn flush(
+        &mut self,
+        driver: &Driver,
+        cmds: vk::CommandBuffer,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.write_offset == 0 {
+            return Ok(());
+        }
+        let offset = self.frame_offset();
+        let size = self.write_offset;
+
+        let Some(staging) = &self.staging else {
+            host_memory::flush_mapped(driver, &self.memory, offset, size)?;
+            return Ok(());
+        };
+
+        host_memory::flush_mapped(driver, &staging.memory, offset, size)?;
+        let region = vk::BufferCopy::default()
+            .src_offset(offset)
+            .dst_offset(offset)
+            .size(size);
+        let barrier = vk::BufferMemoryBarrier2::default()
+            .buffer(self.buffer)
+            .offset(offset)
+            .size(size)
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_stage_mask(vk::PipelineStageFlags2::VERTEX_INPUT)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags2::VERTEX_ATTRIBUTE_READ | vk::AccessFlags2::INDEX_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED);
+        unsafe {
+            driver
+                .device
+                .device
+                .cmd_copy_buffer(cmds, staging.buffer, self.buffer, &[region])
+        };
+        barrier::cmd_barriers(&driver.device, cmds, &[], &[barrier]);
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        unsafe {
+            driver
+                .vma_allocator
+                .destroy_buffer(self.buffer, &mut self.memory);
+            if let Some(mut staging) = self.staging.take() {
+                driver
+                    .vma_allocator
+                    .destroy_buffer(staging.buffer, &mut staging.memory);
+            }
+        }
+    }
+
+    fn frame_offset(&self) -> vk::DeviceSize {
+        region_offset(self.current_frame, self.frame_size)
+    }
+}
+
+/// The size of the buffer holding a region of `frame_size` bytes for each frame in flight.
+fn buffer_size(
+    frame_size: vk::DeviceSize,
+    frame_count: u32,
+) -> Result<vk::DeviceSize, Box<dyn Error>> {
+    if frame_size == 0 || frame_count == 0 {
+        return Err(Box::from(
+            "Dynamic geometry buffers require a non-zero frame size and frame count.",
+        ));
+    }
+    frame_size
+        .checked_mul(frame_count as vk::DeviceSize)
+        .ok_or_else(|| {
+            Box::from(format!(
+                "{frame_count} frames of {frame_size} bytes overflows the buffer size."
+            ))
+        })
+}
+
+/// The region of the buffer which is written by the frame.
+fn frame_slot(frame_index: u64, frame_count: u32) -> u32 {
+    (frame_index % frame_count as u64) as u32
+}
+
+fn region_offset(slot: u32, frame_size: vk::DeviceSize) -> vk::DeviceSize {
+    slot as vk::DeviceSize * frame_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_size_rejects_empty_regions() {
+        assert!(buffer_size(1024, 0).is_err());
+        assert!(buffer_size(0, 3).is_err());
+        assert!(buffer_size(vk::DeviceSize::MAX, 2).is_err());
+        assert_eq!(buffer_size(1024, 3).unwrap(), 3072);
+    }
+
+    #[test]
+    fn frames_cycle_through_regions() {
+        let offsets: Vec<vk::DeviceSize> = (0..7)
+            .map(|frame| region_offset(frame_slot(frame, 3), 256))
+            .collect();
+        assert_eq!(offsets, [0, 256, 512, 0, 256, 512, 0]);
+        assert_eq!(frame_slot(u64::MAX, 1), 0);
+    }
+}
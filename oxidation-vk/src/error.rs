@@ -0,0 +1,82 @@
+use ash::prelude::VkResult;
+use ash::vk;
+use log::error;
+use std::error::Error;
+use std::fmt;
+
+/// Errors returned by the backend and engine.
+#[derive(Debug)]
+pub enum OxidationError {
+    /// A Vulkan call failed with the specified result.
+    VkResult(vk::Result),
+    /// An operation requires a current swapchain but none has been set.
+    NoCurrentSwapchain,
+    /// The device, surface or format doesn't support the requested operation.
+    Unsupported(String),
+    /// Acquiring a swapchain image timed out or no image was ready - this isn't fatal, the
+    /// frame can be skipped and the acquire retried on the next frame.
+    AcquireTimeout,
+    /// Any other error, described by the message.
+    Other(String),
+}
+
+impl fmt::Display for OxidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OxidationError::VkResult(res) => write!(f, "Vulkan error: {res}"),
+            OxidationError::NoCurrentSwapchain => write!(f, "No current swapchain has been set."),
+            OxidationError::Unsupported(msg) => write!(f, "Unsupported: {msg}"),
+            OxidationError::AcquireTimeout => write!(f, "Timed out acquiring a swapchain image."),
+            OxidationError::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl Error for OxidationError {}
+
+impl OxidationError {
+    /// Whether the error is transient, with the frame able to be skipped rather than
+    /// treating the error as fatal.
+    pub fn is_frame_skippable(&self) -> bool {
+        matches!(self, OxidationError::AcquireTimeout)
+    }
+}
+
+impl From<vk::Result> for OxidationError {
+    fn from(res: vk::Result) -> Self {
+        OxidationError::VkResult(res)
+    }
+}
+
+impl From<Box<dyn Error>> for OxidationError {
+    fn from(err: Box<dyn Error>) -> Self {
+        OxidationError::Other(err.to_string())
+    }
+}
+
+/// Check the result of a Vulkan call, logging the failed operation and result code if it
+/// wasn't successful. Usually used via the [`vk_check!`](crate::vk_check) macro.
+pub fn vk_check<T>(res: VkResult<T>, context: &str) -> Result<T, OxidationError> {
+    res.map_err(|err| {
+        error!("{context} failed: {err}");
+        OxidationError::VkResult(err)
+    })
+}
+
+/// Check the result of a Vulkan call, logging the context along with the result code on
+/// failure and converting the error to [`OxidationError::VkResult`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let swapchain = unsafe {
+///     vk_check!(loader.create_swapchain(&create_info, None), "vkCreateSwapchainKHR")?
+/// };
+/// ```
+///
+#[macro_export]
+macro_rules! vk_check {
+    ($expr:expr, $context:expr) => {
+        $crate::error::vk_check($expr, $context)
+    };
+}
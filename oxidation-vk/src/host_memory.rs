@@ -0,0 +1,63 @@
+use crate::Driver;
+use crate::error::OxidationError;
+use crate::vk_check;
+use ash::vk;
+
+/// Whether the memory of the allocation is host coherent, in which case host writes are
+/// visible to the device (and device writes to the host) without flushing or invalidating.
+pub fn is_host_coherent(driver: &Driver, allocation: &vk_mem::Allocation) -> bool {
+    let flags = unsafe {
+        driver
+            .allocator()
+            .get_allocation_memory_properties(allocation)
+    };
+    flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+}
+
+/// Make host writes to the range of the mapped allocation visible to the device. VMA skips
+/// the flush for host coherent memory, and aligns the range to the non-coherent atom size.
+///
+/// # Examples
+///
+/// ```ignore
+/// mapped.copy_from_nonoverlapping(data.as_ptr(), data.len());
+/// host_memory::flush_mapped(&driver, &buffer.memory, 0, data.len() as vk::DeviceSize)?;
+/// ```
+///
+pub fn flush_mapped(
+    driver: &Driver,
+    allocation: &vk_mem::Allocation,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> Result<(), OxidationError> {
+    flush_range(driver.allocator(), allocation, offset, size)
+}
+
+/// As [`flush_mapped`], for when the driver is borrowed elsewhere - i.e. by the staging pool.
+pub(crate) fn flush_range(
+    allocator: &vk_mem::Allocator,
+    allocation: &vk_mem::Allocation,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> Result<(), OxidationError> {
+    vk_check!(
+        allocator.flush_allocation(allocation, offset, size),
+        "vmaFlushAllocation"
+    )
+}
+
+/// Make device writes to the range of the mapped allocation visible to the host, before
+/// reading it. As with [`flush_mapped`], VMA skips this for host coherent memory.
+pub fn invalidate_mapped(
+    driver: &Driver,
+    allocation: &vk_mem::Allocation,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> Result<(), OxidationError> {
+    vk_check!(
+        driver
+            .allocator()
+            .invalidate_allocation(allocation, offset, size),
+        "vmaInvalidateAllocation"
+    )
+}
@@ -8,6 +8,9 @@ pub struct ContextInstance {
     pub instance: ash::Instance,
     pub debug_loader: Option<debug_utils::Instance>,
     pub debug_callback: vk::DebugUtilsMessengerEXT,
+    /// Whether `VK_EXT_surface_maintenance1` (and `VK_KHR_get_surface_capabilities2` which it
+    /// depends on) are enabled - required for the swapchain maintenance device extension.
+    pub surface_maintenance1_enabled: bool,
 }
 
 impl ContextInstance {
@@ -32,7 +35,14 @@ impl ContextInstance {
 
         // Instance extensions.
         let extension_props = unsafe { entry.enumerate_instance_extension_properties(None)? };
-        let instance_extensions = create_extensions(&extension_props, win_extension_names)?;
+        let mut instance_extensions = create_extensions(&extension_props, win_extension_names)?;
+        let surface_maintenance1_enabled =
+            find_extension(ash::ext::surface_maintenance1::NAME, &extension_props)
+                && find_extension(ash::khr::get_surface_capabilities2::NAME, &extension_props);
+        if surface_maintenance1_enabled {
+            instance_extensions.push(ash::khr::get_surface_capabilities2::NAME.as_ptr());
+            instance_extensions.push(ash::ext::surface_maintenance1::NAME.as_ptr());
+        }
 
         let app_name = c"OxidationEngine";
         let app_info = vk::ApplicationInfo::default()
@@ -87,6 +97,7 @@ impl ContextInstance {
             instance: vk_instance,
             debug_loader,
             debug_callback,
+            surface_maintenance1_enabled,
         })
     }
 
@@ -132,7 +143,7 @@ fn find_layer_properties(layer_name: &CStr, layer_props: &[vk::LayerProperties])
     })
 }
 
-fn find_extension(ext_name: &CStr, extensions: &[vk::ExtensionProperties]) -> bool {
+pub(crate) fn find_extension(ext_name: &CStr, extensions: &[vk::ExtensionProperties]) -> bool {
     extensions.iter().any(|layer| {
         let tmp = ext_name.to_string_lossy();
         tmp == unsafe { CStr::from_ptr(layer.extension_name.as_ptr()).to_string_lossy() }
@@ -147,11 +158,12 @@ fn create_extensions(
 
     // Check that all required window extensions are available.
     for ext_name in required_extensions {
-        match find_extension(unsafe { CStr::from_ptr(ext_name) }, extensions) {
+        let name = unsafe { CStr::from_ptr(ext_name) };
+        match find_extension(name, extensions) {
             false => {
-                return Err(Box::from(
-                    "Required extension name not found for device instance.",
-                ));
+                return Err(Box::from(format!(
+                    "Required extension {name:?} not found for device instance."
+                )));
             }
             true => out.push(ext_name),
         };
@@ -172,6 +184,10 @@ fn create_extensions(
     if find_extension(ash::khr::multiview::NAME, extensions) {
         out.push(ash::khr::multiview::NAME.as_ptr());
     }
+    // Required for surfaces to report the extended (i.e. HDR) color spaces.
+    if find_extension(ash::ext::swapchain_colorspace::NAME, extensions) {
+        out.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
+    }
 
     #[cfg(debug_assertions)]
     {
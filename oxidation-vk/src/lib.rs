@@ -1,20 +1,50 @@
 pub mod backend;
+pub mod barrier;
+pub mod buffer;
+pub mod clear;
 pub mod commands;
+pub mod convert;
+pub mod defrag;
+pub mod descriptor;
+pub mod descriptor_buffer;
 pub mod device;
+mod diagnostics;
+pub mod dynamic_buffer;
+pub mod error;
+pub mod host_memory;
 pub mod instance;
+pub mod memory_pool;
+pub mod mip_streaming;
+pub mod mipmap;
+pub mod pipeline;
+pub mod post;
+pub mod rendering;
 mod sampler_cache;
+pub mod shader;
+pub mod sparse;
 pub mod staging_pool;
+pub mod submit_batch;
 pub mod swapchain;
+pub mod sync;
 pub mod texture;
+pub mod thread_commands;
+pub mod timestamp;
+pub mod transient;
+pub mod upload_queue;
 
 use crate::commands::Commands;
+use crate::descriptor_buffer::DescriptorBuffer;
 use crate::device::ContextDevice;
 use crate::instance::ContextInstance;
+use crate::memory_pool::MemoryPools;
 use crate::staging_pool::StagingPool;
+use crate::submit_batch::{SemaphoreSubmit, SubmitBatch};
 
 use crate::sampler_cache::SamplerCache;
 pub use ash::{Entry, Instance, vk};
-use std::ffi::c_char;
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, c_char};
+use std::marker::PhantomData;
 use std::mem::ManuallyDrop;
 pub use std::{error::Error, rc::Rc};
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
@@ -29,14 +59,23 @@ use winit::window::Window;
 /// from the engine side.
 ///
 /// # Safety
-/// The driver itself is to be used on a single thread, though its
-/// associated caches (i.e., pipeline and descriptor) are designed to be
-/// used across multiple threads.
+/// The driver, along with its caches, is to be used on a single thread -
+/// the caches use interior mutability (`RefCell`) so resources can be
+/// created through a shared `Rc<Driver>`. This is enforced by the driver
+/// being neither `Send` nor `Sync`, so it can't be moved to another thread:
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<oxidation_vk::Driver>();
+/// ```
+///
+/// Command buffers can still be recorded on worker threads, using a
+/// [`ThreadLocalCommands`](thread_commands::ThreadLocalCommands) per queue family.
 ///
 /// # Examples
 ///
 /// Driver setup.
-/// ```
+/// ```ignore
 /// use ash::vk;
 /// use winit::{window::WindowAttributes, event_loop};
 /// use oxidation_vk as ovk;
@@ -50,18 +89,31 @@ use winit::window::Window;
 pub struct Driver {
     pub device: ContextDevice,
     pub instance: ContextInstance,
-    vma_allocator: ManuallyDrop<vk_mem::Allocator>,
+    /// Boxed as memory pools refer to the allocator, so it mustn't move with the driver.
+    vma_allocator: ManuallyDrop<Box<vk_mem::Allocator>>,
+    /// Custom memory pools created with [`Driver::create_memory_pool`].
+    memory_pools: MemoryPools,
     /// Semaphore used to signal that the image is ready for presentation.
     image_ready_signal: vk::Semaphore,
     /// The current presentation image index that is written to.
-    current_image_index: u32,
+    current_image_index: Cell<u32>,
     /// The window surface which is associated with this driver context.
     pub surface: vk::SurfaceKHR,
-    pub sampler_cache: sampler_cache::SamplerCache,
+    /// The sampler cache is behind a `RefCell` so resources can be created through a shared driver.
+    pub sampler_cache: RefCell<SamplerCache>,
+    default_sampler_info: Cell<backend::SamplerInfo>,
     /// Separate commands for compute and graphics (should really check if the device has separate queues).
     pub graphics_commands: Commands,
     pub compute_commands: Commands,
     pub staging_pool: StagingPool,
+    /// Used for generating mip maps of formats which can't be blitted - `None` until enabled.
+    mip_generator: Option<mipmap::ComputeMipGenerator>,
+    /// Used for clearing storage images which can't be cleared by a transfer - `None` until enabled.
+    compute_clear: Option<clear::ComputeClear>,
+    /// Used for converting between formats which can't be blitted - `None` until enabled.
+    format_converter: Option<convert::ComputeFormatConverter>,
+    /// Marks the driver as `!Send` and `!Sync` - see the safety section above.
+    _not_send: PhantomData<*const ()>,
 }
 
 impl Driver {
@@ -70,6 +122,23 @@ impl Driver {
         extension_names: Vec<*const c_char>,
         window: &Window,
     ) -> Result<Self, Box<dyn Error>> {
+        // Check the surface extensions required by the window are to be enabled, otherwise
+        // creating the surface will fail with a less helpful error.
+        let display_handle = window.display_handle()?.as_raw();
+        let surface_extensions = ash_window::enumerate_required_extensions(display_handle)?;
+        for required in surface_extensions {
+            let required = unsafe { CStr::from_ptr(*required) };
+            let enabled = extension_names
+                .iter()
+                .any(|name| unsafe { CStr::from_ptr(*name) } == required);
+            if !enabled {
+                return Err(Box::from(format!(
+                    "The surface extension {required:?} required by the window is missing from \
+                    the instance extensions.",
+                )));
+            }
+        }
+
         // Create the main vulkan instance for a given set of display extensions.
         let instance = ContextInstance::new(extension_names)?;
 
@@ -78,7 +147,7 @@ impl Driver {
             ash_window::create_surface(
                 &instance.entry,
                 &instance.instance,
-                window.display_handle().unwrap().as_raw(),
+                display_handle,
                 window.window_handle().unwrap().as_raw(),
                 None,
             )?
@@ -93,11 +162,22 @@ impl Driver {
             device.physical_device,
         );
         create_info.vulkan_api_version = vk::make_api_version(0, 1, 3, 0);
-        let vma_allocator = unsafe { ManuallyDrop::new(vk_mem::Allocator::new(create_info)?) };
+        if device.enabled_features().buffer_device_address {
+            create_info.flags |= vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+        }
+        let vma_allocator = unsafe {
+            ManuallyDrop::new(Box::new(vk_check!(
+                vk_mem::Allocator::new(create_info),
+                "vmaCreateAllocator"
+            )?))
+        };
 
         let semaphore_info = vk::SemaphoreCreateInfo::default();
         let image_ready_signal = unsafe { device.device.create_semaphore(&semaphore_info, None)? };
-        let sampler_cache = SamplerCache::new();
+        let sampler_cache = RefCell::new(SamplerCache::new(
+            device.enabled_features().sampler_anisotropy,
+            &device.limits,
+        ));
 
         let staging_pool = StagingPool::new();
         let graphics_commands = Commands::new(
@@ -111,18 +191,341 @@ impl Driver {
             &device.device,
         );
 
-        Ok(Self {
+        let mut driver = Self {
             device,
             instance,
             vma_allocator,
+            memory_pools: MemoryPools::default(),
             image_ready_signal,
-            current_image_index: 0,
+            current_image_index: Cell::new(0),
             surface,
             sampler_cache,
+            default_sampler_info: Cell::new(backend::SamplerInfo::linear_repeat()),
             graphics_commands,
             compute_commands,
             staging_pool,
-        })
+            mip_generator: None,
+            compute_clear: None,
+            format_converter: None,
+            _not_send: PhantomData,
+        };
+
+        // Descriptors bound through the commands are written into descriptor buffers where
+        // supported, otherwise they fall back to push descriptors. Either both queues use
+        // descriptor buffers or neither does, so set layouts and pipelines created for one
+        // queue's commands can be bound with the other's.
+        if driver.device.descriptor_buffer.is_some() {
+            let size = descriptor_buffer::DESCRIPTOR_BUFFER_SIZE;
+            match (
+                DescriptorBuffer::new(&driver, size),
+                DescriptorBuffer::new(&driver, size),
+            ) {
+                (Ok(graphics), Ok(compute)) => {
+                    driver.graphics_commands.enable_descriptor_buffer(graphics);
+                    driver.compute_commands.enable_descriptor_buffer(compute);
+                }
+                (graphics, compute) => {
+                    for res in [graphics, compute] {
+                        match res {
+                            Ok(mut buffer) => buffer.destroy(&driver),
+                            Err(err) => {
+                                log::warn!("Unable to create a descriptor buffer: {err}")
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(driver)
+    }
+
+    /// The sampler info used by the default sampler - initially linear filtering, repeat
+    /// addressing and access to all mip levels of the image view.
+    pub fn default_sampler_info(&self) -> backend::SamplerInfo {
+        self.default_sampler_info.get()
+    }
+
+    /// Change the sampler info used by the default sampler, i.e. for a global texture quality
+    /// setting. Textures already created with the default sampler keep the previous sampler,
+    /// which remains valid until the driver is destroyed.
+    pub fn set_default_sampler_info(&self, info: backend::SamplerInfo) {
+        self.default_sampler_info.set(info);
+    }
+
+    /// A sampler with sensible defaults for textures which don't require specific sampling.
+    /// This is created on first use and cached along with all other samplers.
+    pub fn default_sampler(&self) -> vk::Sampler {
+        self.sampler_cache
+            .borrow_mut()
+            .get_or_create_sampler(&self.default_sampler_info.get(), &self.device.device)
+    }
+
+    /// The memory allocator used for all buffers and images created through the driver.
+    /// Resources created outside of the crate should be allocated with this rather than a
+    /// separate allocator, and must be destroyed before the driver is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let (buffer, mut memory) = unsafe { driver.allocator().create_buffer(&create_info, &alloc_info)? };
+    /// let stats = driver.allocator().calculate_statistics()?;
+    /// ```
+    ///
+    pub fn allocator(&self) -> &vk_mem::Allocator {
+        &self.vma_allocator
+    }
+
+    /// Defragment device memory, relocating the memory of the resources to compact the
+    /// allocator's memory blocks and free those left empty. Long running streaming workloads
+    /// fragment memory, which can eventually cause allocations to fail despite enough memory
+    /// being free overall.
+    ///
+    /// Only the memory of the specified resources is moved - their images and buffers are
+    /// recreated in the new location, with the contents copied, and their views recreated.
+    /// Transient textures and mapped buffers are never moved. Any descriptors referring to the
+    /// old objects must be rewritten.
+    ///
+    /// This waits for the device to become idle, so should be called at a frame boundary, once
+    /// all submitted work has been waited on.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Every few hundred frames, between frames.
+    /// let mut resources: Vec<Relocatable> = textures.iter_mut().map(Relocatable::Texture).collect();
+    /// let stats = driver.defragment(&mut resources)?;
+    /// log::info!("Defragmentation freed {} bytes.", stats.bytes_freed);
+    /// ```
+    ///
+    pub fn defragment(
+        &self,
+        resources: &mut [defrag::Relocatable],
+    ) -> Result<defrag::DefragStats, error::OxidationError> {
+        defrag::defragment(self, resources)
+    }
+
+    /// The formats and color spaces supported by the window surface, i.e. for listing the
+    /// available SDR and HDR modes before creating a swapchain. See
+    /// [`swapchain::group_surface_formats`] for splitting these by dynamic range.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let formats = driver.supported_surface_formats()?;
+    /// let (sdr, hdr) = oxidation_vk::swapchain::group_surface_formats(&formats);
+    /// ```
+    ///
+    pub fn supported_surface_formats(
+        &self,
+    ) -> Result<Vec<vk::SurfaceFormatKHR>, error::OxidationError> {
+        let surface_loader =
+            ash::khr::surface::Instance::new(&self.instance.entry, &self.instance.instance);
+        unsafe {
+            vk_check!(
+                surface_loader
+                    .get_physical_device_surface_formats(self.device.physical_device, self.surface),
+                "vkGetPhysicalDeviceSurfaceFormatsKHR"
+            )
+        }
+    }
+
+    /// Enable mip map generation with a compute shader for formats which can't be blitted
+    /// but support storage images. See [`mipmap::ComputeMipGenerator`] for the shader interface.
+    pub fn enable_compute_mipmaps(
+        &mut self,
+        shader: vk::ShaderModule,
+    ) -> Result<(), Box<dyn Error>> {
+        let generator = mipmap::ComputeMipGenerator::new(&self.device, shader)?;
+        if let Some(mut old) = self.mip_generator.replace(generator) {
+            old.destroy(&self.device.device);
+        }
+        Ok(())
+    }
+
+    /// Enable clearing storage images with a compute shader for formats which can't be cleared
+    /// by a transfer. See [`clear::ComputeClear`] for the shader interface.
+    pub fn enable_compute_clears(
+        &mut self,
+        shader: vk::ShaderModule,
+    ) -> Result<(), Box<dyn Error>> {
+        let compute_clear = clear::ComputeClear::new(&self.device, shader)?;
+        if let Some(mut old) = self.compute_clear.replace(compute_clear) {
+            old.destroy(&self.device.device);
+        }
+        Ok(())
+    }
+
+    /// Enable converting textures with a compute shader between formats which can't be
+    /// blitted. See [`convert::ComputeFormatConverter`] for the shader interface.
+    pub fn enable_compute_format_conversion(
+        &mut self,
+        shader: vk::ShaderModule,
+    ) -> Result<(), Box<dyn Error>> {
+        let converter = convert::ComputeFormatConverter::new(&self.device, shader)?;
+        if let Some(mut old) = self.format_converter.replace(converter) {
+            old.destroy(&self.device.device);
+        }
+        Ok(())
+    }
+
+    /// Record and submit one-off work to the graphics queue, waiting for it to complete. The
+    /// closure records the work into the command buffer, which has already begun recording.
+    /// As this waits on the queue, it should only be used for setup work such as resource
+    /// initialisation rather than per-frame work.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// driver.submit_immediate(|cmds| {
+    ///     texture.transition(&driver.device, cmds, old_layout, new_layout, src, dst, 1);
+    /// })?;
+    /// ```
+    ///
+    pub fn submit_immediate<F: FnOnce(vk::CommandBuffer)>(
+        &self,
+        f: F,
+    ) -> Result<(), error::OxidationError> {
+        self.submit_immediate_after(&[], f)
+    }
+
+    /// As [`Self::submit_immediate`], but the work waits on the semaphores before executing -
+    /// i.e. waiting for an acquired swapchain image to become available.
+    pub fn submit_immediate_after<F: FnOnce(vk::CommandBuffer)>(
+        &self,
+        wait_semaphores: &[SemaphoreSubmit],
+        f: F,
+    ) -> Result<(), error::OxidationError> {
+        let device = &self.device.device;
+        let cmds = self.graphics_commands.allocate_one_shot(device)?;
+        let fence = match unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None) } {
+            Ok(fence) => fence,
+            Err(err) => {
+                self.graphics_commands.free_one_shot(device, cmds);
+                return Err(err.into());
+            }
+        };
+
+        let res = unsafe {
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device
+                .begin_command_buffer(cmds, &begin_info)
+                .and_then(|_| {
+                    f(cmds);
+                    device.end_command_buffer(cmds)
+                })
+                .and_then(|_| {
+                    let mut batch = SubmitBatch::new();
+                    batch.add(cmds, wait_semaphores, &[]);
+                    batch.submit(&self.device, self.graphics_commands.queue(), fence)
+                })
+                .and_then(|_| device.wait_for_fences(&[fence], true, u64::MAX))
+        };
+
+        unsafe { device.destroy_fence(fence, None) };
+        self.graphics_commands.free_one_shot(device, cmds);
+        res.map_err(error::OxidationError::from)
+    }
+
+    /// The semaphore signalled once the image acquired by [`Self::acquire_next_image`] is
+    /// ready to be rendered into.
+    pub fn image_ready_signal(&self) -> vk::Semaphore {
+        self.image_ready_signal
+    }
+
+    /// The index of the swapchain image which is currently being rendered to.
+    pub fn current_image_index(&self) -> u32 {
+        self.current_image_index.get()
+    }
+
+    /// Acquire the next image of the swapchain, which becomes the current image. Returns the
+    /// image index and whether the swapchain is suboptimal. See
+    /// [`Swapchain::acquire_next_image`](swapchain::Swapchain::acquire_next_image) for details
+    /// of the timeout behaviour.
+    pub fn acquire_next_image(
+        &self,
+        swapchain: &swapchain::Swapchain,
+        timeout_ns: u64,
+    ) -> Result<(u32, bool), error::OxidationError> {
+        let (image_index, suboptimal) =
+            swapchain.acquire_next_image(self.image_ready_signal, timeout_ns)?;
+        self.current_image_index.set(image_index);
+        Ok((image_index, suboptimal))
+    }
+
+    /// Present multiple swapchains with a single queue present, so multi-window setups present
+    /// all windows together. Each entry is the swapchain, the image index to present and the
+    /// semaphore to wait on before presenting (which may be null).
+    ///
+    /// The results are returned per swapchain in the same order as specified - `Ok(true)`
+    /// indicates the swapchain is suboptimal for the surface, and `ERROR_OUT_OF_DATE_KHR` that
+    /// the swapchain must be recreated before presenting again. The other swapchains are
+    /// unaffected by a failed present.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let results = driver.present_multi(&[
+    ///     (&main_swapchain, main_idx, main_signal),
+    ///     (&aux_swapchain, aux_idx, aux_signal),
+    /// ]);
+    /// if results[1] == Err(vk::Result::ERROR_OUT_OF_DATE_KHR) {
+    ///     // Recreate the aux swapchain..
+    /// }
+    /// ```
+    ///
+    pub fn present_multi(
+        &self,
+        presents: &[(&swapchain::Swapchain, u32, vk::Semaphore)],
+    ) -> Vec<Result<bool, vk::Result>> {
+        let Some((first, _, _)) = presents.first() else {
+            return Vec::new();
+        };
+
+        let batch = swapchain::PresentBatch::new(presents.iter().map(|p| (p.0.instance, p.1, p.2)));
+        let mut results = vec![vk::Result::SUCCESS; presents.len()];
+        // Each swapchain with present fences signals a fence once its present completes.
+        let mut present_fences = Vec::with_capacity(presents.len());
+        for (swapchain, image_index, _) in presents {
+            match swapchain.present_fence(&self.device.device, *image_index) {
+                Ok(fence) => present_fences.push(fence),
+                Err(err) => return vec![Err(err); presents.len()],
+            }
+        }
+
+        let mut present_info = batch.present_info(&mut results);
+        // Present with the current mode of each swapchain, which may have changed since
+        // creation - see `Swapchain::set_present_mode`.
+        let present_modes: Vec<vk::PresentModeKHR> =
+            presents.iter().map(|p| p.0.present_mode()).collect();
+        let mut present_mode_info =
+            vk::SwapchainPresentModeInfoEXT::default().present_modes(&present_modes);
+        let mut present_fence_info =
+            vk::SwapchainPresentFenceInfoEXT::default().fences(&present_fences);
+        if self.device.enabled_features().swapchain_maintenance1 {
+            present_info = present_info
+                .push_next(&mut present_mode_info)
+                .push_next(&mut present_fence_info);
+        }
+        // The overall result is ignored as it only reflects the most severe of the individual
+        // results, which are returned instead. Device loss is reported for all swapchains.
+        let res = unsafe {
+            first
+                .swapchain_loader
+                .queue_present(self.device.present_queue, &present_info)
+        };
+        if let Err(err) = res
+            && err == vk::Result::ERROR_DEVICE_LOST
+        {
+            return vec![Err(err); presents.len()];
+        }
+
+        for ((swapchain, image_index, _), res) in presents.iter().zip(results.iter()) {
+            if matches!(*res, vk::Result::SUCCESS | vk::Result::SUBOPTIMAL_KHR) {
+                swapchain.on_presented(*image_index);
+            }
+        }
+        swapchain::PresentBatch::map_results(results)
     }
 
     pub fn is_depth_format(format: &vk::Format) -> bool {
@@ -157,10 +560,27 @@ impl Drop for Driver {
         };
 
         // Manually destroy all objects as relying on RAII for this seems too risky.
-        self.sampler_cache.destroy(&self.device.device);
+        self.sampler_cache.get_mut().destroy(&self.device.device);
+        if let Some(generator) = self.mip_generator.as_mut() {
+            generator.destroy(&self.device.device);
+        }
+        if let Some(compute_clear) = self.compute_clear.as_mut() {
+            compute_clear.destroy(&self.device.device);
+        }
+        if let Some(converter) = self.format_converter.as_mut() {
+            converter.destroy(&self.device.device);
+        }
+        let descriptor_buffers = [
+            self.graphics_commands.take_descriptor_buffer(),
+            self.compute_commands.take_descriptor_buffer(),
+        ];
+        for mut descriptor_buffer in descriptor_buffers.into_iter().flatten() {
+            descriptor_buffer.destroy(self);
+        }
         self.staging_pool.destroy(&self.vma_allocator);
         self.compute_commands.destroy(&self.device.device);
         self.graphics_commands.destroy(&self.device.device);
+        self.memory_pools.destroy_all();
         // Manually dropping the VMA allocator to ensure its lifetime outlives
         // that of the staging pool and resources.
         unsafe { ManuallyDrop::drop(&mut self.vma_allocator) };
@@ -0,0 +1,252 @@
+use crate::Driver;
+use crate::backend::AllocationHint;
+use crate::error::OxidationError;
+use crate::texture::{Texture, TextureInfo};
+use crate::vk_check;
+use ash::vk;
+use std::cell::RefCell;
+use vk_mem::Alloc;
+
+/// Refers to a memory pool created with [`Driver::create_memory_pool`]. Resources are
+/// allocated from the pool with [`AllocationHint::Custom`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct MemoryPoolHandle(usize);
+
+/// The parameters of a custom memory pool, i.e. a pool of a specific memory type with large
+/// blocks for transient render targets.
+///
+/// # Examples
+///
+/// ```ignore
+/// let memory_type_index =
+///     memory_pool::image_memory_type(&driver, &target_info, vk::ImageUsageFlags::COLOR_ATTACHMENT)?;
+/// let pool = driver.create_memory_pool(&MemoryPoolConfig {
+///     memory_type_index,
+///     block_size: 64 << 20,
+///     ..Default::default()
+/// })?;
+/// let info = TextureInfo {
+///     alloc_hint: Some(AllocationHint::Custom(pool)),
+///     ..target_info
+/// };
+/// ```
+///
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MemoryPoolConfig {
+    /// The memory type all allocations of the pool are made from - see [`image_memory_type`]
+    /// and [`buffer_memory_type`].
+    pub memory_type_index: u32,
+    /// The size of each device memory block - zero for the allocator's default.
+    pub block_size: vk::DeviceSize,
+    /// The number of blocks allocated up front, which are never freed.
+    pub min_block_count: usize,
+    /// The maximum number of blocks - zero for no limit.
+    pub max_block_count: usize,
+    /// Use the linear algorithm, which suits allocations freed in the order they were made,
+    /// such as per-frame ring buffers.
+    pub linear: bool,
+}
+
+/// The custom memory pools owned by the driver. Slots are emptied when a pool is destroyed,
+/// so handles to destroyed pools don't refer to newer pools.
+pub(crate) struct MemoryPools<P = vk_mem::AllocatorPool> {
+    pools: RefCell<Vec<Option<P>>>,
+}
+
+impl<P> Default for MemoryPools<P> {
+    fn default() -> Self {
+        Self {
+            pools: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl MemoryPools {
+    pub(crate) fn create(
+        &self,
+        allocator: &vk_mem::Allocator,
+        config: &MemoryPoolConfig,
+    ) -> Result<MemoryPoolHandle, OxidationError> {
+        let create_info = pool_create_info(config);
+        let pool = vk_check!(allocator.create_pool(&create_info), "vmaCreatePool")?;
+        Ok(self.insert(pool))
+    }
+}
+
+impl<P> MemoryPools<P> {
+    fn insert(&self, pool: P) -> MemoryPoolHandle {
+        let mut pools = self.pools.borrow_mut();
+        pools.push(Some(pool));
+        MemoryPoolHandle(pools.len() - 1)
+    }
+
+    /// Call the function with the pool of the handle.
+    pub(crate) fn with<R>(
+        &self,
+        handle: MemoryPoolHandle,
+        f: impl FnOnce(&P) -> R,
+    ) -> Result<R, OxidationError> {
+        let pools = self.pools.borrow();
+        let pool = pools
+            .get(handle.0)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| OxidationError::Other("Invalid memory pool handle.".to_string()))?;
+        Ok(f(pool))
+    }
+
+    pub(crate) fn destroy(&self, handle: MemoryPoolHandle) {
+        if let Some(slot) = self.pools.borrow_mut().get_mut(handle.0) {
+            slot.take();
+        }
+    }
+
+    pub(crate) fn destroy_all(&self) {
+        self.pools.borrow_mut().clear();
+    }
+}
+
+/// The VMA create info of a pool with the config.
+fn pool_create_info(config: &MemoryPoolConfig) -> vk_mem::PoolCreateInfo<'static> {
+    let flags = match config.linear {
+        true => vk_mem::AllocatorPoolCreateFlags::LINEAR_ALGORITHM,
+        false => vk_mem::AllocatorPoolCreateFlags::empty(),
+    };
+    vk_mem::PoolCreateInfo {
+        memory_type_index: config.memory_type_index,
+        flags,
+        block_size: config.block_size,
+        min_block_count: config.min_block_count,
+        max_block_count: config.max_block_count,
+        ..Default::default()
+    }
+}
+
+impl Driver {
+    /// Create a custom memory pool, which textures and buffers are allocated from when created
+    /// with [`AllocationHint::Custom`] - see [`MemoryPoolConfig`].
+    pub fn create_memory_pool(
+        &self,
+        config: &MemoryPoolConfig,
+    ) -> Result<MemoryPoolHandle, OxidationError> {
+        self.memory_pools.create(self.allocator(), config)
+    }
+
+    /// The statistics of the memory pool, i.e. the number and size of its allocations.
+    pub fn memory_pool_statistics(
+        &self,
+        handle: MemoryPoolHandle,
+    ) -> Result<vk_mem::ffi::VmaStatistics, OxidationError> {
+        self.memory_pools.with(handle, |pool| {
+            vk_check!(pool.get_statistics(), "vmaGetPoolStatistics")
+        })?
+    }
+
+    /// Destroy the memory pool, along with its device memory blocks. All resources allocated
+    /// from the pool must have been destroyed.
+    pub fn destroy_memory_pool(&self, handle: MemoryPoolHandle) {
+        self.memory_pools.destroy(handle);
+    }
+
+    /// Call the function with the allocator resources with the allocation hint are allocated
+    /// from - the custom pool for [`AllocationHint::Custom`], otherwise the driver's allocator.
+    pub(crate) fn with_allocator<R>(
+        &self,
+        hint: Option<AllocationHint>,
+        f: impl FnOnce(&dyn Alloc) -> R,
+    ) -> Result<R, OxidationError> {
+        match hint {
+            Some(AllocationHint::Custom(handle)) => {
+                self.memory_pools.with(handle, |pool| f(pool as &dyn Alloc))
+            }
+            _ => Ok(f(self.allocator() as &dyn Alloc)),
+        }
+    }
+}
+
+/// The index of the memory type images with the info and usage are allocated from, for
+/// creating a pool for them.
+pub fn image_memory_type(
+    driver: &Driver,
+    info: &TextureInfo,
+    usage: vk::ImageUsageFlags,
+) -> Result<u32, OxidationError> {
+    let create_info = Texture::pool_image_create_info(info, usage);
+    let alloc_info = match info.alloc_hint {
+        Some(AllocationHint::Custom(_)) | None => AllocationHint::Pooled,
+        Some(hint) => hint,
+    }
+    .to_vma();
+    unsafe {
+        vk_check!(
+            driver
+                .allocator()
+                .find_memory_type_index_for_image_info(create_info, &alloc_info),
+            "vmaFindMemoryTypeIndexForImageInfo"
+        )
+    }
+}
+
+/// The index of the memory type buffers with the usage and allocation hint are allocated
+/// from, for creating a pool for them.
+pub fn buffer_memory_type(
+    driver: &Driver,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    alloc_hint: AllocationHint,
+) -> Result<u32, OxidationError> {
+    let create_info = vk::BufferCreateInfo::default().size(size).usage(usage);
+    unsafe {
+        vk_check!(
+            driver
+                .allocator()
+                .find_memory_type_index_for_buffer_info(&create_info, &alloc_hint.to_vma()),
+            "vmaFindMemoryTypeIndexForBufferInfo"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_create_info_from_config() {
+        let config = MemoryPoolConfig {
+            memory_type_index: 3,
+            block_size: 64 << 20,
+            min_block_count: 1,
+            max_block_count: 4,
+            linear: true,
+        };
+        let info = pool_create_info(&config);
+        assert_eq!(info.memory_type_index, 3);
+        assert_eq!(info.block_size, 64 << 20);
+        assert_eq!((info.min_block_count, info.max_block_count), (1, 4));
+        assert!(
+            info.flags
+                .contains(vk_mem::AllocatorPoolCreateFlags::LINEAR_ALGORITHM)
+        );
+
+        let info = pool_create_info(&MemoryPoolConfig::default());
+        assert!(info.flags.is_empty());
+    }
+
+    #[test]
+    fn destroyed_pool_handles_are_invalid() {
+        let pools = MemoryPools::<u32>::default();
+        let first = pools.insert(1);
+        let second = pools.insert(2);
+        assert_eq!(pools.with(second, |pool| *pool).unwrap(), 2);
+
+        pools.destroy(first);
+        assert!(pools.with(first, |pool| *pool).is_err());
+        // A new pool doesn't take the slot of the destroyed pool.
+        let third = pools.insert(3);
+        assert_ne!(first, third);
+        assert!(pools.with(first, |pool| *pool).is_err());
+        assert_eq!(pools.with(third, |pool| *pool).unwrap(), 3);
+
+        pools.destroy_all();
+        assert!(pools.with(second, |pool| *pool).is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use crate::Driver;
+use crate::error::OxidationError;
+use crate::texture::{Texture, ViewRange};
+use crate::upload_queue::{UploadQueue, UploadTicket};
+use ash::vk;
+use std::borrow::Cow;
+use std::error::Error;
+use std::ops::Range;
+
+/// The data of a range of mip levels loaded by a [`MipSource`].
+pub struct MipData<'a> {
+    pub data: Cow<'a, [u8]>,
+    /// The offsets of each face and level within the data, indexed by
+    /// `face * level_count + level - levels.start`.
+    pub offsets: Vec<vk::DeviceSize>,
+}
+
+/// Loads the data of mip levels of a streamed texture on demand, i.e. from a file on disk.
+pub trait MipSource {
+    /// Load the data of the range of mip levels, for all faces of the texture.
+    fn load(&self, levels: Range<u32>) -> Result<MipData<'_>, Box<dyn Error>>;
+}
+
+/// Refers to a texture added to a [`MipStreamer`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct StreamedTextureId(usize);
+
+struct StreamedTexture {
+    texture: Texture,
+    source: Box<dyn MipSource>,
+    residency: Residency,
+}
+
+/// Which mip levels of a streamed texture are loaded and sampled, generic over the ticket of
+/// an upload.
+struct Residency<T = UploadTicket> {
+    mip_levels: u32,
+    /// The finest level which has been uploaded - the levels from here to the end of the
+    /// chain hold valid data.
+    loaded_base: u32,
+    /// The finest level which is sampled - never finer than the loaded base.
+    resident_base: u32,
+    /// The finest level requested since the last update, if the texture was requested.
+    requested_base: Option<u32>,
+    /// The upload in progress, along with the finest level it loads.
+    pending: Option<(T, u32)>,
+}
+
+impl<T: Copy> Residency<T> {
+    /// No levels are loaded or resident.
+    fn new(mip_levels: u32) -> Self {
+        Self {
+            mip_levels,
+            loaded_base: mip_levels,
+            resident_base: mip_levels,
+            requested_base: None,
+            pending: None,
+        }
+    }
+
+    /// Request the level for the next update, keeping the finest level requested.
+    fn request(&mut self, level: u32) {
+        self.requested_base = Some(
+            self.requested_base
+                .map_or(level, |requested| requested.min(level)),
+        );
+    }
+
+    /// Apply the request made since the last update - the pending upload is loaded if it has
+    /// completed, an upload of the missing levels is started with `upload` and the resident
+    /// levels are updated.
+    /// The residency is updated even if the upload fails, and the upload is retried on the
+    /// next update.
+    fn update(
+        &mut self,
+        upload_complete: bool,
+        upload: impl FnOnce(Range<u32>) -> Result<T, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let coarsest = self.mip_levels - 1;
+        let desired = self.requested_base.take().unwrap_or(coarsest);
+
+        if let Some((_, base)) = self.pending
+            && upload_complete
+        {
+            self.loaded_base = base;
+            self.pending = None;
+        }
+        // Only one upload per texture is in flight, so the levels are loaded in order.
+        let mut res = Ok(());
+        if desired < self.loaded_base && self.pending.is_none() {
+            match upload(desired..self.loaded_base) {
+                Ok(ticket) => self.pending = Some((ticket, desired)),
+                Err(err) => res = Err(err),
+            }
+        }
+        self.resident_base = desired.max(self.loaded_base);
+        res
+    }
+}
+
+/// Streams the mip levels of textures in and out of residency based on how large they
+/// appear on screen, so only the detail which is visible is sampled. Higher detail levels
+/// are uploaded asynchronously through the [`UploadQueue`], finest last, while levels which
+/// are no longer needed are evicted from the sampled view.
+///
+/// Each frame, [`Self::request`] is called with the screen-space size of each visible
+/// texture, followed by [`Self::update`]. Textures which weren't requested since the last
+/// update are evicted down to their coarsest level. The sampled view of each texture covers
+/// its resident levels - see [`Self::view`].
+///
+/// The memory of the full mip chain is allocated when the texture is created, so evicted
+/// levels keep their data and can be made resident again without another upload.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut streamer = MipStreamer::new();
+/// let id = streamer.add(texture, Box::new(KtxMipSource::open("rock.ktx2")?))?;
+///
+/// // Each frame..
+/// streamer.request(id, projected_size_in_pixels);
+/// streamer.update(&driver, &mut upload_queue)?;
+/// upload_queue.record_acquire_barriers(&driver.device, cmds);
+/// if let Some(view) = streamer.view(id, &driver.device.device)? {
+///     // ..bind the view for sampling.
+/// }
+/// ```
+///
+#[derive(Default)]
+pub struct MipStreamer {
+    textures: Vec<Option<StreamedTexture>>,
+}
+
+impl MipStreamer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a texture to stream the mip levels of. None of the levels are resident until the
+    /// first update, which uploads the coarsest level. The texture must have been created
+    /// with its full mip chain and transfer dst usage.
+    pub fn add(
+        &mut self,
+        texture: Texture,
+        source: Box<dyn MipSource>,
+    ) -> Result<StreamedTextureId, Box<dyn Error>> {
+        if !texture.can_transfer_dst() {
+            return Err(Box::from(
+                "Streamed textures require transfer dst usage for uploading mip levels.",
+            ));
+        }
+        let residency = Residency::new(texture.mip_levels());
+        self.textures.push(Some(StreamedTexture {
+            texture,
+            source,
+            residency,
+        }));
+        Ok(StreamedTextureId(self.textures.len() - 1))
+    }
+
+    /// Request the detail the texture needs for this frame, from the size in pixels of its
+    /// largest dimension when projected on screen. If requested more than once before the
+    /// next update, the finest detail is kept.
+    pub fn request(&mut self, id: StreamedTextureId, screen_size: f32) {
+        let Some(streamed) = self.textures.get_mut(id.0).and_then(Option::as_mut) else {
+            return;
+        };
+        let texture = &streamed.texture;
+        let level = desired_mip_level(
+            texture.width().max(texture.height()),
+            texture.mip_levels(),
+            screen_size,
+        );
+        streamed.residency.request(level);
+    }
+
+    /// Apply the requests made since the last update - completed uploads become resident,
+    /// uploads of missing levels are submitted and unrequested levels are evicted. Any
+    /// acquire barriers of the upload queue must be recorded before sampling the views.
+    ///
+    /// A texture whose levels fail to load or upload is logged and keeps its resident levels,
+    /// and the upload is retried on the next update - other textures are still updated.
+    /// Returns an error if the upload queue can't be flushed.
+    pub fn update(
+        &mut self,
+        driver: &Driver,
+        upload_queue: &mut UploadQueue,
+    ) -> Result<(), Box<dyn Error>> {
+        upload_queue.poll(driver);
+        for (idx, streamed) in self.textures.iter_mut().enumerate() {
+            let Some(StreamedTexture {
+                texture,
+                source,
+                residency,
+            }) = streamed
+            else {
+                continue;
+            };
+            let upload_complete = residency
+                .pending
+                .is_some_and(|(ticket, _)| upload_queue.is_complete(ticket));
+            let res = residency.update(upload_complete, |levels| {
+                let mip_data = source.load(levels.clone())?;
+                upload_queue.enqueue_mip_levels(
+                    driver,
+                    texture,
+                    levels,
+                    &mip_data.data,
+                    &mip_data.offsets,
+                )
+            });
+            if let Err(err) = res {
+                log::warn!("Unable to stream the mip levels of texture {idx}: {err}");
+            }
+        }
+        upload_queue.flush(&driver.device)
+    }
+
+    /// The mip levels of the texture which are sampled - empty until the first upload has
+    /// completed.
+    pub fn resident_levels(&self, id: StreamedTextureId) -> Option<Range<u32>> {
+        let streamed = self.textures.get(id.0)?.as_ref()?;
+        Some(streamed.residency.resident_base..streamed.texture.mip_levels())
+    }
+
+    /// Whether an upload of the texture's levels is in progress.
+    pub fn is_streaming(&self, id: StreamedTextureId) -> bool {
+        self.textures
+            .get(id.0)
+            .and_then(Option::as_ref)
+            .is_some_and(|streamed| streamed.residency.pending.is_some())
+    }
+
+    pub fn texture(&self, id: StreamedTextureId) -> Option<&Texture> {
+        Some(&self.textures.get(id.0)?.as_ref()?.texture)
+    }
+
+    /// A view of the resident mip levels of the texture for sampling, or `None` if no levels
+    /// are resident yet (or the id is invalid). The view changes as levels are streamed in and
+    /// evicted.
+    pub fn view(
+        &mut self,
+        id: StreamedTextureId,
+        device: &ash::Device,
+    ) -> Result<Option<vk::ImageView>, OxidationError> {
+        let Some(streamed) = self.textures.get_mut(id.0).and_then(Option::as_mut) else {
+            return Ok(None);
+        };
+        let mip_levels = streamed.texture.mip_levels();
+        let resident_base = streamed.residency.resident_base;
+        if resident_base >= mip_levels {
+            return Ok(None);
+        }
+        let range = ViewRange {
+            base_mip: resident_base,
+            mip_count: mip_levels - resident_base,
+            base_layer: 0,
+            layer_count: streamed.texture.array_layer_count(),
+            aspect_override: None,
+        };
+        streamed.texture.view(range, device).map(Some)
+    }
+
+    /// Stop streaming the texture and return it. Any upload of the texture must have
+    /// completed before it's destroyed.
+    pub fn remove(&mut self, id: StreamedTextureId) -> Option<Texture> {
+        let streamed = self.textures.get_mut(id.0)?.take()?;
+        Some(streamed.texture)
+    }
+
+    /// Destroy all textures. The upload queue must have no uploads of the textures in flight.
+    pub fn destroy(&mut self, driver: &Driver) {
+        for mut streamed in self.textures.drain(..).flatten() {
+            streamed.texture.destroy(driver);
+        }
+    }
+}
+
+/// The finest mip level needed to sample a texture whose largest dimension is `extent`
+/// texels when its largest dimension covers `screen_size` pixels on screen - one texel per
+/// pixel, clamped to the mip chain.
+pub fn desired_mip_level(extent: u32, mip_levels: u32, screen_size: f32) -> u32 {
+    let coarsest = mip_levels.saturating_sub(1);
+    if screen_size <= 0.0 {
+        return coarsest;
+    }
+    let ratio = extent as f32 / screen_size;
+    match ratio > 1.0 {
+        true => (ratio.log2().floor() as u32).min(coarsest),
+        false => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desired_mip_level_is_one_texel_per_pixel() {
+        // A 1024 texel texture has 11 levels.
+        assert_eq!(desired_mip_level(1024, 11, 1024.0), 0);
+        assert_eq!(desired_mip_level(1024, 11, 2048.0), 0);
+        assert_eq!(desired_mip_level(1024, 11, 512.0), 1);
+        assert_eq!(desired_mip_level(1024, 11, 300.0), 1);
+        assert_eq!(desired_mip_level(1024, 11, 1.0), 10);
+    }
+
+    #[test]
+    fn desired_mip_level_is_clamped_to_the_chain() {
+        assert_eq!(desired_mip_level(1024, 4, 1.0), 3);
+        assert_eq!(desired_mip_level(1024, 4, 0.0), 3);
+        assert_eq!(desired_mip_level(1024, 4, -1.0), 3);
+    }
+
+    #[test]
+    fn residency_follows_completed_uploads() {
+        let mut residency = Residency::<u32>::new(4);
+        // The first update uploads the coarsest level.
+        let mut uploaded = None;
+        residency
+            .update(false, |levels| {
+                uploaded = Some(levels);
+                Ok(1)
+            })
+            .unwrap();
+        assert_eq!(uploaded, Some(3..4));
+        assert_eq!(residency.resident_base, 4);
+
+        // Nothing becomes resident, or is uploaded, until the upload completes.
+        residency.request(0);
+        residency
+            .update(false, |_| panic!("an upload is in flight"))
+            .unwrap();
+        assert_eq!(residency.resident_base, 4);
+
+        residency.request(0);
+        residency
+            .update(true, |levels| {
+                uploaded = Some(levels);
+                Ok(2)
+            })
+            .unwrap();
+        assert_eq!(uploaded, Some(0..3));
+        assert_eq!(residency.resident_base, 3);
+
+        residency.request(1);
+        residency.request(0);
+        residency
+            .update(true, |_| panic!("all levels are loaded"))
+            .unwrap();
+        assert_eq!((residency.loaded_base, residency.resident_base), (0, 0));
+
+        // Unrequested textures are evicted to the coarsest level, keeping the loaded data.
+        residency
+            .update(true, |_| panic!("all levels are loaded"))
+            .unwrap();
+        assert_eq!((residency.loaded_base, residency.resident_base), (0, 3));
+    }
+
+    #[test]
+    fn failed_uploads_are_retried() {
+        let mut residency = Residency::<u32>::new(4);
+        assert!(
+            residency
+                .update(false, |_| Err(Box::from("unreadable")))
+                .is_err()
+        );
+        assert!(residency.pending.is_none());
+        assert_eq!(residency.resident_base, 4);
+
+        let mut uploaded = None;
+        residency
+            .update(false, |levels| {
+                uploaded = Some(levels);
+                Ok(1)
+            })
+            .unwrap();
+        assert_eq!(uploaded, Some(3..4));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::barrier;
+use crate::commands::Commands;
+use crate::descriptor::DescriptorWrite;
+use crate::device::ContextDevice;
+use crate::pipeline::ComputePipeline;
+use crate::texture::Texture;
+use ash::vk;
+use std::error::Error;
+
+/// The workgroup size, in both dimensions, of the downsample shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The method used to generate the mip chain of a texture.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MipGenPath {
+    /// Each level is linearly blitted from the previous level.
+    Blit,
+    /// Each level is downsampled from the previous level by a compute shader. Used for
+    /// formats which can't be linearly blitted but can be used as a storage image.
+    Compute,
+}
+
+/// Select the mip generation path from the (optimal tiling) format features. Blitting is
+/// preferred when supported. Returns `None` if the format supports neither path.
+pub fn select_mip_gen_path(features: vk::FormatFeatureFlags) -> Option<MipGenPath> {
+    let blit_features = vk::FormatFeatureFlags::BLIT_SRC
+        | vk::FormatFeatureFlags::BLIT_DST
+        | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+    if features.contains(blit_features) {
+        Some(MipGenPath::Blit)
+    } else if features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) {
+        Some(MipGenPath::Compute)
+    } else {
+        None
+    }
+}
+
+/// Generates mip chains using a downsample compute shader, for formats which don't support
+/// linear blits. The shader is supplied by the user and must have the following interface:
+///
+/// ```glsl
+/// layout (local_size_x = 8, local_size_y = 8) in;
+/// layout (binding = 0, rgba16f) uniform readonly image2DArray srcImage;
+/// layout (binding = 1, rgba16f) uniform writeonly image2DArray dstImage;
+/// ```
+///
+/// A dispatch is made per mip level, with one invocation per texel of the destination level
+/// and the z dimension covering the array layers.
+///
+/// # Examples
+///
+/// ```ignore
+/// driver.enable_compute_mipmaps(downsample_shader)?;
+/// // Textures which can't be blitted now use the compute path.
+/// texture.generate_mipmaps(&mut driver, cmds)?;
+/// ```
+///
+pub struct ComputeMipGenerator {
+    pipeline: ComputePipeline,
+}
+
+impl ComputeMipGenerator {
+    pub fn new(device: &ContextDevice, shader: vk::ShaderModule) -> Result<Self, Box<dyn Error>> {
+        let bindings = [0, 1].map(|binding| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        });
+        let pipeline = ComputePipeline::new(device, shader, &bindings, &[])?;
+        Ok(Self { pipeline })
+    }
+
+    /// Record the downsampling of each mip level from the previous level. The first level is
+    /// expected to be in the transfer dst layout (i.e. straight after upload); all levels will
+    /// be in the shader read-only layout once complete.
+    pub(crate) fn record(
+        &self,
+        device: &ContextDevice,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+        texture: &mut Texture,
+    ) -> Result<(), Box<dyn Error>> {
+        if !texture.can_storage() {
+            return Err(Box::from(
+                "Compute mip generation requires the texture to have storage usage.",
+            ));
+        }
+
+        let info = *texture.info();
+        // Level zero is read from its transfer dst layout; the other levels are discarded.
+        let all_levels = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(vk::REMAINING_MIP_LEVELS)
+            .layer_count(vk::REMAINING_ARRAY_LAYERS);
+        texture.transition_subresource(cmds, device, all_levels, vk::ImageLayout::GENERAL)?;
+
+        unsafe {
+            device.device.cmd_bind_pipeline(
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.pipeline,
+            )
+        };
+
+        let layer_count = texture.array_layer_count();
+        for level in 1..info.mip_levels {
+            let src_view = texture.storage_mip_view(level - 1, &device.device)?;
+            let dst_view = texture.storage_mip_view(level, &device.device)?;
+            let writes =
+                [(0, src_view), (1, dst_view)].map(|(binding, view)| DescriptorWrite::Image {
+                    binding,
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    view,
+                    sampler: vk::Sampler::null(),
+                    layout: vk::ImageLayout::GENERAL,
+                });
+            commands.push_descriptor_set(
+                device,
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.layout,
+                self.pipeline.set_layout,
+                0,
+                &writes,
+            )?;
+
+            let width = (info.width >> level).max(1);
+            let height = (info.height >> level).max(1);
+            commands.dispatch(
+                &device.device,
+                cmds,
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                layer_count,
+            );
+
+            // The level just written is read by the next dispatch.
+            let write_barrier = texture
+                .level_barrier(
+                    level,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                )
+                .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_READ);
+            barrier::cmd_image_barriers(device, cmds, &[write_barrier]);
+        }
+
+        texture.transition_subresource(
+            cmds,
+            device,
+            all_levels,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.pipeline.destroy(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blit_is_preferred_when_supported() {
+        let features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+            | vk::FormatFeatureFlags::STORAGE_IMAGE;
+        assert_eq!(select_mip_gen_path(features), Some(MipGenPath::Blit));
+    }
+
+    #[test]
+    fn storage_only_format_uses_compute() {
+        assert_eq!(
+            select_mip_gen_path(vk::FormatFeatureFlags::STORAGE_IMAGE),
+            Some(MipGenPath::Compute)
+        );
+        // Blitting without linear filtering isn't enough for the blit path.
+        let features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::STORAGE_IMAGE;
+        assert_eq!(select_mip_gen_path(features), Some(MipGenPath::Compute));
+    }
+
+    #[test]
+    fn unsupported_format_has_no_path() {
+        assert_eq!(
+            select_mip_gen_path(vk::FormatFeatureFlags::SAMPLED_IMAGE),
+            None
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use crate::backend::{BlendMode, CullMode, FrontFace, PolygonMode, VertexLayout};
+use crate::descriptor;
+use crate::device::ContextDevice;
+use crate::error::OxidationError;
+use ash::prelude::VkResult;
+use ash::vk;
+
+/// Describes the shaders and fixed-function state of a graphics pipeline. Pipelines
+/// are created for use with dynamic rendering, so the attachment formats which will
+/// be rendered into must be specified.
+#[derive(Debug, Clone)]
+pub struct GraphicsPipelineInfo {
+    pub vertex_shader: vk::ShaderModule,
+    pub fragment_shader: vk::ShaderModule,
+    /// Optional tessellation control and evaluation stages - both must be specified
+    /// to enable tessellation.
+    pub tess_control_shader: Option<vk::ShaderModule>,
+    pub tess_eval_shader: Option<vk::ShaderModule>,
+    /// Optional geometry stage - requires geometry shader support on the device.
+    pub geometry_shader: Option<vk::ShaderModule>,
+    /// The number of control points per patch - only used when tessellation is enabled.
+    pub patch_control_points: u32,
+    /// The vertex buffer layouts - empty if the vertices are generated in the shader.
+    pub vertex_layouts: Vec<VertexLayout>,
+    /// This is ignored when tessellation is enabled, with `PATCH_LIST` used instead.
+    pub topology: vk::PrimitiveTopology,
+    /// Non-fill modes require the `fill_mode_non_solid` device feature.
+    pub polygon_mode: PolygonMode,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub color_formats: Vec<vk::Format>,
+    /// The blend mode of each colour attachment, in the same order as `color_formats`.
+    /// Attachments without a blend mode are opaque.
+    pub blend_modes: Vec<BlendMode>,
+    pub depth_format: vk::Format,
+    /// See [`DepthMode::compare_op`](crate::backend::DepthMode::compare_op) for the op to use with reverse-Z.
+    pub depth_compare_op: vk::CompareOp,
+    pub layout: vk::PipelineLayout,
+    /// The state which is set when recording commands rather than baked into the pipeline.
+    /// The default of a dynamic viewport and scissor allows the pipeline to be used across
+    /// window resizes.
+    pub dynamic_states: Vec<vk::DynamicState>,
+    /// The viewport and scissor baked into the pipeline - these must be set if the viewport
+    /// or scissor (respectively) aren't dynamic, and are ignored otherwise.
+    pub viewport: Option<vk::Viewport>,
+    pub scissor: Option<vk::Rect2D>,
+}
+
+impl Default for GraphicsPipelineInfo {
+    fn default() -> Self {
+        Self {
+            vertex_shader: vk::ShaderModule::null(),
+            fragment_shader: vk::ShaderModule::null(),
+            tess_control_shader: None,
+            tess_eval_shader: None,
+            geometry_shader: None,
+            patch_control_points: 0,
+            vertex_layouts: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: PolygonMode::Fill,
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            color_formats: Vec::new(),
+            blend_modes: Vec::new(),
+            depth_format: vk::Format::UNDEFINED,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            layout: vk::PipelineLayout::null(),
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+            viewport: None,
+            scissor: None,
+        }
+    }
+}
+
+impl GraphicsPipelineInfo {
+    /// Whether this pipeline has tessellation stages.
+    pub fn has_tessellation(&self) -> bool {
+        self.tess_control_shader.is_some() || self.tess_eval_shader.is_some()
+    }
+
+    /// Check the pipeline info is valid for the specified device.
+    fn validate(&self, device: &ContextDevice) -> Result<(), OxidationError> {
+        if !self.dynamic_states.contains(&vk::DynamicState::VIEWPORT) && self.viewport.is_none() {
+            return Err(OxidationError::Other(
+                "A viewport must be specified when the viewport isn't dynamic state.".to_string(),
+            ));
+        }
+        if !self.dynamic_states.contains(&vk::DynamicState::SCISSOR) && self.scissor.is_none() {
+            return Err(OxidationError::Other(
+                "A scissor must be specified when the scissor isn't dynamic state.".to_string(),
+            ));
+        }
+        if self.blend_modes.len() > self.color_formats.len() {
+            return Err(OxidationError::Other(format!(
+                "{} blend modes were specified for {} colour attachments.",
+                self.blend_modes.len(),
+                self.color_formats.len()
+            )));
+        }
+        if self.polygon_mode != PolygonMode::Fill && !device.enabled_features().fill_mode_non_solid
+        {
+            return Err(OxidationError::Unsupported(format!(
+                "Polygon mode {:?} requires the fillModeNonSolid feature, which the device does not support.",
+                self.polygon_mode
+            )));
+        }
+        if self.geometry_shader.is_some() && !device.enabled_features().geometry_shader {
+            return Err(OxidationError::Unsupported(
+                "A geometry shader was specified but the device does not support geometry shaders."
+                    .to_string(),
+            ));
+        }
+        if self.has_tessellation() {
+            if !device.enabled_features().tessellation_shader {
+                return Err(OxidationError::Unsupported(
+                    "Tessellation shaders specified but the device does not support tessellation."
+                        .to_string(),
+                ));
+            }
+            if self.tess_control_shader.is_none() || self.tess_eval_shader.is_none() {
+                return Err(OxidationError::Other(
+                    "Both a tessellation control and evaluation shader must be specified."
+                        .to_string(),
+                ));
+            }
+            if self.patch_control_points == 0
+                || self.patch_control_points > device.limits.max_tessellation_patch_size
+            {
+                return Err(OxidationError::Other(format!(
+                    "Patch control point count of {} is out of range (max: {}).",
+                    self.patch_control_points, device.limits.max_tessellation_patch_size
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct GraphicsPipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+impl GraphicsPipeline {
+    /// Create a new graphics pipeline. Any dynamic state (by default the viewport and
+    /// scissor) must be set before drawing - see [`Commands::set_viewport`] and
+    /// [`Commands::set_scissor`].
+    ///
+    /// [`Commands::set_viewport`]: crate::commands::Commands::set_viewport
+    /// [`Commands::set_scissor`]: crate::commands::Commands::set_scissor
+    pub fn new(
+        device: &ContextDevice,
+        info: &GraphicsPipelineInfo,
+    ) -> Result<Self, OxidationError> {
+        info.validate(device)?;
+
+        let entry_name = c"main";
+        let mut stages = vec![
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(info.vertex_shader)
+                .name(entry_name),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(info.fragment_shader)
+                .name(entry_name),
+        ];
+        if let (Some(control), Some(eval)) = (info.tess_control_shader, info.tess_eval_shader) {
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::TESSELLATION_CONTROL)
+                    .module(control)
+                    .name(entry_name),
+            );
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::TESSELLATION_EVALUATION)
+                    .module(eval)
+                    .name(entry_name),
+            );
+        }
+        if let Some(geometry) = info.geometry_shader {
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::GEOMETRY)
+                    .module(geometry)
+                    .name(entry_name),
+            );
+        }
+
+        let binding_descs: Vec<vk::VertexInputBindingDescription> = info
+            .vertex_layouts
+            .iter()
+            .map(|layout| layout.binding_description())
+            .collect();
+        let attr_descs: Vec<vk::VertexInputAttributeDescription> = info
+            .vertex_layouts
+            .iter()
+            .flat_map(|layout| layout.attribute_descriptions())
+            .collect();
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&binding_descs)
+            .vertex_attribute_descriptions(&attr_descs);
+
+        let topology = match info.has_tessellation() {
+            true => vk::PrimitiveTopology::PATCH_LIST,
+            false => info.topology,
+        };
+        let input_assembly_state =
+            vk::PipelineInputAssemblyStateCreateInfo::default().topology(topology);
+        let tessellation_state = vk::PipelineTessellationStateCreateInfo::default()
+            .patch_control_points(info.patch_control_points);
+
+        // Static state is only provided where it isn't dynamic - otherwise it's ignored.
+        let viewports: Vec<vk::Viewport> = info
+            .viewport
+            .filter(|_| !info.dynamic_states.contains(&vk::DynamicState::VIEWPORT))
+            .into_iter()
+            .collect();
+        let scissors: Vec<vk::Rect2D> = info
+            .scissor
+            .filter(|_| !info.dynamic_states.contains(&vk::DynamicState::SCISSOR))
+            .into_iter()
+            .collect();
+        let mut viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        if !viewports.is_empty() {
+            viewport_state = viewport_state.viewports(&viewports);
+        }
+        if !scissors.is_empty() {
+            viewport_state = viewport_state.scissors(&scissors);
+        }
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&info.dynamic_states);
+
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(info.polygon_mode.to_vk())
+            .cull_mode(info.cull_mode.to_vk())
+            .front_face(info.front_face.to_vk())
+            .line_width(1.0);
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let has_depth = info.depth_format != vk::Format::UNDEFINED;
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(has_depth)
+            .depth_write_enable(has_depth)
+            .depth_compare_op(info.depth_compare_op);
+
+        let blend_attachments: Vec<vk::PipelineColorBlendAttachmentState> = info
+            .color_formats
+            .iter()
+            .enumerate()
+            .map(|(idx, _)| {
+                info.blend_modes
+                    .get(idx)
+                    .copied()
+                    .unwrap_or_default()
+                    .to_vk()
+            })
+            .collect();
+        let color_blend_state =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&blend_attachments);
+
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&info.color_formats)
+            .depth_attachment_format(info.depth_format);
+
+        let mut create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .color_blend_state(&color_blend_state)
+            .dynamic_state(&dynamic_state)
+            .layout(info.layout)
+            .push_next(&mut rendering_info);
+        if info.has_tessellation() {
+            create_info = create_info.tessellation_state(&tessellation_state);
+        }
+
+        let pipeline = unsafe {
+            device
+                .device
+                .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                .map_err(|(_, err)| err)?[0]
+        };
+
+        Ok(Self {
+            pipeline,
+            layout: info.layout,
+        })
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+/// A compute pipeline with a single descriptor set, as used by the built-in compute paths
+/// (mip generation, clears and format conversion). The set layout is created for use with
+/// [`Commands::push_descriptor_set`](crate::commands::Commands::push_descriptor_set).
+pub(crate) struct ComputePipeline {
+    pub(crate) set_layout: vk::DescriptorSetLayout,
+    pub(crate) layout: vk::PipelineLayout,
+    pub(crate) pipeline: vk::Pipeline,
+}
+
+impl ComputePipeline {
+    /// Create the pipeline from the shader, whose entry point must be `main`. If any stage
+    /// of creation fails, the objects which were created are destroyed.
+    pub(crate) fn new(
+        device: &ContextDevice,
+        shader: vk::ShaderModule,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> VkResult<Self> {
+        let mut pipeline = Self {
+            set_layout: vk::DescriptorSetLayout::null(),
+            layout: vk::PipelineLayout::null(),
+            pipeline: vk::Pipeline::null(),
+        };
+        if let Err(err) = pipeline.create(device, shader, bindings, push_constant_ranges) {
+            pipeline.destroy(&device.device);
+            return Err(err);
+        }
+        Ok(pipeline)
+    }
+
+    fn create(
+        &mut self,
+        device: &ContextDevice,
+        shader: vk::ShaderModule,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> VkResult<()> {
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(descriptor::push_set_layout_flags(device))
+            .bindings(bindings);
+        self.set_layout = unsafe {
+            device
+                .device
+                .create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let set_layouts = [self.set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        self.layout = unsafe { device.device.create_pipeline_layout(&layout_info, None)? };
+
+        let stage = vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader)
+            .name(c"main");
+        let create_info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(self.layout);
+        self.pipeline = unsafe {
+            device
+                .device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                .map_err(|(_, err)| err)?[0]
+        };
+        Ok(())
+    }
+
+    /// Destroy the pipeline and its layouts. Null handles (i.e. after a failed creation) are
+    /// ignored.
+    pub(crate) fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
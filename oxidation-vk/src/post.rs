@@ -0,0 +1,451 @@
+use crate::Driver;
+use crate::backend::AllocationHint;
+use crate::buffer::Buffer;
+use crate::commands::Commands;
+use crate::descriptor::DescriptorWrite;
+use crate::descriptor_buffer;
+use crate::device::ContextDevice;
+use crate::error::OxidationError;
+use crate::host_memory;
+use crate::texture::Texture;
+use ash::vk;
+use std::error::Error;
+
+/// The number of bins of the luminance histogram.
+pub const HISTOGRAM_BIN_COUNT: usize = 256;
+
+/// The workgroup size, in both dimensions, of the histogram shader.
+const WORKGROUP_SIZE: u32 = 16;
+
+/// The range of luminance covered by the histogram, and how quickly the average adapts.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HistogramSettings {
+    /// The log2 luminance of the lower bound of the histogram - darker texels are counted in
+    /// the first bin, which is excluded from the average.
+    pub min_log_luminance: f32,
+    /// The log2 luminance of the upper bound of the histogram - brighter texels are counted in
+    /// the last bin.
+    pub max_log_luminance: f32,
+    /// The weight of the new average when blended with that of the previous dispatch - 1.0
+    /// replaces the previous average, lower values adapt gradually over several frames.
+    pub adaptation: f32,
+}
+
+impl Default for HistogramSettings {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -10.0,
+            max_log_luminance: 2.0,
+            adaptation: 1.0,
+        }
+    }
+}
+
+impl HistogramSettings {
+    /// The bin which a texel of the luminance is counted in, matching the histogram shader.
+    pub fn luminance_bin(&self, luminance: f32) -> usize {
+        let range = self.max_log_luminance - self.min_log_luminance;
+        if luminance < self.min_log_luminance.exp2() || range <= 0.0 {
+            return 0;
+        }
+        let t = ((luminance.log2() - self.min_log_luminance) / range).clamp(0.0, 1.0);
+        1 + (t * (HISTOGRAM_BIN_COUNT - 2) as f32) as usize
+    }
+
+    /// The average luminance of the histogram, matching the average shader without adaptation.
+    /// Returns `None` if all texels are in the first bin.
+    pub fn average_luminance(&self, bins: &[u32]) -> Option<f32> {
+        let range = self.max_log_luminance - self.min_log_luminance;
+        let (weighted, count) = bins.iter().enumerate().skip(1).fold(
+            (0.0f64, 0u64),
+            |(weighted, count), (bin, texels)| {
+                (
+                    weighted + (bin - 1) as f64 * *texels as f64,
+                    count + *texels as u64,
+                )
+            },
+        );
+        if count == 0 {
+            return None;
+        }
+        let t = (weighted / count as f64) as f32 / (HISTOGRAM_BIN_COUNT - 2) as f32;
+        Some((self.min_log_luminance + t * range).exp2())
+    }
+}
+
+/// The push constants of both shaders.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct HistogramConstants {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    texel_count: u32,
+    adaptation: f32,
+}
+
+/// Computes a luminance histogram of a sampled HDR texture, and the average luminance for auto
+/// exposure. The histogram and average are written to storage buffers, which the tonemap pass
+/// can read directly or which can be read back once the dispatch has completed.
+///
+/// The shaders are supplied by the user and must have the following interfaces. The
+/// histogram shader is dispatched with an invocation per texel and counts texels below
+/// `exp2(minLogLuminance)` in bin 0, with the remainder spread over bins 1 to 255 by
+/// log2 luminance - see [`HistogramSettings::luminance_bin`]:
+///
+/// ```glsl
+/// layout (local_size_x = 16, local_size_y = 16) in;
+/// layout (binding = 0) uniform sampler2D hdrImage;
+/// layout (binding = 1) buffer Histogram { uint bins[256]; };
+/// layout (push_constant) uniform PushConstants {
+///     float minLogLuminance; float logLuminanceRange; uint texelCount; float adaptation;
+/// };
+/// ```
+///
+/// The average shader is dispatched as a single workgroup, with an invocation per bin. It
+/// writes the (adapted) average luminance, excluding bin 0, and must clear the bins for the
+/// next dispatch:
+///
+/// ```glsl
+/// layout (local_size_x = 256) in;
+/// layout (binding = 1) buffer Histogram { uint bins[256]; };
+/// layout (binding = 2) buffer Average { float averageLuminance; };
+/// layout (push_constant) uniform PushConstants { /* as above */ };
+/// ```
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut histogram = LuminanceHistogram::new(&driver, histogram_shader, average_shader)?;
+/// // Each frame, once the HDR target is in the shader read layout..
+/// histogram.record(&driver.device, &mut driver.compute_commands, cmds, &hdr_target)?;
+/// // The tonemap pass reads `histogram.average_buffer()`, or once the frame has completed..
+/// let average = histogram.read_average(&driver)?;
+/// ```
+///
+pub struct LuminanceHistogram {
+    set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    histogram_pipeline: vk::Pipeline,
+    average_pipeline: vk::Pipeline,
+    histogram: Buffer,
+    average: Buffer,
+    settings: HistogramSettings,
+}
+
+impl LuminanceHistogram {
+    pub fn new(
+        driver: &Driver,
+        histogram_shader: vk::ShaderModule,
+        average_shader: vk::ShaderModule,
+    ) -> Result<Self, Box<dyn Error>> {
+        let device = &driver.device;
+        let binding = |binding: u32, ty: vk::DescriptorType| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        };
+        let bindings = [
+            binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER),
+            binding(2, vk::DescriptorType::STORAGE_BUFFER),
+        ];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(descriptor_buffer::set_layout_flags(
+                device,
+                &driver.compute_commands,
+            ))
+            .bindings(&bindings);
+        let set_layout = unsafe {
+            device
+                .device
+                .create_descriptor_set_layout(&set_layout_info, None)?
+        };
+
+        let set_layouts = [set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<HistogramConstants>() as u32)];
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { device.device.create_pipeline_layout(&layout_info, None)? };
+
+        let create_info = |shader: vk::ShaderModule| {
+            let stage = vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(shader)
+                .name(c"main");
+            vk::ComputePipelineCreateInfo::default()
+                .flags(descriptor_buffer::pipeline_create_flags(
+                    &driver.compute_commands,
+                ))
+                .stage(stage)
+                .layout(layout)
+        };
+        let pipelines = unsafe {
+            device
+                .device
+                .create_compute_pipelines(
+                    vk::PipelineCache::null(),
+                    &[create_info(histogram_shader), create_info(average_shader)],
+                    None,
+                )
+                .map_err(|(_, err)| err)?
+        };
+
+        // Both buffers are host visible so they can be read back without a copy - they're
+        // small enough that the device accessing host memory isn't a concern.
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST;
+        let histogram = Buffer::new(
+            driver,
+            (HISTOGRAM_BIN_COUNT * size_of::<u32>()) as vk::DeviceSize,
+            usage,
+            AllocationHint::Mapped,
+            &[],
+        )?;
+        let average = Buffer::new(
+            driver,
+            size_of::<f32>() as vk::DeviceSize,
+            usage,
+            AllocationHint::Mapped,
+            &[],
+        )?;
+
+        let mut histogram = Self {
+            set_layout,
+            layout,
+            histogram_pipeline: pipelines[0],
+            average_pipeline: pipelines[1],
+            histogram,
+            average,
+            settings: HistogramSettings::default(),
+        };
+        // The average shader clears the bins after each dispatch, so they only need clearing
+        // once up front.
+        let res = driver.submit_immediate(|cmds| {
+            for buffer in [&histogram.histogram, &histogram.average] {
+                unsafe {
+                    device
+                        .device
+                        .cmd_fill_buffer(cmds, buffer.buffer, 0, vk::WHOLE_SIZE, 0)
+                };
+            }
+            histogram.buffer_barrier(
+                device,
+                cmds,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            );
+        });
+        if let Err(err) = res {
+            histogram.destroy(driver);
+            return Err(Box::new(err));
+        }
+        Ok(histogram)
+    }
+
+    pub fn settings(&self) -> HistogramSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: HistogramSettings) {
+        self.settings = settings;
+    }
+
+    /// The buffer holding the bins of the histogram, as `u32` texel counts.
+    pub fn histogram_buffer(&self) -> vk::Buffer {
+        self.histogram.buffer
+    }
+
+    /// The buffer holding the average luminance as a single `f32`, for the tonemap pass.
+    pub fn average_buffer(&self) -> vk::Buffer {
+        self.average.buffer
+    }
+
+    /// Record the histogram and average dispatches over the texture, which must be sampled
+    /// and in the shader read only layout. The results are visible to all later commands,
+    /// and to the host once the command buffer has completed.
+    pub fn record(
+        &self,
+        device: &ContextDevice,
+        commands: &mut Commands,
+        cmds: vk::CommandBuffer,
+        texture: &Texture,
+    ) -> Result<(), Box<dyn Error>> {
+        if !texture.can_sample() || texture.layout() != vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL {
+            return Err(Box::new(OxidationError::Unsupported(String::from(
+                "The luminance histogram requires a sampled texture in the shader read only layout.",
+            ))));
+        }
+        let extent = texture.extent();
+        let settings = self.settings;
+        let constants = HistogramConstants {
+            min_log_luminance: settings.min_log_luminance,
+            log_luminance_range: settings.max_log_luminance - settings.min_log_luminance,
+            texel_count: extent.width * extent.height,
+            adaptation: settings.adaptation,
+        };
+        let writes = [
+            DescriptorWrite::Image {
+                binding: 0,
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                view: texture.sampled_view(),
+                sampler: texture.sampler(),
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+            DescriptorWrite::Buffer {
+                binding: 1,
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                buffer: self.histogram.buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            },
+            DescriptorWrite::Buffer {
+                binding: 2,
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                buffer: self.average.buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            },
+        ];
+
+        // The results of the previous dispatch may still be being read, i.e. by the tonemap
+        // pass of the previous frame.
+        self.buffer_barrier(
+            device,
+            cmds,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::empty(),
+        );
+        for (pipeline, groups) in [
+            (
+                self.histogram_pipeline,
+                (
+                    extent.width.div_ceil(WORKGROUP_SIZE),
+                    extent.height.div_ceil(WORKGROUP_SIZE),
+                ),
+            ),
+            (self.average_pipeline, (1, 1)),
+        ] {
+            unsafe {
+                device
+                    .device
+                    .cmd_bind_pipeline(cmds, vk::PipelineBindPoint::COMPUTE, pipeline);
+                device.device.cmd_push_constants(
+                    cmds,
+                    self.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    std::slice::from_raw_parts(
+                        (&constants as *const HistogramConstants) as *const u8,
+                        size_of::<HistogramConstants>(),
+                    ),
+                );
+            }
+            commands.bind_descriptor_set(
+                device,
+                cmds,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                self.set_layout,
+                0,
+                &writes,
+            )?;
+            commands.dispatch(&device.device, cmds, groups.0, groups.1, 1);
+            self.buffer_barrier(
+                device,
+                cmds,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_WRITE,
+            );
+        }
+        Ok(())
+    }
+
+    /// Make the writes of the source stage to both buffers visible to all later commands,
+    /// including host reads.
+    fn buffer_barrier(
+        &self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        src_access: vk::AccessFlags,
+    ) {
+        let barriers = [&self.histogram, &self.average].map(|buffer| {
+            vk::BufferMemoryBarrier::default()
+                .buffer(buffer.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .src_access_mask(src_access)
+                .dst_access_mask(
+                    vk::AccessFlags::SHADER_READ
+                        | vk::AccessFlags::SHADER_WRITE
+                        | vk::AccessFlags::HOST_READ,
+                )
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        });
+        unsafe {
+            device.device.cmd_pipeline_barrier(
+                cmds,
+                src_stage,
+                vk::PipelineStageFlags::ALL_COMMANDS | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &barriers,
+                &[],
+            )
+        };
+    }
+
+    /// Read back the average luminance written by the last dispatch, which must have completed.
+    pub fn read_average(&self, driver: &Driver) -> Result<f32, Box<dyn Error>> {
+        let mut average = [0.0f32];
+        self.read(driver, &self.average, &mut average)?;
+        Ok(average[0])
+    }
+
+    /// Read back the histogram bins. The average shader clears the bins, so this is only
+    /// meaningful if the average shader is modified to retain them, i.e. for debugging.
+    pub fn read_histogram(&self, driver: &Driver) -> Result<Vec<u32>, Box<dyn Error>> {
+        let mut bins = vec![0u32; HISTOGRAM_BIN_COUNT];
+        self.read(driver, &self.histogram, &mut bins)?;
+        Ok(bins)
+    }
+
+    fn read<T: Copy>(
+        &self,
+        driver: &Driver,
+        buffer: &Buffer,
+        dst: &mut [T],
+    ) -> Result<(), Box<dyn Error>> {
+        let size = size_of_val(dst) as vk::DeviceSize;
+        host_memory::invalidate_mapped(driver, &buffer.memory, 0, size)?;
+        let mapped = driver
+            .vma_allocator
+            .get_allocation_info(&buffer.memory)
+            .mapped_data as *const T;
+        if mapped.is_null() {
+            return Err(Box::from(
+                "The luminance histogram buffers aren't host visible.",
+            ));
+        }
+        unsafe { mapped.copy_to_nonoverlapping(dst.as_mut_ptr(), dst.len()) };
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        let device = &driver.device.device;
+        unsafe {
+            device.destroy_pipeline(self.histogram_pipeline, None);
+            device.destroy_pipeline(self.average_pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        self.histogram.destroy(driver);
+        self.average.destroy(driver);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::backend::DepthMode;
+use ash::vk;
+
+/// Describes an image view which will be rendered into using dynamic rendering.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderingAttachment {
+    pub view: vk::ImageView,
+    /// The layout the image will be in at the time of rendering.
+    pub layout: vk::ImageLayout,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    /// The colour the attachment is cleared to when the load op is `CLEAR`.
+    pub clear_color: [f32; 4],
+    /// The depth/stencil values the attachment is cleared to when the load op is `CLEAR` -
+    /// only used for depth/stencil attachments.
+    pub clear_depth_stencil: vk::ClearDepthStencilValue,
+}
+
+impl Default for RenderingAttachment {
+    fn default() -> Self {
+        Self {
+            view: vk::ImageView::null(),
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            clear_depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }
+    }
+}
+
+impl RenderingAttachment {
+    pub(crate) fn to_vk(self) -> vk::RenderingAttachmentInfo<'static> {
+        vk::RenderingAttachmentInfo::default()
+            .image_view(self.view)
+            .image_layout(self.layout)
+            .load_op(self.load_op)
+            .store_op(self.store_op)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.clear_color,
+                },
+            })
+    }
+
+    pub(crate) fn to_vk_depth_stencil(self) -> vk::RenderingAttachmentInfo<'static> {
+        vk::RenderingAttachmentInfo::default()
+            .image_view(self.view)
+            .image_layout(self.layout)
+            .load_op(self.load_op)
+            .store_op(self.store_op)
+            .clear_value(vk::ClearValue {
+                depth_stencil: self.clear_depth_stencil,
+            })
+    }
+
+    /// A depth attachment cleared with the clear value of the specified depth mode.
+    pub fn depth(view: vk::ImageView, mode: DepthMode) -> Self {
+        Self {
+            view,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            clear_depth_stencil: mode.clear_value(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The parameters required to begin a dynamic rendering pass.
+///
+/// # Examples
+///
+/// Render to both layers of a stereo array image in a single pass.
+/// ```ignore
+/// use ash::vk;
+/// use oxidation_vk::commands::Commands;
+/// use oxidation_vk::rendering::{RenderingAttachment, RenderingInfo};
+///
+/// let info = RenderingInfo {
+///     render_area: vk::Rect2D::default().extent(extents),
+///     view_mask: 0b11,
+///     color_attachments: vec![RenderingAttachment { view, ..Default::default() }],
+///     ..Default::default()
+/// };
+/// Commands::begin_rendering(&driver.device, cmds, &info)?;
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct RenderingInfo {
+    pub render_area: vk::Rect2D,
+    /// The number of layers rendered to - ignored when the view mask is non-zero.
+    pub layer_count: u32,
+    /// When non-zero, multiview rendering is enabled with each set bit denoting
+    /// the array layer (view) that will be rendered to.
+    pub view_mask: u32,
+    pub color_attachments: Vec<RenderingAttachment>,
+    pub depth_attachment: Option<RenderingAttachment>,
+    /// Only required if the depth attachment format has a stencil aspect, in which case
+    /// this is usually the same view as the depth attachment.
+    pub stencil_attachment: Option<RenderingAttachment>,
+}
+
+impl Default for RenderingInfo {
+    fn default() -> Self {
+        Self {
+            render_area: vk::Rect2D::default(),
+            layer_count: 1,
+            view_mask: 0,
+            color_attachments: Vec::new(),
+            depth_attachment: None,
+            stencil_attachment: None,
+        }
+    }
+}
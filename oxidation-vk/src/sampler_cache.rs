@@ -1,18 +1,27 @@
 use crate::backend;
 use ash::vk;
+use log::warn;
 use std::collections::HashMap;
 
 pub struct SamplerCache {
     samplers: HashMap<backend::SamplerInfo, vk::Sampler>,
+    /// Whether the device has sampler anisotropy enabled. If not, anisotropy
+    /// is disabled for all samplers regardless of the requested sampler info.
+    anisotropy_enabled: bool,
+    max_anisotropy: f32,
+    max_lod_bias: f32,
 }
 
 /// A cache for Vulkan sampler objects. Allows for re-using the same samplers
 /// which fit the requested sampler parameters rather than creating new
 /// samplers on each request. Also, simplifies the destruction at the point of termination.
 impl SamplerCache {
-    pub fn new() -> Self {
+    pub fn new(anisotropy_enabled: bool, limits: &vk::PhysicalDeviceLimits) -> Self {
         Self {
             samplers: HashMap::new(),
+            anisotropy_enabled,
+            max_anisotropy: limits.max_sampler_anisotropy,
+            max_lod_bias: limits.max_sampler_lod_bias,
         }
     }
 
@@ -26,12 +35,35 @@ impl SamplerCache {
             return *sampler;
         }
 
-        let create_info = vk::SamplerCreateInfo {
+        let create_info = self.create_info(info);
+        let sampler = unsafe { device.create_sampler(&create_info, None).unwrap() };
+        let res = self.samplers.insert(*info, sampler);
+        match res {
+            None => sampler,
+            Some(_sampler) => {
+                panic!("Internal error: Sampler already found in cache map.")
+            }
+        }
+    }
+
+    /// The create info of the sampler, with the anisotropy and lod bias limited to those
+    /// supported by the device. The lod bias of the sampler info is in quarter mip levels.
+    fn create_info(&self, info: &backend::SamplerInfo) -> vk::SamplerCreateInfo<'static> {
+        let mut anisotropy_enable = info.enable_anisotropy;
+        if anisotropy_enable == vk::TRUE && !self.anisotropy_enabled {
+            warn!("Sampler anisotropy requested but not supported by the device - disabling.");
+            anisotropy_enable = vk::FALSE;
+        }
+
+        vk::SamplerCreateInfo {
             border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
             compare_enable: info.enable_compare,
-            anisotropy_enable: info.enable_anisotropy,
-            max_anisotropy: info.anisotropy as f32,
-            max_lod: info.mip_levels as f32,
+            anisotropy_enable,
+            max_anisotropy: (info.anisotropy as f32).min(self.max_anisotropy),
+            mip_lod_bias: (info.mip_lod_bias as f32 / 4.0)
+                .clamp(-self.max_lod_bias, self.max_lod_bias),
+            min_lod: info.min_lod as f32,
+            max_lod: info.max_lod(),
             mipmap_mode: vk::SamplerMipmapMode::LINEAR,
             min_filter: info.min_filter.to_vk(),
             mag_filter: info.mag_filter.to_vk(),
@@ -40,15 +72,6 @@ impl SamplerCache {
             address_mode_w: info.addr_mode_w.to_vk(),
             compare_op: info.compare_op.to_vk(),
             ..Default::default()
-        };
-
-        let sampler = unsafe { device.create_sampler(&create_info, None).unwrap() };
-        let res = self.samplers.insert(*info, sampler);
-        match res {
-            None => sampler,
-            Some(_sampler) => {
-                panic!("Internal error: Sampler already found in cache map.")
-            }
         }
     }
 
@@ -59,8 +82,46 @@ impl SamplerCache {
     }
 }
 
-impl Default for SamplerCache {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_anisotropy: f32, max_lod_bias: f32) -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            max_sampler_anisotropy: max_anisotropy,
+            max_sampler_lod_bias: max_lod_bias,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn anisotropy_is_clamped_to_the_device_limit() {
+        let cache = SamplerCache::new(true, &limits(8.0, 2.0));
+        let create_info = cache.create_info(&backend::SamplerInfo::anisotropic(16));
+        assert_eq!(create_info.anisotropy_enable, vk::TRUE);
+        assert_eq!(create_info.max_anisotropy, 8.0);
+    }
+
+    #[test]
+    fn anisotropy_is_disabled_when_unsupported() {
+        let cache = SamplerCache::new(false, &limits(16.0, 2.0));
+        let create_info = cache.create_info(&backend::SamplerInfo::anisotropic(16));
+        assert_eq!(create_info.anisotropy_enable, vk::FALSE);
+    }
+
+    #[test]
+    fn lod_bias_is_in_quarter_levels_and_clamped() {
+        let cache = SamplerCache::new(true, &limits(16.0, 1.0));
+        let bias = |mip_lod_bias| {
+            let info = backend::SamplerInfo {
+                mip_lod_bias,
+                ..backend::SamplerInfo::linear_repeat()
+            };
+            cache.create_info(&info).mip_lod_bias
+        };
+        assert_eq!(bias(2), 0.5);
+        assert_eq!(bias(-3), -0.75);
+        assert_eq!(bias(8), 1.0);
+        assert_eq!(bias(-8), -1.0);
     }
 }
@@ -0,0 +1,172 @@
+use crate::vk_check;
+use ash::vk;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+/// Resolves shader names to compiled SPIR-V, so shaders can be loaded in the same way whether
+/// they're read from disk during development or embedded in the binary for release.
+pub trait ShaderSource {
+    /// Load the SPIR-V of the named shader. Returns an error if the source has no shader of
+    /// the name.
+    fn load(&self, name: &str) -> Result<Cow<'_, [u8]>, Box<dyn Error>>;
+}
+
+/// Shaders embedded in the binary, i.e. with `include_bytes!`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let shaders = EmbeddedShaders::new()
+///     .with("triangle.vert", include_bytes!("../shaders/triangle.vert.spv"))
+///     .with("triangle.frag", include_bytes!("../shaders/triangle.frag.spv"));
+/// let vertex = ShaderModule::from_source(&driver.device.device, &shaders, "triangle.vert")?;
+/// ```
+///
+#[derive(Debug, Default, Clone)]
+pub struct EmbeddedShaders {
+    shaders: HashMap<String, &'static [u8]>,
+}
+
+impl EmbeddedShaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the SPIR-V of a shader under the name.
+    pub fn with(mut self, name: &str, spirv: &'static [u8]) -> Self {
+        self.shaders.insert(name.to_string(), spirv);
+        self
+    }
+}
+
+impl ShaderSource for EmbeddedShaders {
+    fn load(&self, name: &str) -> Result<Cow<'_, [u8]>, Box<dyn Error>> {
+        let spirv = self
+            .shaders
+            .get(name)
+            .ok_or_else(|| format!("No embedded shader named \"{name}\"."))?;
+        Ok(Cow::Borrowed(spirv))
+    }
+}
+
+/// Shaders read from files in a directory, with the shader name as the path relative to the
+/// directory. The files are read on each load, so edited shaders are picked up when reloaded.
+#[derive(Debug, Clone)]
+pub struct DirectoryShaders {
+    root: PathBuf,
+}
+
+impl DirectoryShaders {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ShaderSource for DirectoryShaders {
+    fn load(&self, name: &str) -> Result<Cow<'_, [u8]>, Box<dyn Error>> {
+        let path = self.root.join(name);
+        let spirv = std::fs::read(&path)
+            .map_err(|err| format!("Unable to read shader {}: {err}", path.display()))?;
+        Ok(Cow::Owned(spirv))
+    }
+}
+
+/// A shader module created from SPIR-V, along with the name it was loaded with.
+pub struct ShaderModule {
+    module: vk::ShaderModule,
+    name: String,
+}
+
+impl ShaderModule {
+    /// Load the named shader from the source and create a module from it. Returns an error if
+    /// the source can't resolve the name, or the shader isn't valid SPIR-V.
+    pub fn from_source(
+        device: &ash::Device,
+        source: &dyn ShaderSource,
+        name: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let spirv = source.load(name)?;
+        let module = create_module(device, &spirv)
+            .map_err(|err| format!("Unable to create shader module \"{name}\": {err}"))?;
+        Ok(Self {
+            module,
+            name: name.to_string(),
+        })
+    }
+
+    pub fn module(&self) -> vk::ShaderModule {
+        self.module
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe { device.destroy_shader_module(self.module, None) };
+    }
+}
+
+/// Create a shader module from SPIR-V bytes, which needn't be 4 byte aligned - the words are
+/// copied and the magic number checked, accounting for the endianness.
+pub fn create_module(
+    device: &ash::Device,
+    spirv: &[u8],
+) -> Result<vk::ShaderModule, Box<dyn Error>> {
+    let code = read_spirv(spirv)?;
+    let create_info = vk::ShaderModuleCreateInfo::default().code(&code);
+    let module = unsafe {
+        vk_check!(
+            device.create_shader_module(&create_info, None),
+            "vkCreateShaderModule"
+        )?
+    };
+    Ok(module)
+}
+
+/// Read SPIR-V bytes into words, checking the magic number.
+fn read_spirv(spirv: &[u8]) -> Result<Vec<u32>, Box<dyn Error>> {
+    let code = ash::util::read_spv(&mut Cursor::new(spirv))
+        .map_err(|err| format!("Invalid SPIR-V: {err}"))?;
+    if code.first() != Some(&0x0723_0203) {
+        return Err(Box::from("Invalid SPIR-V: the magic number is missing."));
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SPIR-V magic number followed by a version word, little and big endian.
+    const SPIRV_LE: &[u8] = &[0x03, 0x02, 0x23, 0x07, 0x00, 0x00, 0x01, 0x00];
+    const SPIRV_BE: &[u8] = &[0x07, 0x23, 0x02, 0x03, 0x00, 0x01, 0x00, 0x00];
+
+    #[test]
+    fn embedded_shaders_resolve_by_name() {
+        let shaders = EmbeddedShaders::new()
+            .with("triangle.vert", SPIRV_LE)
+            .with("triangle.frag", SPIRV_BE);
+        assert_eq!(&*shaders.load("triangle.vert").unwrap(), SPIRV_LE);
+        assert_eq!(&*shaders.load("triangle.frag").unwrap(), SPIRV_BE);
+    }
+
+    #[test]
+    fn embedded_shaders_reject_missing_names() {
+        let shaders = EmbeddedShaders::new().with("triangle.vert", SPIRV_LE);
+        let err = shaders.load("triangle.frag").unwrap_err();
+        assert!(err.to_string().contains("triangle.frag"));
+    }
+
+    #[test]
+    fn spirv_magic_number_is_checked() {
+        assert_eq!(read_spirv(SPIRV_LE).unwrap(), [0x0723_0203, 0x0001_0000]);
+        assert_eq!(read_spirv(SPIRV_BE).unwrap(), [0x0723_0203, 0x0001_0000]);
+        assert!(read_spirv(&[0xde, 0xad, 0xbe, 0xef]).is_err());
+        // Not a whole number of words.
+        assert!(read_spirv(&SPIRV_LE[..6]).is_err());
+    }
+}
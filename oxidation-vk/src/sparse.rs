@@ -0,0 +1,678 @@
+use crate::Driver;
+use crate::error::OxidationError;
+use crate::vk_check;
+use ash::vk;
+use std::collections::HashMap;
+use vk_mem::Alloc;
+
+/// A 2D colour texture with sparse residency - the image is divided into tiles, with memory
+/// only bound to the tiles which are committed. Reads from non-resident tiles return
+/// undefined values unless the device reports `residencyNonResidentStrict`, so the shader
+/// should only sample committed tiles (i.e. via an indirection texture). The mip tail (the
+/// levels smaller than a tile) is always resident, as is the metadata aspect for formats
+/// which have one.
+///
+/// Tile data is uploaded by copying into the committed region of the image - see
+/// [`Self::tile_region`]. Binding is submitted to the graphics queue and waits for
+/// completion, so tiles should be committed/evicted in batches away from the render loop.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut terrain = SparseTexture::new(
+///     &driver,
+///     16384,
+///     16384,
+///     vk::Format::R8G8B8A8_UNORM,
+///     1,
+///     vk::ImageUsageFlags::SAMPLED,
+/// )?;
+/// terrain.commit_tile(&driver, 0, 4, 7)?;
+/// let region = terrain.tile_region(0, 4, 7);
+/// // ..copy the tile data into the region.
+/// terrain.evict_tile(&driver, 0, 4, 7)?;
+/// ```
+///
+pub struct SparseTexture {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    /// The size of a tile in texels.
+    tile_extent: vk::Extent3D,
+    /// The memory requirements of a single tile.
+    tile_requirements: vk::MemoryRequirements,
+    /// The first mip level which is part of the mip tail - `mip_levels` if there's no tail.
+    mip_tail_first_lod: u32,
+    mip_tail: Option<vk_mem::Allocation>,
+    /// The memory of the metadata aspect, if the image has one.
+    metadata: Option<vk_mem::Allocation>,
+    /// The memory of the committed tiles, keyed by their mip level and tile coordinates.
+    tiles: HashMap<(u32, u32, u32), vk_mem::Allocation>,
+}
+
+impl SparseTexture {
+    /// Create a sparse texture with no committed tiles. Returns an error if the device doesn't
+    /// support sparse residency for 2D images, or doesn't support the format as a sparse image
+    /// with the usage.
+    pub fn new(
+        driver: &Driver,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        mip_levels: u32,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<Self, OxidationError> {
+        if !driver.device.enabled_features().sparse_residency_image_2d {
+            return Err(OxidationError::Unsupported(
+                "Sparse residency for 2D images is not supported by the device.".to_string(),
+            ));
+        }
+        let usage = usage | vk::ImageUsageFlags::TRANSFER_DST;
+        let format_props = unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_sparse_image_format_properties(
+                    driver.device.physical_device,
+                    format,
+                    vk::ImageType::TYPE_2D,
+                    vk::SampleCountFlags::TYPE_1,
+                    usage,
+                    vk::ImageTiling::OPTIMAL,
+                )
+        };
+        if format_props.is_empty() {
+            return Err(OxidationError::Unsupported(format!(
+                "Format {format:?} is not supported for sparse images with usage {usage:?}."
+            )));
+        }
+
+        let device = &driver.device.device;
+        let create_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::SPARSE_BINDING | vk::ImageCreateFlags::SPARSE_RESIDENCY)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image = unsafe { vk_check!(device.create_image(&create_info, None), "vkCreateImage")? };
+
+        let tile_requirements = unsafe { device.get_image_memory_requirements(image) };
+        let sparse_requirements = unsafe { device.get_image_sparse_memory_requirements(image) };
+        let Some(colour_requirements) = sparse_requirements.iter().find(|req| {
+            req.format_properties
+                .aspect_mask
+                .contains(vk::ImageAspectFlags::COLOR)
+        }) else {
+            unsafe { device.destroy_image(image, None) };
+            return Err(OxidationError::Unsupported(format!(
+                "Format {format:?} has no colour aspect sparse memory requirements."
+            )));
+        };
+
+        let mut texture = Self {
+            image,
+            image_view: vk::ImageView::null(),
+            format,
+            extent: vk::Extent2D { width, height },
+            mip_levels,
+            tile_extent: colour_requirements.format_properties.image_granularity,
+            tile_requirements: vk::MemoryRequirements {
+                size: tile_requirements.alignment,
+                ..tile_requirements
+            },
+            mip_tail_first_lod: colour_requirements.image_mip_tail_first_lod.min(mip_levels),
+            mip_tail: None,
+            metadata: None,
+            tiles: HashMap::new(),
+        };
+        if texture.mip_tail_first_lod < mip_levels {
+            match texture.bind_tail(
+                driver,
+                colour_requirements,
+                vk::SparseMemoryBindFlags::empty(),
+            ) {
+                Ok(memory) => texture.mip_tail = Some(memory),
+                Err(err) => {
+                    texture.destroy(driver);
+                    return Err(err);
+                }
+            }
+        }
+        // The metadata aspect (i.e. used for compression) is entirely within its mip tail and
+        // must be bound before the image is used.
+        if let Some(metadata_requirements) = sparse_requirements.iter().find(|req| {
+            req.format_properties
+                .aspect_mask
+                .contains(vk::ImageAspectFlags::METADATA)
+        }) {
+            match texture.bind_tail(
+                driver,
+                metadata_requirements,
+                vk::SparseMemoryBindFlags::METADATA,
+            ) {
+                Ok(memory) => texture.metadata = Some(memory),
+                Err(err) => {
+                    texture.destroy(driver);
+                    return Err(err);
+                }
+            }
+        }
+
+        let view_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .level_count(mip_levels)
+                    .layer_count(1),
+            );
+        match unsafe { device.create_image_view(&view_info, None) } {
+            Ok(view) => texture.image_view = view,
+            Err(err) => {
+                texture.destroy(driver);
+                return Err(err.into());
+            }
+        }
+        Ok(texture)
+    }
+
+    #[inline]
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    #[inline]
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    #[inline]
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// The size of a tile in texels.
+    #[inline]
+    pub fn tile_extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.tile_extent.width,
+            height: self.tile_extent.height,
+        }
+    }
+
+    /// The size of the memory backing a single tile in bytes.
+    #[inline]
+    pub fn tile_size(&self) -> vk::DeviceSize {
+        self.tile_requirements.size
+    }
+
+    /// The first mip level of the mip tail, which is always resident.
+    #[inline]
+    pub fn mip_tail_first_lod(&self) -> u32 {
+        self.mip_tail_first_lod
+    }
+
+    /// The number of tiles in each dimension of the mip level. Levels in the mip tail have
+    /// no tiles.
+    pub fn tile_count(&self, mip_level: u32) -> (u32, u32) {
+        if mip_level >= self.mip_tail_first_lod {
+            return (0, 0);
+        }
+        let (width, height) = self.mip_extent(mip_level);
+        (
+            width.div_ceil(self.tile_extent.width),
+            height.div_ceil(self.tile_extent.height),
+        )
+    }
+
+    /// Whether the tile has memory bound to it.
+    pub fn is_resident(&self, mip_level: u32, x: u32, y: u32) -> bool {
+        mip_level >= self.mip_tail_first_lod || self.tiles.contains_key(&(mip_level, x, y))
+    }
+
+    /// The region of the image covered by the tile - tiles at the right and bottom edges of
+    /// the level are clamped to the level extent.
+    pub fn tile_region(&self, mip_level: u32, x: u32, y: u32) -> vk::Rect2D {
+        let (width, height) = self.mip_extent(mip_level);
+        let offset_x = x * self.tile_extent.width;
+        let offset_y = y * self.tile_extent.height;
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: offset_x as i32,
+                y: offset_y as i32,
+            },
+            extent: vk::Extent2D {
+                width: self.tile_extent.width.min(width - offset_x),
+                height: self.tile_extent.height.min(height - offset_y),
+            },
+        }
+    }
+
+    /// Bind memory to the tile so it can be written and sampled. Committing a resident tile
+    /// has no effect.
+    pub fn commit_tile(
+        &mut self,
+        driver: &Driver,
+        mip_level: u32,
+        x: u32,
+        y: u32,
+    ) -> Result<(), OxidationError> {
+        self.check_tile(mip_level, x, y)?;
+        if self.is_resident(mip_level, x, y) {
+            return Ok(());
+        }
+
+        let mut memory = unsafe {
+            vk_check!(
+                driver
+                    .allocator()
+                    .allocate_memory(&self.tile_requirements, &tile_alloc_info()),
+                "vmaAllocateMemory"
+            )?
+        };
+        let alloc_info = driver.allocator().get_allocation_info(&memory);
+        let res = self.bind_tile(
+            driver,
+            mip_level,
+            x,
+            y,
+            alloc_info.device_memory,
+            alloc_info.offset,
+        );
+        if let Err(err) = res {
+            unsafe { driver.allocator().free_memory(&mut memory) };
+            return Err(err);
+        }
+        self.tiles.insert((mip_level, x, y), memory);
+        Ok(())
+    }
+
+    /// Unbind the memory of the tile and free it. The tile must no longer be in use by the
+    /// device. Evicting a non-resident tile has no effect.
+    pub fn evict_tile(
+        &mut self,
+        driver: &Driver,
+        mip_level: u32,
+        x: u32,
+        y: u32,
+    ) -> Result<(), OxidationError> {
+        self.check_tile(mip_level, x, y)?;
+        let Some(mut memory) = self.tiles.remove(&(mip_level, x, y)) else {
+            return Ok(());
+        };
+        let res = self.bind_tile(driver, mip_level, x, y, vk::DeviceMemory::null(), 0);
+        unsafe { driver.allocator().free_memory(&mut memory) };
+        res
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        let device = &driver.device.device;
+        unsafe {
+            device.destroy_image_view(self.image_view, None);
+            device.destroy_image(self.image, None);
+            for (_, mut memory) in self.tiles.drain() {
+                driver.allocator().free_memory(&mut memory);
+            }
+            for mut memory in self.mip_tail.take().into_iter().chain(self.metadata.take()) {
+                driver.allocator().free_memory(&mut memory);
+            }
+        }
+    }
+
+    fn mip_extent(&self, mip_level: u32) -> (u32, u32) {
+        (
+            (self.extent.width >> mip_level).max(1),
+            (self.extent.height >> mip_level).max(1),
+        )
+    }
+
+    fn check_tile(&self, mip_level: u32, x: u32, y: u32) -> Result<(), OxidationError> {
+        let (count_x, count_y) = self.tile_count(mip_level);
+        if x >= count_x || y >= count_y {
+            return Err(OxidationError::Other(format!(
+                "Tile ({x}, {y}) of mip level {mip_level} is out of range - the level has \
+                {count_x}x{count_y} tiles (the mip tail starts at level {}).",
+                self.mip_tail_first_lod
+            )));
+        }
+        Ok(())
+    }
+
+    fn bind_tile(
+        &self,
+        driver: &Driver,
+        mip_level: u32,
+        x: u32,
+        y: u32,
+        memory: vk::DeviceMemory,
+        memory_offset: vk::DeviceSize,
+    ) -> Result<(), OxidationError> {
+        let region = self.tile_region(mip_level, x, y);
+        let binds = [vk::SparseImageMemoryBind::default()
+            .subresource(vk::ImageSubresource {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level,
+                array_layer: 0,
+            })
+            .offset(vk::Offset3D {
+                x: region.offset.x,
+                y: region.offset.y,
+                z: 0,
+            })
+            .extent(vk::Extent3D {
+                width: region.extent.width,
+                height: region.extent.height,
+                depth: 1,
+            })
+            .memory(memory)
+            .memory_offset(memory_offset)];
+        let image_binds = [vk::SparseImageMemoryBindInfo::default()
+            .image(self.image)
+            .binds(&binds)];
+        let bind_info = vk::BindSparseInfo::default().image_binds(&image_binds);
+        bind_sparse(driver, &bind_info)
+    }
+
+    /// Bind memory to the mip tail of the aspect described by the requirements - `flags` must
+    /// be `METADATA` for the metadata aspect. The memory is freed if binding fails.
+    fn bind_tail(
+        &self,
+        driver: &Driver,
+        requirements: &vk::SparseImageMemoryRequirements,
+        flags: vk::SparseMemoryBindFlags,
+    ) -> Result<vk_mem::Allocation, OxidationError> {
+        let tail_requirements = vk::MemoryRequirements {
+            size: requirements.image_mip_tail_size,
+            ..self.tile_requirements
+        };
+        let mut memory = unsafe {
+            vk_check!(
+                driver
+                    .allocator()
+                    .allocate_memory(&tail_requirements, &tile_alloc_info()),
+                "vmaAllocateMemory"
+            )?
+        };
+        let alloc_info = driver.allocator().get_allocation_info(&memory);
+
+        let binds = [vk::SparseMemoryBind::default()
+            .resource_offset(requirements.image_mip_tail_offset)
+            .size(requirements.image_mip_tail_size)
+            .memory(alloc_info.device_memory)
+            .memory_offset(alloc_info.offset)
+            .flags(flags)];
+        let opaque_binds = [vk::SparseImageOpaqueMemoryBindInfo::default()
+            .image(self.image)
+            .binds(&binds)];
+        let bind_info = vk::BindSparseInfo::default().image_opaque_binds(&opaque_binds);
+        if let Err(err) = bind_sparse(driver, &bind_info) {
+            unsafe { driver.allocator().free_memory(&mut memory) };
+            return Err(err);
+        }
+        Ok(memory)
+    }
+}
+
+/// A buffer with sparse residency - the buffer is divided into pages, with memory only bound
+/// to the pages which are committed, so data sets larger than device memory can be streamed
+/// in and out of a single buffer. Accesses to non-resident pages return undefined values
+/// unless the device reports `residencyNonResidentStrict`, and writes to them are discarded.
+///
+/// Data is uploaded by copying into the bound range, i.e. with `vkCmdCopyBuffer`. Binding is
+/// submitted to the graphics queue and waits for completion, as with [`SparseTexture`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut voxels = SparseBuffer::new(&driver, 16 << 30, vk::BufferUsageFlags::STORAGE_BUFFER)?;
+/// let brick = 3 * voxels.page_size();
+/// voxels.bind_range(&driver, brick, voxels.page_size())?;
+/// // ..copy the brick data into the range.
+/// voxels.unbind_range(&driver, brick, voxels.page_size())?;
+/// ```
+///
+pub struct SparseBuffer {
+    pub buffer: vk::Buffer,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    /// The memory requirements of a single page.
+    page_requirements: vk::MemoryRequirements,
+    /// The memory of the committed pages, keyed by their index.
+    pages: HashMap<vk::DeviceSize, vk_mem::Allocation>,
+}
+
+impl SparseBuffer {
+    /// Create a sparse buffer of the size with no committed pages. Returns an error if the
+    /// device doesn't support sparse residency for buffers.
+    pub fn new(
+        driver: &Driver,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self, OxidationError> {
+        if !driver.device.enabled_features().sparse_residency_buffer {
+            return Err(OxidationError::Unsupported(
+                "Sparse residency for buffers is not supported by the device.".to_string(),
+            ));
+        }
+        let usage = usage | vk::BufferUsageFlags::TRANSFER_DST;
+        let device = &driver.device.device;
+        let create_info = vk::BufferCreateInfo::default()
+            .flags(vk::BufferCreateFlags::SPARSE_BINDING | vk::BufferCreateFlags::SPARSE_RESIDENCY)
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer =
+            unsafe { vk_check!(device.create_buffer(&create_info, None), "vkCreateBuffer")? };
+
+        // The alignment of a sparse buffer is the size of its pages.
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+        Ok(Self {
+            buffer,
+            size,
+            usage,
+            page_requirements: vk::MemoryRequirements {
+                size: requirements.alignment,
+                ..requirements
+            },
+            pages: HashMap::new(),
+        })
+    }
+
+    #[inline]
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    #[inline]
+    pub fn usage(&self) -> vk::BufferUsageFlags {
+        self.usage
+    }
+
+    /// The size of a page in bytes - the granularity memory is bound at.
+    #[inline]
+    pub fn page_size(&self) -> vk::DeviceSize {
+        self.page_requirements.size
+    }
+
+    /// The number of pages in the buffer.
+    pub fn page_count(&self) -> vk::DeviceSize {
+        self.size.div_ceil(self.page_size())
+    }
+
+    /// The number of pages with memory bound to them.
+    pub fn resident_page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Whether all pages overlapping the range have memory bound to them.
+    pub fn is_resident(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> bool {
+        self.page_range(offset, size)
+            .is_ok_and(|mut pages| pages.all(|page| self.pages.contains_key(&page)))
+    }
+
+    /// Bind memory to all pages overlapping the range so it can be accessed. Pages which are
+    /// already resident are left as is.
+    pub fn bind_range(
+        &mut self,
+        driver: &Driver,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<(), OxidationError> {
+        let mut allocated = Vec::new();
+        let mut binds = Vec::new();
+        for page in self.page_range(offset, size)? {
+            if self.pages.contains_key(&page) {
+                continue;
+            }
+            let memory = unsafe {
+                driver
+                    .allocator()
+                    .allocate_memory(&self.page_requirements, &tile_alloc_info())
+            };
+            let memory = match vk_check!(memory, "vmaAllocateMemory") {
+                Ok(memory) => memory,
+                Err(err) => {
+                    free_pages(driver, allocated);
+                    return Err(err);
+                }
+            };
+            let alloc_info = driver.allocator().get_allocation_info(&memory);
+            binds.push(self.page_bind(page, alloc_info.device_memory, alloc_info.offset));
+            allocated.push((page, memory));
+        }
+        if binds.is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = self.bind(driver, &binds) {
+            free_pages(driver, allocated);
+            return Err(err);
+        }
+        self.pages.extend(allocated);
+        Ok(())
+    }
+
+    /// Unbind the memory of all pages overlapping the range and free it. The pages must no
+    /// longer be in use by the device. Non-resident pages are ignored.
+    pub fn unbind_range(
+        &mut self,
+        driver: &Driver,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<(), OxidationError> {
+        let mut freed = Vec::new();
+        let mut binds = Vec::new();
+        for page in self.page_range(offset, size)? {
+            if let Some(memory) = self.pages.remove(&page) {
+                binds.push(self.page_bind(page, vk::DeviceMemory::null(), 0));
+                freed.push((page, memory));
+            }
+        }
+        if binds.is_empty() {
+            return Ok(());
+        }
+        let res = self.bind(driver, &binds);
+        free_pages(driver, freed);
+        res
+    }
+
+    pub fn destroy(&mut self, driver: &Driver) {
+        unsafe { driver.device.device.destroy_buffer(self.buffer, None) };
+        free_pages(driver, self.pages.drain());
+    }
+
+    /// The indices of the pages overlapping the range.
+    fn page_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<std::ops::Range<vk::DeviceSize>, OxidationError> {
+        if size == 0 || offset.checked_add(size).is_none_or(|end| end > self.size) {
+            return Err(OxidationError::Other(format!(
+                "Range of {size} bytes at offset {offset} is out of bounds of the sparse buffer \
+                of {} bytes.",
+                self.size
+            )));
+        }
+        let page_size = self.page_size();
+        Ok(offset / page_size..(offset + size).div_ceil(page_size))
+    }
+
+    /// The bind of the page to the memory - the last page is clamped to the buffer size.
+    fn page_bind(
+        &self,
+        page: vk::DeviceSize,
+        memory: vk::DeviceMemory,
+        memory_offset: vk::DeviceSize,
+    ) -> vk::SparseMemoryBind {
+        let resource_offset = page * self.page_size();
+        vk::SparseMemoryBind::default()
+            .resource_offset(resource_offset)
+            .size(self.page_size().min(self.size - resource_offset))
+            .memory(memory)
+            .memory_offset(memory_offset)
+    }
+
+    fn bind(&self, driver: &Driver, binds: &[vk::SparseMemoryBind]) -> Result<(), OxidationError> {
+        let buffer_binds = [vk::SparseBufferMemoryBindInfo::default()
+            .buffer(self.buffer)
+            .binds(binds)];
+        let bind_info = vk::BindSparseInfo::default().buffer_binds(&buffer_binds);
+        bind_sparse(driver, &bind_info)
+    }
+}
+
+/// Free the memory of the pages.
+fn free_pages(
+    driver: &Driver,
+    pages: impl IntoIterator<Item = (vk::DeviceSize, vk_mem::Allocation)>,
+) {
+    for (_, mut memory) in pages {
+        unsafe { driver.allocator().free_memory(&mut memory) };
+    }
+}
+
+/// The allocation info for tile memory - the generic VMA usages can't be used for allocations
+/// which aren't associated with a buffer or image at creation, so device local memory is
+/// requested explicitly.
+fn tile_alloc_info() -> vk_mem::AllocationCreateInfo {
+    vk_mem::AllocationCreateInfo {
+        required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ..Default::default()
+    }
+}
+
+/// Submit the sparse binding to the graphics queue and wait for it to complete.
+fn bind_sparse(driver: &Driver, bind_info: &vk::BindSparseInfo) -> Result<(), OxidationError> {
+    let device = &driver.device.device;
+    let fence = unsafe {
+        vk_check!(
+            device.create_fence(&vk::FenceCreateInfo::default(), None),
+            "vkCreateFence"
+        )?
+    };
+    let res = unsafe {
+        device
+            .queue_bind_sparse(
+                driver.device.graphics_queue,
+                std::slice::from_ref(bind_info),
+                fence,
+            )
+            .and_then(|_| device.wait_for_fences(&[fence], true, u64::MAX))
+    };
+    unsafe { device.destroy_fence(fence, None) };
+    vk_check!(res, "vkQueueBindSparse")
+}
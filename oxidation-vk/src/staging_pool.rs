@@ -1,4 +1,6 @@
 use crate::commands;
+use crate::error::OxidationError;
+use crate::vk_check;
 use ash::vk;
 use vk_mem::Alloc;
 
@@ -44,19 +46,19 @@ impl StagingPool {
         &mut self,
         required_size: vk::DeviceSize,
         vma_allocator: &vk_mem::Allocator,
-    ) -> &mut Instance {
+    ) -> Result<&mut Instance, OxidationError> {
         // Check whether there are any free stages that fit the required size specification.
         if let Some(instance) = self
             .free_stages
             .iter_mut()
             .find(|instance| instance.size >= required_size)
         {
-            return instance;
+            return Ok(instance);
         }
         // If not, create a new stage.
-        let instance = create_stage(vma_allocator, required_size);
+        let instance = create_stage(vma_allocator, required_size)?;
         self.in_use_stages.push(instance);
-        self.in_use_stages.last_mut().unwrap()
+        Ok(self.in_use_stages.last_mut().unwrap())
     }
 
     /// Garbage collection - free stage buffers which exceed the designated max frame
@@ -103,18 +105,55 @@ impl Default for StagingPool {
     }
 }
 
-fn create_stage(vma_alloc: &vk_mem::Allocator, size: vk::DeviceSize) -> Instance {
+pub(crate) fn create_stage(
+    vma_alloc: &vk_mem::Allocator,
+    size: vk::DeviceSize,
+) -> Result<Instance, OxidationError> {
+    validate_stage_size(size)?;
     let buffer_create_info = vk::BufferCreateInfo::default()
         .usage(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
         .size(size);
+    // Stages are written to sequentially by the host so must be mappable.
     let alloc_create_info = vk_mem::AllocationCreateInfo {
         usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
         ..Default::default()
     };
-    let (buffer, alloc) = unsafe {
-        vma_alloc
-            .create_buffer(&buffer_create_info, &alloc_create_info)
-            .unwrap()
+    let (buffer, alloc) = vk_check!(
+        unsafe { vma_alloc.create_buffer(&buffer_create_info, &alloc_create_info) },
+        "vmaCreateBuffer"
+    )?;
+    Ok(Instance::new(buffer, size, alloc))
+}
+
+/// Create a stage for downloading data from the device. Unlike upload stages, these are
+/// read by the host so should be placed in cached memory.
+pub(crate) fn create_download_stage(
+    vma_alloc: &vk_mem::Allocator,
+    size: vk::DeviceSize,
+) -> Result<Instance, OxidationError> {
+    validate_stage_size(size)?;
+    let buffer_create_info = vk::BufferCreateInfo::default()
+        .usage(vk::BufferUsageFlags::TRANSFER_DST)
+        .size(size);
+    let alloc_create_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM,
+        ..Default::default()
     };
-    Instance::new(buffer, size, alloc)
+    let (buffer, alloc) = vk_check!(
+        unsafe { vma_alloc.create_buffer(&buffer_create_info, &alloc_create_info) },
+        "vmaCreateBuffer"
+    )?;
+    Ok(Instance::new(buffer, size, alloc))
+}
+
+/// Buffers of zero size are invalid, so there is nothing to stage.
+fn validate_stage_size(size: vk::DeviceSize) -> Result<(), OxidationError> {
+    match size {
+        0 => Err(OxidationError::Other(
+            "Unable to create a staging buffer with no data.".to_string(),
+        )),
+        _ => Ok(()),
+    }
 }
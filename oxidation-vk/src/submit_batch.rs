@@ -0,0 +1,190 @@
+use crate::device::ContextDevice;
+use ash::prelude::VkResult;
+use ash::vk;
+
+/// A semaphore along with the pipeline stages at which it is waited upon or signalled.
+#[derive(Debug, Copy, Clone)]
+pub struct SemaphoreSubmit {
+    pub semaphore: vk::Semaphore,
+    pub stage_mask: vk::PipelineStageFlags2,
+}
+
+impl SemaphoreSubmit {
+    pub fn new(semaphore: vk::Semaphore, stage_mask: vk::PipelineStageFlags2) -> Self {
+        Self {
+            semaphore,
+            stage_mask,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BatchEntry {
+    cmds: vk::CommandBuffer,
+    wait_semaphores: Vec<SemaphoreSubmit>,
+    signal_semaphores: Vec<SemaphoreSubmit>,
+}
+
+/// Collects a number of command buffers, along with their wait and signal semaphores,
+/// so they can be submitted to a queue with a single call. Each submit call has a
+/// non-trivial CPU overhead, so batching the many small submissions required per frame
+/// (shadow passes, post-processing, etc.) reduces this cost.
+///
+/// If `VK_KHR_synchronization2` is enabled on the device, `vkQueueSubmit2` is used,
+/// otherwise this falls back to the legacy submit.
+///
+/// # Examples
+///
+/// ```ignore
+/// use oxidation_vk::submit_batch::{SemaphoreSubmit, SubmitBatch};
+///
+/// let mut batch = SubmitBatch::new();
+/// batch.add(shadow_cmds, &[], &[SemaphoreSubmit::new(shadow_done, vk::PipelineStageFlags2::ALL_COMMANDS)]);
+/// batch.add(lighting_cmds, &[SemaphoreSubmit::new(shadow_done, vk::PipelineStageFlags2::FRAGMENT_SHADER)], &[]);
+/// batch.submit(&driver.device, driver.device.graphics_queue, fence)?;
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct SubmitBatch {
+    entries: Vec<BatchEntry>,
+}
+
+impl SubmitBatch {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a command buffer to the batch. Command buffers are executed in the order
+    /// they are added.
+    pub fn add(
+        &mut self,
+        cmds: vk::CommandBuffer,
+        wait_semaphores: &[SemaphoreSubmit],
+        signal_semaphores: &[SemaphoreSubmit],
+    ) -> &mut Self {
+        self.entries.push(BatchEntry {
+            cmds,
+            wait_semaphores: wait_semaphores.to_vec(),
+            signal_semaphores: signal_semaphores.to_vec(),
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Submit all command buffers in the batch with a single queue submission. The
+    /// fence, if not null, is signalled once all command buffers have completed.
+    /// The batch is cleared after submitting.
+    pub fn submit(
+        &mut self,
+        device: &ContextDevice,
+        queue: vk::Queue,
+        fence: vk::Fence,
+    ) -> VkResult<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+
+        let res = match device.enabled_features().synchronization2 {
+            true => self.submit2(&device.device, queue, fence),
+            false => self.submit_legacy(&device.device, queue, fence),
+        };
+        self.entries.clear();
+        res
+    }
+
+    fn submit2(&self, device: &ash::Device, queue: vk::Queue, fence: vk::Fence) -> VkResult<()> {
+        let to_vk = |semaphores: &[SemaphoreSubmit]| -> Vec<vk::SemaphoreSubmitInfo> {
+            semaphores
+                .iter()
+                .map(|s| {
+                    vk::SemaphoreSubmitInfo::default()
+                        .semaphore(s.semaphore)
+                        .stage_mask(s.stage_mask)
+                })
+                .collect()
+        };
+        let waits: Vec<Vec<vk::SemaphoreSubmitInfo>> = self
+            .entries
+            .iter()
+            .map(|entry| to_vk(&entry.wait_semaphores))
+            .collect();
+        let signals: Vec<Vec<vk::SemaphoreSubmitInfo>> = self
+            .entries
+            .iter()
+            .map(|entry| to_vk(&entry.signal_semaphores))
+            .collect();
+        let cmd_infos: Vec<[vk::CommandBufferSubmitInfo; 1]> = self
+            .entries
+            .iter()
+            .map(|entry| [vk::CommandBufferSubmitInfo::default().command_buffer(entry.cmds)])
+            .collect();
+
+        let submit_infos: Vec<vk::SubmitInfo2> = (0..self.entries.len())
+            .map(|idx| {
+                vk::SubmitInfo2::default()
+                    .wait_semaphore_infos(&waits[idx])
+                    .command_buffer_infos(&cmd_infos[idx])
+                    .signal_semaphore_infos(&signals[idx])
+            })
+            .collect();
+        unsafe { device.queue_submit2(queue, &submit_infos, fence) }
+    }
+
+    fn submit_legacy(
+        &self,
+        device: &ash::Device,
+        queue: vk::Queue,
+        fence: vk::Fence,
+    ) -> VkResult<()> {
+        let waits: Vec<Vec<vk::Semaphore>> = self
+            .entries
+            .iter()
+            .map(|entry| entry.wait_semaphores.iter().map(|s| s.semaphore).collect())
+            .collect();
+        // The legacy stage flags share the same bit values as the lower 32-bits of the sync2 flags.
+        let wait_stages: Vec<Vec<vk::PipelineStageFlags>> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .wait_semaphores
+                    .iter()
+                    .map(|s| vk::PipelineStageFlags::from_raw(s.stage_mask.as_raw() as u32))
+                    .collect()
+            })
+            .collect();
+        let signals: Vec<Vec<vk::Semaphore>> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .signal_semaphores
+                    .iter()
+                    .map(|s| s.semaphore)
+                    .collect()
+            })
+            .collect();
+        let cmds: Vec<[vk::CommandBuffer; 1]> =
+            self.entries.iter().map(|entry| [entry.cmds]).collect();
+
+        let submit_infos: Vec<vk::SubmitInfo> = (0..self.entries.len())
+            .map(|idx| {
+                vk::SubmitInfo::default()
+                    .wait_semaphores(&waits[idx])
+                    .wait_dst_stage_mask(&wait_stages[idx])
+                    .command_buffers(&cmds[idx])
+                    .signal_semaphores(&signals[idx])
+            })
+            .collect();
+        unsafe { device.queue_submit(queue, &submit_infos, fence) }
+    }
+}
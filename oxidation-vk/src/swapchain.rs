@@ -1,13 +1,168 @@
+use crate::Driver;
+use crate::backend::PresentMode;
+use crate::barrier;
 use crate::device::ContextDevice;
+use crate::error::OxidationError;
+use crate::host_memory;
 use crate::instance::ContextInstance;
-use crate::texture::{Texture, TextureInfo};
+use crate::staging_pool;
+use crate::sync::{self, FenceWait};
+use crate::texture::{Texture, TextureInfo, TextureType};
+use crate::vk_check;
 
+use ash::prelude::VkResult;
 use ash::{
     khr::{surface, swapchain},
     vk,
 };
+use log::warn;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::error::Error;
 
+/// The contents of a captured swapchain image as tightly packed RGBA8 pixels.
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A swapchain image along with its view and the parameters needed to render into it - see
+/// [`Swapchain::iter_images`].
+#[derive(Debug, Copy, Clone)]
+pub struct SwapchainImage {
+    /// The index of the image, as returned when acquiring it.
+    pub index: u32,
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    /// The format of the view, which differs from the image format with sRGB views.
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+/// The dynamic range category of a surface color space.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ColorSpaceCategory {
+    Sdr,
+    Hdr,
+}
+
+impl ColorSpaceCategory {
+    /// The category of the color space. Wide gamut color spaces with a standard dynamic
+    /// range, such as Display P3, are categorised as SDR.
+    pub fn from_color_space(color_space: vk::ColorSpaceKHR) -> Self {
+        match color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT
+            | vk::ColorSpaceKHR::HDR10_HLG_EXT
+            | vk::ColorSpaceKHR::DOLBYVISION_EXT
+            | vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+            | vk::ColorSpaceKHR::EXTENDED_SRGB_NONLINEAR_EXT
+            | vk::ColorSpaceKHR::BT2020_LINEAR_EXT => ColorSpaceCategory::Hdr,
+            _ => ColorSpaceCategory::Sdr,
+        }
+    }
+}
+
+/// Split the surface formats into those with SDR and HDR color spaces, in that order.
+pub fn group_surface_formats(
+    formats: &[vk::SurfaceFormatKHR],
+) -> (Vec<vk::SurfaceFormatKHR>, Vec<vk::SurfaceFormatKHR>) {
+    formats.iter().partition(|format| {
+        ColorSpaceCategory::from_color_space(format.color_space) == ColorSpaceCategory::Sdr
+    })
+}
+
+/// The outcome of changing the present mode of a swapchain.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PresentModeChange {
+    /// The present mode is used from the next present, without recreating the swapchain.
+    Applied,
+    /// The present mode isn't compatible with the swapchain, which must be recreated with
+    /// [`Swapchain::recreate_with_present_mode`].
+    RequiresRecreate,
+}
+
+/// How the swapchain images are shared when the graphics and present queue families differ.
+/// When they're the same family the images are always exclusive, with no ownership transfer.
+///
+/// Concurrent sharing needs no extra synchronisation, but prevents some drivers from using
+/// compressed render target layouts, which costs bandwidth on every frame. Exclusive sharing
+/// avoids this at the cost of an ownership transfer before each present - a release barrier
+/// on the graphics queue and a matching acquire barrier submitted to the present queue,
+/// see [`Swapchain::present_barriers`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum PresentSharing {
+    #[default]
+    Concurrent,
+    Exclusive,
+}
+
+/// The barriers which transition a swapchain image for presenting - see
+/// [`Swapchain::present_barriers`].
+#[derive(Debug, Copy, Clone)]
+pub struct PresentBarriers {
+    /// Recorded on the graphics queue after rendering into the image. With an ownership
+    /// transfer this is the release half, so the dst stage and access are ignored.
+    pub release: vk::ImageMemoryBarrier2<'static>,
+    /// The acquire half of the ownership transfer, recorded on the present queue in a
+    /// submission which waits on the graphics work and is waited on by the present. Only
+    /// required for exclusive images when the queue families differ.
+    pub acquire: Option<vk::ImageMemoryBarrier2<'static>>,
+}
+
+/// The swapchains, image indices and wait semaphores of a multi-swapchain present, packed
+/// into the arrays of a single `VkPresentInfoKHR` - see [`crate::Driver::present_multi`].
+pub(crate) struct PresentBatch {
+    swapchains: Vec<vk::SwapchainKHR>,
+    image_indices: Vec<u32>,
+    wait_semaphores: Vec<vk::Semaphore>,
+}
+
+impl PresentBatch {
+    /// Null semaphores are skipped, as there is nothing to wait on.
+    pub(crate) fn new(
+        presents: impl IntoIterator<Item = (vk::SwapchainKHR, u32, vk::Semaphore)>,
+    ) -> Self {
+        let mut batch = Self {
+            swapchains: Vec::new(),
+            image_indices: Vec::new(),
+            wait_semaphores: Vec::new(),
+        };
+        for (swapchain, image_index, semaphore) in presents {
+            batch.swapchains.push(swapchain);
+            batch.image_indices.push(image_index);
+            if semaphore != vk::Semaphore::null() {
+                batch.wait_semaphores.push(semaphore);
+            }
+        }
+        batch
+    }
+
+    /// The present info, with the result of each swapchain's present written to `results`.
+    pub(crate) fn present_info<'a>(
+        &'a self,
+        results: &'a mut [vk::Result],
+    ) -> vk::PresentInfoKHR<'a> {
+        vk::PresentInfoKHR::default()
+            .swapchains(&self.swapchains)
+            .image_indices(&self.image_indices)
+            .wait_semaphores(&self.wait_semaphores)
+            .results(results)
+    }
+
+    /// Convert the per-swapchain results of the present - `Ok(true)` if suboptimal.
+    pub(crate) fn map_results(results: Vec<vk::Result>) -> Vec<Result<bool, vk::Result>> {
+        results
+            .into_iter()
+            .map(|res| match res {
+                vk::Result::SUCCESS => Ok(false),
+                vk::Result::SUBOPTIMAL_KHR => Ok(true),
+                err => Err(err),
+            })
+            .collect()
+    }
+}
+
 /// A swapchain is Vulkan's abstract object that deals with rendering
 /// an image to the surface. The swapchain handles the images which will
 /// be rendered to based upon the current index - usual setup gives
@@ -15,20 +170,46 @@ use std::error::Error;
 ///
 /// # Examples
 ///
-/// ```
+/// ```ignore
 /// let instance = oxidation_vk::instance::ContextInstance::new();
 /// let device = oxidation_vk::device::ContextDevice::new();
 /// let win_size = (1980, 1080);
-/// let swapchain = oxidation_vk::swapchain::Swapchain::new(&instance, &device, _, win_size.0, win_size.1);
+/// let swapchain = oxidation_vk::swapchain::Swapchain::new(&instance, &device, _, win_size.0, win_size.1, false, 1);
 /// ```
 ///
 pub struct Swapchain {
     pub instance: vk::SwapchainKHR,
     pub extents: vk::Extent2D,
     pub surface_format: vk::SurfaceFormatKHR,
+    /// The format of the image views - this differs from the surface format when sRGB
+    /// views are created over UNORM images.
+    pub view_format: vk::Format,
+    /// The usage flags the swapchain images were created with.
+    pub usage: vk::ImageUsageFlags,
+    /// The number of array layers of each image - more than one for layered stereo rendering.
+    array_layers: u32,
     pub swapchain_loader: swapchain::Device,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
+    /// The present mode used when presenting the swapchain images.
+    present_mode: vk::PresentModeKHR,
+    /// The present modes which can be switched to without recreating the swapchain - requires
+    /// `VK_EXT_swapchain_maintenance1`, otherwise only the current mode.
+    compatible_present_modes: Vec<vk::PresentModeKHR>,
+    /// Whether `VK_EXT_swapchain_maintenance1` was enabled when the swapchain was created.
+    swapchain_maintenance1: bool,
+    pre_transform: vk::SurfaceTransformFlagsKHR,
+    composite_alpha: vk::CompositeAlphaFlagsKHR,
+    /// Fences signalled once the present of each image has completed - requires
+    /// `VK_EXT_swapchain_maintenance1`, otherwise empty.
+    present_fences: Vec<vk::Fence>,
+    /// The images presented with a fence which hasn't yet been waited on, oldest first.
+    pending_presents: RefCell<VecDeque<u32>>,
+    /// The number of times the swapchain has been recreated.
+    generation: u64,
+    present_sharing: PresentSharing,
+    graphics_queue_idx: u32,
+    present_queue_idx: u32,
 }
 
 impl Swapchain {
@@ -52,53 +233,466 @@ impl Swapchain {
             .unwrap_or(&surface_formats[0])
     }
 
-    /// Find a suitable presentation mode. The order of preference is:
-    /// 1. Mailbox -> 2. FIFO -> 3. Immediate
-    fn find_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
-        if present_modes.contains(&vk::PresentModeKHR::MAILBOX) {
-            vk::PresentModeKHR::MAILBOX
-        } else if present_modes.contains(&vk::PresentModeKHR::FIFO) {
-            return vk::PresentModeKHR::FIFO;
-        } else {
-            return vk::PresentModeKHR::IMMEDIATE;
+    /// Find a suitable presentation mode. The preferred mode is used if supported, otherwise
+    /// the order of preference is:
+    /// 1. Mailbox -> 2. FIFO relaxed -> 3. FIFO -> 4. Immediate
+    fn find_present_mode(
+        present_modes: &[vk::PresentModeKHR],
+        preferred: Option<vk::PresentModeKHR>,
+    ) -> vk::PresentModeKHR {
+        if let Some(preferred) = preferred
+            && present_modes.contains(&preferred)
+        {
+            return preferred;
+        }
+        [
+            PresentMode::Mailbox,
+            PresentMode::FifoRelaxed,
+            PresentMode::Fifo,
+        ]
+        .iter()
+        .map(PresentMode::to_vk)
+        .find(|mode| present_modes.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::IMMEDIATE)
+    }
+
+    /// The present modes which a swapchain created with the present mode can switch to
+    /// without recreation, as reported by `VK_EXT_surface_maintenance1`.
+    fn query_compatible_present_modes(
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<Vec<vk::PresentModeKHR>, OxidationError> {
+        let loader =
+            ash::khr::get_surface_capabilities2::Instance::new(&instance.entry, &instance.instance);
+        let mut surface_present_mode =
+            vk::SurfacePresentModeEXT::default().present_mode(present_mode);
+        let surface_info = vk::PhysicalDeviceSurfaceInfo2KHR::default()
+            .surface(*surface)
+            .push_next(&mut surface_present_mode);
+
+        // The count is queried first, followed by the modes.
+        let mut compatibility = vk::SurfacePresentModeCompatibilityEXT::default();
+        let mut caps = vk::SurfaceCapabilities2KHR::default().push_next(&mut compatibility);
+        unsafe {
+            vk_check!(
+                loader.get_physical_device_surface_capabilities2(
+                    device.physical_device,
+                    &surface_info,
+                    &mut caps
+                ),
+                "vkGetPhysicalDeviceSurfaceCapabilities2KHR"
+            )?
+        };
+        let mut modes =
+            vec![vk::PresentModeKHR::default(); compatibility.present_mode_count as usize];
+        let mut compatibility =
+            vk::SurfacePresentModeCompatibilityEXT::default().present_modes(&mut modes);
+        let mut caps = vk::SurfaceCapabilities2KHR::default().push_next(&mut compatibility);
+        unsafe {
+            vk_check!(
+                loader.get_physical_device_surface_capabilities2(
+                    device.physical_device,
+                    &surface_info,
+                    &mut caps
+                ),
+                "vkGetPhysicalDeviceSurfaceCapabilities2KHR"
+            )?
+        };
+        let count = compatibility.present_mode_count as usize;
+        modes.truncate(count);
+        if !modes.contains(&present_mode) {
+            modes.push(present_mode);
+        }
+        Ok(modes)
+    }
+
+    /// The present mode used when presenting the swapchain images.
+    #[inline]
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    /// Change the present mode, e.g. to toggle vsync. If the device supports
+    /// `VK_EXT_swapchain_maintenance1` and the mode is compatible with the swapchain, the mode
+    /// is used from the next present. Otherwise the swapchain must be recreated with
+    /// [`Self::recreate_with_present_mode`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mode = match vsync {
+    ///     true => vk::PresentModeKHR::FIFO,
+    ///     false => vk::PresentModeKHR::MAILBOX,
+    /// };
+    /// if swapchain.set_present_mode(mode) == PresentModeChange::RequiresRecreate {
+    ///     swapchain.recreate_with_present_mode(&driver.instance, &driver.device, &driver.surface, mode)?;
+    /// }
+    /// ```
+    ///
+    pub fn set_present_mode(&mut self, present_mode: vk::PresentModeKHR) -> PresentModeChange {
+        let change = Self::present_mode_change(
+            self.present_mode,
+            present_mode,
+            &self.compatible_present_modes,
+            self.swapchain_maintenance1,
+        );
+        if change == PresentModeChange::Applied {
+            self.present_mode = present_mode;
+        }
+        change
+    }
+
+    /// Whether the present mode can be switched to from the current mode without recreating
+    /// the swapchain. Switching requires `VK_EXT_swapchain_maintenance1` and the mode to be
+    /// one of the compatible modes the swapchain was created with.
+    fn present_mode_change(
+        current: vk::PresentModeKHR,
+        requested: vk::PresentModeKHR,
+        compatible_modes: &[vk::PresentModeKHR],
+        swapchain_maintenance1: bool,
+    ) -> PresentModeChange {
+        if requested == current || (swapchain_maintenance1 && compatible_modes.contains(&requested))
+        {
+            return PresentModeChange::Applied;
         }
+        PresentModeChange::RequiresRecreate
+    }
+
+    /// Recreate the swapchain with the present mode, if supported by the surface, retaining
+    /// the extent and formats. If the mode isn't supported, the best supported mode is used
+    /// instead - check [`Self::present_mode`] for the mode selected. The device must be idle,
+    /// as the images of the old swapchain are destroyed.
+    pub fn recreate_with_present_mode(
+        &mut self,
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<(), Box<dyn Error>> {
+        self.replace(instance, device, surface, self.extents, present_mode)
+    }
+
+    /// Recreate the swapchain for the new window size, i.e. after a resize or when the
+    /// swapchain is out of date, retaining the present mode and formats. The surface
+    /// capabilities are queried again, so the pre-transform and composite alpha are
+    /// renegotiated - these may change when the display is rotated or the compositor changes.
+    /// The device must be idle, as the images of the old swapchain are destroyed.
+    pub fn recreate(
+        &mut self,
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+        win_width: u32,
+        win_height: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let win_extent = vk::Extent2D {
+            width: win_width,
+            height: win_height,
+        };
+        self.replace(instance, device, surface, win_extent, self.present_mode)
+    }
+
+    fn replace(
+        &mut self,
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+        win_extent: vk::Extent2D,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<(), Box<dyn Error>> {
+        let srgb_views = self.view_format != self.surface_format.format;
+        let swapchain = Self::create(
+            instance,
+            device,
+            surface,
+            win_extent,
+            srgb_views,
+            self.array_layers,
+            Some(present_mode),
+            self.present_sharing,
+            self.instance,
+        )?;
+        let generation = self.generation + 1;
+        let mut old = std::mem::replace(self, swapchain);
+        old.destroy(&device.device);
+        self.generation = generation;
+        Ok(())
+    }
+
+    /// How the images are shared between the graphics and present queue families.
+    #[inline]
+    pub fn present_sharing(&self) -> PresentSharing {
+        self.present_sharing
+    }
+
+    /// Whether presenting requires the ownership of the images to be transferred from the
+    /// graphics to the present queue family - see [`Self::present_barriers`].
+    pub fn requires_ownership_transfer(&self) -> bool {
+        self.present_sharing == PresentSharing::Exclusive
+            && self.graphics_queue_idx != self.present_queue_idx
+    }
+
+    /// The barriers which transition the image from the layout it was rendered in to the
+    /// present layout, once the stages and accesses have completed. Includes the queue
+    /// ownership transfer when required, otherwise only the release barrier is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let barriers = swapchain.present_barriers(
+    ///     image_index,
+    ///     vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    ///     vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+    ///     vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+    /// );
+    /// barrier::cmd_image_barriers(&driver.device, graphics_cmds, &[barriers.release]);
+    /// if let Some(acquire) = barriers.acquire {
+    ///     barrier::cmd_image_barriers(&driver.device, present_cmds, &[acquire]);
+    /// }
+    /// ```
+    ///
+    pub fn present_barriers(
+        &self,
+        image_index: u32,
+        old_layout: vk::ImageLayout,
+        src_stage: vk::PipelineStageFlags2,
+        src_access: vk::AccessFlags2,
+    ) -> PresentBarriers {
+        let barrier = vk::ImageMemoryBarrier2::default()
+            .image(self.images[image_index as usize])
+            .old_layout(old_layout)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(self.array_layers),
+            )
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access);
+        let transfer = self
+            .requires_ownership_transfer()
+            .then_some((self.graphics_queue_idx, self.present_queue_idx));
+        present_barriers(barrier, transfer)
+    }
+
+    /// The number of times the swapchain has been recreated - image indices acquired before a
+    /// recreation refer to the destroyed images, so mustn't be presented.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The surface transform applied to the images when presented, relative to the
+    /// natural orientation of the display.
+    #[inline]
+    pub fn pre_transform(&self) -> vk::SurfaceTransformFlagsKHR {
+        self.pre_transform
+    }
+
+    /// How the alpha channel of the images is treated when composited.
+    #[inline]
+    pub fn composite_alpha(&self) -> vk::CompositeAlphaFlagsKHR {
+        self.composite_alpha
+    }
+
+    /// The current extent of the surface, as reported by its capabilities. The extent is zero
+    /// while the window is minimized, when no swapchain can be created for the surface - and
+    /// `u32::MAX` if the extent is determined by the swapchain rather than the window.
+    pub fn surface_extent(
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+    ) -> Result<vk::Extent2D, OxidationError> {
+        let surface_loader = surface::Instance::new(&instance.entry, &instance.instance);
+        let surface_caps = unsafe {
+            vk_check!(
+                surface_loader
+                    .get_physical_device_surface_capabilities(device.physical_device, *surface),
+                "vkGetPhysicalDeviceSurfaceCapabilitiesKHR"
+            )?
+        };
+        Ok(surface_caps.current_extent)
+    }
+
+    /// The number of array layers of each swapchain image.
+    #[inline]
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    /// The dimensions and format of the swapchain image views - layered swapchains are
+    /// viewed as 2D arrays.
+    pub fn image_info(&self) -> TextureInfo {
+        view_info(self.extents, self.view_format, self.array_layers)
+    }
+
+    /// The number of images in the swapchain.
+    #[inline]
+    pub fn image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Iterate over the swapchain images, along with their views, format and extent - i.e.
+    /// for setting up per-image attachments.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let targets: Vec<RenderingAttachment> = swapchain
+    ///     .iter_images()
+    ///     .map(|image| RenderingAttachment { view: image.view, ..Default::default() })
+    ///     .collect();
+    /// ```
+    ///
+    pub fn iter_images(&self) -> impl ExactSizeIterator<Item = SwapchainImage> + '_ {
+        swapchain_images(
+            &self.images,
+            &self.image_views,
+            self.view_format,
+            self.extents,
+        )
+    }
+
+    /// The format of the swapchain color image views - required when creating pipelines
+    /// which render into the swapchain.
+    #[inline]
+    pub fn color_format(&self) -> vk::Format {
+        self.view_format
+    }
+
+    /// Whether the hardware applies sRGB encoding when writing to the swapchain. If so,
+    /// shaders should output linear colour values rather than gamma correcting themselves.
+    pub fn srgb_encoding(&self) -> bool {
+        srgb_format(self.view_format) == Some(self.view_format)
+    }
+
+    /// Convert a nominal SDR clear colour into the values to clear the swapchain images with,
+    /// so it appears the same whatever the color space - see [`encode_clear_color`].
+    pub fn encode_clear_color(&self, color: [f32; 4]) -> [f32; 4] {
+        encode_clear_color(
+            color,
+            self.surface_format.color_space,
+            self.srgb_encoding(),
+            SDR_WHITE_NITS,
+        )
     }
 
     /// Create a new swapchain instance based upon the specified Vulkan window surface.
+    ///
+    /// If `srgb_views` is set and the surface format is UNORM, the image views are created
+    /// with the sRGB equivalent format so the hardware applies the sRGB encoding. This
+    /// requires `VK_KHR_swapchain_mutable_format` - if not supported, UNORM views are used.
+    ///
+    /// `array_layers` is the number of layers of each image, usually 1. Layered stereo
+    /// rendering uses 2, with one layer per eye, i.e. combined with multiview. Returns an
+    /// error if the surface doesn't support the number of layers.
     pub fn new(
         instance: &ContextInstance,
         device: &ContextDevice,
         surface: &vk::SurfaceKHR,
         win_width: u32,
         win_height: u32,
+        srgb_views: bool,
+        array_layers: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let win_extent = vk::Extent2D {
+            width: win_width,
+            height: win_height,
+        };
+        Self::create(
+            instance,
+            device,
+            surface,
+            win_extent,
+            srgb_views,
+            array_layers,
+            None,
+            PresentSharing::default(),
+            vk::SwapchainKHR::null(),
+        )
+    }
+
+    /// As [`Self::new`], but with the sharing mode of the images when the graphics and
+    /// present queue families differ - see [`PresentSharing`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_present_sharing(
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+        win_width: u32,
+        win_height: u32,
+        srgb_views: bool,
+        array_layers: u32,
+        present_sharing: PresentSharing,
+    ) -> Result<Self, Box<dyn Error>> {
+        let win_extent = vk::Extent2D {
+            width: win_width,
+            height: win_height,
+        };
+        Self::create(
+            instance,
+            device,
+            surface,
+            win_extent,
+            srgb_views,
+            array_layers,
+            None,
+            present_sharing,
+            vk::SwapchainKHR::null(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        instance: &ContextInstance,
+        device: &ContextDevice,
+        surface: &vk::SurfaceKHR,
+        win_extent: vk::Extent2D,
+        srgb_views: bool,
+        array_layers: u32,
+        preferred_present_mode: Option<vk::PresentModeKHR>,
+        present_sharing: PresentSharing,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<Self, Box<dyn Error>> {
         let surface_loader = surface::Instance::new(&instance.entry, &instance.instance);
 
         let surface_caps = unsafe {
-            surface_loader
-                .get_physical_device_surface_capabilities(device.physical_device, *surface)
-                .unwrap()
+            vk_check!(
+                surface_loader
+                    .get_physical_device_surface_capabilities(device.physical_device, *surface),
+                "vkGetPhysicalDeviceSurfaceCapabilitiesKHR"
+            )?
         };
         let surface_formats = unsafe {
-            surface_loader
-                .get_physical_device_surface_formats(device.physical_device, *surface)
-                .expect("Unable to get physical device surface formats.")
+            vk_check!(
+                surface_loader
+                    .get_physical_device_surface_formats(device.physical_device, *surface),
+                "vkGetPhysicalDeviceSurfaceFormatsKHR"
+            )?
         };
         let surface_present_modes = unsafe {
-            surface_loader
-                .get_physical_device_surface_present_modes(device.physical_device, *surface)
-                .expect("Unable to get physical device surface present modes.")
+            vk_check!(
+                surface_loader
+                    .get_physical_device_surface_present_modes(device.physical_device, *surface),
+                "vkGetPhysicalDeviceSurfacePresentModesKHR"
+            )?
         };
 
         let surface_format = Self::find_surface_format(&surface_formats);
-        let present_mode = Self::find_present_mode(&surface_present_modes);
+        let present_mode = Self::find_present_mode(&surface_present_modes, preferred_present_mode);
+        let (pre_transform, composite_alpha) = negotiate_composition(&surface_caps);
 
         let mut extents = surface_caps.current_extent;
         if surface_caps.current_extent.width == u32::MAX {
-            extents.width = win_width
+            extents.width = win_extent
+                .width
                 .max(surface_caps.min_image_extent.width)
                 .min(surface_caps.max_image_extent.width);
-            extents.height = win_height
+            extents.height = win_extent
+                .height
                 .max(surface_caps.min_image_extent.height)
                 .min(surface_caps.max_image_extent.height);
         }
@@ -109,76 +703,935 @@ impl Swapchain {
             image_count = surface_caps.max_image_count;
         }
 
-        let mut create_info = vk::SwapchainCreateInfoKHR::default()
+        // Transfer src usage is required for capturing the swapchain images, though not all surfaces support this.
+        let mut usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        if surface_caps
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+
+        let create_info = vk::SwapchainCreateInfoKHR::default()
             .image_extent(extents)
             .image_format(surface_format.format)
             .min_image_count(image_count)
             .surface(*surface)
             .present_mode(present_mode)
             .image_color_space(surface_format.color_space)
-            .pre_transform(surface_caps.current_transform)
-            .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+            .pre_transform(pre_transform)
+            .composite_alpha(composite_alpha)
+            .image_usage(usage)
+            .old_swapchain(old_swapchain);
+        let mut create_info = with_array_layers(create_info, array_layers, &surface_caps)?;
 
-        create_info = if surface_caps
-            .supported_composite_alpha
-            .contains(vk::CompositeAlphaFlagsKHR::INHERIT)
+        let queue_families = [device.graphics_queue_idx, device.present_queue_idx];
+        create_info = match device.graphics_queue_idx != device.present_queue_idx
+            && present_sharing == PresentSharing::Concurrent
         {
-            create_info.composite_alpha(vk::CompositeAlphaFlagsKHR::INHERIT)
-        } else {
-            create_info.composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            true => create_info
+                .image_sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queue_families),
+            false => create_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE),
         };
 
-        create_info = if device.graphics_queue_idx != device.present_queue_idx {
-            create_info.image_sharing_mode(vk::SharingMode::CONCURRENT)
-        } else {
-            create_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+        // Create the images with a mutable format so they can be viewed as sRGB.
+        let mut view_format = surface_format.format;
+        let srgb_view_format =
+            srgb_format(surface_format.format).filter(|format| *format != surface_format.format);
+        let mut view_formats: Vec<vk::Format> = Vec::new();
+        if let Some(srgb_format) = srgb_view_format
+            && srgb_views
+        {
+            if device.swapchain_mutable_format_enabled {
+                view_format = srgb_format;
+                view_formats = vec![surface_format.format, srgb_format];
+                create_info = create_info.flags(vk::SwapchainCreateFlagsKHR::MUTABLE_FORMAT);
+            } else {
+                warn!(
+                    "sRGB swapchain views requested but VK_KHR_swapchain_mutable_format is not supported."
+                );
+            }
+        }
+        let mut format_list_info =
+            vk::ImageFormatListCreateInfo::default().view_formats(&view_formats);
+        if !view_formats.is_empty() {
+            create_info = create_info.push_next(&mut format_list_info);
+        }
+
+        // With swapchain maintenance, the swapchain is created with all present modes it may
+        // switch to without recreation.
+        let compatible_present_modes = match device.enabled_features().swapchain_maintenance1 {
+            true => Self::query_compatible_present_modes(instance, device, surface, present_mode)?,
+            false => vec![present_mode],
         };
+        let mut present_modes_info = vk::SwapchainPresentModesCreateInfoEXT::default()
+            .present_modes(&compatible_present_modes);
+        if device.enabled_features().swapchain_maintenance1 {
+            create_info = create_info.push_next(&mut present_modes_info);
+        }
 
         let swapchain_loader = swapchain::Device::new(&instance.instance, &device.device);
-        let swapchain = unsafe { swapchain_loader.create_swapchain(&create_info, None)? };
+        let swapchain = unsafe {
+            vk_check!(
+                swapchain_loader.create_swapchain(&create_info, None),
+                "vkCreateSwapchainKHR"
+            )?
+        };
 
         // Create the image views required to render into the swapchain images.
-        let images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
+        let images = unsafe {
+            vk_check!(
+                swapchain_loader.get_swapchain_images(swapchain),
+                "vkGetSwapchainImagesKHR"
+            )?
+        };
         let image_views = Self::create_image_views(
             &images,
-            extents.width,
-            extents.height,
-            surface_format.format,
+            &view_info(extents, view_format, array_layers),
             &device.device,
         );
+        let mut present_fences = Vec::new();
+        if device.enabled_features().swapchain_maintenance1 {
+            for _ in 0..images.len() {
+                let fence = unsafe {
+                    vk_check!(
+                        device
+                            .device
+                            .create_fence(&vk::FenceCreateInfo::default(), None),
+                        "vkCreateFence"
+                    )?
+                };
+                present_fences.push(fence);
+            }
+        }
 
         Ok(Self {
             instance: swapchain,
             extents,
             surface_format,
+            view_format,
+            usage,
+            array_layers,
             swapchain_loader,
             images,
             image_views,
+            present_mode,
+            compatible_present_modes,
+            swapchain_maintenance1: device.enabled_features().swapchain_maintenance1,
+            pre_transform,
+            composite_alpha,
+            present_fences,
+            pending_presents: RefCell::new(VecDeque::new()),
+            generation: 0,
+            present_sharing,
+            graphics_queue_idx: device.graphics_queue_idx,
+            present_queue_idx: device.present_queue_idx,
+        })
+    }
+
+    /// Whether presents of the swapchain signal a fence on completion, which can be waited on
+    /// with [`Self::wait_for_presents`] to pace the CPU. Without present fences, the CPU is
+    /// only paced by acquiring images.
+    pub fn has_present_fences(&self) -> bool {
+        !self.present_fences.is_empty()
+    }
+
+    /// Wait until no more than `max_pending` presents remain in progress, or the timeout in
+    /// nanoseconds elapses. Returns the indices of the images whose presents were waited on.
+    /// Returns immediately if the swapchain has no present fences.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Let the CPU run at most one frame ahead of the presentation engine.
+    /// if swapchain.wait_for_presents(&driver.device.device, 1, timeout_ns)? == FenceWait::TimedOut {
+    ///     // Skip the frame..
+    /// }
+    /// ```
+    ///
+    pub fn wait_for_presents(
+        &self,
+        device: &ash::Device,
+        max_pending: usize,
+        timeout_ns: u64,
+    ) -> Result<FenceWait, OxidationError> {
+        let mut pending = self.pending_presents.borrow_mut();
+        pace_presents(&mut pending, max_pending, |image_index| {
+            let fence = self.present_fences[image_index as usize];
+            sync::wait_all(device, &[fence], timeout_ns)
+        })
+    }
+
+    /// The fence to signal on completion of the next present of the image - null if the
+    /// swapchain has no present fences. The previous present of the image must complete
+    /// before its fence can be reused.
+    pub(crate) fn present_fence(
+        &self,
+        device: &ash::Device,
+        image_index: u32,
+    ) -> VkResult<vk::Fence> {
+        let Some(fence) = self.present_fences.get(image_index as usize).copied() else {
+            return Ok(vk::Fence::null());
+        };
+        let mut pending = self.pending_presents.borrow_mut();
+        if let Some(idx) = pending.iter().position(|pending| *pending == image_index) {
+            unsafe { device.wait_for_fences(&[fence], true, u64::MAX)? };
+            pending.remove(idx);
+        }
+        unsafe { device.reset_fences(&[fence])? };
+        Ok(fence)
+    }
+
+    /// Record that the image was presented with its present fence.
+    pub(crate) fn on_presented(&self, image_index: u32) {
+        if self.has_present_fences() {
+            self.pending_presents.borrow_mut().push_back(image_index);
+        }
+    }
+
+    /// Acquire the next swapchain image to render into, returning the image index and whether
+    /// the swapchain is suboptimal for the surface. The semaphore is signalled once the image
+    /// is ready to be written to.
+    ///
+    /// If no image becomes available within the timeout (for instance, the window is occluded
+    /// and the compositor has stalled), `OxidationError::AcquireTimeout` is returned, in which
+    /// case the frame should be skipped rather than blocking the render loop.
+    pub fn acquire_next_image(
+        &self,
+        signal: vk::Semaphore,
+        timeout_ns: u64,
+    ) -> Result<(u32, bool), OxidationError> {
+        let res = unsafe {
+            self.swapchain_loader.acquire_next_image(
+                self.instance,
+                timeout_ns,
+                signal,
+                vk::Fence::null(),
+            )
+        };
+        match res {
+            // A suboptimal swapchain can still be presented to.
+            Ok(res) => Ok(res),
+            Err(vk::Result::TIMEOUT) | Err(vk::Result::NOT_READY) => {
+                Err(OxidationError::AcquireTimeout)
+            }
+            Err(err) => Err(OxidationError::VkResult(err)),
+        }
+    }
+
+    /// Copy the contents of the specified swapchain image into host memory. `layout` is the
+    /// current layout of the image - i.e. the present layout once it has been rendered and
+    /// presented - and the image is returned to this layout after the copy. Only the first
+    /// array layer of layered swapchains is captured.
+    /// This waits on the device, so should only be used for debugging and screenshots.
+    pub fn capture(
+        &self,
+        driver: &Driver,
+        image_index: u32,
+        layout: vk::ImageLayout,
+    ) -> Result<FrameCapture, OxidationError> {
+        if !self.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            return Err(OxidationError::Unsupported(String::from(
+                "The surface does not support capturing of swapchain images.",
+            )));
+        }
+        let swizzle = match self.surface_format.format {
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => true,
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => false,
+            format => {
+                return Err(OxidationError::Unsupported(format!(
+                    "Capturing swapchain images of format {format:?} is not supported."
+                )));
+            }
+        };
+        let image = *self
+            .images
+            .get(image_index as usize)
+            .ok_or_else(|| OxidationError::Other(format!("Invalid image index: {image_index}")))?;
+
+        let device = &driver.device.device;
+        let (width, height) = (self.extents.width, self.extents.height);
+        let size = width as vk::DeviceSize * height as vk::DeviceSize * 4;
+        let mut stage = staging_pool::create_download_stage(&driver.vma_allocator, size)?;
+
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+        let to_transfer = vk::ImageMemoryBarrier2::default()
+            .image(image)
+            .old_layout(layout)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_stage_mask(barrier::layout_stage_flags(layout))
+            .src_access_mask(barrier::src_access_flags(layout))
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(range);
+        // The contents of an undefined image may be discarded, so it's left in the transfer layout.
+        let restored_layout = match layout {
+            vk::ImageLayout::UNDEFINED => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            layout => layout,
+        };
+        let to_restore = to_transfer
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(restored_layout)
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_READ)
+            .dst_stage_mask(barrier::layout_stage_flags(restored_layout))
+            .dst_access_mask(barrier::dst_access_flags(restored_layout));
+        // Buffer row length and image height of zero specify the buffer is tightly packed.
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            });
+
+        let res = driver.submit_immediate(|cmds| {
+            barrier::cmd_image_barriers(&driver.device, cmds, &[to_transfer]);
+            unsafe {
+                device.cmd_copy_image_to_buffer(
+                    cmds,
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    stage.buffer,
+                    &[region],
+                )
+            };
+            if restored_layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+                barrier::cmd_image_barriers(&driver.device, cmds, &[to_restore]);
+            }
+        });
+
+        // The stage is destroyed whether or not the copy succeeded.
+        let mut pixels = vec![0u8; size as usize];
+        let copy_res = res.and_then(|_| unsafe {
+            host_memory::invalidate_mapped(driver, &stage.memory, 0, size)?;
+            let mapped = driver.vma_allocator.map_memory(&mut stage.memory)?;
+            mapped.copy_to(pixels.as_mut_ptr(), size as usize);
+            driver.vma_allocator.unmap_memory(&mut stage.memory);
+            Ok(())
+        });
+        unsafe {
+            driver
+                .vma_allocator
+                .destroy_buffer(stage.buffer, &mut stage.memory)
+        };
+        copy_res?;
+
+        if swizzle {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(FrameCapture {
+            width,
+            height,
+            pixels,
         })
     }
 
     fn create_image_views(
         images: &[vk::Image],
-        width: u32,
-        height: u32,
-        format: vk::Format,
+        info: &TextureInfo,
         device: &ash::Device,
     ) -> Vec<vk::ImageView> {
         let mut views = Vec::new();
-        let info = TextureInfo {
-            width,
-            height,
-            format,
-            ..Default::default()
-        };
         for image in images {
-            views.push(Texture::create_image_view(image, &info, 0, 1, device));
+            views.push(Texture::create_image_view(image, info, 0, 1, device));
         }
         views
     }
 
-    pub fn destroy(&mut self) {
+    /// Destroy the image views and the swapchain. The swapchain images must no longer be in
+    /// use by the device.
+    pub fn destroy(&mut self, device: &ash::Device) {
+        // The swapchain can only be destroyed once all presents of its images have completed.
+        if let Err(err) = self.wait_for_presents(device, 0, u64::MAX) {
+            warn!("Failed waiting for the swapchain presents to complete: {err}");
+        }
+        for fence in self.present_fences.drain(..) {
+            unsafe { device.destroy_fence(fence, None) };
+        }
+        for view in self.image_views.drain(..) {
+            unsafe { device.destroy_image_view(view, None) };
+        }
+        self.images.clear();
         unsafe { self.swapchain_loader.destroy_swapchain(self.instance, None) };
     }
 }
+
+/// The images paired with their views, in image index order.
+fn swapchain_images<'a>(
+    images: &'a [vk::Image],
+    views: &'a [vk::ImageView],
+    format: vk::Format,
+    extent: vk::Extent2D,
+) -> impl ExactSizeIterator<Item = SwapchainImage> + 'a {
+    images
+        .iter()
+        .zip(views)
+        .enumerate()
+        .map(move |(index, (image, view))| SwapchainImage {
+            index: index as u32,
+            image: *image,
+            view: *view,
+            format,
+            extent,
+        })
+}
+
+/// Wait on the oldest of the pending presents, using the wait function, until no more than
+/// `max_pending` remain. A present which times out remains pending.
+/// Split the barrier into the present barriers, with the ownership transfer from the graphics
+/// to the present queue family if `transfer` is `Some((graphics, present))`.
+fn present_barriers(
+    barrier: vk::ImageMemoryBarrier2<'static>,
+    transfer: Option<(u32, u32)>,
+) -> PresentBarriers {
+    let Some((graphics_queue_idx, present_queue_idx)) = transfer else {
+        return PresentBarriers {
+            release: barrier
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE),
+            acquire: None,
+        };
+    };
+    // Both halves specify the same layouts and queue families, so the layout transition
+    // happens once, between the release and the acquire.
+    let transfer = barrier
+        .src_queue_family_index(graphics_queue_idx)
+        .dst_queue_family_index(present_queue_idx);
+    PresentBarriers {
+        release: transfer,
+        acquire: Some(
+            transfer
+                .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                .src_access_mask(vk::AccessFlags2::NONE)
+                .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE),
+        ),
+    }
+}
+
+fn pace_presents(
+    pending: &mut VecDeque<u32>,
+    max_pending: usize,
+    mut wait: impl FnMut(u32) -> Result<FenceWait, OxidationError>,
+) -> Result<FenceWait, OxidationError> {
+    let mut completed = Vec::new();
+    while pending.len() > max_pending {
+        let image_index = pending[0];
+        if wait(image_index)? == FenceWait::TimedOut {
+            return Ok(FenceWait::TimedOut);
+        }
+        pending.pop_front();
+        completed.push(image_index as usize);
+    }
+    Ok(FenceWait::Signalled(completed))
+}
+
+/// Set the number of array layers of the swapchain images, which must be supported by the
+/// surface.
+fn with_array_layers<'a>(
+    create_info: vk::SwapchainCreateInfoKHR<'a>,
+    array_layers: u32,
+    surface_caps: &vk::SurfaceCapabilitiesKHR,
+) -> Result<vk::SwapchainCreateInfoKHR<'a>, OxidationError> {
+    if array_layers == 0 || array_layers > surface_caps.max_image_array_layers {
+        return Err(OxidationError::Unsupported(format!(
+            "Swapchain images with {array_layers} array layers were requested, but the surface supports 1 to {} layers.",
+            surface_caps.max_image_array_layers
+        )));
+    }
+    Ok(create_info.image_array_layers(array_layers))
+}
+
+/// The view parameters of swapchain images with the extent, format and number of layers.
+fn view_info(extent: vk::Extent2D, format: vk::Format, array_layers: u32) -> TextureInfo {
+    let ty = match array_layers {
+        1 => TextureType::Texture2d,
+        _ => TextureType::Array2d,
+    };
+    TextureInfo {
+        width: extent.width,
+        height: extent.height,
+        array_layers,
+        format,
+        ty,
+        ..Default::default()
+    }
+}
+
+/// Determine the pre-transform and composite alpha from the surface capabilities. The
+/// current transform of the surface is used, so the compositor doesn't have to rotate the
+/// images, and the composite alpha is the first supported in order of preference:
+/// 1. Inherit -> 2. Opaque -> 3. Pre-multiplied -> 4. Post-multiplied
+pub fn negotiate_composition(
+    caps: &vk::SurfaceCapabilitiesKHR,
+) -> (vk::SurfaceTransformFlagsKHR, vk::CompositeAlphaFlagsKHR) {
+    let pre_transform = match caps.supported_transforms.contains(caps.current_transform) {
+        true => caps.current_transform,
+        false => vk::SurfaceTransformFlagsKHR::IDENTITY,
+    };
+    let composite_alpha = [
+        vk::CompositeAlphaFlagsKHR::INHERIT,
+        vk::CompositeAlphaFlagsKHR::OPAQUE,
+        vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+        vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+    ]
+    .into_iter()
+    .find(|alpha| caps.supported_composite_alpha.contains(*alpha))
+    .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
+    (pre_transform, composite_alpha)
+}
+
+/// The luminance SDR white is displayed at on HDR surfaces, in nits - the reference white of
+/// BT.2408.
+pub const SDR_WHITE_NITS: f32 = 203.0;
+
+/// The luminance of 1.0 in the extended sRGB linear (scRGB) color space, in nits.
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// Convert a nominal SDR colour - sRGB encoded, as colours are usually specified - into the
+/// values to clear an image in the color space with, so it appears the same on SDR and HDR
+/// surfaces. `srgb_encoding` is whether the hardware applies the sRGB encoding on writes,
+/// and `sdr_white_nits` the luminance SDR white is displayed at on HDR surfaces.
+///
+/// The transfer function of the color space is applied, i.e. the PQ curve for HDR10, after
+/// converting to BT.2020 primaries where required. Color spaces without a known transfer
+/// function are treated as sRGB. Alpha is passed through unchanged.
+///
+/// # Examples
+///
+/// ```
+/// # use oxidation_vk::swapchain::{SDR_WHITE_NITS, encode_clear_color};
+/// # use oxidation_vk::vk;
+/// let color = [0.1, 0.2, 0.3, 1.0];
+/// let sdr = encode_clear_color(color, vk::ColorSpaceKHR::SRGB_NONLINEAR, false, SDR_WHITE_NITS);
+/// assert_eq!(sdr, color);
+/// // SDR white is well below the 10,000 nit peak of the PQ curve.
+/// let hdr10 = encode_clear_color([1.0; 4], vk::ColorSpaceKHR::HDR10_ST2084_EXT, false, SDR_WHITE_NITS);
+/// assert!((hdr10[0] - 0.58).abs() < 0.01);
+/// ```
+///
+pub fn encode_clear_color(
+    color: [f32; 4],
+    color_space: vk::ColorSpaceKHR,
+    srgb_encoding: bool,
+    sdr_white_nits: f32,
+) -> [f32; 4] {
+    let [r, g, b, a] = color;
+    let linear = [r, g, b].map(srgb_to_linear);
+    let rgb = match color_space {
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT => {
+            let scale = sdr_white_nits / 10_000.0;
+            bt709_to_bt2020(linear).map(|c| pq_encode(c * scale))
+        }
+        vk::ColorSpaceKHR::HDR10_HLG_EXT => {
+            // Scaled so SDR white is at 75% of the signal range, as recommended by BT.2408.
+            bt709_to_bt2020(linear).map(|c| hlg_encode(c * 0.2647))
+        }
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => {
+            let scale = sdr_white_nits / SCRGB_WHITE_NITS;
+            linear.map(|c| c * scale)
+        }
+        vk::ColorSpaceKHR::BT2020_LINEAR_EXT => bt709_to_bt2020(linear),
+        vk::ColorSpaceKHR::BT709_LINEAR_EXT | vk::ColorSpaceKHR::DISPLAY_P3_LINEAR_EXT => linear,
+        // The hardware encodes linear values, otherwise the sRGB values are written as is.
+        _ => match srgb_encoding {
+            true => linear,
+            false => [r, g, b],
+        },
+    };
+    [rgb[0], rgb[1], rgb[2], a]
+}
+
+/// The sRGB EOTF, converting an encoded value to linear.
+fn srgb_to_linear(c: f32) -> f32 {
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// Convert linear BT.709 (sRGB) primaries to BT.2020 primaries.
+fn bt709_to_bt2020([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.6274 * r + 0.3293 * g + 0.0433 * b,
+        0.0691 * r + 0.9195 * g + 0.0114 * b,
+        0.0164 * r + 0.0880 * g + 0.8956 * b,
+    ]
+}
+
+/// The SMPTE ST 2084 (PQ) inverse EOTF, with the luminance normalised to 10,000 nits.
+fn pq_encode(y: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let ym = y.max(0.0).powf(M1);
+    ((C1 + C2 * ym) / (1.0 + C3 * ym)).powf(M2)
+}
+
+/// The hybrid log-gamma OETF of BT.2100, with scene light normalised to 1.0.
+fn hlg_encode(e: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+    let e = e.max(0.0);
+    match e <= 1.0 / 12.0 {
+        true => (3.0 * e).sqrt(),
+        false => A * (12.0 * e - B).ln() + C,
+    }
+}
+
+/// The sRGB equivalent of the specified format, if there is one.
+fn srgb_format(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Some(vk::Format::B8G8R8A8_SRGB),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Some(vk::Format::R8G8B8A8_SRGB),
+        vk::Format::A8B8G8R8_UNORM_PACK32 | vk::Format::A8B8G8R8_SRGB_PACK32 => {
+            Some(vk::Format::A8B8G8R8_SRGB_PACK32)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    #[test]
+    fn composition_uses_current_transform() {
+        let caps = vk::SurfaceCapabilitiesKHR {
+            supported_transforms: vk::SurfaceTransformFlagsKHR::IDENTITY
+                | vk::SurfaceTransformFlagsKHR::ROTATE_90,
+            current_transform: vk::SurfaceTransformFlagsKHR::ROTATE_90,
+            supported_composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
+            ..Default::default()
+        };
+        let (transform, _) = negotiate_composition(&caps);
+        assert_eq!(transform, vk::SurfaceTransformFlagsKHR::ROTATE_90);
+
+        let unsupported = vk::SurfaceCapabilitiesKHR {
+            supported_transforms: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            ..caps
+        };
+        let (transform, _) = negotiate_composition(&unsupported);
+        assert_eq!(transform, vk::SurfaceTransformFlagsKHR::IDENTITY);
+    }
+
+    #[test]
+    fn composite_alpha_follows_preference() {
+        let caps = |supported_composite_alpha| vk::SurfaceCapabilitiesKHR {
+            supported_composite_alpha,
+            ..Default::default()
+        };
+        let alpha = |caps| negotiate_composition(&caps).1;
+        assert_eq!(
+            alpha(caps(
+                vk::CompositeAlphaFlagsKHR::OPAQUE | vk::CompositeAlphaFlagsKHR::INHERIT
+            )),
+            vk::CompositeAlphaFlagsKHR::INHERIT
+        );
+        assert_eq!(
+            alpha(caps(
+                vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED
+                    | vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED
+            )),
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED
+        );
+        assert_eq!(
+            alpha(caps(vk::CompositeAlphaFlagsKHR::empty())),
+            vk::CompositeAlphaFlagsKHR::OPAQUE
+        );
+    }
+
+    #[test]
+    fn surface_formats_are_grouped_by_dynamic_range() {
+        let srgb = surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+        let p3 = surface_format(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT,
+        );
+        let hdr10 = surface_format(
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        );
+        let scrgb = surface_format(
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        );
+
+        let (sdr, hdr) = group_surface_formats(&[hdr10, srgb, scrgb, p3]);
+        assert_eq!(sdr, [srgb, p3]);
+        assert_eq!(hdr, [hdr10, scrgb]);
+    }
+
+    #[test]
+    fn surface_formats_without_hdr_are_all_sdr() {
+        let srgb = surface_format(
+            vk::Format::B8G8R8A8_UNORM,
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        );
+        let (sdr, hdr) = group_surface_formats(&[srgb]);
+        assert_eq!(sdr, [srgb]);
+        assert!(hdr.is_empty());
+        assert_eq!(group_surface_formats(&[]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn present_mode_prefers_supported_request() {
+        let modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(
+            Swapchain::find_present_mode(&modes, Some(vk::PresentModeKHR::IMMEDIATE)),
+            vk::PresentModeKHR::IMMEDIATE
+        );
+    }
+
+    #[test]
+    fn present_mode_falls_back_when_unsupported() {
+        let modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::FIFO_RELAXED];
+        assert_eq!(
+            Swapchain::find_present_mode(&modes, Some(vk::PresentModeKHR::MAILBOX)),
+            vk::PresentModeKHR::FIFO_RELAXED
+        );
+        assert_eq!(
+            Swapchain::find_present_mode(&[], None),
+            vk::PresentModeKHR::IMMEDIATE
+        );
+    }
+
+    #[test]
+    fn present_batch_packs_swapchains_into_one_present() {
+        let main = vk::SwapchainKHR::from_raw(1);
+        let aux = vk::SwapchainKHR::from_raw(2);
+        let signal = vk::Semaphore::from_raw(3);
+        let batch = PresentBatch::new([(main, 0, signal), (aux, 2, vk::Semaphore::null())]);
+
+        let mut results = [vk::Result::SUCCESS; 2];
+        let info = batch.present_info(&mut results);
+        assert_eq!(info.swapchain_count, 2);
+        let swapchains = unsafe { std::slice::from_raw_parts(info.p_swapchains, 2) };
+        assert_eq!(swapchains, [main, aux]);
+        let indices = unsafe { std::slice::from_raw_parts(info.p_image_indices, 2) };
+        assert_eq!(indices, [0, 2]);
+        // The null semaphore of the aux swapchain isn't waited on.
+        assert_eq!(info.wait_semaphore_count, 1);
+        assert_eq!(unsafe { *info.p_wait_semaphores }, signal);
+        assert!(!info.p_results.is_null());
+    }
+
+    #[test]
+    fn present_batch_results_are_per_swapchain() {
+        let results = PresentBatch::map_results(vec![
+            vk::Result::SUCCESS,
+            vk::Result::SUBOPTIMAL_KHR,
+            vk::Result::ERROR_OUT_OF_DATE_KHR,
+        ]);
+        assert_eq!(
+            results,
+            [Ok(false), Ok(true), Err(vk::Result::ERROR_OUT_OF_DATE_KHR)]
+        );
+    }
+
+    #[test]
+    fn compatible_present_mode_is_applied_without_recreate() {
+        let compatible = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX];
+        assert_eq!(
+            Swapchain::present_mode_change(
+                vk::PresentModeKHR::FIFO,
+                vk::PresentModeKHR::MAILBOX,
+                &compatible,
+                true
+            ),
+            PresentModeChange::Applied
+        );
+        // The current mode never requires recreation, even without swapchain maintenance.
+        assert_eq!(
+            Swapchain::present_mode_change(
+                vk::PresentModeKHR::FIFO,
+                vk::PresentModeKHR::FIFO,
+                &[vk::PresentModeKHR::FIFO],
+                false
+            ),
+            PresentModeChange::Applied
+        );
+    }
+
+    #[test]
+    fn incompatible_present_mode_requires_recreate() {
+        let compatible = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX];
+        assert_eq!(
+            Swapchain::present_mode_change(
+                vk::PresentModeKHR::FIFO,
+                vk::PresentModeKHR::IMMEDIATE,
+                &compatible,
+                true
+            ),
+            PresentModeChange::RequiresRecreate
+        );
+        // Without swapchain maintenance the mode can't be changed at present time.
+        assert_eq!(
+            Swapchain::present_mode_change(
+                vk::PresentModeKHR::FIFO,
+                vk::PresentModeKHR::MAILBOX,
+                &compatible,
+                false
+            ),
+            PresentModeChange::RequiresRecreate
+        );
+    }
+
+    #[test]
+    fn array_layers_must_be_supported_by_the_surface() {
+        let caps = vk::SurfaceCapabilitiesKHR {
+            max_image_array_layers: 1,
+            ..Default::default()
+        };
+        let res = with_array_layers(vk::SwapchainCreateInfoKHR::default(), 2, &caps);
+        assert!(matches!(res, Err(OxidationError::Unsupported(_))));
+        let res = with_array_layers(vk::SwapchainCreateInfoKHR::default(), 0, &caps);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn supported_array_layers_are_set_on_the_create_info() {
+        let caps = vk::SurfaceCapabilitiesKHR {
+            max_image_array_layers: 2,
+            ..Default::default()
+        };
+        let create_info =
+            with_array_layers(vk::SwapchainCreateInfoKHR::default(), 2, &caps).unwrap();
+        assert_eq!(create_info.image_array_layers, 2);
+        let info = view_info(vk::Extent2D::default(), vk::Format::UNDEFINED, 2);
+        assert!(matches!(info.ty, TextureType::Array2d));
+        assert_eq!(info.array_layers, 2);
+    }
+
+    #[test]
+    fn pacing_waits_on_the_oldest_presents() {
+        let mut pending = VecDeque::from([2, 0, 1]);
+        let mut waited = Vec::new();
+        let res = pace_presents(&mut pending, 1, |image_index| {
+            waited.push(image_index);
+            Ok(FenceWait::Signalled(vec![0]))
+        });
+        assert_eq!(res.unwrap(), FenceWait::Signalled(vec![2, 0]));
+        assert_eq!(waited, [2, 0]);
+        assert_eq!(pending, [1]);
+    }
+
+    #[test]
+    fn timed_out_present_remains_pending() {
+        let mut pending = VecDeque::from([2, 0]);
+        let res = pace_presents(&mut pending, 0, |_| Ok(FenceWait::TimedOut));
+        assert_eq!(res.unwrap(), FenceWait::TimedOut);
+        assert_eq!(pending, [2, 0]);
+    }
+
+    #[test]
+    fn pacing_without_present_fences_never_waits() {
+        // Without present fences no presents are recorded as pending, leaving the frame
+        // fences and the acquire to pace the CPU.
+        let mut pending = VecDeque::new();
+        let res = pace_presents(&mut pending, 0, |_| unreachable!());
+        assert_eq!(res.unwrap(), FenceWait::Signalled(Vec::new()));
+    }
+
+    #[test]
+    fn iter_images_yields_each_image_with_its_view() {
+        let images: Vec<vk::Image> = (1..=3).map(vk::Image::from_raw).collect();
+        let views: Vec<vk::ImageView> = (11..=13).map(vk::ImageView::from_raw).collect();
+        let extent = vk::Extent2D {
+            width: 1280,
+            height: 720,
+        };
+        let iter = swapchain_images(&images, &views, vk::Format::B8G8R8A8_SRGB, extent);
+        assert_eq!(iter.len(), 3);
+        for (idx, image) in iter.enumerate() {
+            assert_eq!(image.index, idx as u32);
+            assert_eq!(image.image, images[idx]);
+            assert_eq!(image.view, views[idx]);
+            assert_eq!(image.format, vk::Format::B8G8R8A8_SRGB);
+            assert_eq!(image.extent, extent);
+        }
+    }
+
+    fn render_barrier() -> vk::ImageMemoryBarrier2<'static> {
+        vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+    }
+
+    #[test]
+    fn exclusive_present_transfers_ownership_to_the_present_queue() {
+        let barriers = present_barriers(render_barrier(), Some((0, 2)));
+        let acquire = barriers.acquire.expect("an acquire barrier");
+        for barrier in [barriers.release, acquire] {
+            assert_eq!(barrier.src_queue_family_index, 0);
+            assert_eq!(barrier.dst_queue_family_index, 2);
+            assert_eq!(
+                barrier.old_layout,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            );
+            assert_eq!(barrier.new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+        }
+        assert_eq!(
+            barriers.release.src_stage_mask,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+        );
+        assert_eq!(
+            barriers.release.src_access_mask,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+        );
+        assert_eq!(acquire.src_stage_mask, vk::PipelineStageFlags2::NONE);
+        assert_eq!(
+            acquire.dst_stage_mask,
+            vk::PipelineStageFlags2::BOTTOM_OF_PIPE
+        );
+    }
+
+    #[test]
+    fn present_without_a_transfer_only_releases() {
+        let barriers = present_barriers(render_barrier(), None);
+        assert!(barriers.acquire.is_none());
+        assert_eq!(
+            barriers.release.src_queue_family_index,
+            vk::QUEUE_FAMILY_IGNORED
+        );
+        assert_eq!(
+            barriers.release.dst_queue_family_index,
+            vk::QUEUE_FAMILY_IGNORED
+        );
+        assert_eq!(
+            barriers.release.new_layout,
+            vk::ImageLayout::PRESENT_SRC_KHR
+        );
+        assert_eq!(
+            barriers.release.dst_stage_mask,
+            vk::PipelineStageFlags2::BOTTOM_OF_PIPE
+        );
+    }
+}
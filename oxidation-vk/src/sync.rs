@@ -0,0 +1,158 @@
+use crate::error::OxidationError;
+use crate::vk_check;
+use ash::prelude::VkResult;
+use ash::vk;
+
+/// The result of waiting on a set of fences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenceWait {
+    /// The wait completed - holds the indices of the fences which were signalled.
+    Signalled(Vec<usize>),
+    /// The timeout elapsed before the wait completed.
+    TimedOut,
+}
+
+impl FenceWait {
+    pub fn is_signalled(&self) -> bool {
+        matches!(self, FenceWait::Signalled(_))
+    }
+}
+
+/// Wait for all of the fences to be signalled, or the timeout in nanoseconds to elapse. A
+/// timeout is reported as [`FenceWait::TimedOut`] rather than an error, so it can be handled
+/// separately from a lost device.
+///
+/// # Examples
+///
+/// ```ignore
+/// // Wait up to a second for the frames in flight to complete.
+/// match sync::wait_all(&driver.device.device, &frame_fences, 1_000_000_000)? {
+///     FenceWait::Signalled(_) => sync::reset(&driver.device.device, &frame_fences)?,
+///     FenceWait::TimedOut => log::warn!("The GPU is taking more than a second per frame."),
+/// }
+/// ```
+///
+pub fn wait_all(
+    device: &ash::Device,
+    fences: &[vk::Fence],
+    timeout_ns: u64,
+) -> Result<FenceWait, OxidationError> {
+    wait(device, fences, true, timeout_ns)
+}
+
+/// Wait for any of the fences to be signalled, or the timeout in nanoseconds to elapse.
+/// Returns the indices of all of the fences which were signalled once the wait completed.
+pub fn wait_any(
+    device: &ash::Device,
+    fences: &[vk::Fence],
+    timeout_ns: u64,
+) -> Result<FenceWait, OxidationError> {
+    wait(device, fences, false, timeout_ns)
+}
+
+/// Reset the fences to the unsignalled state, ready for reuse in another submission.
+pub fn reset(device: &ash::Device, fences: &[vk::Fence]) -> Result<(), OxidationError> {
+    debug_assert_valid(fences);
+    if fences.is_empty() {
+        return Ok(());
+    }
+    unsafe { vk_check!(device.reset_fences(fences), "vkResetFences") }
+}
+
+fn wait(
+    device: &ash::Device,
+    fences: &[vk::Fence],
+    wait_all: bool,
+    timeout_ns: u64,
+) -> Result<FenceWait, OxidationError> {
+    debug_assert_valid(fences);
+    wait_with(
+        fences.len(),
+        wait_all,
+        || unsafe { device.wait_for_fences(fences, wait_all, timeout_ns) },
+        |idx| unsafe { device.get_fence_status(fences[idx]) },
+    )
+}
+
+/// Interpret the result of the wait on the fences, querying the status of each fence once a
+/// wait for any of them has completed.
+fn wait_with(
+    fence_count: usize,
+    wait_all: bool,
+    wait: impl FnOnce() -> VkResult<()>,
+    mut status: impl FnMut(usize) -> VkResult<bool>,
+) -> Result<FenceWait, OxidationError> {
+    if fence_count == 0 {
+        return Ok(FenceWait::Signalled(Vec::new()));
+    }
+    match wait() {
+        Ok(()) => {}
+        Err(vk::Result::TIMEOUT) => return Ok(FenceWait::TimedOut),
+        Err(err) => return vk_check!(Err(err), "vkWaitForFences"),
+    }
+    if wait_all {
+        return Ok(FenceWait::Signalled((0..fence_count).collect()));
+    }
+
+    let mut signalled = Vec::new();
+    for idx in 0..fence_count {
+        if vk_check!(status(idx), "vkGetFenceStatus")? {
+            signalled.push(idx);
+        }
+    }
+    Ok(FenceWait::Signalled(signalled))
+}
+
+/// Fences are created per submission rather than taken from a pool, so the best that can be
+/// checked is that each is a live handle which appears only once.
+fn debug_assert_valid(fences: &[vk::Fence]) {
+    debug_assert!(
+        fences.iter().all(|fence| *fence != vk::Fence::null()),
+        "Null fences can't be waited on or reset."
+    );
+    debug_assert!(
+        fences
+            .iter()
+            .enumerate()
+            .all(|(idx, fence)| !fences[..idx].contains(fence)),
+        "The fences contain duplicates."
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_all_reports_every_fence_once_complete() {
+        let res = wait_with(3, true, || Ok(()), |_| unreachable!());
+        assert_eq!(res.unwrap(), FenceWait::Signalled(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn wait_any_reports_the_signalled_fences() {
+        let signalled = [false, true, true];
+        let res = wait_with(3, false, || Ok(()), |idx| Ok(signalled[idx]));
+        assert_eq!(res.unwrap(), FenceWait::Signalled(vec![1, 2]));
+    }
+
+    #[test]
+    fn timeout_is_distinct_from_success_and_failure() {
+        let res = wait_with(2, true, || Err(vk::Result::TIMEOUT), |_| Ok(true));
+        let res = res.unwrap();
+        assert_eq!(res, FenceWait::TimedOut);
+        assert!(!res.is_signalled());
+
+        let res = wait_with(2, true, || Err(vk::Result::ERROR_DEVICE_LOST), |_| Ok(true));
+        assert!(matches!(
+            res,
+            Err(OxidationError::VkResult(vk::Result::ERROR_DEVICE_LOST))
+        ));
+    }
+
+    #[test]
+    fn waiting_on_no_fences_completes_immediately() {
+        let res = wait_with(0, true, || unreachable!(), |_| unreachable!());
+        assert_eq!(res.unwrap(), FenceWait::Signalled(Vec::new()));
+    }
+}
@@ -1,7 +1,21 @@
 use crate::Driver;
-use crate::backend::SamplerInfo;
-use crate::sampler_cache::SamplerCache;
+use crate::backend::{AllocationHint, SamplerInfo};
+use crate::barrier;
+use crate::clear;
+use crate::convert::{self, ConversionPath};
+use crate::device::{self, ContextDevice};
+use crate::error::OxidationError;
+use crate::host_memory;
+use crate::mipmap::{self, MipGenPath};
+use crate::staging_pool;
+use crate::swapchain::FrameCapture;
+use crate::vk_check;
+use ash::prelude::VkResult;
 use ash::vk;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::Range;
 use vk_mem::Alloc;
 
 const MAX_MIP_LEVEL_COUNT: usize = 12;
@@ -14,6 +28,72 @@ pub enum TextureType {
     CubeArray2d,
 }
 
+/// A subresource range of a texture, used to request image views of part of the texture.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ViewRange {
+    pub base_mip: u32,
+    pub mip_count: u32,
+    pub base_layer: u32,
+    pub layer_count: u32,
+    /// Overrides the aspects of the view, which by default are all aspects of the format - for
+    /// example, to sample only the depth aspect of a depth/stencil texture. Must be a subset
+    /// of the format's aspects.
+    pub aspect_override: Option<vk::ImageAspectFlags>,
+}
+
+impl ViewRange {
+    /// A range covering a single mip level of all array layers.
+    pub fn mip(level: u32, layer_count: u32) -> Self {
+        Self {
+            base_mip: level,
+            mip_count: 1,
+            base_layer: 0,
+            layer_count,
+            aspect_override: None,
+        }
+    }
+
+    /// Restrict the view to the specified aspects.
+    pub fn aspect(mut self, aspect: vk::ImageAspectFlags) -> Self {
+        self.aspect_override = Some(aspect);
+        self
+    }
+}
+
+/// Image views of sub-ranges of a texture, keyed on the range and view type, which are
+/// created on first request.
+#[derive(Default)]
+struct ViewCache {
+    views: HashMap<(ViewRange, vk::ImageViewType), vk::ImageView>,
+}
+
+impl ViewCache {
+    fn get(&self, range: ViewRange, view_type: vk::ImageViewType) -> Option<vk::ImageView> {
+        self.views.get(&(range, view_type)).copied()
+    }
+
+    /// The cached view, or a view created with `create` if there isn't one. Failed creations
+    /// aren't cached.
+    fn get_or_create(
+        &mut self,
+        range: ViewRange,
+        view_type: vk::ImageViewType,
+        create: impl FnOnce() -> VkResult<vk::ImageView>,
+    ) -> VkResult<vk::ImageView> {
+        if let Some(view) = self.get(range, view_type) {
+            return Ok(view);
+        }
+        let view = create()?;
+        self.views.insert((range, view_type), view);
+        Ok(view)
+    }
+
+    /// Remove all views from the cache, for destroying.
+    fn drain(&mut self) -> impl Iterator<Item = vk::ImageView> + '_ {
+        self.views.drain().map(|(_, view)| view)
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 /// Describes the dimensions and type of the texture.
 pub struct TextureInfo {
@@ -23,6 +103,57 @@ pub struct TextureInfo {
     pub array_layers: u32,
     pub format: vk::Format,
     pub ty: TextureType,
+    /// How the image memory is allocated - if not specified, large attachments are given a
+    /// dedicated allocation and all other textures are pooled - see
+    /// [`AllocationHint::default_for_image`].
+    pub alloc_hint: Option<AllocationHint>,
+    /// The swizzle applied when sampling the image view, i.e. to read a single channel
+    /// texture as luminance. Attachments and storage images require the identity mapping.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use oxidation_vk::texture::TextureInfo;
+    /// # use oxidation_vk::vk;
+    /// // A red channel font atlas, read as white text with coverage in the alpha channel.
+    /// let info = TextureInfo {
+    ///     format: vk::Format::R8_UNORM,
+    ///     components: vk::ComponentMapping {
+    ///         r: vk::ComponentSwizzle::ONE,
+    ///         g: vk::ComponentSwizzle::ONE,
+    ///         b: vk::ComponentSwizzle::ONE,
+    ///         a: vk::ComponentSwizzle::R,
+    ///     },
+    ///     ..Default::default()
+    /// };
+    /// ```
+    ///
+    pub components: vk::ComponentMapping,
+    /// The tiling of the image. Linear images can be written directly by the host with
+    /// [`Texture::write_linear`], avoiding a staging copy for small, frequently updated
+    /// textures - but are limited to a single mip level and layer, and are usually slower to
+    /// sample. Linear images default to host visible memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use oxidation_vk::texture::TextureInfo;
+    /// # use oxidation_vk::vk;
+    /// // A small lightmap updated by the CPU each frame.
+    /// let info = TextureInfo {
+    ///     width: 64,
+    ///     height: 64,
+    ///     format: vk::Format::R8G8B8A8_UNORM,
+    ///     tiling: vk::ImageTiling::LINEAR,
+    ///     initial_layout: vk::ImageLayout::PREINITIALIZED,
+    ///     ..Default::default()
+    /// };
+    /// ```
+    ///
+    pub tiling: vk::ImageTiling,
+    /// The layout the image is created in - either undefined, or pre-initialized for linear
+    /// images whose contents are written by the host before their first use.
+    pub initial_layout: vk::ImageLayout,
 }
 
 impl Default for TextureInfo {
@@ -34,6 +165,10 @@ impl Default for TextureInfo {
             array_layers: 1,
             format: vk::Format::UNDEFINED,
             ty: TextureType::Texture2d,
+            alloc_hint: None,
+            components: vk::ComponentMapping::default(),
+            tiling: vk::ImageTiling::OPTIMAL,
+            initial_layout: vk::ImageLayout::UNDEFINED,
         }
     }
 }
@@ -43,102 +178,899 @@ impl Default for TextureInfo {
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// use ash::vk;
-/// use oxidation_vk::backend::SamplerInfo;
 /// use oxidation_vk::texture::{Texture, TextureInfo};
 /// let info = TextureInfo {
 ///     width: 1920,
 ///     height: 1080,
 ///     ..Default::default()
 /// };
-/// let texture = Texture::new(&info, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, ..);
+/// let texture = Texture::new(
+///     &driver,
+///     &info,
+///     vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+///     None,
+///     &[],
+/// )?;
 /// ```
 ///
 pub struct Texture {
     info: TextureInfo,
+    usage_flags: vk::ImageUsageFlags,
+    sharing_mode: vk::SharingMode,
+    /// The queue families the image is shared between - empty for exclusive sharing.
+    queue_families: Vec<u32>,
     image_layout: vk::ImageLayout,
+    /// The layout of the stencil aspect of depth/stencil formats. This only differs from the
+    /// image layout when the aspects have been transitioned separately.
+    stencil_layout: vk::ImageLayout,
     image: vk::Image,
-    vma_alloc: vk_mem::Allocation,
-    image_views: Vec<vk::ImageView>,
+    /// The memory allocation of the image - `None` when the image is owned elsewhere, i.e. a
+    /// swapchain image, in which case only the views are destroyed with the texture.
+    vma_alloc: Option<vk_mem::Allocation>,
+    /// The parent image view covering all mip levels and array layers.
+    image_view: vk::ImageView,
+    /// Views of sub-ranges of the texture (i.e. single mip levels or cube faces) which are
+    /// created on demand, so textures with deep mip chains don't create views which are never
+    /// used. This includes the single mip level storage views used by compute passes.
+    view_cache: ViewCache,
+    /// The current layout of each face of a cube texture.
+    face_layouts: Vec<vk::ImageLayout>,
+    /// The current layout of each mip level of each array layer, indexed by
+    /// `layer * mip_levels + level`.
+    subresource_layouts: Vec<vk::ImageLayout>,
     sampler: vk::Sampler,
     frames_until_gc: u32,
+    /// The most recent frame the texture was bound in - see [`Texture::is_in_flight`].
+    last_used_frame: Cell<Option<u64>>,
 }
 
 impl Texture {
+    /// Create a new texture. If no sampler info is specified, the driver's default sampler is used.
+    /// If the texture will be accessed by more than one queue family, these can be specified
+    /// with `shared_queues` - see [`device::queue_sharing`].
     pub fn new(
+        driver: &Driver,
         info: &TextureInfo,
         usage_flags: vk::ImageUsageFlags,
-        vma_alloc: vk_mem::Allocator,
-        device: &ash::Device,
-        sampler_cache: &mut SamplerCache,
-        sampler_info: &SamplerInfo,
-    ) -> Self {
-        assert!(sampler_info.mip_levels <= MAX_MIP_LEVEL_COUNT as u32);
-        let (image, allocation) = Self::create_image(info, usage_flags, vma_alloc);
+        sampler_info: Option<&SamplerInfo>,
+        shared_queues: &[u32],
+    ) -> Result<Self, Box<dyn Error>> {
+        if info.mip_levels == 0 || info.mip_levels > MAX_MIP_LEVEL_COUNT as u32 {
+            return Err(Box::from(format!(
+                "The texture has {} mip levels, it must have between 1 and {MAX_MIP_LEVEL_COUNT}.",
+                info.mip_levels
+            )));
+        }
+        validate_components(&info.components, usage_flags)?;
+        validate_tiling(info, usage_flags, || unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_format_properties(driver.device.physical_device, info.format)
+                .linear_tiling_features
+        })?;
+        let device = &driver.device.device;
+        let (image, allocation) = driver.with_allocator(info.alloc_hint, |vma_alloc| {
+            Self::create_image(info, usage_flags, shared_queues, vma_alloc)
+        })??;
 
-        let mut image_views = Vec::new();
-        // The parent image view which depicts the total number of mip levels for the texture.
-        image_views.push(Self::create_image_view(
-            &image,
-            info,
-            0,
-            info.mip_levels,
-            device,
-        ));
+        let sampler = match sampler_info {
+            Some(sampler_info) => driver
+                .sampler_cache
+                .borrow_mut()
+                .get_or_create_sampler(sampler_info, device),
+            None => driver.default_sampler(),
+        };
 
-        // Generate an image view for each mip level.
-        for mip_level in 1..info.mip_levels {
-            image_views.push(Self::create_image_view(&image, info, mip_level, 1, device));
+        let (sharing_mode, queue_families) = device::queue_sharing(shared_queues);
+        let layout = get_image_layout(&info.format, &usage_flags);
+        let mut texture = Self::with_image(
+            device,
+            info,
+            usage_flags,
+            (sharing_mode, queue_families),
+            (image, Some(allocation)),
+            layout,
+            sampler,
+        );
+        texture.subresource_layouts.fill(info.initial_layout);
+        texture.face_layouts.fill(info.initial_layout);
+        // Sampled views may only have a single aspect, so a depth only view is created up front
+        // for sampling combined depth/stencil textures - see `sampled_view()`.
+        if texture.has_combined_depth_stencil()
+            && usage_flags.contains(vk::ImageUsageFlags::SAMPLED)
+        {
+            texture.aspect_view(vk::ImageAspectFlags::DEPTH, device)?;
         }
+        Ok(texture)
+    }
+
+    /// Wrap an image which is owned elsewhere, i.e. a swapchain image, as a texture so it can
+    /// be used wherever an engine texture is expected. The texture creates and owns its views,
+    /// but destroying it leaves the image itself untouched. The image must outlive the texture.
+    ///
+    /// `layout` is the current layout of the image - `vk::ImageLayout::UNDEFINED` for newly
+    /// acquired swapchain images.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let info = TextureInfo {
+    ///     width: swapchain.extents.width,
+    ///     height: swapchain.extents.height,
+    ///     format: swapchain.view_format,
+    ///     ..Default::default()
+    /// };
+    /// let target = Texture::from_raw_image(&driver, swapchain.images[0], &info, swapchain.usage, vk::ImageLayout::UNDEFINED);
+    /// ```
+    ///
+    pub fn from_raw_image(
+        driver: &Driver,
+        image: vk::Image,
+        info: &TextureInfo,
+        usage_flags: vk::ImageUsageFlags,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        let mut texture = Self::with_image(
+            &driver.device.device,
+            info,
+            usage_flags,
+            (vk::SharingMode::EXCLUSIVE, Vec::new()),
+            (image, None),
+            layout,
+            driver.default_sampler(),
+        );
+        texture.subresource_layouts.fill(layout);
+        texture.face_layouts.fill(layout);
+        texture
+    }
+
+    /// Whether the image is owned by the texture, rather than wrapped with `from_raw_image()`.
+    pub fn owns_image(&self) -> bool {
+        self.vma_alloc.is_some()
+    }
+
+    fn with_image(
+        device: &ash::Device,
+        info: &TextureInfo,
+        usage_flags: vk::ImageUsageFlags,
+        (sharing_mode, queue_families): (vk::SharingMode, Vec<u32>),
+        (image, vma_alloc): (vk::Image, Option<vk_mem::Allocation>),
+        layout: vk::ImageLayout,
+        sampler: vk::Sampler,
+    ) -> Self {
+        // The parent image view which depicts the total number of mip levels for the texture.
+        // Views of sub-ranges are created on demand.
+        let image_view = Self::create_image_view(&image, info, 0, info.mip_levels, device);
 
-        let sampler = sampler_cache.get_or_create_sampler(sampler_info, device);
+        let face_count = match info.ty {
+            TextureType::Cube2d | TextureType::CubeArray2d => {
+                compute_array_layers(&info.ty, info.array_layers)
+            }
+            _ => 0,
+        };
 
         Self {
             info: *info,
-            image_layout: get_image_layout(&info.format, &usage_flags),
-            image: vk::Image::default(),
-            vma_alloc: allocation,
-            image_views,
+            usage_flags,
+            sharing_mode,
+            queue_families,
+            image_layout: layout,
+            stencil_layout: layout,
+            image,
+            vma_alloc,
+            image_view,
+            view_cache: ViewCache::default(),
+            face_layouts: vec![vk::ImageLayout::UNDEFINED; face_count as usize],
+            subresource_layouts: vec![
+                vk::ImageLayout::UNDEFINED;
+                (compute_array_layers(&info.ty, info.array_layers) * info.mip_levels)
+                    as usize
+            ],
             frames_until_gc: 0,
+            last_used_frame: Cell::new(None),
             sampler,
         }
     }
 
+    /// Create a texture and upload its contents in a single step, leaving the texture ready
+    /// for sampling in the shader read-only layout. The upload is recorded into the current
+    /// graphics command buffer.
+    ///
+    /// The data is expected to be tightly packed, with each array layer containing all of its
+    /// mip levels in turn. If `generate_mipmaps` is set, the data only contains the first mip
+    /// level of a single layer, with the remaining levels generated on the device.
+    ///
+    /// Returns an error if the size of the data doesn't match the format and dimensions of the
+    /// texture, or if the format is block compressed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let info = TextureInfo {
+    ///     width: 32,
+    ///     height: 32,
+    ///     format: vk::Format::R8G8B8A8_SRGB,
+    ///     ..Default::default()
+    /// };
+    /// let icon = Texture::with_data(&mut driver, &info, vk::ImageUsageFlags::SAMPLED, None, &pixels, false)?;
+    /// ```
+    ///
+    pub fn with_data(
+        driver: &mut Driver,
+        info: &TextureInfo,
+        usage_flags: vk::ImageUsageFlags,
+        sampler_info: Option<&SamplerInfo>,
+        data: &[u8],
+        generate_mipmaps: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let texel_size = texel_size(info.format).ok_or_else(|| {
+            format!(
+                "Format {:?} is not supported for initial texture data.",
+                info.format
+            )
+        })?;
+        let level_size = |level: u32| {
+            (info.width >> level).max(1) as vk::DeviceSize
+                * (info.height >> level).max(1) as vk::DeviceSize
+                * texel_size as vk::DeviceSize
+        };
+
+        let layer_count = compute_array_layers(&info.ty, info.array_layers);
+        if generate_mipmaps && layer_count > 1 {
+            return Err(Box::from(
+                "Mip generation from initial data is only supported for single layer textures.",
+            ));
+        }
+        let mut offsets = Vec::new();
+        let mut expected_size = 0;
+        match generate_mipmaps {
+            true => expected_size = level_size(0),
+            false => {
+                for _ in 0..layer_count {
+                    for level in 0..info.mip_levels {
+                        offsets.push(expected_size);
+                        expected_size += level_size(level);
+                    }
+                }
+            }
+        }
+        if data.len() as vk::DeviceSize != expected_size {
+            return Err(Box::from(format!(
+                "Initial texture data is {} bytes, but {expected_size} bytes are required.",
+                data.len()
+            )));
+        }
+
+        let mut texture = Self::new(driver, info, usage_flags, sampler_info, &[])?;
+        if let Err(err) = texture.map(
+            driver,
+            data.as_ptr(),
+            expected_size,
+            &offsets,
+            generate_mipmaps,
+        ) {
+            texture.destroy(driver);
+            return Err(err);
+        }
+        Ok(texture)
+    }
+
+    /// The dimensions and type of this texture.
+    pub fn info(&self) -> &TextureInfo {
+        &self.info
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.info.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.info.height
+    }
+
+    /// The extent of the first mip level.
+    #[inline]
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.info.width,
+            height: self.info.height,
+        }
+    }
+
+    #[inline]
+    pub fn format(&self) -> vk::Format {
+        self.info.format
+    }
+
+    #[inline]
+    pub fn mip_levels(&self) -> u32 {
+        self.info.mip_levels
+    }
+
+    /// The usage flags of the image - the flags the texture was created with, along with the
+    /// transfer usage which is implicitly added to all but transient attachments.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        image_usage(self.usage_flags, self.owns_image())
+    }
+
+    /// Whether the texture can be the source of copies and blits.
+    #[inline]
+    pub fn can_transfer_src(&self) -> bool {
+        self.usage().contains(vk::ImageUsageFlags::TRANSFER_SRC)
+    }
+
+    /// Whether the texture can be the destination of copies, blits and transfer clears.
+    #[inline]
+    pub fn can_transfer_dst(&self) -> bool {
+        self.usage().contains(vk::ImageUsageFlags::TRANSFER_DST)
+    }
+
+    /// Whether the texture can be sampled in a shader.
+    #[inline]
+    pub fn can_sample(&self) -> bool {
+        self.usage().contains(vk::ImageUsageFlags::SAMPLED)
+    }
+
+    /// Whether the texture can be read and written as a storage image in a shader.
+    #[inline]
+    pub fn can_storage(&self) -> bool {
+        self.usage().contains(vk::ImageUsageFlags::STORAGE)
+    }
+
+    /// Returns an error naming the operation if the texture lacks any of the usage flags, rather
+    /// than leaving the invalid usage to the validation layers.
+    fn require_usage(
+        &self,
+        required: vk::ImageUsageFlags,
+        operation: &str,
+    ) -> Result<(), OxidationError> {
+        check_usage(self.usage(), required, operation)
+    }
+
+    /// Whether the texture can be accessed concurrently by multiple queue families without
+    /// requiring queue family ownership transfers.
+    pub fn sharing_mode(&self) -> vk::SharingMode {
+        self.sharing_mode
+    }
+
+    /// The image view covering all mip levels of the texture.
+    pub fn image_view(&self) -> vk::ImageView {
+        self.image_view
+    }
+
+    /// The image view to bind when sampling the texture. This is the view covering all mip
+    /// levels, other than for combined depth/stencil formats where only the depth aspect can
+    /// be sampled - i.e. for shadow maps sampled with [`SamplerInfo::shadow_comparison`].
+    pub fn sampled_view(&self) -> vk::ImageView {
+        if !self.has_combined_depth_stencil() {
+            return self.image_view;
+        }
+        self.view_cache
+            .get(self.depth_view_range(), view_type(&self.info.ty))
+            .unwrap_or(self.image_view)
+    }
+
+    /// The range of the depth only view of all mip levels and array layers.
+    fn depth_view_range(&self) -> ViewRange {
+        ViewRange {
+            base_mip: 0,
+            mip_count: self.info.mip_levels,
+            base_layer: 0,
+            layer_count: self.array_layer_count(),
+            aspect_override: None,
+        }
+        .aspect(vk::ImageAspectFlags::DEPTH)
+    }
+
+    fn has_combined_depth_stencil(&self) -> bool {
+        get_aspect_mask(self.info.format)
+            == vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+    }
+
+    /// An image view of the specified range of the texture, which is created on first request
+    /// and cached thereafter. A range covering the whole texture returns the parent view.
+    ///
+    /// The view type matches the texture when all array layers are covered, otherwise a 2D view
+    /// is created for a single layer or a 2D array view for multiple layers.
+    pub fn view(
+        &mut self,
+        range: ViewRange,
+        device: &ash::Device,
+    ) -> Result<vk::ImageView, OxidationError> {
+        let layer_count = self.array_layer_count();
+        let format_aspect = get_aspect_mask(self.info.format);
+        debug_assert!(
+            range
+                .aspect_override
+                .is_none_or(|aspect| format_aspect.contains(aspect)),
+            "The view aspect must be a subset of the format aspects."
+        );
+        if range.base_mip == 0
+            && range.mip_count == self.info.mip_levels
+            && range.base_layer == 0
+            && range.layer_count == layer_count
+            && range
+                .aspect_override
+                .is_none_or(|aspect| aspect == format_aspect)
+        {
+            return Ok(self.image_view);
+        }
+
+        let view_type = if range.layer_count == layer_count {
+            view_type(&self.info.ty)
+        } else if range.layer_count == 1 {
+            vk::ImageViewType::TYPE_2D
+        } else {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        };
+        self.cached_view(range, view_type, device)
+    }
+
+    fn cached_view(
+        &mut self,
+        range: ViewRange,
+        view_type: vk::ImageViewType,
+        device: &ash::Device,
+    ) -> Result<vk::ImageView, OxidationError> {
+        let (image, format) = (self.image, self.info.format);
+        vk_check!(
+            self.view_cache.get_or_create(range, view_type, || {
+                create_range_view(image, format, view_type, range, device)
+            }),
+            "vkCreateImageView"
+        )
+    }
+
+    /// The image view of a single mip level, covering all array layers.
+    pub fn mip_view(
+        &mut self,
+        level: u32,
+        device: &ash::Device,
+    ) -> Result<vk::ImageView, OxidationError> {
+        self.view(ViewRange::mip(level, self.array_layer_count()), device)
+    }
+
+    /// A view of all mip levels and array layers of the texture, restricted to the specified
+    /// aspects - e.g. a depth only view of a depth/stencil texture for sampling a shadow map.
+    ///
+    /// Returns an error if the aspects aren't a subset of the aspects of the texture format.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // A shadow map with a D32_SFLOAT_S8_UINT format.
+    /// let depth_view = shadow_map.aspect_view(vk::ImageAspectFlags::DEPTH, &driver.device.device)?;
+    /// ```
+    ///
+    pub fn aspect_view(
+        &mut self,
+        aspect: vk::ImageAspectFlags,
+        device: &ash::Device,
+    ) -> Result<vk::ImageView, Box<dyn Error>> {
+        let format_aspect = get_aspect_mask(self.info.format);
+        if aspect.is_empty() || !format_aspect.contains(aspect) {
+            return Err(Box::from(format!(
+                "View aspect {aspect:?} is not a subset of the aspects {format_aspect:?} of format {:?}.",
+                self.info.format
+            )));
+        }
+        let range = ViewRange {
+            base_mip: 0,
+            mip_count: self.info.mip_levels,
+            base_layer: 0,
+            layer_count: self.array_layer_count(),
+            aspect_override: None,
+        }
+        .aspect(aspect);
+        Ok(self.view(range, device)?)
+    }
+
+    /// The 2D image view of a single face of a cube texture, i.e. for use as a render attachment.
+    /// For cube arrays, the face index is `array_layer * 6 + face`.
+    pub fn face_view(
+        &mut self,
+        face: u32,
+        device: &ash::Device,
+    ) -> Result<vk::ImageView, OxidationError> {
+        if face as usize >= self.face_layouts.len() {
+            return Err(OxidationError::Other(format!(
+                "Face index {face} is out of range (face count: {}).",
+                self.face_layouts.len()
+            )));
+        }
+        let range = ViewRange {
+            base_mip: 0,
+            mip_count: self.info.mip_levels,
+            base_layer: face,
+            layer_count: 1,
+            aspect_override: None,
+        };
+        self.view(range, device)
+    }
+
+    /// Record that the texture is used by the frame, i.e. when bound for drawing.
+    pub fn mark_used(&self, frame: u64) {
+        let last = self.last_used_frame.get().unwrap_or(0);
+        self.last_used_frame.set(Some(last.max(frame)));
+    }
+
+    /// The most recent frame the texture was used in - `None` if it has never been used.
+    pub fn last_used_frame(&self) -> Option<u64> {
+        self.last_used_frame.get()
+    }
+
+    /// Whether a frame which used the texture may still be executing on the device, so the
+    /// texture can't yet be destroyed. `completed_frame` is the most recent frame whose work
+    /// is known to have completed, i.e. once the fence signalled by its submission has been
+    /// waited on - `None` if no frame has completed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// texture.mark_used(10);
+    /// assert!(texture.is_in_flight(Some(9)));
+    /// assert!(!texture.is_in_flight(Some(10)));
+    /// ```
+    ///
+    pub fn is_in_flight(&self, completed_frame: Option<u64>) -> bool {
+        is_frame_in_flight(self.last_used_frame.get(), completed_frame)
+    }
+
+    pub fn sampler(&self) -> vk::Sampler {
+        self.sampler
+    }
+
+    /// Replace the sampler used with the texture. The sampler must outlive the texture, i.e.
+    /// a sampler owned by the driver's sampler cache.
+    pub fn set_sampler(&mut self, sampler: vk::Sampler) {
+        self.sampler = sampler;
+    }
+
+    /// Copy the first mip level and layer of the texture into host memory as tightly packed
+    /// RGBA8 pixels, i.e. to read back an offscreen render target. The texture must have been
+    /// created with transfer source usage and be in the transfer source layout. This waits on
+    /// the device, so shouldn't be used in a performance sensitive path.
+    pub fn capture(&self, driver: &Driver) -> Result<FrameCapture, OxidationError> {
+        self.require_usage(vk::ImageUsageFlags::TRANSFER_SRC, "Capturing a texture")?;
+        if self.image_layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+            return Err(OxidationError::Other(format!(
+                "Capturing a texture requires the transfer source layout (current layout: {:?}).",
+                self.image_layout
+            )));
+        }
+        let swizzle = match self.info.format {
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => true,
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => false,
+            format => {
+                return Err(OxidationError::Unsupported(format!(
+                    "Capturing textures of format {format:?} is not supported."
+                )));
+            }
+        };
+
+        let (width, height) = (self.info.width, self.info.height);
+        let size = (width * height * 4) as vk::DeviceSize;
+        let mut stage = staging_pool::create_download_stage(&driver.vma_allocator, size)?;
+        // Buffer row length and image height of zero specify the buffer is tightly packed.
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            });
+        let res = driver.submit_immediate(|cmds| unsafe {
+            driver.device.device.cmd_copy_image_to_buffer(
+                cmds,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                stage.buffer,
+                &[region],
+            );
+        });
+
+        let mut pixels = vec![0u8; size as usize];
+        let copy_res = res.and_then(|_| unsafe {
+            host_memory::invalidate_mapped(driver, &stage.memory, 0, size)?;
+            let mapped = driver.vma_allocator.map_memory(&mut stage.memory)?;
+            mapped.copy_to(pixels.as_mut_ptr(), size as usize);
+            driver.vma_allocator.unmap_memory(&mut stage.memory);
+            Ok(())
+        });
+        unsafe {
+            driver
+                .vma_allocator
+                .destroy_buffer(stage.buffer, &mut stage.memory)
+        };
+        copy_res?;
+
+        if swizzle {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(FrameCapture {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Destroy the image views and the image along with its memory allocation. The sampler
+    /// is owned by the sampler cache so is not destroyed here, and images wrapped with
+    /// `from_raw_image()` are left to their owner.
+    pub fn destroy(&mut self, driver: &Driver) {
+        let views = std::iter::once(std::mem::take(&mut self.image_view))
+            .chain(self.view_cache.drain())
+            .filter(|view| *view != vk::ImageView::null());
+        for view in views {
+            unsafe { driver.device.device.destroy_image_view(view, None) };
+        }
+        if let Some(vma_alloc) = self.vma_alloc.as_mut() {
+            unsafe { driver.vma_allocator.destroy_image(self.image, vma_alloc) };
+        }
+    }
+
     /// Create a Vulkan image object and the corresponding memory allocation.
     pub fn create_image(
         info: &TextureInfo,
         usage_flags: vk::ImageUsageFlags,
-        vma_alloc: vk_mem::Allocator,
-    ) -> (vk::Image, vk_mem::Allocation) {
+        shared_queues: &[u32],
+        vma_alloc: &dyn Alloc,
+    ) -> Result<(vk::Image, vk_mem::Allocation), Box<dyn Error>> {
+        let (sharing_mode, queue_family_indices) = device::queue_sharing(shared_queues);
+        let create_info =
+            Self::image_create_info(info, usage_flags, sharing_mode, &queue_family_indices);
+
+        // The size of the base level is enough to decide on a dedicated allocation.
+        let approx_size = info.width as vk::DeviceSize
+            * info.height as vk::DeviceSize
+            * compute_array_layers(&info.ty, info.array_layers) as vk::DeviceSize
+            * texel_size(info.format).unwrap_or(4) as vk::DeviceSize;
+        let alloc_hint = info.alloc_hint.unwrap_or_else(|| {
+            AllocationHint::default_for_image(info.tiling, usage_flags, approx_size)
+        });
+        // Lazily allocated memory is usually only available on tile-based GPUs.
+        let alloc_hint = match alloc_hint {
+            AllocationHint::LazilyAllocated
+                if !has_lazily_allocated_memory(vma_alloc.allocator()) =>
+            {
+                AllocationHint::Dedicated
+            }
+            hint => hint,
+        };
+        let alloc_info = alloc_hint.to_vma();
+
+        let res = unsafe {
+            vk_check!(
+                vma_alloc.create_image(&create_info, &alloc_info),
+                "vmaCreateImage"
+            )?
+        };
+        Ok(res)
+    }
+
+    /// The create info of exclusively owned images with the info and usage, for finding the
+    /// memory type they're allocated from.
+    pub(crate) fn pool_image_create_info(
+        info: &TextureInfo,
+        usage_flags: vk::ImageUsageFlags,
+    ) -> vk::ImageCreateInfo<'static> {
+        Self::image_create_info(info, usage_flags, vk::SharingMode::EXCLUSIVE, &[])
+    }
+
+    fn image_create_info<'a>(
+        info: &TextureInfo,
+        usage_flags: vk::ImageUsageFlags,
+        sharing_mode: vk::SharingMode,
+        queue_family_indices: &'a [u32],
+    ) -> vk::ImageCreateInfo<'a> {
         let extents = vk::Extent3D {
             width: info.width,
             height: info.height,
             depth: 1,
         };
 
-        let create_info = vk::ImageCreateInfo {
+        let flags = match info.ty {
+            TextureType::Cube2d | TextureType::CubeArray2d => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            _ => vk::ImageCreateFlags::empty(),
+        };
+
+        vk::ImageCreateInfo {
+            flags,
             image_type: vk::ImageType::TYPE_2D, // TODO: support 3d images
             format: info.format,
             extent: extents,
             mip_levels: info.mip_levels,
             array_layers: compute_array_layers(&info.ty, info.array_layers),
             samples: vk::SampleCountFlags::TYPE_1,
-            tiling: vk::ImageTiling::OPTIMAL,
-            usage: vk::ImageUsageFlags::TRANSFER_DST | usage_flags,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            initial_layout: vk::ImageLayout::UNDEFINED,
+            tiling: info.tiling,
+            usage: implicit_usage(usage_flags) | usage_flags,
+            sharing_mode,
+            initial_layout: info.initial_layout,
             ..Default::default()
-        };
+        }
+        .queue_family_indices(queue_family_indices)
+    }
 
-        let alloc_info = vk_mem::AllocationCreateInfo {
-            usage: vk_mem::MemoryUsage::Auto,
-            flags: vk_mem::AllocationCreateFlags::DEDICATED_MEMORY,
-            priority: 1.0,
-            ..Default::default()
+    pub(crate) fn vma_allocation(&self) -> Option<&vk_mem::Allocation> {
+        self.vma_alloc.as_ref()
+    }
+
+    /// Whether the texture can be relocated by defragmentation - the contents of transient
+    /// attachments can't be copied, wrapped images aren't allocated by the driver, and linear
+    /// images are written in place by the host.
+    pub(crate) fn is_relocatable(&self) -> bool {
+        self.owns_image()
+            && self.info.tiling == vk::ImageTiling::OPTIMAL
+            && !self
+                .usage_flags
+                .contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT)
+    }
+
+    /// Create an image, not yet bound to memory, with the same parameters as the image of
+    /// this texture.
+    pub(crate) fn create_unbound_image(&self, device: &ash::Device) -> VkResult<vk::Image> {
+        let create_info = Self::image_create_info(
+            &self.info,
+            self.usage_flags,
+            self.sharing_mode,
+            &self.queue_families,
+        );
+        unsafe { device.create_image(&create_info, None) }
+    }
+
+    /// Record the copy of all subresources to the new image, which must be bound to memory,
+    /// leaving each subresource of the new image in its tracked layout. The texture isn't
+    /// modified - once the copy has completed, [`Self::replace_image`] swaps to the new image.
+    pub(crate) fn record_relocation(
+        &self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        new_image: vk::Image,
+    ) {
+        let aspect = get_aspect_mask(self.info.format);
+        let runs = self.layout_runs(aspect);
+        let all = vk::ImageSubresourceRange::default()
+            .aspect_mask(aspect)
+            .level_count(vk::REMAINING_MIP_LEVELS)
+            .layer_count(vk::REMAINING_ARRAY_LAYERS);
+        let to_src = runs.iter().map(|(layout, range)| {
+            relocation_barrier(
+                self.image,
+                *range,
+                *layout,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            )
+        });
+        let to_dst = relocation_barrier(
+            new_image,
+            all,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        let barriers: Vec<_> = to_src.chain(std::iter::once(to_dst)).collect();
+        barrier::cmd_image_barriers(device, cmds, &barriers);
+
+        let layer_count = self.array_layer_count();
+        let regions: Vec<vk::ImageCopy> = (0..self.info.mip_levels)
+            .map(|level| {
+                let layers = vk::ImageSubresourceLayers::default()
+                    .aspect_mask(aspect)
+                    .mip_level(level)
+                    .layer_count(layer_count);
+                vk::ImageCopy::default()
+                    .src_subresource(layers)
+                    .dst_subresource(layers)
+                    .extent(vk::Extent3D {
+                        width: (self.info.width >> level).max(1),
+                        height: (self.info.height >> level).max(1),
+                        depth: 1,
+                    })
+            })
+            .collect();
+        unsafe {
+            device.device.cmd_copy_image(
+                cmds,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                new_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            )
         };
 
-        unsafe { vma_alloc.create_image(&create_info, &alloc_info).unwrap() }
+        // Subresources which were undefined have no contents, so are left as copied.
+        let restore: Vec<_> = runs
+            .iter()
+            .filter(|(layout, _)| *layout != vk::ImageLayout::UNDEFINED)
+            .map(|(layout, range)| {
+                relocation_barrier(
+                    new_image,
+                    *range,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    *layout,
+                )
+            })
+            .collect();
+        barrier::cmd_image_barriers(device, cmds, &restore);
+    }
+
+    /// Replace the image of the texture with the new image, once the copy recorded by
+    /// [`Self::record_relocation`] has completed. The default and sampled views are recreated,
+    /// while other views created on demand are recreated on their next use. Returns the old
+    /// image and its views, to be destroyed by the caller - on error, the texture is left
+    /// unchanged.
+    pub(crate) fn replace_image(
+        &mut self,
+        device: &ash::Device,
+        new_image: vk::Image,
+    ) -> VkResult<(vk::Image, Vec<vk::ImageView>)> {
+        let new_view =
+            Self::create_image_view(&new_image, &self.info, 0, self.info.mip_levels, device);
+        let ty = view_type(&self.info.ty);
+        let mut depth_view = None;
+        if self.has_combined_depth_stencil()
+            && self.usage_flags.contains(vk::ImageUsageFlags::SAMPLED)
+        {
+            let range = self.depth_view_range();
+            match create_range_view(new_image, self.info.format, ty, range, device) {
+                Ok(view) => depth_view = Some((range, view)),
+                Err(err) => {
+                    unsafe { device.destroy_image_view(new_view, None) };
+                    return Err(err);
+                }
+            }
+        }
+
+        let old_image = std::mem::replace(&mut self.image, new_image);
+        let old_views: Vec<vk::ImageView> = std::iter::once(self.image_view)
+            .chain(self.view_cache.drain())
+            .filter(|view| *view != vk::ImageView::null())
+            .collect();
+        self.image_view = new_view;
+        if let Some((range, view)) = depth_view {
+            self.view_cache.get_or_create(range, ty, || Ok(view))?;
+        }
+        Ok((old_image, old_views))
+    }
+
+    /// The runs of consecutive layers of each mip level which share a tracked layout.
+    fn layout_runs(
+        &self,
+        aspect: vk::ImageAspectFlags,
+    ) -> Vec<(vk::ImageLayout, vk::ImageSubresourceRange)> {
+        let layer_count = self.array_layer_count();
+        let mut runs = Vec::new();
+        for level in 0..self.info.mip_levels {
+            let mut layer = 0;
+            while layer < layer_count {
+                let layout = self.subresource_layouts[self.subresource_idx(level, layer)];
+                let first_layer = layer;
+                while layer < layer_count
+                    && self.subresource_layouts[self.subresource_idx(level, layer)] == layout
+                {
+                    layer += 1;
+                }
+                let range = vk::ImageSubresourceRange::default()
+                    .aspect_mask(aspect)
+                    .base_mip_level(level)
+                    .level_count(1)
+                    .base_array_layer(first_layer)
+                    .layer_count(layer - first_layer);
+                runs.push((layout, range));
+            }
+        }
+        runs
     }
 
     /// Create a Vulkan image view object for a specified image.
@@ -149,36 +1081,110 @@ impl Texture {
         mip_count: u32,
         device: &ash::Device,
     ) -> vk::ImageView {
-        let components = vk::ComponentMapping {
-            r: vk::ComponentSwizzle::IDENTITY,
-            g: vk::ComponentSwizzle::IDENTITY,
-            b: vk::ComponentSwizzle::IDENTITY,
-            a: vk::ComponentSwizzle::IDENTITY,
-        };
         let sub_resource = vk::ImageSubresourceRange {
             aspect_mask: get_aspect_mask(info.format),
             base_mip_level: mip_level,
             base_array_layer: 0,
             level_count: mip_count,
-            layer_count: 1,
+            layer_count: compute_array_layers(&info.ty, info.array_layers),
         };
 
-        let mut create_info = vk::ImageViewCreateInfo {
+        let create_info = vk::ImageViewCreateInfo {
             image: *image,
-            view_type: vk::ImageViewType::TYPE_2D,
+            view_type: view_type(&info.ty),
             format: info.format,
-            components,
+            components: info.components,
             subresource_range: sub_resource,
             ..Default::default()
         };
+        unsafe { device.create_image_view(&create_info, None).unwrap() }
+    }
+
+    /// Write tightly packed texel data directly into the memory of a linear texture, without a
+    /// staging copy. The texture must be in the pre-initialized or general layout, and not in
+    /// use by the device - a texture updated each frame should be kept in the general layout,
+    /// with the writes synchronised with the frames in flight.
+    ///
+    /// Returns an error if the texture isn't linear and host visible, or the size of the data
+    /// doesn't match its dimensions.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut lightmap = Texture::new(&driver, &info, vk::ImageUsageFlags::SAMPLED, None, &[])?;
+    /// lightmap.write_linear(&driver, &texels)?;
+    /// // Transition to the shader read-only layout, retaining the pre-initialized contents.
+    /// lightmap.transition(&driver.device, cmds, vk::ImageLayout::PREINITIALIZED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags2::HOST, vk::PipelineStageFlags2::FRAGMENT_SHADER, 1);
+    /// ```
+    ///
+    pub fn write_linear(&self, driver: &Driver, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.info.tiling != vk::ImageTiling::LINEAR {
+            return Err(Box::from(
+                "Only linear textures can be written by the host.",
+            ));
+        }
+        let layout = self.subresource_layouts[0];
+        if !matches!(
+            layout,
+            vk::ImageLayout::PREINITIALIZED | vk::ImageLayout::GENERAL
+        ) {
+            return Err(Box::from(format!(
+                "Host writes require the pre-initialized or general layout (current layout: {layout:?})."
+            )));
+        }
+        let texel_size = texel_size(self.info.format).ok_or_else(|| {
+            format!(
+                "Format {:?} is not supported for host writes.",
+                self.info.format
+            )
+        })? as usize;
+        let row_size = self.info.width as usize * texel_size;
+        if data.len() != row_size * self.info.height as usize {
+            return Err(Box::from(format!(
+                "Expected {} bytes of texel data, but {} were provided.",
+                row_size * self.info.height as usize,
+                data.len()
+            )));
+        }
+        let allocation = self
+            .vma_alloc
+            .as_ref()
+            .ok_or("Wrapped images can't be written by the host.")?;
+        let mapped = driver
+            .vma_allocator
+            .get_allocation_info(allocation)
+            .mapped_data as *mut u8;
+        if mapped.is_null() {
+            return Err(Box::from(
+                "The texture memory isn't mapped - linear textures must use mapped allocations.",
+            ));
+        }
 
-        create_info.view_type = match info.ty {
-            TextureType::Cube2d => vk::ImageViewType::CUBE,
-            TextureType::CubeArray2d => vk::ImageViewType::CUBE_ARRAY,
-            TextureType::Array2d => vk::ImageViewType::TYPE_2D_ARRAY,
-            TextureType::Texture2d => vk::ImageViewType::TYPE_2D,
+        // Rows of linear images may be padded, so are copied individually at the row pitch.
+        let subresource = vk::ImageSubresource::default().aspect_mask(vk::ImageAspectFlags::COLOR);
+        let subresource_layout = unsafe {
+            driver
+                .device
+                .device
+                .get_image_subresource_layout(self.image, subresource)
         };
-        unsafe { device.create_image_view(&create_info, None).unwrap() }
+        for (row, texels) in data.chunks_exact(row_size).enumerate() {
+            let offset =
+                subresource_layout.offset + row as vk::DeviceSize * subresource_layout.row_pitch;
+            // SAFETY: the row lies within the subresource, which isn't in use by the device.
+            unsafe {
+                mapped
+                    .add(offset as usize)
+                    .copy_from_nonoverlapping(texels.as_ptr(), row_size)
+            };
+        }
+        host_memory::flush_mapped(
+            driver,
+            allocation,
+            subresource_layout.offset,
+            subresource_layout.size,
+        )?;
+        Ok(())
     }
 
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
@@ -194,32 +1200,571 @@ impl Texture {
         data_size: vk::DeviceSize,
         offsets: &[vk::DeviceSize],
         generate_mipmaps: bool,
-    ) {
-        let stage = driver.staging_pool.get(data_size, &driver.vma_allocator);
-
-        let mapped = unsafe { driver.vma_allocator.map_memory(&mut stage.memory).unwrap() };
-        unsafe { mapped.copy_from(data, data_size as usize) };
-        unsafe { driver.vma_allocator.unmap_memory(&mut stage.memory) };
-        driver
-            .vma_allocator
-            .flush_allocation(&stage.memory, 0, data_size)
-            .expect("Failed to flush memory");
+    ) -> Result<(), Box<dyn Error>> {
+        self.require_usage(vk::ImageUsageFlags::TRANSFER_DST, "Uploading texture data")?;
+        if generate_mipmaps {
+            self.mip_gen_path(driver)?;
+        }
+        let stage = driver.staging_pool.get(data_size, &driver.vma_allocator)?;
 
-        let cmds = driver.graphics_commands.get(&driver.device.device);
-        let mut image_copy_info: Vec<vk::BufferImageCopy> = Vec::new();
+        unsafe {
+            let mapped = vk_check!(
+                driver.vma_allocator.map_memory(&mut stage.memory),
+                "vmaMapMemory"
+            )?;
+            mapped.copy_from(data, data_size as usize);
+            driver.vma_allocator.unmap_memory(&mut stage.memory);
+        }
+        host_memory::flush_range(&driver.vma_allocator, &stage.memory, 0, data_size)?;
 
-        if !generate_mipmaps {
-            let array_count = compute_array_layers(&self.info.ty, self.info.array_layers);
-            image_copy_info.resize_with(
-                (array_count * self.info.mip_levels) as usize,
-                Default::default,
-            );
-            for face in 0..array_count {
-                for level in 0..self.info.mip_levels {
-                    let idx = (face * self.info.mip_levels + level) as usize;
+        let cmds = driver.graphics_commands.get(&driver.device)?;
+        let image_copy_info = self.copy_regions(offsets, generate_mipmaps)?;
 
-                    let image_subresource = vk::ImageSubresourceLayers::default()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        // Transition all mips to for dst transfer - this is required as the last step in copying is
+        // then to transition all mips ready for shader read. Not having the levels in the correct
+        // layout leads to validation warnings.
+        let transition_count = match generate_mipmaps {
+            true => 1,
+            false => self.info.mip_levels as usize,
+        };
+
+        self.transition(
+            &driver.device,
+            cmds,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags2::NONE,
+            vk::PipelineStageFlags2::COPY,
+            transition_count,
+        );
+
+        // Copy the image from the staging buffer to the device.
+        unsafe {
+            driver.device.device.cmd_copy_buffer_to_image(
+                cmds,
+                stage.buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &image_copy_info,
+            )
+        };
+
+        // If required, now generate the mip-maps for the image - this also transitions
+        // all levels ready for reads by the fragment shader.
+        if generate_mipmaps {
+            return self.generate_mipmaps(driver, cmds);
+        }
+
+        // Transition the image(s) ready for reads by the fragment shader.
+        self.transition(
+            &driver.device,
+            cmds,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::COPY,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            transition_count,
+        );
+        Ok(())
+    }
+
+    /// Generate the mip chain from the first mip level, which must be in the transfer dst
+    /// layout. Each level is linearly blitted from the previous level where the format supports
+    /// it, otherwise a compute shader is used to downsample each level if the format supports
+    /// storage images - this requires the texture to have storage usage and the compute path
+    /// to have been enabled with [`Driver::enable_compute_mipmaps`]. All levels will be in the
+    /// shader read-only layout once complete.
+    pub fn generate_mipmaps(
+        &mut self,
+        driver: &mut Driver,
+        cmds: vk::CommandBuffer,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.mip_gen_path(driver)? {
+            MipGenPath::Blit => self.blit_mipmaps(&driver.device, cmds),
+            MipGenPath::Compute => {
+                let Driver {
+                    device,
+                    graphics_commands,
+                    mip_generator,
+                    ..
+                } = driver;
+                let generator = mip_generator.as_ref().ok_or(
+                    "The format requires compute mip generation but no generator has been enabled.",
+                )?;
+                generator.record(device, graphics_commands, cmds, self)
+            }
+        }
+    }
+
+    /// The path used to generate the mip chain of the texture, checking that the texture and
+    /// driver support it so nothing is recorded when mip generation would fail.
+    fn mip_gen_path(&self, driver: &Driver) -> Result<MipGenPath, Box<dyn Error>> {
+        let format_props = unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_format_properties(
+                    driver.device.physical_device,
+                    self.info.format,
+                )
+        };
+        match mipmap::select_mip_gen_path(format_props.optimal_tiling_features) {
+            Some(MipGenPath::Blit) => {
+                self.require_usage(
+                    vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+                    "Blitting mip maps",
+                )?;
+                Ok(MipGenPath::Blit)
+            }
+            Some(MipGenPath::Compute) => {
+                if driver.mip_generator.is_none() {
+                    return Err(Box::from(
+                        "The format requires compute mip generation but no generator has been enabled.",
+                    ));
+                }
+                self.require_usage(vk::ImageUsageFlags::STORAGE, "Compute mip generation")?;
+                Ok(MipGenPath::Compute)
+            }
+            None => Err(Box::from(format!(
+                "Format {:?} supports neither blitting nor storage - unable to generate mip maps.",
+                self.info.format
+            ))),
+        }
+    }
+
+    /// Clear all mip levels and array layers of a storage texture to the value, leaving the
+    /// texture in the general layout. The existing contents are discarded.
+    ///
+    /// `vkCmdClearColorImage` is used where the format supports it and the texture has transfer
+    /// dst usage, otherwise a compute shader is used - this requires storage usage and the
+    /// compute path to have been enabled with [`Driver::enable_compute_clears`]. Textures
+    /// without storage usage can be cleared as long as the transfer path is available.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Reset the accumulation buffer.
+    /// let zero = vk::ClearColorValue { float32: [0.0; 4] };
+    /// accumulation.clear_storage(&mut driver, cmds, zero)?;
+    /// ```
+    ///
+    pub fn clear_storage(
+        &mut self,
+        driver: &mut Driver,
+        cmds: vk::CommandBuffer,
+        value: vk::ClearColorValue,
+    ) -> Result<(), Box<dyn Error>> {
+        let format_props = unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_format_properties(
+                    driver.device.physical_device,
+                    self.info.format,
+                )
+        };
+        let transfer_clear = clear::supports_transfer_clear(format_props.optimal_tiling_features)
+            && self.can_transfer_dst();
+        if !transfer_clear {
+            self.require_usage(
+                vk::ImageUsageFlags::STORAGE,
+                "Clearing a texture by compute",
+            )?;
+            if driver.compute_clear.is_none() {
+                return Err(Box::from(
+                    "The texture can't be cleared by a transfer but compute clears haven't been enabled.",
+                ));
+            }
+        }
+        let (dst_stage, dst_access) = match transfer_clear {
+            true => (
+                vk::PipelineStageFlags2::CLEAR,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            false => (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+        };
+        // The contents are to be overwritten, so the old layout can be discarded.
+        let barriers: Vec<vk::ImageMemoryBarrier2> = (0..self.info.mip_levels)
+            .map(|level| {
+                self.level_barrier(
+                    level,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    dst_stage,
+                )
+                .dst_access_mask(dst_access)
+            })
+            .collect();
+        barrier::cmd_image_barriers(&driver.device, cmds, &barriers);
+        self.set_image_layout(vk::ImageLayout::GENERAL);
+
+        if !transfer_clear {
+            let Driver {
+                device,
+                graphics_commands,
+                compute_clear,
+                ..
+            } = driver;
+            let compute_clear = compute_clear.as_ref().ok_or(
+                "The texture can't be cleared by a transfer but compute clears haven't been enabled.",
+            )?;
+            return compute_clear.record(device, graphics_commands, cmds, self, value);
+        }
+
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(self.info.mip_levels)
+            .base_array_layer(0)
+            .layer_count(self.array_layer_count());
+        unsafe {
+            driver.device.device.cmd_clear_color_image(
+                cmds,
+                self.image,
+                vk::ImageLayout::GENERAL,
+                &value,
+                &[range],
+            )
+        };
+
+        let barriers: Vec<vk::ImageMemoryBarrier2> = (0..self.info.mip_levels)
+            .map(|level| {
+                self.level_barrier(
+                    level,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags2::CLEAR,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                )
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+            })
+            .collect();
+        barrier::cmd_image_barriers(&driver.device, cmds, &barriers);
+        Ok(())
+    }
+
+    /// Create a copy of the texture in another format, i.e. to convert a format which can't
+    /// be sampled or rendered to one which can. All mip levels and array layers are converted
+    /// and the new texture, which uses the same sampler, is left in the shader read-only layout.
+    ///
+    /// The conversion is blitted where the formats support it, which requires the source to
+    /// have transfer src usage. Otherwise a compute shader is used - this requires the source
+    /// to have sampled usage and the compute path to have been enabled with
+    /// [`Driver::enable_compute_format_conversion`]. The source is left in the transfer src or
+    /// shader read-only layout respectively.
+    ///
+    /// Only colour formats can be converted.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let rgba = texture.convert_format(&mut driver, vk::Format::R8G8B8A8_UNORM, cmds)?;
+    /// texture.destroy(&driver);
+    /// ```
+    ///
+    pub fn convert_format(
+        &mut self,
+        driver: &mut Driver,
+        dst_format: vk::Format,
+        cmds: vk::CommandBuffer,
+    ) -> Result<Texture, Box<dyn Error>> {
+        if get_aspect_mask(self.info.format) != vk::ImageAspectFlags::COLOR
+            || get_aspect_mask(dst_format) != vk::ImageAspectFlags::COLOR
+        {
+            return Err(Box::from(format!(
+                "Unable to convert from {:?} to {dst_format:?} - only colour formats can be converted.",
+                self.info.format
+            )));
+        }
+
+        let features = |format: vk::Format| unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_format_properties(driver.device.physical_device, format)
+                .optimal_tiling_features
+        };
+        let path = convert::select_conversion_path(features(self.info.format), features(dst_format))
+            .ok_or_else(|| {
+                format!(
+                    "Conversion from {:?} to {dst_format:?} is supported by neither blitting nor compute.",
+                    self.info.format
+                )
+            })?;
+        let (required_usage, extra_usage) = match path {
+            ConversionPath::Blit => (
+                vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::ImageUsageFlags::empty(),
+            ),
+            ConversionPath::Compute => (vk::ImageUsageFlags::SAMPLED, vk::ImageUsageFlags::STORAGE),
+        };
+        self.require_usage(
+            required_usage,
+            &format!("Converting the texture with the {path:?} path"),
+        )?;
+        if path == ConversionPath::Compute && driver.format_converter.is_none() {
+            return Err(Box::from(
+                "The formats require compute conversion but it hasn't been enabled.",
+            ));
+        }
+
+        // The compute path samples through the swizzle, so the converted texels are already
+        // swizzled, whereas blits copy the texels as they are.
+        let components = match path {
+            ConversionPath::Blit => self.info.components,
+            ConversionPath::Compute => vk::ComponentMapping::default(),
+        };
+        let info = TextureInfo {
+            format: dst_format,
+            components,
+            ..self.info
+        };
+        let mut dst = Texture::new(driver, &info, self.usage_flags | extra_usage, None, &[])?;
+        dst.sampler = self.sampler;
+        let mip_levels = self.info.mip_levels as usize;
+        let res = match path {
+            ConversionPath::Blit => {
+                self.blit_to(&driver.device, cmds, &mut dst);
+                Ok(())
+            }
+            ConversionPath::Compute => {
+                let mut barriers = self.layout_barriers(
+                    self.image_layout,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    mip_levels,
+                );
+                barriers.extend(dst.layout_barriers(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags2::NONE,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    mip_levels,
+                ));
+                barrier::cmd_image_barriers(&driver.device, cmds, &barriers);
+                self.set_image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+                let sampler = driver.default_sampler();
+                let Driver {
+                    device,
+                    graphics_commands,
+                    format_converter,
+                    ..
+                } = driver;
+                match format_converter.as_ref() {
+                    Some(converter) => {
+                        converter.record(device, graphics_commands, cmds, sampler, self, &mut dst)
+                    }
+                    None => Err(Box::from(
+                        "The formats require compute conversion but it hasn't been enabled.",
+                    )),
+                }
+            }
+        };
+        if let Err(err) = res {
+            dst.destroy(driver);
+            return Err(err);
+        }
+        Ok(dst)
+    }
+
+    /// Blit all mip levels and array layers to the destination, which must have the same
+    /// dimensions. The destination is left in the shader read-only layout.
+    fn blit_to(&mut self, device: &ContextDevice, cmds: vk::CommandBuffer, dst: &mut Texture) {
+        let mip_levels = self.info.mip_levels as usize;
+        let mut barriers = self.layout_barriers(
+            self.image_layout,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::PipelineStageFlags2::BLIT,
+            mip_levels,
+        );
+        barriers.extend(dst.layout_barriers(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags2::NONE,
+            vk::PipelineStageFlags2::BLIT,
+            mip_levels,
+        ));
+        barrier::cmd_image_barriers(device, cmds, &barriers);
+        self.set_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+
+        let layer_count = self.array_layer_count();
+        let blits: Vec<vk::ImageBlit> = (0..self.info.mip_levels)
+            .map(|level| {
+                let subresource = vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(level)
+                    .base_array_layer(0)
+                    .layer_count(layer_count);
+                let extent = vk::Offset3D {
+                    x: (self.info.width >> level).max(1) as i32,
+                    y: (self.info.height >> level).max(1) as i32,
+                    z: 1,
+                };
+                vk::ImageBlit::default()
+                    .src_subresource(subresource)
+                    .src_offsets([vk::Offset3D::default(), extent])
+                    .dst_subresource(subresource)
+                    .dst_offsets([vk::Offset3D::default(), extent])
+            })
+            .collect();
+        unsafe {
+            device.device.cmd_blit_image(
+                cmds,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &blits,
+                vk::Filter::NEAREST,
+            )
+        };
+
+        dst.transition(
+            device,
+            cmds,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::BLIT,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            mip_levels,
+        );
+    }
+
+    fn blit_mipmaps(
+        &mut self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+    ) -> Result<(), Box<dyn Error>> {
+        let layer_count = self.array_layer_count();
+        let level_range = |level: u32| {
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(level)
+                .level_count(1)
+                .layer_count(layer_count)
+        };
+        for level in 1..self.info.mip_levels {
+            self.transition_subresource(
+                cmds,
+                device,
+                level_range(level - 1),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            )?;
+            self.transition_subresource(
+                cmds,
+                device,
+                level_range(level),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            )?;
+
+            let subresource = |level: u32| {
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(level)
+                    .base_array_layer(0)
+                    .layer_count(layer_count)
+            };
+            let level_offset = |level: u32| vk::Offset3D {
+                x: (self.info.width >> level).max(1) as i32,
+                y: (self.info.height >> level).max(1) as i32,
+                z: 1,
+            };
+            let blit = vk::ImageBlit::default()
+                .src_subresource(subresource(level - 1))
+                .src_offsets([vk::Offset3D::default(), level_offset(level - 1)])
+                .dst_subresource(subresource(level))
+                .dst_offsets([vk::Offset3D::default(), level_offset(level)]);
+            unsafe {
+                device.device.cmd_blit_image(
+                    cmds,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR,
+                )
+            };
+        }
+
+        // All but the last level are now in the transfer src layout.
+        let all_levels = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(vk::REMAINING_MIP_LEVELS)
+            .layer_count(vk::REMAINING_ARRAY_LAYERS);
+        self.transition_subresource(
+            cmds,
+            device,
+            all_levels,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+    }
+
+    /// The copy regions of a range of mip levels for all faces, with the offsets of each face
+    /// and level indexed by `face * level_count + level - levels.start`.
+    pub(crate) fn level_copy_regions(
+        &self,
+        levels: Range<u32>,
+        offsets: &[vk::DeviceSize],
+    ) -> Result<Vec<vk::BufferImageCopy>, OxidationError> {
+        let array_count = compute_array_layers(&self.info.ty, self.info.array_layers);
+        let level_count = levels.len() as u32;
+        validate_offset_count(offsets, (array_count * level_count) as usize)?;
+        let regions = (0..array_count)
+            .flat_map(|face| levels.clone().map(move |level| (face, level)))
+            .map(|(face, level)| {
+                let idx = (face * level_count + level - levels.start) as usize;
+                vk::BufferImageCopy::default()
+                    .buffer_offset(offsets[idx])
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .mip_level(level)
+                            .layer_count(1)
+                            .base_array_layer(face),
+                    )
+                    .image_extent(
+                        vk::Extent3D::default()
+                            .width((self.info.width >> level).max(1))
+                            .height((self.info.height >> level).max(1))
+                            .depth(1),
+                    )
+            })
+            .collect();
+        Ok(regions)
+    }
+
+    /// The buffer to image copy regions for each face and mip level of the texture, where
+    /// the offsets are the locations of each region within the buffer. If generating mip-maps,
+    /// only the first mip level is copied.
+    pub(crate) fn copy_regions(
+        &self,
+        offsets: &[vk::DeviceSize],
+        generate_mipmaps: bool,
+    ) -> Result<Vec<vk::BufferImageCopy>, OxidationError> {
+        let mut image_copy_info: Vec<vk::BufferImageCopy> = Vec::new();
+
+        if !generate_mipmaps {
+            let array_count = compute_array_layers(&self.info.ty, self.info.array_layers);
+            validate_offset_count(offsets, (array_count * self.info.mip_levels) as usize)?;
+            image_copy_info.resize_with(
+                (array_count * self.info.mip_levels) as usize,
+                Default::default,
+            );
+            for face in 0..array_count {
+                for level in 0..self.info.mip_levels {
+                    let idx = (face * self.info.mip_levels + level) as usize;
+
+                    let image_subresource = vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
                         .mip_level(level)
                         .layer_count(1)
                         .base_array_layer(face);
@@ -254,135 +1799,668 @@ impl Texture {
                 .image_extent(extents);
         }
 
-        // Transition all mips to for dst transfer - this is required as the last step in copying is
-        // then to transition all mips ready for shader read. Not having the levels in the correct
-        // layout leads to validation warnings.
-        let transition_count = match generate_mipmaps {
-            true => 1,
-            false => self.info.mip_levels as usize,
-        };
+        Ok(image_copy_info)
+    }
 
-        self.transition(
-            &driver.device.device,
-            cmds,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::PipelineStageFlags::TRANSFER,
-            transition_count,
+    #[allow(clippy::too_many_arguments)]
+    /// Transition an image to the new specified layout.
+    /// This can be done for all mip levels by specifying the level count.
+    /// Uses synchronization2 barriers if supported by the device, otherwise the legacy barrier.
+    pub fn transition(
+        &mut self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_stage_flags: vk::PipelineStageFlags2,
+        dst_stage_flags: vk::PipelineStageFlags2,
+        level_count: usize,
+    ) {
+        let memory_barriers = self.layout_barriers(
+            old_layout,
+            new_layout,
+            src_stage_flags,
+            dst_stage_flags,
+            level_count,
         );
+        barrier::cmd_image_barriers(device, cmds, &memory_barriers);
 
-        // Copy the image from the staging buffer to the device.
-        unsafe {
-            driver.device.device.cmd_copy_buffer_to_image(
-                cmds,
-                stage.buffer,
-                self.image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &image_copy_info,
-            )
+        self.image_layout = new_layout;
+        self.stencil_layout = new_layout;
+        self.face_layouts.fill(new_layout);
+        for layer in 0..self.array_layer_count() {
+            for level in 0..level_count as u32 {
+                let idx = self.subresource_idx(level, layer);
+                self.subresource_layouts[idx] = new_layout;
+            }
+        }
+    }
+
+    /// Transition a precise range of mip levels and array layers to the new layout, leaving
+    /// the remaining subresources unaffected - for example a single level during mip
+    /// generation. The old layout of each subresource is taken from its tracked layout, so
+    /// subresources in differing layouts are transitioned correctly by a single call.
+    ///
+    /// Returns an error if the range is outside of the texture.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Transition levels 1 and 2 of all layers, i.e. to write them with a blit.
+    /// let range = vk::ImageSubresourceRange::default()
+    ///     .aspect_mask(vk::ImageAspectFlags::COLOR)
+    ///     .base_mip_level(1)
+    ///     .level_count(2)
+    ///     .layer_count(vk::REMAINING_ARRAY_LAYERS);
+    /// texture.transition_subresource(cmds, &driver.device, range, vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
+    /// assert_eq!(texture.subresource_layout(0, 0), Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+    /// assert_eq!(texture.subresource_layout(1, 0), Some(vk::ImageLayout::TRANSFER_DST_OPTIMAL));
+    /// ```
+    ///
+    pub fn transition_subresource(
+        &mut self,
+        cmds: vk::CommandBuffer,
+        device: &ContextDevice,
+        range: vk::ImageSubresourceRange,
+        new_layout: vk::ImageLayout,
+    ) -> Result<(), Box<dyn Error>> {
+        let mip_levels = self.info.mip_levels;
+        let layer_count = self.array_layer_count();
+        let level_count = match range.level_count {
+            vk::REMAINING_MIP_LEVELS => mip_levels.saturating_sub(range.base_mip_level),
+            count => count,
+        };
+        let range_layer_count = match range.layer_count {
+            vk::REMAINING_ARRAY_LAYERS => layer_count.saturating_sub(range.base_array_layer),
+            count => count,
         };
+        let levels = range.base_mip_level..range.base_mip_level + level_count;
+        let layers = range.base_array_layer..range.base_array_layer + range_layer_count;
+        if levels.is_empty()
+            || layers.is_empty()
+            || levels.end > mip_levels
+            || layers.end > layer_count
+        {
+            return Err(Box::from(format!(
+                "Subresource range (levels {levels:?}, layers {layers:?}) is outside of the texture (mip levels: {mip_levels}, layers: {layer_count}).",
+            )));
+        }
 
-        // Transition the image(s) ready for reads by the fragment shader.
-        self.transition(
-            &driver.device.device,
-            cmds,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::PipelineStageFlags::FRAGMENT_SHADER,
-            transition_count,
-        );
+        // Consecutive layers of a level which share a layout are transitioned by one barrier.
+        let mut barriers = Vec::new();
+        for level in levels.clone() {
+            let mut layer = layers.start;
+            while layer < layers.end {
+                let old_layout = self.subresource_layouts[self.subresource_idx(level, layer)];
+                let first_layer = layer;
+                while layer < layers.end
+                    && self.subresource_layouts[self.subresource_idx(level, layer)] == old_layout
+                {
+                    layer += 1;
+                }
+                let barrier_range = vk::ImageSubresourceRange::default()
+                    .aspect_mask(range.aspect_mask)
+                    .base_mip_level(level)
+                    .level_count(1)
+                    .base_array_layer(first_layer)
+                    .layer_count(layer - first_layer);
+                barriers.push(
+                    vk::ImageMemoryBarrier2::default()
+                        .image(self.image)
+                        .old_layout(old_layout)
+                        .new_layout(new_layout)
+                        .subresource_range(barrier_range)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .src_stage_mask(barrier::layout_stage_flags(old_layout))
+                        .dst_stage_mask(barrier::layout_stage_flags(new_layout))
+                        .src_access_mask(barrier::src_access_flags(old_layout))
+                        .dst_access_mask(barrier::dst_access_flags(new_layout)),
+                );
+            }
+        }
+        barrier::cmd_image_barriers(device, cmds, &barriers);
 
-        // If required, now generate the mip-maps for the image.
-        if generate_mipmaps {
-            // TODO: Add mip map generation.
+        for level in levels {
+            for layer in layers.clone() {
+                let idx = self.subresource_idx(level, layer);
+                self.subresource_layouts[idx] = new_layout;
+            }
+        }
+        // The whole image layout is only known once all subresources share a layout.
+        if self
+            .subresource_layouts
+            .iter()
+            .all(|layout| *layout == new_layout)
+        {
+            self.image_layout = new_layout;
+            self.stencil_layout = new_layout;
         }
+        Ok(())
     }
 
-    #[allow(clippy::too_many_arguments, clippy::needless_range_loop)]
-    /// Transition an image to the new specified layout.
-    /// This can be done for all mip levels by specifying the level count.
-    pub fn transition(
+    /// The tracked layout of a single mip level of an array layer (or cube face), or `None`
+    /// if out of range.
+    pub fn subresource_layout(&self, level: u32, layer: u32) -> Option<vk::ImageLayout> {
+        if level >= self.info.mip_levels || layer >= self.array_layer_count() {
+            return None;
+        }
+        Some(self.subresource_layouts[self.subresource_idx(level, layer)])
+    }
+
+    fn subresource_idx(&self, level: u32, layer: u32) -> usize {
+        (layer * self.info.mip_levels + level) as usize
+    }
+
+    /// Transition all mip levels of a single face of a cube texture to the new layout, for
+    /// example to render into the face. For cube arrays, the face index is `array_layer * 6 + face`.
+    /// The other faces are unaffected.
+    pub fn face_layout_transition(
         &mut self,
-        device: &ash::Device,
+        face: u32,
         cmds: vk::CommandBuffer,
+        device: &ContextDevice,
+        new_layout: vk::ImageLayout,
+    ) -> Result<(), Box<dyn Error>> {
+        if !matches!(self.info.ty, TextureType::Cube2d | TextureType::CubeArray2d) {
+            return Err(Box::from(
+                "Face layout transitions are only valid for cube textures.",
+            ));
+        }
+        let Some(old_layout) = self.face_layouts.get(face as usize).copied() else {
+            return Err(Box::from(format!(
+                "Face index {face} is out of range (face count: {}).",
+                self.face_layouts.len()
+            )));
+        };
+
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(get_aspect_mask(self.info.format))
+            .base_mip_level(0)
+            .level_count(self.info.mip_levels)
+            .base_array_layer(face)
+            .layer_count(1);
+        let memory_barrier = vk::ImageMemoryBarrier2::default()
+            .image(self.image)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .subresource_range(range)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_stage_mask(barrier::layout_stage_flags(old_layout))
+            .dst_stage_mask(barrier::layout_stage_flags(new_layout))
+            .src_access_mask(barrier::src_access_flags(old_layout))
+            .dst_access_mask(barrier::dst_access_flags(new_layout));
+        barrier::cmd_image_barriers(device, cmds, &[memory_barrier]);
+
+        self.face_layouts[face as usize] = new_layout;
+        for level in 0..self.info.mip_levels {
+            let idx = self.subresource_idx(level, face);
+            self.subresource_layouts[idx] = new_layout;
+        }
+        // Once all faces have been transitioned, the whole image is in the new layout.
+        if self
+            .subresource_layouts
+            .iter()
+            .all(|layout| *layout == new_layout)
+        {
+            self.image_layout = new_layout;
+            self.stencil_layout = new_layout;
+        }
+        Ok(())
+    }
+
+    /// Create the barriers, one per mip level, required to transition the image layout.
+    pub(crate) fn layout_barriers(
+        &self,
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
-        src_stage_flags: vk::PipelineStageFlags,
-        dst_stage_flags: vk::PipelineStageFlags,
+        src_stage_flags: vk::PipelineStageFlags2,
+        dst_stage_flags: vk::PipelineStageFlags2,
         level_count: usize,
-    ) {
-        let mask = get_aspect_mask(self.info.format);
-        let array_count = compute_array_layers(&self.info.ty, self.info.array_layers);
+    ) -> Vec<vk::ImageMemoryBarrier2<'static>> {
+        (0..level_count as u32)
+            .map(|level| {
+                self.level_barrier(
+                    level,
+                    old_layout,
+                    new_layout,
+                    src_stage_flags,
+                    dst_stage_flags,
+                )
+            })
+            .collect()
+    }
+
+    /// Create the barrier required to transition the layout of a single mip level, across all
+    /// array layers.
+    pub(crate) fn level_barrier(
+        &self,
+        level: u32,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_stage_flags: vk::PipelineStageFlags2,
+        dst_stage_flags: vk::PipelineStageFlags2,
+    ) -> vk::ImageMemoryBarrier2<'static> {
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(get_aspect_mask(self.info.format))
+            .base_mip_level(level)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(self.array_layer_count());
+        vk::ImageMemoryBarrier2::default()
+            .image(self.image)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .subresource_range(range)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_stage_mask(src_stage_flags)
+            .dst_stage_mask(dst_stage_flags)
+            .src_access_mask(barrier::src_access_flags(old_layout))
+            .dst_access_mask(barrier::dst_access_flags(new_layout))
+    }
 
-        let mut ranges: [vk::ImageSubresourceRange; MAX_MIP_LEVEL_COUNT] = Default::default();
-
-        for level in 0..self.info.mip_levels as usize {
-            ranges[level] = ranges[level]
-                .aspect_mask(mask)
-                .level_count(0)
-                .layer_count(array_count)
-                .base_mip_level(self.info.mip_levels)
-                .base_array_layer(0)
-                .base_mip_level(level as u32)
-                .level_count(1);
-        }
-
-        let src_barrier: vk::AccessFlags = match old_layout {
-            vk::ImageLayout::UNDEFINED => vk::AccessFlags::empty(),
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::AccessFlags::TRANSFER_READ,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags::TRANSFER_WRITE,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::AccessFlags::SHADER_READ,
-            _ => vk::AccessFlags::empty(),
-        };
-
-        let dst_barrier: vk::AccessFlags = match new_layout {
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::AccessFlags::TRANSFER_READ,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags::TRANSFER_WRITE,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::AccessFlags::SHADER_READ,
-            vk::ImageLayout::GENERAL => {
-                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE
+    /// The total number of array layers of the image - six per cube.
+    pub(crate) fn array_layer_count(&self) -> u32 {
+        compute_array_layers(&self.info.ty, self.info.array_layers)
+    }
+
+    /// A view of a single mip level, covering all array layers as a 2D array, for storage
+    /// image access. The view is created on first use.
+    pub(crate) fn storage_mip_view(
+        &mut self,
+        level: u32,
+        device: &ash::Device,
+    ) -> Result<vk::ImageView, OxidationError> {
+        let range = ViewRange::mip(level, self.array_layer_count());
+        self.cached_view(range, vk::ImageViewType::TYPE_2D_ARRAY, device)
+    }
+
+    pub fn image(&self) -> vk::Image {
+        self.image
+    }
+
+    /// The current layout of the image - for depth/stencil formats, this is the layout of the
+    /// depth aspect.
+    pub fn layout(&self) -> vk::ImageLayout {
+        self.image_layout
+    }
+
+    /// The layout of the stencil aspect, or `None` if the format has no stencil aspect.
+    pub fn stencil_layout(&self) -> Option<vk::ImageLayout> {
+        Driver::is_stencil_format(&self.info.format).then_some(self.stencil_layout)
+    }
+
+    /// Transition only the depth or stencil aspect of a depth/stencil texture to the new layout,
+    /// leaving the other aspect unaffected - for example to sample the depth aspect while the
+    /// stencil aspect is still written. All mip levels and array layers are transitioned.
+    ///
+    /// Requires the `separateDepthStencilLayouts` feature, and the new layout must be one of the
+    /// depth or stencil specific layouts for the aspect, or a layout which applies to any aspect
+    /// such as `GENERAL` or `TRANSFER_DST_OPTIMAL`.
+    pub fn aspect_layout_transition(
+        &mut self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        aspect: vk::ImageAspectFlags,
+        new_layout: vk::ImageLayout,
+    ) -> Result<(), Box<dyn Error>> {
+        if !device.enabled_features().separate_depth_stencil_layouts {
+            return Err(Box::from(
+                "Separate depth/stencil layouts are not supported by the device.",
+            ));
+        }
+        let old_layout = match aspect {
+            vk::ImageAspectFlags::DEPTH if Driver::is_depth_format(&self.info.format) => {
+                self.image_layout
+            }
+            vk::ImageAspectFlags::STENCIL if Driver::is_stencil_format(&self.info.format) => {
+                self.stencil_layout
             }
-            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
-                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
-                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            _ => {
+                return Err(Box::from(format!(
+                    "Aspect {aspect:?} is not a single aspect of format {:?}.",
+                    self.info.format
+                )));
             }
-            _ => vk::AccessFlags::empty(),
         };
+        if !is_aspect_layout(aspect, new_layout) {
+            return Err(Box::from(format!(
+                "Layout {new_layout:?} can't be used for the {aspect:?} aspect alone."
+            )));
+        }
 
-        let mut memory_barriers: [vk::ImageMemoryBarrier; MAX_MIP_LEVEL_COUNT] = Default::default();
-        for i in 0..level_count {
-            memory_barriers[i] = memory_barriers[i]
-                .image(self.image)
-                .old_layout(old_layout)
-                .new_layout(new_layout)
-                .subresource_range(ranges[i])
-                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .src_access_mask(src_barrier)
-                .dst_access_mask(dst_barrier);
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(aspect)
+            .base_mip_level(0)
+            .level_count(self.info.mip_levels)
+            .base_array_layer(0)
+            .layer_count(self.array_layer_count());
+        let memory_barrier = vk::ImageMemoryBarrier2::default()
+            .image(self.image)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .subresource_range(range)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_stage_mask(barrier::layout_stage_flags(old_layout))
+            .dst_stage_mask(barrier::layout_stage_flags(new_layout))
+            .src_access_mask(barrier::src_access_flags(old_layout))
+            .dst_access_mask(barrier::dst_access_flags(new_layout));
+        barrier::cmd_image_barriers(device, cmds, &[memory_barrier]);
+
+        match aspect {
+            vk::ImageAspectFlags::DEPTH => {
+                self.image_layout = new_layout;
+                self.subresource_layouts.fill(new_layout);
+            }
+            _ => self.stencil_layout = new_layout,
         }
+        Ok(())
+    }
 
-        unsafe {
-            device.cmd_pipeline_barrier(
-                cmds,
-                src_stage_flags,
-                dst_stage_flags,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                &memory_barriers,
-            )
+    pub(crate) fn set_image_layout(&mut self, layout: vk::ImageLayout) {
+        self.image_layout = layout;
+        self.stencil_layout = layout;
+        self.subresource_layouts.fill(layout);
+    }
+
+    /// Set the tracked layout of a range of mip levels across all array layers, i.e. after
+    /// uploading the levels.
+    pub(crate) fn set_levels_layout(&mut self, levels: Range<u32>, layout: vk::ImageLayout) {
+        for layer in 0..self.array_layer_count() {
+            for level in levels.clone() {
+                let idx = self.subresource_idx(level, layer);
+                self.subresource_layouts[idx] = layout;
+            }
+        }
+        if self.subresource_layouts.iter().all(|l| *l == layout) {
+            self.image_layout = layout;
+            self.stencil_layout = layout;
+        }
+    }
+}
+
+fn view_type(tex_type: &TextureType) -> vk::ImageViewType {
+    match tex_type {
+        TextureType::Cube2d => vk::ImageViewType::CUBE,
+        TextureType::CubeArray2d => vk::ImageViewType::CUBE_ARRAY,
+        TextureType::Array2d => vk::ImageViewType::TYPE_2D_ARRAY,
+        TextureType::Texture2d => vk::ImageViewType::TYPE_2D,
+    }
+}
+
+/// Whether the mapping leaves each component unchanged.
+fn is_identity_mapping(components: &vk::ComponentMapping) -> bool {
+    let is_identity = |swizzle: vk::ComponentSwizzle, component: vk::ComponentSwizzle| {
+        swizzle == vk::ComponentSwizzle::IDENTITY || swizzle == component
+    };
+    is_identity(components.r, vk::ComponentSwizzle::R)
+        && is_identity(components.g, vk::ComponentSwizzle::G)
+        && is_identity(components.b, vk::ComponentSwizzle::B)
+        && is_identity(components.a, vk::ComponentSwizzle::A)
+}
+
+/// Linear images are limited to a single 2D mip level and layer, and the format must support
+/// the usage with linear tiling, as queried by `linear_features`. Only linear images can be
+/// pre-initialized.
+fn validate_tiling(
+    info: &TextureInfo,
+    usage_flags: vk::ImageUsageFlags,
+    linear_features: impl FnOnce() -> vk::FormatFeatureFlags,
+) -> Result<(), OxidationError> {
+    if !matches!(
+        info.initial_layout,
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PREINITIALIZED
+    ) {
+        return Err(OxidationError::Other(format!(
+            "Textures can only be created in the undefined or pre-initialized layout, not {:?}.",
+            info.initial_layout
+        )));
+    }
+    if info.tiling != vk::ImageTiling::LINEAR {
+        return match info.initial_layout {
+            vk::ImageLayout::PREINITIALIZED => Err(OxidationError::Unsupported(String::from(
+                "Only linear textures can be created in the pre-initialized layout.",
+            ))),
+            _ => Ok(()),
         };
+    }
+    if !matches!(info.ty, TextureType::Texture2d) || info.mip_levels != 1 || info.array_layers != 1
+    {
+        return Err(OxidationError::Unsupported(format!(
+            "Linear textures must be 2D with a single mip level and layer ({:?}, {} mips, {} layers).",
+            info.ty, info.mip_levels, info.array_layers
+        )));
+    }
 
-        self.image_layout = new_layout;
+    let features = linear_features();
+    let usage = implicit_usage(usage_flags) | usage_flags;
+    let required = [
+        (
+            vk::ImageUsageFlags::SAMPLED,
+            vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        ),
+        (
+            vk::ImageUsageFlags::STORAGE,
+            vk::FormatFeatureFlags::STORAGE_IMAGE,
+        ),
+        (
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT,
+        ),
+        (
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        ),
+        (
+            vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::FormatFeatureFlags::TRANSFER_SRC,
+        ),
+        (
+            vk::ImageUsageFlags::TRANSFER_DST,
+            vk::FormatFeatureFlags::TRANSFER_DST,
+        ),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| usage.contains(*flag))
+    .fold(vk::FormatFeatureFlags::empty(), |acc, (_, feature)| {
+        acc | feature
+    });
+    if !features.contains(required) {
+        return Err(OxidationError::Unsupported(format!(
+            "Format {:?} doesn't support {:?} with linear tiling.",
+            info.format,
+            required & !features
+        )));
     }
+    Ok(())
 }
 
-fn compute_array_layers(tex_type: &TextureType, array_count: u32) -> u32 {
+/// Swizzles are only applied when sampling - views used as attachments or storage images must
+/// have the identity mapping.
+fn validate_components(
+    components: &vk::ComponentMapping,
+    usage_flags: vk::ImageUsageFlags,
+) -> Result<(), OxidationError> {
+    let identity_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+        | vk::ImageUsageFlags::INPUT_ATTACHMENT
+        | vk::ImageUsageFlags::STORAGE;
+    if is_identity_mapping(components) || !usage_flags.intersects(identity_usage) {
+        return Ok(());
+    }
+    Err(OxidationError::Unsupported(format!(
+        "Textures with {:?} usage require the identity component mapping, not {components:?}.",
+        usage_flags & identity_usage
+    )))
+}
+
+/// An offset is required for each region copied from the buffer.
+fn validate_offset_count(
+    offsets: &[vk::DeviceSize],
+    region_count: usize,
+) -> Result<(), OxidationError> {
+    if offsets.len() >= region_count {
+        return Ok(());
+    }
+    Err(OxidationError::Other(format!(
+        "{} buffer offsets were given for {region_count} copy regions.",
+        offsets.len()
+    )))
+}
+
+/// Whether the frame a resource was last used in hasn't been confirmed as completed.
+fn is_frame_in_flight(last_used_frame: Option<u64>, completed_frame: Option<u64>) -> bool {
+    last_used_frame.is_some_and(|frame| completed_frame.is_none_or(|completed| frame > completed))
+}
+
+/// A layout transition of a subresource range of an image being relocated, with the stages
+/// and accesses derived from the layouts.
+fn relocation_barrier(
+    image: vk::Image,
+    range: vk::ImageSubresourceRange,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) -> vk::ImageMemoryBarrier2<'static> {
+    vk::ImageMemoryBarrier2::default()
+        .image(image)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .subresource_range(range)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .src_stage_mask(barrier::layout_stage_flags(old_layout))
+        .dst_stage_mask(barrier::layout_stage_flags(new_layout))
+        .src_access_mask(barrier::src_access_flags(old_layout))
+        .dst_access_mask(barrier::dst_access_flags(new_layout))
+}
+
+/// Whether the layout can be used when transitioning only the depth or stencil aspect of a
+/// depth/stencil image - the layouts specific to the other aspect, or which describe both
+/// aspects, are not allowed.
+fn is_aspect_layout(aspect: vk::ImageAspectFlags, layout: vk::ImageLayout) -> bool {
+    match layout {
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL => {
+            aspect == vk::ImageAspectFlags::DEPTH
+        }
+        vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL => aspect == vk::ImageAspectFlags::STENCIL,
+        vk::ImageLayout::GENERAL
+        | vk::ImageLayout::ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::READ_ONLY_OPTIMAL
+        | vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        | vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        | vk::ImageLayout::TRANSFER_DST_OPTIMAL => true,
+        _ => false,
+    }
+}
+
+fn create_range_view(
+    image: vk::Image,
+    format: vk::Format,
+    view_type: vk::ImageViewType,
+    range: ViewRange,
+    device: &ash::Device,
+) -> VkResult<vk::ImageView> {
+    let sub_resource = vk::ImageSubresourceRange::default()
+        .aspect_mask(
+            range
+                .aspect_override
+                .unwrap_or_else(|| get_aspect_mask(format)),
+        )
+        .base_mip_level(range.base_mip)
+        .level_count(range.mip_count)
+        .base_array_layer(range.base_layer)
+        .layer_count(range.layer_count);
+    let create_info = vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(view_type)
+        .format(format)
+        .subresource_range(sub_resource);
+    unsafe { device.create_image_view(&create_info, None) }
+}
+
+/// Whether the device has a memory type which supports lazily allocated memory.
+/// The usage implicitly added to images created with the usage flags. Mip levels are blitted
+/// from the previous level when generating mip maps, and the contents are copied when the image
+/// is relocated by defragmentation. Transient attachments can only be used as attachments, so
+/// no transfer usage is added.
+fn implicit_usage(usage_flags: vk::ImageUsageFlags) -> vk::ImageUsageFlags {
+    match usage_flags.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+        true => vk::ImageUsageFlags::empty(),
+        false => vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+    }
+}
+
+/// The usage of an image created with the usage flags - images owned elsewhere (i.e. swapchain
+/// images) only have the flags they were created with.
+fn image_usage(usage_flags: vk::ImageUsageFlags, owns_image: bool) -> vk::ImageUsageFlags {
+    match owns_image {
+        true => implicit_usage(usage_flags) | usage_flags,
+        false => usage_flags,
+    }
+}
+
+fn check_usage(
+    usage: vk::ImageUsageFlags,
+    required: vk::ImageUsageFlags,
+    operation: &str,
+) -> Result<(), OxidationError> {
+    if usage.contains(required) {
+        return Ok(());
+    }
+    Err(OxidationError::Unsupported(format!(
+        "{operation} requires {required:?} usage, but the texture has {usage:?} usage."
+    )))
+}
+
+pub fn has_lazily_allocated_memory(vma_alloc: &vk_mem::Allocator) -> bool {
+    let props = unsafe { vma_alloc.get_memory_properties() };
+    props.memory_types[..props.memory_type_count as usize]
+        .iter()
+        .any(|ty| {
+            ty.property_flags
+                .contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED)
+        })
+}
+
+/// The size in bytes of a texel of an uncompressed color format, or `None` for block
+/// compressed, depth/stencil and other formats which aren't supported for uploads.
+fn texel_size(format: vk::Format) -> Option<u32> {
+    let size = match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SRGB => {
+            1
+        }
+        vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R8G8_UINT
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_UINT
+        | vk::Format::R16_SFLOAT => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R32G32_SFLOAT => 8,
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT => 16,
+        _ => return None,
+    };
+    Some(size)
+}
+
+pub(crate) fn compute_array_layers(tex_type: &TextureType, array_count: u32) -> u32 {
     match tex_type {
         TextureType::Array2d => array_count,
         TextureType::Cube2d => 6,
@@ -399,6 +2477,10 @@ fn get_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
         vk::Format::D24_UNORM_S8_UINT => {
             vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
         }
+        vk::Format::D16_UNORM_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
         vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
         vk::Format::D16_UNORM => vk::ImageAspectFlags::DEPTH,
         _ => vk::ImageAspectFlags::COLOR,
@@ -414,3 +2496,231 @@ fn get_image_layout(format: &vk::Format, usage_flags: &vk::ImageUsageFlags) -> v
         vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn texel_size_of_formats() {
+        assert_eq!(texel_size(vk::Format::R8_UNORM), Some(1));
+        assert_eq!(texel_size(vk::Format::R16_SFLOAT), Some(2));
+        assert_eq!(texel_size(vk::Format::R8G8B8A8_SRGB), Some(4));
+        assert_eq!(texel_size(vk::Format::R16G16B16A16_SFLOAT), Some(8));
+        assert_eq!(texel_size(vk::Format::R32G32B32A32_SFLOAT), Some(16));
+    }
+
+    #[test]
+    fn texel_size_rejects_unsupported_formats() {
+        assert_eq!(texel_size(vk::Format::BC7_UNORM_BLOCK), None);
+        assert_eq!(texel_size(vk::Format::D32_SFLOAT), None);
+        assert_eq!(texel_size(vk::Format::UNDEFINED), None);
+    }
+
+    #[test]
+    fn aspect_layout_matches_aspect() {
+        let depth = vk::ImageAspectFlags::DEPTH;
+        let stencil = vk::ImageAspectFlags::STENCIL;
+        assert!(is_aspect_layout(
+            depth,
+            vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+        ));
+        assert!(is_aspect_layout(
+            stencil,
+            vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
+        ));
+        assert!(is_aspect_layout(stencil, vk::ImageLayout::GENERAL));
+        assert!(!is_aspect_layout(
+            depth,
+            vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL
+        ));
+        assert!(!is_aspect_layout(
+            stencil,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        ));
+    }
+
+    #[test]
+    fn aspect_layout_rejects_combined_and_color_layouts() {
+        for layout in [
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::UNDEFINED,
+        ] {
+            assert!(!is_aspect_layout(vk::ImageAspectFlags::DEPTH, layout));
+        }
+    }
+
+    #[test]
+    fn view_cache_returns_cached_view() {
+        let mut cache = ViewCache::default();
+        let range = ViewRange::mip(1, 6);
+        let view = cache
+            .get_or_create(range, vk::ImageViewType::TYPE_2D_ARRAY, || {
+                Ok(vk::ImageView::from_raw(1))
+            })
+            .unwrap();
+        let hit = cache
+            .get_or_create(range, vk::ImageViewType::TYPE_2D_ARRAY, || {
+                panic!("the cached view should be returned")
+            })
+            .unwrap();
+        assert_eq!(hit, view);
+        assert_eq!(
+            cache.get(range, vk::ImageViewType::TYPE_2D_ARRAY),
+            Some(view)
+        );
+    }
+
+    #[test]
+    fn view_cache_keys_on_view_type() {
+        let mut cache = ViewCache::default();
+        let range = ViewRange::mip(0, 6);
+        cache
+            .get_or_create(range, vk::ImageViewType::CUBE, || {
+                Ok(vk::ImageView::from_raw(1))
+            })
+            .unwrap();
+        let storage = cache
+            .get_or_create(range, vk::ImageViewType::TYPE_2D_ARRAY, || {
+                Ok(vk::ImageView::from_raw(2))
+            })
+            .unwrap();
+        assert_eq!(storage, vk::ImageView::from_raw(2));
+        assert_eq!(cache.drain().count(), 2);
+    }
+
+    #[test]
+    fn view_cache_does_not_cache_failures() {
+        let mut cache = ViewCache::default();
+        let range = ViewRange::mip(0, 1);
+        let res = cache.get_or_create(range, vk::ImageViewType::TYPE_2D, || {
+            Err(vk::Result::ERROR_OUT_OF_HOST_MEMORY)
+        });
+        assert_eq!(res, Err(vk::Result::ERROR_OUT_OF_HOST_MEMORY));
+        assert_eq!(cache.get(range, vk::ImageViewType::TYPE_2D), None);
+    }
+
+    #[test]
+    fn texture_is_in_flight_until_its_frame_completes() {
+        assert!(!is_frame_in_flight(None, None));
+        assert!(!is_frame_in_flight(None, Some(3)));
+        assert!(is_frame_in_flight(Some(3), None));
+        assert!(is_frame_in_flight(Some(3), Some(2)));
+        assert!(!is_frame_in_flight(Some(3), Some(3)));
+        assert!(!is_frame_in_flight(Some(3), Some(4)));
+    }
+
+    #[test]
+    fn copy_regions_require_an_offset_per_region() {
+        assert!(validate_offset_count(&[0, 64, 80], 3).is_ok());
+        assert!(validate_offset_count(&[0, 64, 80, 84], 3).is_ok());
+        assert!(validate_offset_count(&[0, 64], 3).is_err());
+        assert!(validate_offset_count(&[], 1).is_err());
+    }
+
+    #[test]
+    fn owned_images_have_implicit_transfer_usage() {
+        let usage = image_usage(vk::ImageUsageFlags::SAMPLED, true);
+        assert!(usage.contains(vk::ImageUsageFlags::SAMPLED));
+        assert!(
+            usage.contains(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST)
+        );
+        assert!(!usage.contains(vk::ImageUsageFlags::STORAGE));
+
+        let transient =
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+        assert_eq!(image_usage(transient, true), transient);
+    }
+
+    #[test]
+    fn borrowed_images_only_have_their_creation_usage() {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        assert_eq!(image_usage(usage, false), usage);
+    }
+
+    #[test]
+    fn missing_usage_is_an_error() {
+        let usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        assert!(check_usage(usage, vk::ImageUsageFlags::TRANSFER_DST, "Copying").is_ok());
+        let res = check_usage(usage, vk::ImageUsageFlags::TRANSFER_SRC, "Copying");
+        assert!(matches!(res, Err(OxidationError::Unsupported(msg)) if msg.starts_with("Copying")));
+    }
+
+    #[test]
+    fn only_linear_textures_can_be_preinitialized() {
+        let no_features = || vk::FormatFeatureFlags::empty();
+        let info = TextureInfo {
+            initial_layout: vk::ImageLayout::PREINITIALIZED,
+            ..Default::default()
+        };
+        assert!(matches!(
+            validate_tiling(&info, vk::ImageUsageFlags::empty(), no_features),
+            Err(OxidationError::Unsupported(_))
+        ));
+
+        let info = TextureInfo {
+            initial_layout: vk::ImageLayout::GENERAL,
+            ..Default::default()
+        };
+        assert!(validate_tiling(&info, vk::ImageUsageFlags::empty(), no_features).is_err());
+        assert!(
+            validate_tiling(
+                &TextureInfo::default(),
+                vk::ImageUsageFlags::empty(),
+                || { panic!("optimal textures don't query linear features") }
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn linear_textures_are_single_level_2d() {
+        let linear = TextureInfo {
+            tiling: vk::ImageTiling::LINEAR,
+            initial_layout: vk::ImageLayout::PREINITIALIZED,
+            ..Default::default()
+        };
+        let all_features = || vk::FormatFeatureFlags::from_raw(u32::MAX);
+        assert!(validate_tiling(&linear, vk::ImageUsageFlags::empty(), all_features).is_ok());
+
+        for info in [
+            TextureInfo {
+                mip_levels: 2,
+                ..linear
+            },
+            TextureInfo {
+                array_layers: 6,
+                ..linear
+            },
+            TextureInfo {
+                ty: TextureType::Array2d,
+                ..linear
+            },
+        ] {
+            assert!(matches!(
+                validate_tiling(&info, vk::ImageUsageFlags::empty(), all_features),
+                Err(OxidationError::Unsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn linear_textures_require_format_features_for_their_usage() {
+        let linear = TextureInfo {
+            tiling: vk::ImageTiling::LINEAR,
+            ..Default::default()
+        };
+        // Textures are implicitly transfer sources and destinations.
+        let features = || {
+            vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::TRANSFER_SRC
+                | vk::FormatFeatureFlags::TRANSFER_DST
+        };
+        assert!(validate_tiling(&linear, vk::ImageUsageFlags::SAMPLED, features).is_ok());
+        let err = validate_tiling(&linear, vk::ImageUsageFlags::STORAGE, features).unwrap_err();
+        assert!(matches!(err, OxidationError::Unsupported(msg) if msg.contains("STORAGE_IMAGE")));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::error::OxidationError;
+use crate::vk_check;
+use ash::vk;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+/// Vends command buffers for a queue family from a command pool per thread, so worker threads
+/// can record in parallel - command pools can't be used from more than one thread at once.
+/// Pools are created lazily on the first allocation from each thread.
+///
+/// Unlike the [`Driver`](crate::Driver), this can be shared between threads. The recorded
+/// command buffers are usually secondary command buffers, executed from a primary command
+/// buffer on the main thread.
+///
+/// A pool is kept for every thread which has allocated, until [`Self::destroy`] - so with
+/// short-lived threads, each thread should call [`Self::release`] before exiting, otherwise
+/// its pool is leaked until then. Long-lived worker threads, i.e. a thread pool, don't need to.
+///
+/// # Examples
+///
+/// ```ignore
+/// let commands = Arc::new(ThreadLocalCommands::new(&driver.device.device, driver.device.graphics_queue_idx));
+/// let handles: Vec<_> = (0..4).map(|_| {
+///     let commands = commands.clone();
+///     std::thread::spawn(move || {
+///         let cmds = commands.allocate(vk::CommandBufferLevel::SECONDARY)?;
+///         // ..begin and record the command buffer.
+///         Ok::<_, OxidationError>(cmds)
+///     })
+/// }).collect();
+/// // Once all threads have joined and the frame has completed on the device..
+/// commands.reset()?;
+/// ```
+///
+pub struct ThreadLocalCommands {
+    device: ash::Device,
+    queue_family_idx: u32,
+    pools: PerThread<vk::CommandPool>,
+}
+
+impl ThreadLocalCommands {
+    pub fn new(device: &ash::Device, queue_family_idx: u32) -> Self {
+        Self {
+            device: device.clone(),
+            queue_family_idx,
+            pools: PerThread::default(),
+        }
+    }
+
+    pub fn queue_family_idx(&self) -> u32 {
+        self.queue_family_idx
+    }
+
+    /// Allocate a command buffer from the pool of the calling thread. The command buffer must
+    /// only be recorded on this thread, but can be submitted from any thread.
+    pub fn allocate(
+        &self,
+        level: vk::CommandBufferLevel,
+    ) -> Result<vk::CommandBuffer, OxidationError> {
+        let pool = self.pools.get_or_try_insert(|| {
+            // The buffers are reset together with the pool, rather than individually.
+            let create_info = vk::CommandPoolCreateInfo::default()
+                .queue_family_index(self.queue_family_idx)
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+            unsafe {
+                vk_check!(
+                    self.device.create_command_pool(&create_info, None),
+                    "vkCreateCommandPool"
+                )
+            }
+        })?;
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(pool)
+            .level(level)
+            .command_buffer_count(1);
+        let cmds = unsafe {
+            vk_check!(
+                self.device.allocate_command_buffers(&alloc_info),
+                "vkAllocateCommandBuffers"
+            )?
+        };
+        Ok(cmds[0])
+    }
+
+    /// The number of threads which have allocated command buffers.
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Destroy the pool of the calling thread, i.e. before the thread exits, freeing its
+    /// command buffers, which must no longer be in use by the device. A later allocation from
+    /// the thread creates a new pool.
+    pub fn release(&self) {
+        if let Some(pool) = self.pools.remove_current() {
+            unsafe { self.device.destroy_command_pool(pool, None) };
+        }
+    }
+
+    /// Reset the pools of all threads, returning their command buffers to the initial state
+    /// for re-recording. No thread may be recording, and the device must have finished
+    /// executing all command buffers allocated since the last reset.
+    pub fn reset(&self) -> Result<(), OxidationError> {
+        for pool in self.pools.values() {
+            unsafe {
+                vk_check!(
+                    self.device
+                        .reset_command_pool(pool, vk::CommandPoolResetFlags::empty()),
+                    "vkResetCommandPool"
+                )?
+            };
+        }
+        Ok(())
+    }
+
+    /// Destroy the pools of all threads, freeing their command buffers, which must no longer
+    /// be in use by the device.
+    pub fn destroy(&mut self) {
+        for pool in self.pools.drain() {
+            unsafe { self.device.destroy_command_pool(pool, None) };
+        }
+    }
+}
+
+/// A value per thread, created on first use from each thread.
+struct PerThread<T> {
+    values: Mutex<HashMap<ThreadId, T>>,
+}
+
+impl<T> Default for PerThread<T> {
+    fn default() -> Self {
+        Self {
+            values: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Copy> PerThread<T> {
+    /// The value of the calling thread, created if the thread has no value yet.
+    fn get_or_try_insert<E>(&self, create: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let mut values = self.values.lock().unwrap();
+        let id = thread::current().id();
+        if let Some(value) = values.get(&id) {
+            return Ok(*value);
+        }
+        let value = create()?;
+        values.insert(id, value);
+        Ok(value)
+    }
+
+    /// Remove the value of the calling thread.
+    fn remove_current(&self) -> Option<T> {
+        self.values.lock().unwrap().remove(&thread::current().id())
+    }
+
+    fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    fn values(&self) -> Vec<T> {
+        self.values.lock().unwrap().values().copied().collect()
+    }
+
+    fn drain(&mut self) -> Vec<T> {
+        self.values
+            .get_mut()
+            .unwrap()
+            .drain()
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn each_thread_gets_its_own_value() {
+        let values = Arc::new(PerThread::default());
+        let created = Arc::new(AtomicU32::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let (values, created) = (values.clone(), created.clone());
+                thread::spawn(move || {
+                    let create = || Ok::<_, ()>(created.fetch_add(1, Ordering::Relaxed));
+                    let first = values.get_or_try_insert(create).unwrap();
+                    // Later calls from the same thread return the same value.
+                    let second = values.get_or_try_insert(create).unwrap();
+                    assert_eq!(first, second);
+                    first
+                })
+            })
+            .collect();
+        let mut per_thread: Vec<u32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        per_thread.sort();
+        assert_eq!(per_thread, [0, 1, 2, 3]);
+        assert_eq!(created.load(Ordering::Relaxed), 4);
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn released_thread_value_is_removed() {
+        let values = Arc::new(PerThread::default());
+        values.get_or_try_insert(|| Ok::<_, ()>(1)).unwrap();
+        let worker = values.clone();
+        thread::spawn(move || {
+            worker.get_or_try_insert(|| Ok::<_, ()>(2)).unwrap();
+            assert_eq!(worker.remove_current(), Some(2));
+            assert_eq!(worker.remove_current(), None);
+        })
+        .join()
+        .unwrap();
+        // Only the value of the releasing thread is removed.
+        assert_eq!(values.values(), [1]);
+    }
+
+    #[test]
+    fn failed_creation_inserts_nothing() {
+        let values = PerThread::<u32>::default();
+        assert_eq!(values.get_or_try_insert(|| Err("failed")), Err("failed"));
+        assert_eq!(values.len(), 0);
+    }
+}
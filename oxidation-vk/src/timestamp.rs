@@ -0,0 +1,157 @@
+use crate::Driver;
+use crate::barrier;
+use crate::device::ContextDevice;
+use crate::error::OxidationError;
+use crate::vk_check;
+use ash::vk;
+use std::time::Duration;
+
+/// Measures the GPU time of work recorded between a pair of timestamps. The timer has a number
+/// of slots, usually one per frame in flight, so the results of previous frames can be read
+/// without waiting while the current frame is recorded.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut timer = GpuTimer::new(&driver, 3)?;
+///
+/// // Each frame..
+/// let slot = (frame_index % 3) as u32;
+/// timer.begin(&driver.device, cmds, slot);
+/// // ..record the frame.
+/// timer.end(&driver.device, cmds, slot);
+///
+/// // Later, once the frame has completed.
+/// if let Some(gpu_time) = timer.elapsed(&driver.device.device, slot) {
+///     println!("GPU time: {gpu_time:?}");
+/// }
+/// ```
+///
+pub struct GpuTimer {
+    query_pool: vk::QueryPool,
+    /// The number of nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    /// The timestamps are masked to this many valid bits.
+    valid_mask: u64,
+    /// Whether both timestamps of each slot have been written since the slot was last read.
+    pending: Vec<bool>,
+}
+
+impl GpuTimer {
+    /// Create a timer with the number of slots. Returns an error if the graphics queue doesn't
+    /// support timestamps.
+    pub fn new(driver: &Driver, slot_count: u32) -> Result<Self, OxidationError> {
+        let queue_props = unsafe {
+            driver
+                .instance
+                .instance
+                .get_physical_device_queue_family_properties(driver.device.physical_device)
+        };
+        let valid_bits =
+            queue_props[driver.device.graphics_queue_idx as usize].timestamp_valid_bits;
+        if valid_bits == 0 {
+            return Err(OxidationError::Unsupported(
+                "The graphics queue does not support timestamp queries.".to_string(),
+            ));
+        }
+
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(slot_count * 2);
+        let query_pool = unsafe {
+            vk_check!(
+                driver.device.device.create_query_pool(&create_info, None),
+                "vkCreateQueryPool"
+            )?
+        };
+
+        Ok(Self {
+            query_pool,
+            timestamp_period: driver.device.limits.timestamp_period as f64,
+            valid_mask: match valid_bits {
+                64 => u64::MAX,
+                bits => (1 << bits) - 1,
+            },
+            pending: vec![false; slot_count as usize],
+        })
+    }
+
+    /// Write the start timestamp of the slot, once all previous commands have begun. This must
+    /// be recorded outside of a render pass, as the slot queries are also reset.
+    pub fn begin(&mut self, device: &ContextDevice, cmds: vk::CommandBuffer, slot: u32) {
+        unsafe {
+            device
+                .device
+                .cmd_reset_query_pool(cmds, self.query_pool, slot * 2, 2)
+        };
+        self.write_timestamp(device, cmds, vk::PipelineStageFlags2::NONE, slot * 2);
+        self.pending[slot as usize] = false;
+    }
+
+    /// Write the end timestamp of the slot, once all previous commands have completed.
+    pub fn end(&mut self, device: &ContextDevice, cmds: vk::CommandBuffer, slot: u32) {
+        self.write_timestamp(
+            device,
+            cmds,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            slot * 2 + 1,
+        );
+        self.pending[slot as usize] = true;
+    }
+
+    /// Write a timestamp with `vkCmdWriteTimestamp2` if `VK_KHR_synchronization2` is enabled,
+    /// otherwise with the legacy equivalent of the stage.
+    fn write_timestamp(
+        &self,
+        device: &ContextDevice,
+        cmds: vk::CommandBuffer,
+        stage: vk::PipelineStageFlags2,
+        query: u32,
+    ) {
+        if device.enabled_features().synchronization2 {
+            unsafe {
+                device
+                    .device
+                    .cmd_write_timestamp2(cmds, stage, self.query_pool, query)
+            };
+            return;
+        }
+        unsafe {
+            device.device.cmd_write_timestamp(
+                cmds,
+                barrier::to_legacy_stage_flags(stage),
+                self.query_pool,
+                query,
+            )
+        };
+    }
+
+    /// The time between the timestamps of the slot. This doesn't wait - `None` is returned if
+    /// the work hasn't completed yet, or if the slot has no new result since it was last read.
+    pub fn elapsed(&mut self, device: &ash::Device, slot: u32) -> Option<Duration> {
+        if !self.pending[slot as usize] {
+            return None;
+        }
+        let mut timestamps = [0u64; 2];
+        unsafe {
+            device
+                .get_query_pool_results(
+                    self.query_pool,
+                    slot * 2,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+                .ok()?
+        };
+        self.pending[slot as usize] = false;
+
+        let ticks = (timestamps[1].wrapping_sub(timestamps[0])) & self.valid_mask;
+        Some(Duration::from_nanos(
+            (ticks as f64 * self.timestamp_period) as u64,
+        ))
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe { device.destroy_query_pool(self.query_pool, None) };
+    }
+}
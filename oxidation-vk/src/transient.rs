@@ -0,0 +1,331 @@
+use crate::Driver;
+use crate::texture::{self, Texture, TextureInfo, TextureType};
+use ash::vk;
+use std::error::Error;
+use vk_mem::Alloc;
+
+/// Describes a transient image - one which is only used between (and including) the first
+/// and last pass indices, so its memory can be shared with images used in other passes.
+#[derive(Debug, Copy, Clone)]
+pub struct TransientImageDesc {
+    pub info: TextureInfo,
+    pub usage: vk::ImageUsageFlags,
+    pub first_pass: u32,
+    pub last_pass: u32,
+}
+
+impl TransientImageDesc {
+    fn overlaps(&self, other: &TransientImageDesc) -> bool {
+        self.first_pass <= other.last_pass && other.first_pass <= self.last_pass
+    }
+}
+
+/// A transient image bound to a region of the shared memory block.
+pub struct TransientImage {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub info: TextureInfo,
+    /// The offset of the image within the shared memory block.
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+/// The memory usage of the transient images, with and without aliasing.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct TransientMemoryStats {
+    pub image_count: usize,
+    /// The memory which would be required if each image had its own allocation.
+    pub unaliased_size: vk::DeviceSize,
+    /// The size of the shared memory block.
+    pub aliased_size: vk::DeviceSize,
+}
+
+impl TransientMemoryStats {
+    /// The memory saved by aliasing images with disjoint lifetimes.
+    pub fn saved_size(&self) -> vk::DeviceSize {
+        self.unaliased_size.saturating_sub(self.aliased_size)
+    }
+}
+
+/// Compute the offset of each image within a shared memory block, such that images whose
+/// lifetimes overlap never overlap in memory. Returns the offsets, in the order of the
+/// descriptions, along with the total size of the block.
+///
+/// Images are placed in order of decreasing size, each at the lowest aligned offset which
+/// doesn't conflict with the images already placed.
+pub fn plan_placements(
+    descs: &[TransientImageDesc],
+    requirements: &[vk::MemoryRequirements],
+) -> (Vec<vk::DeviceSize>, vk::DeviceSize) {
+    let mut order: Vec<usize> = (0..descs.len()).collect();
+    order.sort_by(|a, b| requirements[*b].size.cmp(&requirements[*a].size));
+
+    let mut offsets = vec![0; descs.len()];
+    let mut placed: Vec<usize> = Vec::new();
+    let mut total_size = 0;
+    for idx in order {
+        let reqs = &requirements[idx];
+        let conflicts: Vec<usize> = placed
+            .iter()
+            .copied()
+            .filter(|other| descs[idx].overlaps(&descs[*other]))
+            .collect();
+
+        // Candidate offsets are the start of the block and the end of each conflicting image.
+        let mut candidates: Vec<vk::DeviceSize> = std::iter::once(0)
+            .chain(
+                conflicts
+                    .iter()
+                    .map(|other| offsets[*other] + requirements[*other].size),
+            )
+            .map(|offset| offset.next_multiple_of(reqs.alignment.max(1)))
+            .collect();
+        candidates.sort_unstable();
+
+        let offset = candidates
+            .into_iter()
+            .find(|offset| {
+                conflicts.iter().all(|other| {
+                    let other_start = offsets[*other];
+                    let other_end = other_start + requirements[*other].size;
+                    offset + reqs.size <= other_start || *offset >= other_end
+                })
+            })
+            .expect("The end of the last conflicting image is always a valid placement.");
+
+        offsets[idx] = offset;
+        total_size = total_size.max(offset + reqs.size);
+        placed.push(idx);
+    }
+    (offsets, total_size)
+}
+
+/// Allocates transient images from a single shared memory block, with images whose lifetimes
+/// don't overlap aliasing the same memory. This reduces the memory required by render targets
+/// which are only used by a few passes of the frame.
+///
+/// The contents of an aliased image are undefined at the start of its lifetime, so it must be
+/// transitioned from the `UNDEFINED` layout on first use each frame.
+///
+/// # Examples
+///
+/// ```ignore
+/// let descs = [
+///     TransientImageDesc { info: gbuffer_info, usage, first_pass: 0, last_pass: 1 },
+///     TransientImageDesc { info: bloom_info, usage, first_pass: 2, last_pass: 3 },
+/// ];
+/// let transients = TransientAllocator::new(&driver, &descs)?;
+/// println!("Saved {} bytes", transients.stats().saved_size());
+/// ```
+///
+pub struct TransientAllocator {
+    images: Vec<TransientImage>,
+    memory: Option<vk_mem::Allocation>,
+    stats: TransientMemoryStats,
+}
+
+impl TransientAllocator {
+    pub fn new(driver: &Driver, descs: &[TransientImageDesc]) -> Result<Self, Box<dyn Error>> {
+        let mut allocator = Self {
+            images: Vec::with_capacity(descs.len()),
+            memory: None,
+            stats: TransientMemoryStats::default(),
+        };
+        // Everything created so far is destroyed if a later step fails.
+        if let Err(err) = allocator.create(driver, descs) {
+            allocator.destroy(driver);
+            return Err(err);
+        }
+        Ok(allocator)
+    }
+
+    fn create(
+        &mut self,
+        driver: &Driver,
+        descs: &[TransientImageDesc],
+    ) -> Result<(), Box<dyn Error>> {
+        let device = &driver.device.device;
+        for desc in descs {
+            let create_info = vk::ImageCreateInfo::default()
+                .flags(match desc.info.ty {
+                    TextureType::Cube2d | TextureType::CubeArray2d => {
+                        vk::ImageCreateFlags::CUBE_COMPATIBLE
+                    }
+                    _ => vk::ImageCreateFlags::empty(),
+                })
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(desc.info.format)
+                .extent(vk::Extent3D {
+                    width: desc.info.width,
+                    height: desc.info.height,
+                    depth: 1,
+                })
+                .mip_levels(desc.info.mip_levels)
+                .array_layers(texture::compute_array_layers(
+                    &desc.info.ty,
+                    desc.info.array_layers,
+                ))
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(desc.usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+            let image = unsafe { device.create_image(&create_info, None)? };
+            // The view is created once the image is bound to memory.
+            self.images.push(TransientImage {
+                image,
+                image_view: vk::ImageView::null(),
+                info: desc.info,
+                offset: 0,
+                size: 0,
+            });
+        }
+        if self.images.is_empty() {
+            return Ok(());
+        }
+
+        let requirements: Vec<vk::MemoryRequirements> = self
+            .images
+            .iter()
+            .map(|image| unsafe { device.get_image_memory_requirements(image.image) })
+            .collect();
+        let (offsets, total_size) = plan_placements(descs, &requirements);
+
+        let memory_type_bits = requirements
+            .iter()
+            .fold(u32::MAX, |bits, reqs| bits & reqs.memory_type_bits);
+        if memory_type_bits == 0 {
+            return Err(Box::from(
+                "The transient images don't share a common memory type so can't be aliased.",
+            ));
+        }
+
+        let block_reqs = vk::MemoryRequirements {
+            size: total_size,
+            alignment: requirements.iter().map(|r| r.alignment).max().unwrap_or(1),
+            memory_type_bits,
+        };
+        let alloc_info = vk_mem::AllocationCreateInfo {
+            required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            flags: vk_mem::AllocationCreateFlags::DEDICATED_MEMORY,
+            ..Default::default()
+        };
+        let memory = self.memory.insert(unsafe {
+            driver
+                .vma_allocator
+                .allocate_memory(&block_reqs, &alloc_info)?
+        });
+
+        let alloc_info = driver.vma_allocator.get_allocation_info(memory);
+        for (image, (offset, reqs)) in self
+            .images
+            .iter_mut()
+            .zip(offsets.iter().zip(requirements.iter()))
+        {
+            unsafe {
+                device.bind_image_memory(
+                    image.image,
+                    alloc_info.device_memory,
+                    alloc_info.offset + offset,
+                )?
+            };
+            image.image_view = Texture::create_image_view(
+                &image.image,
+                &image.info,
+                0,
+                image.info.mip_levels,
+                device,
+            );
+            image.offset = *offset;
+            image.size = reqs.size;
+        }
+
+        self.stats = TransientMemoryStats {
+            image_count: self.images.len(),
+            unaliased_size: requirements.iter().map(|reqs| reqs.size).sum(),
+            aliased_size: total_size,
+        };
+        Ok(())
+    }
+
+    /// The transient image at the index of its description.
+    pub fn image(&self, idx: usize) -> Option<&TransientImage> {
+        self.images.get(idx)
+    }
+
+    pub fn stats(&self) -> TransientMemoryStats {
+        self.stats
+    }
+
+    /// Destroy the images and free the shared memory block. Views which haven't been
+    /// created are null, so destroying them is a no-op.
+    pub fn destroy(&mut self, driver: &Driver) {
+        let device = &driver.device.device;
+        for image in self.images.drain(..) {
+            unsafe {
+                device.destroy_image_view(image.image_view, None);
+                device.destroy_image(image.image, None);
+            }
+        }
+        if let Some(mut memory) = self.memory.take() {
+            unsafe { driver.vma_allocator.free_memory(&mut memory) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(first_pass: u32, last_pass: u32) -> TransientImageDesc {
+        TransientImageDesc {
+            info: TextureInfo::default(),
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            first_pass,
+            last_pass,
+        }
+    }
+
+    fn reqs(size: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size,
+            alignment,
+            memory_type_bits: 1,
+        }
+    }
+
+    #[test]
+    fn disjoint_lifetimes_alias_the_same_offset() {
+        let descs = [desc(0, 1), desc(2, 3)];
+        let (offsets, total_size) = plan_placements(&descs, &[reqs(1024, 256), reqs(512, 256)]);
+        assert_eq!(offsets, [0, 0]);
+        assert_eq!(total_size, 1024);
+    }
+
+    #[test]
+    fn overlapping_lifetimes_are_placed_apart() {
+        let descs = [desc(0, 2), desc(1, 3)];
+        let (offsets, total_size) = plan_placements(&descs, &[reqs(1024, 256), reqs(512, 256)]);
+        assert_eq!(offsets, [0, 1024]);
+        assert_eq!(total_size, 1536);
+    }
+
+    #[test]
+    fn placements_respect_alignment() {
+        let descs = [desc(0, 1), desc(1, 2)];
+        let (offsets, total_size) = plan_placements(&descs, &[reqs(1000, 1), reqs(100, 256)]);
+        assert_eq!(offsets, [0, 1024]);
+        assert_eq!(total_size, 1124);
+    }
+
+    #[test]
+    fn freed_gap_is_reused() {
+        // The first two images are placed side by side, the third only overlaps the second so
+        // reuses the memory of the first.
+        let descs = [desc(0, 1), desc(1, 2), desc(2, 3)];
+        let (offsets, total_size) =
+            plan_placements(&descs, &[reqs(1024, 256), reqs(1024, 256), reqs(512, 256)]);
+        assert_eq!(offsets, [0, 1024, 0]);
+        assert_eq!(total_size, 2048);
+    }
+}
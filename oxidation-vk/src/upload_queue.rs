@@ -0,0 +1,346 @@
+use crate::Driver;
+use crate::barrier;
+use crate::device::ContextDevice;
+use crate::host_memory;
+use crate::staging_pool::{self, Instance};
+use crate::sync;
+use crate::texture::Texture;
+use ash::vk;
+use std::error::Error;
+use std::ops::Range;
+
+/// Identifies an upload (or rather the batch it was recorded into) so its completion can be polled.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct UploadTicket(u64);
+
+struct UploadBatch {
+    ticket: u64,
+    cmds: vk::CommandBuffer,
+    fence: vk::Fence,
+    /// Staging buffers which must be kept alive until the batch has completed.
+    stages: Vec<Instance>,
+    /// Queue family ownership acquire barriers which need to be recorded on the graphics
+    /// queue once this batch has completed.
+    acquire_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+}
+
+/// Uploads resources to the device asynchronously via the transfer queue, so streaming
+/// large assets doesn't block the main thread waiting on the graphics queue.
+///
+/// Uploads are recorded into a batch which is submitted with `flush()`. Completion is
+/// checked with `poll()` and `is_complete()`. If the device has a dedicated transfer queue,
+/// images must be acquired by the graphics queue before use by recording the barriers
+/// returned from completed uploads with `record_acquire_barriers()`.
+///
+/// # Examples
+///
+/// ```ignore
+/// use oxidation_vk::upload_queue::UploadQueue;
+///
+/// let mut upload_queue = UploadQueue::new(&driver.device);
+/// let ticket = upload_queue.enqueue_texture(&driver, &mut texture, &data, &offsets)?;
+/// upload_queue.flush(&driver.device)?;
+///
+/// // Some time later..
+/// upload_queue.poll(&driver);
+/// if upload_queue.is_complete(ticket) {
+///     upload_queue.record_acquire_barriers(&driver.device, cmds);
+/// }
+/// ```
+///
+pub struct UploadQueue {
+    queue: vk::Queue,
+    queue_family_idx: u32,
+    /// The queue family which takes ownership of uploaded resources.
+    dst_queue_family_idx: u32,
+    cmd_pool: vk::CommandPool,
+    current_batch: Option<UploadBatch>,
+    in_flight_batches: Vec<UploadBatch>,
+    /// Acquire barriers from completed batches which have yet to be recorded.
+    pending_acquire_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+    next_ticket: u64,
+}
+
+impl UploadQueue {
+    pub fn new(device: &ContextDevice) -> Self {
+        let create_info = vk::CommandPoolCreateInfo::default()
+            .queue_family_index(device.transfer_queue_idx)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        let cmd_pool = unsafe {
+            device
+                .device
+                .create_command_pool(&create_info, None)
+                .unwrap()
+        };
+
+        Self {
+            queue: device.transfer_queue,
+            queue_family_idx: device.transfer_queue_idx,
+            dst_queue_family_idx: device.graphics_queue_idx,
+            cmd_pool,
+            current_batch: None,
+            in_flight_batches: Vec::new(),
+            pending_acquire_barriers: Vec::new(),
+            next_ticket: 0,
+        }
+    }
+
+    /// Whether uploads are made on a separate queue family, requiring ownership of the
+    /// resources to be transferred to the graphics queue.
+    pub fn requires_ownership_transfer(&self) -> bool {
+        self.queue_family_idx != self.dst_queue_family_idx
+    }
+
+    /// Record an upload of the image data, including all mip levels and faces, to the texture.
+    /// The offsets are the locations of each face and mip level within the data. The texture
+    /// will be in the shader read-only layout once the upload has completed.
+    pub fn enqueue_texture(
+        &mut self,
+        driver: &Driver,
+        texture: &mut Texture,
+        data: &[u8],
+        offsets: &[vk::DeviceSize],
+    ) -> Result<UploadTicket, Box<dyn Error>> {
+        let regions = texture.copy_regions(offsets, false)?;
+        let levels = 0..texture.mip_levels();
+        self.enqueue_levels(driver, texture, levels, data, &regions)
+    }
+
+    /// Record an upload of a range of mip levels of the texture, for all faces, leaving the
+    /// other levels untouched - i.e. streaming in higher detail levels of a texture which is
+    /// being sampled. The offsets are the locations of each face and level of the range
+    /// within the data, indexed by `face * level_count + level - levels.start`. The levels
+    /// will be in the shader read-only layout once the upload has completed.
+    pub fn enqueue_mip_levels(
+        &mut self,
+        driver: &Driver,
+        texture: &mut Texture,
+        levels: Range<u32>,
+        data: &[u8],
+        offsets: &[vk::DeviceSize],
+    ) -> Result<UploadTicket, Box<dyn Error>> {
+        if levels.is_empty() || levels.end > texture.mip_levels() {
+            return Err(Box::from(format!(
+                "Mip levels {levels:?} are outside of the texture (mip levels: {}).",
+                texture.mip_levels()
+            )));
+        }
+        let regions = texture.level_copy_regions(levels.clone(), offsets)?;
+        self.enqueue_levels(driver, texture, levels, data, &regions)
+    }
+
+    fn enqueue_levels(
+        &mut self,
+        driver: &Driver,
+        texture: &mut Texture,
+        levels: Range<u32>,
+        data: &[u8],
+        regions: &[vk::BufferImageCopy],
+    ) -> Result<UploadTicket, Box<dyn Error>> {
+        let device = &driver.device.device;
+        let data_size = data.len() as vk::DeviceSize;
+
+        let mut stage = staging_pool::create_stage(&driver.vma_allocator, data_size)?;
+        unsafe {
+            let mapped = driver.vma_allocator.map_memory(&mut stage.memory)?;
+            mapped.copy_from(data.as_ptr(), data.len());
+            driver.vma_allocator.unmap_memory(&mut stage.memory);
+        }
+        host_memory::flush_mapped(driver, &stage.memory, 0, data_size)?;
+
+        // Concurrently shared textures don't require an ownership transfer.
+        let separate_queue = self.requires_ownership_transfer();
+        let ownership_transfer =
+            separate_queue && texture.sharing_mode() == vk::SharingMode::EXCLUSIVE;
+        let (src_family_idx, dst_family_idx) = (self.queue_family_idx, self.dst_queue_family_idx);
+        let batch = self.current_batch(device)?;
+        let level_barriers = |old_layout, new_layout, src_stage, dst_stage| -> Vec<_> {
+            levels
+                .clone()
+                .map(|level| {
+                    texture.level_barrier(level, old_layout, new_layout, src_stage, dst_stage)
+                })
+                .collect()
+        };
+
+        let barriers = level_barriers(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags2::NONE,
+            vk::PipelineStageFlags2::COPY,
+        );
+        barrier::cmd_image_barriers(&driver.device, batch.cmds, &barriers);
+
+        unsafe {
+            device.cmd_copy_buffer_to_image(
+                batch.cmds,
+                stage.buffer,
+                texture.image(),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                regions,
+            )
+        };
+
+        let barriers = level_barriers(
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::COPY,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        );
+        if ownership_transfer {
+            // The release half of the ownership transfer - the dst access is ignored by the
+            // transfer queue and instead specified by the acquire barrier.
+            let release: Vec<vk::ImageMemoryBarrier2> = barriers
+                .iter()
+                .map(|b| {
+                    b.src_queue_family_index(src_family_idx)
+                        .dst_queue_family_index(dst_family_idx)
+                        .dst_stage_mask(vk::PipelineStageFlags2::NONE)
+                        .dst_access_mask(vk::AccessFlags2::NONE)
+                })
+                .collect();
+            barrier::cmd_image_barriers(&driver.device, batch.cmds, &release);
+
+            batch.acquire_barriers.extend(barriers.iter().map(|b| {
+                b.src_queue_family_index(src_family_idx)
+                    .dst_queue_family_index(dst_family_idx)
+                    .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                    .src_access_mask(vk::AccessFlags2::NONE)
+            }));
+        } else if separate_queue {
+            // Shader stages aren't supported by a transfer only queue - completion of the
+            // upload is instead guaranteed by the fence being signalled.
+            let transition: Vec<vk::ImageMemoryBarrier2> = barriers
+                .iter()
+                .map(|b| {
+                    b.dst_stage_mask(vk::PipelineStageFlags2::NONE)
+                        .dst_access_mask(vk::AccessFlags2::NONE)
+                })
+                .collect();
+            barrier::cmd_image_barriers(&driver.device, batch.cmds, &transition);
+        } else {
+            barrier::cmd_image_barriers(&driver.device, batch.cmds, &barriers);
+        }
+
+        batch.stages.push(stage);
+        texture.set_levels_layout(levels, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        Ok(UploadTicket(batch.ticket))
+    }
+
+    /// Submit all recorded uploads to the transfer queue.
+    pub fn flush(&mut self, device: &ContextDevice) -> Result<(), Box<dyn Error>> {
+        let Some(batch) = self.current_batch.take() else {
+            return Ok(());
+        };
+
+        let cmds = [batch.cmds];
+        let submit_info = vk::SubmitInfo::default().command_buffers(&cmds);
+        unsafe {
+            device.device.end_command_buffer(batch.cmds)?;
+            device
+                .device
+                .queue_submit(self.queue, &[submit_info], batch.fence)?;
+        }
+        self.in_flight_batches.push(batch);
+        Ok(())
+    }
+
+    /// Check the in-flight uploads for completion, releasing the resources of those which
+    /// have completed.
+    pub fn poll(&mut self, driver: &Driver) {
+        let device = &driver.device.device;
+        let mut idx = 0;
+        while idx < self.in_flight_batches.len() {
+            let fence = self.in_flight_batches[idx].fence;
+            let signalled = unsafe { device.get_fence_status(fence) }.unwrap_or(false);
+            if !signalled {
+                idx += 1;
+                continue;
+            }
+
+            let mut batch = self.in_flight_batches.remove(idx);
+            self.pending_acquire_barriers
+                .append(&mut batch.acquire_barriers);
+            self.destroy_batch(driver, batch);
+        }
+    }
+
+    /// Whether the upload associated with the ticket has completed. This is only updated by `poll()`.
+    pub fn is_complete(&self, ticket: UploadTicket) -> bool {
+        let in_progress = self
+            .current_batch
+            .iter()
+            .chain(self.in_flight_batches.iter())
+            .any(|batch| batch.ticket == ticket.0);
+        ticket.0 < self.next_ticket && !in_progress
+    }
+
+    /// Record the queue ownership acquire barriers for all completed uploads. This must be
+    /// called on a graphics command buffer before the uploaded resources are used.
+    pub fn record_acquire_barriers(&mut self, device: &ContextDevice, cmds: vk::CommandBuffer) {
+        barrier::cmd_image_barriers(device, cmds, &self.pending_acquire_barriers);
+        self.pending_acquire_barriers.clear();
+    }
+
+    /// Wait for all in-flight uploads to complete and destroy all resources. Any uploads
+    /// which haven't been flushed are discarded.
+    pub fn destroy(&mut self, driver: &Driver) {
+        let device = &driver.device.device;
+        let fences: Vec<vk::Fence> = self
+            .in_flight_batches
+            .iter()
+            .map(|batch| batch.fence)
+            .collect();
+        sync::wait_all(device, &fences, u64::MAX).unwrap();
+
+        let batches: Vec<UploadBatch> = self
+            .in_flight_batches
+            .drain(..)
+            .chain(self.current_batch.take())
+            .collect();
+        for batch in batches {
+            self.destroy_batch(driver, batch);
+        }
+        unsafe { device.destroy_command_pool(self.cmd_pool, None) };
+    }
+
+    fn current_batch(&mut self, device: &ash::Device) -> Result<&mut UploadBatch, Box<dyn Error>> {
+        if self.current_batch.is_none() {
+            let alloc_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(self.cmd_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+            let cmds = unsafe { device.allocate_command_buffers(&alloc_info)?[0] };
+            let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None)? };
+
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            unsafe { device.begin_command_buffer(cmds, &begin_info)? };
+
+            self.current_batch = Some(UploadBatch {
+                ticket: self.next_ticket,
+                cmds,
+                fence,
+                stages: Vec::new(),
+                acquire_barriers: Vec::new(),
+            });
+            self.next_ticket += 1;
+        }
+        Ok(self.current_batch.as_mut().unwrap())
+    }
+
+    fn destroy_batch(&self, driver: &Driver, mut batch: UploadBatch) {
+        let device = &driver.device.device;
+        for stage in batch.stages.iter_mut() {
+            unsafe {
+                driver
+                    .vma_allocator
+                    .destroy_buffer(stage.buffer, &mut stage.memory)
+            };
+        }
+        unsafe {
+            device.free_command_buffers(self.cmd_pool, &[batch.cmds]);
+            device.destroy_fence(batch.fence, None);
+        }
+    }
+}